@@ -0,0 +1,28 @@
+//! Compares the throughput of committing a `DiskStore` after different numbers of transactions.
+//! Run with `cargo bench --features rocks --bench commit_every`.
+
+use std::time::Instant;
+use tempdir::TempDir;
+use transaction_machine::{ClientState, ClientStore, DiskStore, Transaction};
+
+/// The number of deposits that are applied for each setting.
+const DEPOSITS: u32 = 20_000;
+/// The numbers of transactions after which the store is committed that are compared.
+const COMMIT_EVERY: [Option<usize>; 4] = [None, Some(1), Some(100), Some(10_000)];
+
+fn main() {
+    for commit_every in COMMIT_EVERY {
+        let dir = TempDir::new("commit_every").unwrap();
+        let mut store = ClientStore::new(DiskStore::new(dir.path()).unwrap());
+        store.set_commit_every(commit_every);
+
+        let mut client = ClientState::new(1);
+        let start = Instant::now();
+        for tx in 1..=DEPOSITS {
+            client
+                .execute_transaction(Transaction::deposit(1, tx, 1.0), &store)
+                .unwrap();
+        }
+        println!("commit_every {:?}: {:?}", commit_every, start.elapsed());
+    }
+}
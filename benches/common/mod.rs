@@ -0,0 +1,42 @@
+//! Helpers shared by the benchmarks of the IO task. Each benchmark is its own crate and not every
+//! one uses every helper.
+#![allow(dead_code)]
+
+use tokio::sync::mpsc;
+use transaction_machine::{ClientStore, IoConfig, IoTask, StoreEngine, Transaction};
+
+/// The capacity of each client's channel.
+const CHANNEL_SIZE: usize = 256;
+
+/// Returns `count` deposits, withdrawals, disputes and resolves that are interleaved across
+/// `clients` clients.
+pub fn interleaved_feed(clients: u16, count: u32) -> Vec<Transaction> {
+    (0..count)
+        .map(|tx| {
+            let client = (tx % clients as u32) as u16 + 1;
+            match tx % 7 {
+                3 => Transaction::withdrawal(client, tx, 15.0),
+                5 => Transaction::dispute(client, tx - 5),
+                6 => Transaction::resolve(client, tx - 6),
+                _ => Transaction::deposit(client, tx, 10.0),
+            }
+        })
+        .collect()
+}
+
+/// Executes `transactions` against `store` in an IO task configured by `config`.
+pub async fn run<D>(store: ClientStore<D>, transactions: Vec<Transaction>, config: IoConfig)
+where
+    D: StoreEngine + 'static,
+{
+    let (tx, rx) = mpsc::channel(transactions.len() + 1);
+    for transaction in transactions {
+        tx.send(transaction).await.unwrap();
+    }
+    drop(tx);
+
+    IoTask::new(rx, store, config, None)
+        .run(CHANNEL_SIZE)
+        .await
+        .unwrap();
+}
@@ -0,0 +1,63 @@
+//! Compares reading keys one at a time, which takes the memory store's lock or makes a RocksDB
+//! call for every key, with reading them in batches, which takes the lock or makes the call once
+//! for each 256 keys. Run with `cargo bench --bench get_batch`, adding `--features rocks` to
+//! include a `DiskStore`.
+
+use std::time::{Duration, Instant};
+#[cfg(feature = "rocks")]
+use tempdir::TempDir;
+#[cfg(feature = "rocks")]
+use transaction_machine::DiskStore;
+use transaction_machine::{Keyspace, MemStore, StoreEngine};
+
+/// The number of keys that are written and then read.
+const KEYS: u32 = 200_000;
+/// The number of keys that are read by each `get_batch`.
+const BATCH_SIZE: usize = 256;
+
+/// Writes `KEYS` keys to the `Clients` keyspace of `engine`.
+fn write_keys<D: StoreEngine>(engine: &D) {
+    let value = [0u8; 64];
+    for key in 0..KEYS {
+        engine
+            .put(Keyspace::Clients, &u32::to_be_bytes(key), &value)
+            .unwrap();
+    }
+}
+
+/// Returns how long it takes to read every key from the `Clients` keyspace of `engine`, either
+/// with a `get` for each key or with a `get_batch` for each `BATCH_SIZE` keys.
+fn time_gets<D: StoreEngine>(engine: &D, batched: bool) -> Duration {
+    let keys = (0..KEYS).map(u32::to_be_bytes).collect::<Vec<_>>();
+    let keys = keys.iter().map(|key| key.as_slice()).collect::<Vec<_>>();
+    let start = Instant::now();
+    for chunk in keys.chunks(BATCH_SIZE) {
+        if batched {
+            assert_eq!(
+                engine.get_batch(Keyspace::Clients, chunk).unwrap().len(),
+                chunk.len()
+            );
+        } else {
+            for key in chunk {
+                assert!(engine.get(Keyspace::Clients, key).unwrap().is_some());
+            }
+        }
+    }
+    start.elapsed()
+}
+
+fn main() {
+    let engine = MemStore::default();
+    write_keys(&engine);
+    println!("memory get: {:?}", time_gets(&engine, false));
+    println!("memory get_batch: {:?}", time_gets(&engine, true));
+
+    #[cfg(feature = "rocks")]
+    {
+        let dir = TempDir::new("get_batch").unwrap();
+        let engine = DiskStore::new(dir.path()).unwrap();
+        write_keys(&engine);
+        println!("disk get: {:?}", time_gets(&engine, false));
+        println!("disk get_batch: {:?}", time_gets(&engine, true));
+    }
+}
@@ -0,0 +1,31 @@
+//! Compares the throughput of different micro-batch sizes. Run with
+//! `cargo bench --bench micro_batch`.
+
+mod common;
+
+use std::time::Instant;
+use tokio::runtime::Runtime;
+use transaction_machine::{ClientStore, IoConfig, MemStore};
+
+/// The number of clients that the transactions are interleaved across.
+const CLIENTS: u16 = 64;
+/// The number of transactions that are executed.
+const TRANSACTIONS: u32 = 200_000;
+/// The micro-batch sizes that are compared, where `None` receives each transaction on its own.
+const MICRO_BATCHES: [Option<usize>; 4] = [None, Some(8), Some(64), Some(512)];
+
+fn main() {
+    let transactions = common::interleaved_feed(CLIENTS, TRANSACTIONS);
+    let runtime = Runtime::new().unwrap();
+
+    for micro_batch in MICRO_BATCHES {
+        let config = IoConfig {
+            micro_batch,
+            ..Default::default()
+        };
+        let store = ClientStore::new(MemStore::default());
+        let start = Instant::now();
+        runtime.block_on(common::run(store, transactions.clone(), config));
+        println!("micro_batch {:?}: {:?}", micro_batch, start.elapsed());
+    }
+}
@@ -0,0 +1,41 @@
+//! Measures a run of a deposit for each of 60,000 new clients against a `SqliteStore`, so that
+//! every transaction starts a client, followed by a second run of the same deposits for the
+//! existing clients, both with and without the clients indexed. Run with
+//! `cargo bench --features sqlite --bench new_clients`.
+
+mod common;
+
+use std::time::Instant;
+use tempdir::TempDir;
+use tokio::runtime::Runtime;
+use transaction_machine::{ClientStore, IoConfig, SqliteStore, Transaction};
+
+/// The number of clients that are each sent a deposit in a run.
+const CLIENTS: u32 = 60_000;
+
+fn main() {
+    let runtime = Runtime::new().unwrap();
+
+    for index_clients in [false, true] {
+        let dir = TempDir::new("new_clients").unwrap();
+        let store = ClientStore::new(SqliteStore::new(dir.path().join("store.db")).unwrap());
+        let config = IoConfig {
+            index_clients,
+            ..Default::default()
+        };
+
+        for (run, first_tx) in [("new", 0), ("existing", CLIENTS)] {
+            let transactions = (0..CLIENTS)
+                .map(|tx| Transaction::deposit(tx as u16 + 1, first_tx + tx, 1.0))
+                .collect();
+            let start = Instant::now();
+            runtime.block_on(common::run(store.clone(), transactions, config));
+            println!(
+                "{} clients, index {}: {:?}",
+                run,
+                index_clients,
+                start.elapsed()
+            );
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Compares the time taken to read a large input file with different capacities of the CSV
+//! reader's buffer. Run with `cargo bench --bench reader_buffer`.
+
+use futures::future::join;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tempdir::TempDir;
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc;
+use transaction_machine::{reader_task, ReaderConfig};
+
+/// The number of transactions in the input file.
+const TRANSACTIONS: u32 = 2_000_000;
+/// The buffer capacities, in bytes, that are compared.
+const CAPACITIES: [usize; 5] = [1 << 10, 8 << 10, 64 << 10, 256 << 10, 1 << 20];
+/// The capacity of the channel that transactions are read in to.
+const CHANNEL_SIZE: usize = 1024;
+
+/// Writes a file of `TRANSACTIONS` deposits, withdrawals and disputes across many clients.
+fn write_input(path: &Path) {
+    let mut file = BufWriter::new(File::create(path).unwrap());
+    writeln!(file, "type, client, tx, amount").unwrap();
+    for tx in 1..=TRANSACTIONS {
+        let client = tx % 1000;
+        match tx % 3 {
+            0 => writeln!(file, "deposit, {}, {}, {}.1234", client, tx, tx % 100).unwrap(),
+            1 => writeln!(file, "withdrawal, {}, {}, 0.5", client, tx).unwrap(),
+            _ => writeln!(file, "dispute, {}, {},", client, tx - 2).unwrap(),
+        }
+    }
+    file.flush().unwrap();
+}
+
+/// Reads every transaction in `path` with `config`, returning how many were read.
+async fn read(path: PathBuf, config: ReaderConfig) -> usize {
+    let (tx, mut rx) = mpsc::channel(CHANNEL_SIZE);
+    let drain = async move {
+        let mut count = 0;
+        while rx.recv().await.is_some() {
+            count += 1;
+        }
+        count
+    };
+
+    let (summary, count) = join(reader_task(vec![path], config, tx, None, None), drain).await;
+    summary.unwrap();
+    count
+}
+
+fn main() {
+    let dir = TempDir::new("reader_buffer").unwrap();
+    let path = dir.path().join("input.csv");
+    write_input(&path);
+    let runtime = Runtime::new().unwrap();
+
+    for capacity in CAPACITIES {
+        let config = ReaderConfig {
+            buffer_capacity: capacity,
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let count = runtime.block_on(read(path.clone(), config));
+        println!(
+            "buffer_capacity {:>8}: {:?} for {} transactions",
+            capacity,
+            start.elapsed(),
+            count
+        );
+    }
+}
@@ -0,0 +1,32 @@
+//! Compares the throughput of running each client in its own cached task with sharding clients
+//! across worker tasks, for more clients than the cache holds. Run with
+//! `cargo bench --bench sharded`.
+
+mod common;
+
+use std::time::Instant;
+use tokio::runtime::Runtime;
+use transaction_machine::{ClientStore, IoConfig, MemStore};
+
+/// The number of clients that the transactions are interleaved across.
+const CLIENTS: u16 = 10_000;
+/// The number of transactions that are executed.
+const TRANSACTIONS: u32 = 200_000;
+/// The numbers of worker tasks that are compared, where `None` runs each client in its own task.
+const SHARD_WORKERS: [Option<usize>; 4] = [None, Some(1), Some(4), Some(16)];
+
+fn main() {
+    let transactions = common::interleaved_feed(CLIENTS, TRANSACTIONS);
+    let runtime = Runtime::new().unwrap();
+
+    for shard_workers in SHARD_WORKERS {
+        let config = IoConfig {
+            shard_workers,
+            ..Default::default()
+        };
+        let store = ClientStore::new(MemStore::default());
+        let start = Instant::now();
+        runtime.block_on(common::run(store, transactions.clone(), config));
+        println!("shard_workers {:?}: {:?}", shard_workers, start.elapsed());
+    }
+}
@@ -0,0 +1,65 @@
+//! Compares the size and encoding time of client states and transactions with each value codec.
+//! Run with `cargo bench --features compression --bench value_codec`.
+
+use std::time::Instant;
+use transaction_machine::{ClientState, ClientStore, MemStore, Transaction, ValueCodec};
+
+/// The number of clients that each execute a deposit, a withdrawal and a dispute.
+const CLIENTS: u16 = 10_000;
+/// The value codecs that are compared.
+const CODECS: [ValueCodec; 4] = [
+    ValueCodec::Plain,
+    ValueCodec::Compact,
+    ValueCodec::Zstd,
+    ValueCodec::Lz4,
+];
+
+/// Executes a deposit, a withdrawal and a dispute for the client with `client_id` against `store`.
+/// Returns the resulting client state.
+fn disputed_client(client_id: u16, store: &ClientStore<MemStore>) -> ClientState {
+    let mut client = ClientState::new(client_id);
+    for transaction in [
+        Transaction::deposit(client_id, 1, 10.5),
+        Transaction::withdrawal(client_id, 2, 2.25),
+        Transaction::deposit(client_id, 3, 1.0),
+        Transaction::dispute(client_id, 3),
+    ] {
+        client.execute_transaction(transaction, store).unwrap();
+    }
+    client
+}
+
+fn main() {
+    let store = ClientStore::new(MemStore::default());
+    let clients = (1..=CLIENTS)
+        .map(|client_id| disputed_client(client_id, &store))
+        .collect::<Vec<_>>();
+    let ids = (1..=CLIENTS)
+        .flat_map(|client_id| (1..=3).map(move |tx| (client_id, tx)))
+        .collect::<Vec<_>>();
+    let transactions = store
+        .get_transactions(&ids)
+        .unwrap()
+        .into_iter()
+        .map(Option::unwrap)
+        .collect::<Vec<Transaction>>();
+
+    for codec in CODECS {
+        let start = Instant::now();
+        let state_bytes = clients
+            .iter()
+            .map(|client| codec.serialize(client).unwrap().len())
+            .sum::<usize>();
+        let tx_bytes = transactions
+            .iter()
+            .map(|transaction| codec.serialize(transaction).unwrap().len())
+            .sum::<usize>();
+        println!(
+            "{:?}: {} bytes of client states, {} bytes of transactions in {:?}",
+            codec,
+            state_bytes,
+            tx_bytes,
+            start.elapsed()
+        );
+    }
+}
@@ -0,0 +1,53 @@
+//! Compares the throughput of writing each transaction and its client's state as two puts or as
+//! a single batch. Run with `cargo bench --features sqlite --bench write_batch`, adding
+//! `--features rocks` to include a `DiskStore`.
+
+use std::time::{Duration, Instant};
+use tempdir::TempDir;
+#[cfg(feature = "rocks")]
+use transaction_machine::DiskStore;
+use transaction_machine::{BatchWrite, Keyspace, SqliteStore, StoreEngine};
+
+/// The number of transactions that are written to a `DiskStore`.
+#[cfg(feature = "rocks")]
+const DISK_WRITES: u32 = 100_000;
+/// The number of transactions that are written to a `SqliteStore`.
+const SQLITE_WRITES: u32 = 20_000;
+
+/// Returns how long it takes to write `count` transactions, along with their client's state, to
+/// `engine` either as two puts or as a single batch each.
+fn time_writes<D: StoreEngine>(engine: &D, batched: bool, count: u32) -> Duration {
+    let value = [0u8; 64];
+    let start = Instant::now();
+    for tx in 0..count {
+        let key = tx.to_be_bytes();
+        if batched {
+            let writes = [
+                BatchWrite::Put(Keyspace::Transactions, &key[..], &value[..]),
+                BatchWrite::Put(Keyspace::Clients, &[1][..], &value[..]),
+            ];
+            engine.write_batch(&writes).unwrap();
+        } else {
+            engine.put(Keyspace::Transactions, &key, &value).unwrap();
+            engine.put(Keyspace::Clients, &[1], &value).unwrap();
+        }
+    }
+    start.elapsed()
+}
+
+fn main() {
+    #[cfg(feature = "rocks")]
+    for batched in [false, true] {
+        let dir = TempDir::new("write_batch").unwrap();
+        let engine = DiskStore::new(dir.path()).unwrap();
+        let elapsed = time_writes(&engine, batched, DISK_WRITES);
+        println!("disk batched {}: {:?}", batched, elapsed);
+    }
+
+    for batched in [false, true] {
+        let dir = TempDir::new("write_batch").unwrap();
+        let engine = SqliteStore::new(dir.path().join("store.db")).unwrap();
+        let elapsed = time_writes(&engine, batched, SQLITE_WRITES);
+        println!("sqlite batched {}: {:?}", batched, elapsed);
+    }
+}
@@ -0,0 +1,646 @@
+#[cfg(test)]
+mod tests;
+
+use crate::client::{
+    ChargedBackResolvePolicy, CorruptStatePolicy, Policy, ValueCodec, WithdrawalDisputePolicy,
+    ZeroDisputePolicy, SECONDS_PER_DAY,
+};
+use crate::io::IoConfig;
+use crate::parser::reader::{is_stdin, ReaderConfig};
+use crate::parser::writer::{LineEnding, OutputFormat, SortBy, StatusGroup, WriterConfig};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+
+const BUFFER_CAPACITY_FLAG: &str = "--buffer-capacity";
+const STRICT_FREEZE_FLAG: &str = "--strict-freeze";
+const CLIENT_METADATA_FLAG: &str = "--client-metadata";
+const ASSUME_SORTED_FLAG: &str = "--assume-sorted-by-client";
+const INDEX_CLIENTS_FLAG: &str = "--index-clients";
+const ON_CORRUPT_STATE_FLAG: &str = "--on-corrupt-state";
+const MAX_OPEN_DISPUTES_FLAG: &str = "--max-open-disputes";
+const REJECTS_FLAG: &str = "--rejects";
+const MANIFEST_FLAG: &str = "--manifest";
+const WARNINGS_FLAG: &str = "--warnings";
+const OUTPUT_FLAG: &str = "--output";
+const CHECK_BOOKS_FLAG: &str = "--check-books";
+const VERIFY_WORKERS_FLAG: &str = "--verify-workers";
+#[cfg(feature = "latency")]
+const LATENCY_FLAG: &str = "--latency";
+const METRICS_FLAG: &str = "--metrics";
+const WARN_PRECISION_LOSS_FLAG: &str = "--warn-precision-loss";
+const SKIP_MALFORMED_FLAG: &str = "--skip-malformed";
+const ERROR_RATE_FLAG: &str = "--error-rate";
+const FLUSH_ON_ERROR_FLAG: &str = "--flush-on-error";
+const SORT_BY_FLAG: &str = "--sort-by";
+const FORMAT_FLAG: &str = "--format";
+const LINE_ENDING_FLAG: &str = "--line-ending";
+const BOM_FLAG: &str = "--bom";
+const TOP_FLAG: &str = "--top";
+const GROUP_BY_STATUS_FLAG: &str = "--group-by-status";
+const GROUP_SEPARATOR_FLAG: &str = "--group-separator";
+const TX_RANGE_FLAG: &str = "--tx-range";
+const POLICY_FLAG: &str = "--policy";
+const FLAG_DRAIN_PATTERN_FLAG: &str = "--flag-drain-pattern";
+const MICRO_BATCH_FLAG: &str = "--micro-batch";
+const SHARD_WORKERS_FLAG: &str = "--shard-workers";
+const MAX_CLIENTS_FLAG: &str = "--max-clients";
+const SKIP_CORRUPT_RECORDS_FLAG: &str = "--skip-corrupt-records";
+const SKIP_UNTOUCHED_FLAG: &str = "--skip-untouched";
+const FOLLOW_FLAG: &str = "--follow";
+const WITHDRAWAL_DISPUTES_FLAG: &str = "--withdrawal-disputes";
+const ALLOW_EMPTY_FLAG: &str = "--allow-empty";
+const MAX_DISPUTED_TOTAL_FLAG: &str = "--max-disputed-total";
+const IDLE_TIMEOUT_FLAG: &str = "--idle-timeout";
+const DEFER_DISPUTES_FLAG: &str = "--defer-disputes";
+const DISPUTE_WINDOW_FLAG: &str = "--dispute-window";
+const RESOLVE_CHARGED_BACK_FLAG: &str = "--resolve-charged-back";
+const ZERO_DISPUTES_FLAG: &str = "--zero-disputes";
+const VALUE_CODEC_FLAG: &str = "--value-codec";
+const STORE_DIR_FLAG: &str = "--store-dir";
+const COMMIT_EVERY_FLAG: &str = "--commit-every";
+#[cfg(feature = "mmap")]
+const MMAP_FLAG: &str = "--mmap";
+const THREADS_FLAG: &str = "--threads";
+const DELIMITER_FLAG: &str = "--delimiter";
+
+/// The environment variable that sets the number of runtime worker threads if `--threads` is not
+/// provided.
+pub const THREADS_ENV: &str = "TRANSACTION_MACHINE_THREADS";
+
+/// The maximum number of transactions that may be generated. Every generated transaction is held
+/// in memory until the file is written.
+pub const MAX_GENERATE_COUNT: usize = 10_000_000;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ArgsError {
+    #[error("Missing a value for argument `{0}`")]
+    MissingValue(String),
+    #[error("Invalid value `{value}` provided for argument `{arg}`")]
+    InvalidValue { arg: String, value: String },
+    #[error("Unknown argument `{0}`")]
+    UnknownArgument(String),
+    #[error("`{0}` requires the `rocks` feature")]
+    RequiresRocks(String),
+    #[error("`{0}` requires the `rocks` or `sqlite` feature")]
+    RequiresStore(String),
+    #[error("The number of transactions to generate was not specified: `generate <count>`")]
+    MissingCount,
+    #[error("Cannot generate `{count}` transactions, the maximum is {max}")]
+    CountTooLarge { count: usize, max: usize },
+    #[error(
+        "Generating zero transactions produces an empty file; provide `--allow-empty` to do so"
+    )]
+    EmptyCount,
+    #[error("`--follow` cannot be used when reading from the standard input")]
+    FollowStdin,
+    #[cfg(feature = "mmap")]
+    #[error("`--mmap` cannot be used with `--follow` or when reading from the standard input")]
+    MmapUnsupported,
+}
+
+/// Arguments for processing input files.
+#[derive(Debug, PartialEq)]
+pub struct RunArgs {
+    /// The CSV files to process, in order.
+    pub inputs: Vec<PathBuf>,
+    /// Configuration for the CSV reader.
+    pub reader: ReaderConfig,
+    /// The policies to execute transactions with.
+    pub policy: Policy,
+    /// Configuration for the IO task.
+    pub io: IoConfig,
+    /// Configuration for writing the client states.
+    pub writer: WriterConfig,
+    /// A CSV file containing metadata to attach to clients before processing.
+    pub client_metadata: Option<PathBuf>,
+    /// A file to write every transaction that was read but not applied to.
+    pub rejects: Option<PathBuf>,
+    /// A file to write a JSON manifest of the run to once processing completes.
+    pub manifest: Option<PathBuf>,
+    /// A file to write every warning that was raised to as JSON once processing completes.
+    pub warnings: Option<PathBuf>,
+    /// A file to write the client states to. If it is not set, then they are written to the
+    /// standard output.
+    pub output: Option<PathBuf>,
+    /// Whether to report percentiles of the time taken to execute each transaction. This requires
+    /// the `latency` feature.
+    pub latency: bool,
+    /// Whether to report counts of the transactions that were applied and rejected, and the
+    /// throughput of the run.
+    pub metrics: bool,
+    /// Whether to check that the client states balance with the stored transactions once
+    /// processing completes, failing the run if they do not.
+    pub check_books: bool,
+    /// The number of threads to check the books with.
+    pub verify_workers: usize,
+    /// How client states and transactions are encoded in the store.
+    pub value_codec: ValueCodec,
+    /// The directory of a persistent store to execute transactions against. If it is not set,
+    /// then an in-memory store is used.
+    pub store_dir: Option<PathBuf>,
+    /// If set, then the store is committed after every `n` transactions that are applied, rather
+    /// than leaving durability to the store.
+    pub commit_every: Option<usize>,
+}
+
+impl RunArgs {
+    /// Parses any further input files and optional arguments in `args` that follow the first
+    /// `input` file.
+    pub fn parse<I>(input: String, mut args: I) -> Result<RunArgs, ArgsError>
+    where
+        I: Iterator<Item = String>,
+    {
+        let mut run_args = RunArgs {
+            inputs: vec![PathBuf::from(input)],
+            reader: ReaderConfig::default(),
+            policy: Policy::default(),
+            io: IoConfig::default(),
+            writer: WriterConfig::default(),
+            client_metadata: None,
+            rejects: None,
+            manifest: None,
+            warnings: None,
+            output: None,
+            latency: false,
+            metrics: false,
+            check_books: false,
+            verify_workers: 1,
+            value_codec: ValueCodec::default(),
+            store_dir: None,
+            commit_every: None,
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                BUFFER_CAPACITY_FLAG => {
+                    let capacity = parse_value::<usize, _>(&arg, &mut args)?;
+                    if capacity == 0 {
+                        return Err(ArgsError::InvalidValue {
+                            arg,
+                            value: capacity.to_string(),
+                        });
+                    }
+                    run_args.reader.buffer_capacity = capacity;
+                }
+                STRICT_FREEZE_FLAG => run_args.policy.strict_freeze = true,
+                WARN_PRECISION_LOSS_FLAG => run_args.reader.warn_precision_loss = true,
+                SKIP_MALFORMED_FLAG => run_args.reader.skip_malformed = true,
+                ASSUME_SORTED_FLAG => run_args.io.assume_sorted = true,
+                INDEX_CLIENTS_FLAG => run_args.io.index_clients = true,
+                FLUSH_ON_ERROR_FLAG => run_args.io.flush_on_error = true,
+                FLAG_DRAIN_PATTERN_FLAG => {
+                    let window = parse_value::<usize, _>(&arg, &mut args)?;
+                    if window == 0 {
+                        return Err(ArgsError::InvalidValue {
+                            arg,
+                            value: window.to_string(),
+                        });
+                    }
+                    run_args.io.drain_window = Some(window);
+                }
+                MICRO_BATCH_FLAG => {
+                    let size = parse_value::<usize, _>(&arg, &mut args)?;
+                    if size == 0 {
+                        return Err(ArgsError::InvalidValue {
+                            arg,
+                            value: size.to_string(),
+                        });
+                    }
+                    run_args.io.micro_batch = Some(size);
+                }
+                MAX_CLIENTS_FLAG => {
+                    let capacity = parse_value::<usize, _>(&arg, &mut args)?;
+                    if capacity == 0 {
+                        return Err(ArgsError::InvalidValue {
+                            arg,
+                            value: capacity.to_string(),
+                        });
+                    }
+                    run_args.io.max_clients = Some(capacity);
+                }
+                SHARD_WORKERS_FLAG => {
+                    let workers = parse_value::<usize, _>(&arg, &mut args)?;
+                    if workers == 0 {
+                        return Err(ArgsError::InvalidValue {
+                            arg,
+                            value: workers.to_string(),
+                        });
+                    }
+                    run_args.io.shard_workers = Some(workers);
+                }
+                DELIMITER_FLAG => {
+                    let delimiter = parse_value::<String, _>(&arg, &mut args)?;
+                    run_args.reader.delimiter = parse_delimiter(&arg, delimiter)?;
+                }
+                TX_RANGE_FLAG => {
+                    run_args.reader.tx_range = Some(parse_value(&arg, &mut args)?);
+                }
+                FORMAT_FLAG => {
+                    run_args.writer.format = parse_value::<OutputFormat, _>(&arg, &mut args)?;
+                }
+                SORT_BY_FLAG => {
+                    run_args.writer.sort_by = parse_value::<SortBy, _>(&arg, &mut args)?;
+                }
+                LINE_ENDING_FLAG => {
+                    run_args.writer.line_ending = parse_value::<LineEnding, _>(&arg, &mut args)?;
+                }
+                BOM_FLAG => run_args.writer.bom = true,
+                TOP_FLAG => {
+                    let n = parse_value::<usize, _>(&arg, &mut args)?;
+                    if n == 0 {
+                        return Err(ArgsError::InvalidValue {
+                            arg,
+                            value: n.to_string(),
+                        });
+                    }
+                    run_args.writer.top = Some(n);
+                }
+                GROUP_BY_STATUS_FLAG => {
+                    run_args.writer.group_by_status =
+                        Some(parse_value::<StatusGroup, _>(&arg, &mut args)?);
+                }
+                GROUP_SEPARATOR_FLAG => run_args.writer.group_separator = true,
+                SKIP_CORRUPT_RECORDS_FLAG => run_args.writer.skip_corrupt = true,
+                SKIP_UNTOUCHED_FLAG => run_args.writer.skip_untouched = true,
+                FOLLOW_FLAG => run_args.reader.follow = true,
+                #[cfg(any(feature = "rocks", feature = "sqlite"))]
+                STORE_DIR_FLAG => run_args.store_dir = Some(parse_value(&arg, &mut args)?),
+                #[cfg(not(any(feature = "rocks", feature = "sqlite")))]
+                STORE_DIR_FLAG => return Err(ArgsError::RequiresStore(arg)),
+                #[cfg(any(feature = "rocks", feature = "sqlite"))]
+                COMMIT_EVERY_FLAG => {
+                    let commit_every = parse_value::<usize, _>(&arg, &mut args)?;
+                    if commit_every == 0 {
+                        return Err(ArgsError::InvalidValue {
+                            arg,
+                            value: commit_every.to_string(),
+                        });
+                    }
+                    run_args.commit_every = Some(commit_every);
+                }
+                #[cfg(not(any(feature = "rocks", feature = "sqlite")))]
+                COMMIT_EVERY_FLAG => return Err(ArgsError::RequiresStore(arg)),
+                #[cfg(feature = "mmap")]
+                MMAP_FLAG => run_args.reader.mmap = true,
+                VALUE_CODEC_FLAG => {
+                    run_args.value_codec = parse_value::<ValueCodec, _>(&arg, &mut args)?;
+                }
+                ON_CORRUPT_STATE_FLAG => {
+                    run_args.policy.corrupt_state =
+                        parse_value::<CorruptStatePolicy, _>(&arg, &mut args)?;
+                }
+                WITHDRAWAL_DISPUTES_FLAG => {
+                    run_args.policy.withdrawal_dispute =
+                        parse_value::<WithdrawalDisputePolicy, _>(&arg, &mut args)?;
+                }
+                RESOLVE_CHARGED_BACK_FLAG => {
+                    run_args.policy.resolve_charged_back =
+                        parse_value::<ChargedBackResolvePolicy, _>(&arg, &mut args)?;
+                }
+                ZERO_DISPUTES_FLAG => {
+                    run_args.policy.zero_dispute =
+                        parse_value::<ZeroDisputePolicy, _>(&arg, &mut args)?;
+                }
+                MAX_DISPUTED_TOTAL_FLAG => {
+                    let ceiling = parse_value::<f64, _>(&arg, &mut args)?;
+                    if ceiling.is_sign_negative() || !ceiling.is_finite() {
+                        return Err(ArgsError::InvalidValue {
+                            arg,
+                            value: ceiling.to_string(),
+                        });
+                    }
+                    run_args.io.max_disputed_total = Some(ceiling);
+                }
+                DISPUTE_WINDOW_FLAG => {
+                    let days = parse_value::<u64, _>(&arg, &mut args)?;
+                    // A window of zero days would reject every dispute, and one that overflows
+                    // cannot be represented.
+                    let seconds = days
+                        .checked_mul(SECONDS_PER_DAY)
+                        .filter(|_| days != 0)
+                        .ok_or_else(|| ArgsError::InvalidValue {
+                            arg,
+                            value: days.to_string(),
+                        })?;
+                    run_args.policy.dispute_window = Some(Duration::from_secs(seconds));
+                }
+                IDLE_TIMEOUT_FLAG => {
+                    let seconds = parse_value::<u64, _>(&arg, &mut args)?;
+                    if seconds == 0 {
+                        return Err(ArgsError::InvalidValue {
+                            arg,
+                            value: seconds.to_string(),
+                        });
+                    }
+                    run_args.io.idle_timeout = Some(Duration::from_secs(seconds));
+                }
+                DEFER_DISPUTES_FLAG => {
+                    let capacity = parse_value::<usize, _>(&arg, &mut args)?;
+                    if capacity == 0 {
+                        return Err(ArgsError::InvalidValue {
+                            arg,
+                            value: capacity.to_string(),
+                        });
+                    }
+                    run_args.io.defer_disputes = Some(capacity);
+                }
+                MAX_OPEN_DISPUTES_FLAG => {
+                    run_args.policy.max_open_disputes = Some(parse_value(&arg, &mut args)?);
+                }
+                REJECTS_FLAG => {
+                    run_args.rejects = Some(parse_value(&arg, &mut args)?);
+                }
+                #[cfg(feature = "latency")]
+                LATENCY_FLAG => run_args.latency = true,
+                METRICS_FLAG => run_args.metrics = true,
+                CHECK_BOOKS_FLAG => run_args.check_books = true,
+                VERIFY_WORKERS_FLAG => {
+                    let workers = parse_value::<usize, _>(&arg, &mut args)?;
+                    if workers == 0 {
+                        return Err(ArgsError::InvalidValue {
+                            arg,
+                            value: workers.to_string(),
+                        });
+                    }
+                    run_args.verify_workers = workers;
+                }
+                MANIFEST_FLAG => {
+                    run_args.manifest = Some(parse_value(&arg, &mut args)?);
+                }
+                WARNINGS_FLAG => {
+                    run_args.warnings = Some(parse_value(&arg, &mut args)?);
+                }
+                OUTPUT_FLAG => {
+                    run_args.output = Some(parse_value(&arg, &mut args)?);
+                }
+                CLIENT_METADATA_FLAG => {
+                    run_args.client_metadata = Some(parse_value(&arg, &mut args)?);
+                }
+                _ if !arg.starts_with("--") => run_args.inputs.push(PathBuf::from(arg)),
+                _ => return Err(ArgsError::UnknownArgument(arg)),
+            }
+        }
+
+        let reads_stdin = run_args.inputs.iter().any(|input| is_stdin(input));
+        if run_args.reader.follow && reads_stdin {
+            return Err(ArgsError::FollowStdin);
+        }
+        #[cfg(feature = "mmap")]
+        if run_args.reader.mmap && (run_args.reader.follow || reads_stdin) {
+            return Err(ArgsError::MmapUnsupported);
+        }
+
+        Ok(run_args)
+    }
+}
+
+/// Arguments for replaying the transactions in a persistent store under a different set of
+/// policies.
+#[derive(Debug, PartialEq)]
+pub struct ReplayArgs {
+    /// The directory of the store to replay.
+    pub store: PathBuf,
+    /// The policies to replay the transactions with.
+    pub policy: Policy,
+}
+
+impl ReplayArgs {
+    /// Parses any optional arguments in `args` that follow the `store` directory.
+    pub fn parse<I>(store: String, mut args: I) -> Result<ReplayArgs, ArgsError>
+    where
+        I: Iterator<Item = String>,
+    {
+        let mut replay_args = ReplayArgs {
+            store: PathBuf::from(store),
+            policy: Policy::default(),
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                POLICY_FLAG => replay_args.policy = parse_value::<Policy, _>(&arg, &mut args)?,
+                _ => return Err(ArgsError::UnknownArgument(arg)),
+            }
+        }
+
+        Ok(replay_args)
+    }
+}
+
+/// Arguments for compacting a persistent store.
+#[derive(Debug, PartialEq)]
+pub struct CompactArgs {
+    /// The directory of the store to compact.
+    pub store: PathBuf,
+    /// The policies that transactions will be executed with against the store. If none are
+    /// provided, then the policies that the store was last run with are used.
+    pub policy: Option<Policy>,
+}
+
+impl CompactArgs {
+    /// Parses any optional arguments in `args` that follow the `store` directory.
+    pub fn parse<I>(store: String, mut args: I) -> Result<CompactArgs, ArgsError>
+    where
+        I: Iterator<Item = String>,
+    {
+        let mut compact_args = CompactArgs {
+            store: PathBuf::from(store),
+            policy: None,
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                POLICY_FLAG => {
+                    compact_args.policy = Some(parse_value::<Policy, _>(&arg, &mut args)?)
+                }
+                _ => return Err(ArgsError::UnknownArgument(arg)),
+            }
+        }
+
+        Ok(compact_args)
+    }
+}
+
+/// Arguments for retrying the transactions that a prior run rejected.
+#[derive(Debug, PartialEq)]
+pub struct RetryArgs {
+    /// The rejects file that was written by the prior run.
+    pub input: PathBuf,
+    /// The directory of the store to apply the transactions to.
+    pub store: PathBuf,
+    /// The policies to execute transactions with.
+    pub policy: Policy,
+    /// A file to write every transaction that is rejected again to.
+    pub rejects: Option<PathBuf>,
+}
+
+impl RetryArgs {
+    /// Parses any optional arguments in `args` that follow the `input` rejects file and the `store`
+    /// directory.
+    pub fn parse<I>(input: String, store: String, mut args: I) -> Result<RetryArgs, ArgsError>
+    where
+        I: Iterator<Item = String>,
+    {
+        let mut retry_args = RetryArgs {
+            input: PathBuf::from(input),
+            store: PathBuf::from(store),
+            policy: Policy::default(),
+            rejects: None,
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                POLICY_FLAG => retry_args.policy = parse_value::<Policy, _>(&arg, &mut args)?,
+                REJECTS_FLAG => retry_args.rejects = Some(parse_value(&arg, &mut args)?),
+                _ => return Err(ArgsError::UnknownArgument(arg)),
+            }
+        }
+
+        Ok(retry_args)
+    }
+}
+
+/// Arguments for building the runtime that a command is executed on.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct RuntimeArgs {
+    /// The number of worker threads. If it is not set, then one is started for each core.
+    pub threads: Option<usize>,
+}
+
+impl RuntimeArgs {
+    /// Parses a leading `--threads <n>` option from `args`, returning it along with the arguments
+    /// that follow it. If the option is not provided, then the number of threads is parsed from
+    /// `env`, the value of the `THREADS_ENV` variable, if it is set.
+    pub fn parse<I>(args: I, env: Option<String>) -> Result<(RuntimeArgs, Vec<String>), ArgsError>
+    where
+        I: Iterator<Item = String>,
+    {
+        let mut args = args.peekable();
+        let threads = match args.next_if(|arg| arg == THREADS_FLAG) {
+            Some(arg) => Some(parse_threads(
+                &arg,
+                parse_value::<String, _>(&arg, &mut args)?,
+            )?),
+            None => env
+                .map(|value| parse_threads(THREADS_ENV, value))
+                .transpose()?,
+        };
+
+        Ok((RuntimeArgs { threads }, args.collect()))
+    }
+}
+
+/// Parses the field delimiter set by `arg`. This is either a single ASCII character, other than a
+/// quote or a line ending, or `\t` for a tab.
+fn parse_delimiter(arg: &str, value: String) -> Result<u8, ArgsError> {
+    match value.as_bytes() {
+        b"\\t" => Ok(b'\t'),
+        [delimiter] if delimiter.is_ascii() && !matches!(delimiter, b'"' | b'\n' | b'\r') => {
+            Ok(*delimiter)
+        }
+        _ => Err(ArgsError::InvalidValue {
+            arg: arg.to_string(),
+            value,
+        }),
+    }
+}
+
+/// Parses the number of worker threads set by `arg`, which must be non-zero.
+fn parse_threads(arg: &str, value: String) -> Result<usize, ArgsError> {
+    match usize::from_str(value.as_str()) {
+        Ok(threads) if threads > 0 => Ok(threads),
+        _ => Err(ArgsError::InvalidValue {
+            arg: arg.to_string(),
+            value,
+        }),
+    }
+}
+
+/// Arguments for generating a CSV file.
+#[derive(Debug, Default, PartialEq)]
+pub struct GenerateArgs {
+    /// The number of transactions to generate.
+    pub count: usize,
+    /// The probability, between 0 and 1, of injecting a malformed row before each transaction.
+    pub error_rate: f64,
+}
+
+impl GenerateArgs {
+    /// Parses the generator count, which must be at most `MAX_GENERATE_COUNT`, and any optional
+    /// arguments in `args` that follow it. A count of zero is only accepted if `--allow-empty` is
+    /// provided.
+    pub fn parse<I>(mut args: I) -> Result<GenerateArgs, ArgsError>
+    where
+        I: Iterator<Item = String>,
+    {
+        let count = args.next().ok_or(ArgsError::MissingCount)?;
+        let count = usize::from_str(count.as_str()).map_err(|_| ArgsError::InvalidValue {
+            arg: "count".to_string(),
+            value: count,
+        })?;
+        if count > MAX_GENERATE_COUNT {
+            return Err(ArgsError::CountTooLarge {
+                count,
+                max: MAX_GENERATE_COUNT,
+            });
+        }
+
+        let mut generate_args = GenerateArgs {
+            count,
+            ..Default::default()
+        };
+        let mut allow_empty = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                ERROR_RATE_FLAG => {
+                    let error_rate = parse_value::<f64, _>(&arg, &mut args)?;
+                    if !(0.0..=1.0).contains(&error_rate) {
+                        return Err(ArgsError::InvalidValue {
+                            arg,
+                            value: error_rate.to_string(),
+                        });
+                    }
+                    generate_args.error_rate = error_rate;
+                }
+                ALLOW_EMPTY_FLAG => allow_empty = true,
+                _ => return Err(ArgsError::UnknownArgument(arg)),
+            }
+        }
+
+        if count == 0 && !allow_empty {
+            return Err(ArgsError::EmptyCount);
+        }
+
+        Ok(generate_args)
+    }
+}
+
+/// Takes the next value from `args` and parses it as a `T`. Returns an error if there is no value
+/// or if it fails to parse.
+fn parse_value<T, I>(arg: &str, args: &mut I) -> Result<T, ArgsError>
+where
+    T: FromStr,
+    I: Iterator<Item = String>,
+{
+    let value = args
+        .next()
+        .ok_or_else(|| ArgsError::MissingValue(arg.to_string()))?;
+    T::from_str(value.as_str()).map_err(|_| ArgsError::InvalidValue {
+        arg: arg.to_string(),
+        value,
+    })
+}
+
+/// Returns an error for the first argument left in `args`, for a command whose arguments have all
+/// been read.
+pub fn parse_end<I>(mut args: I) -> Result<(), ArgsError>
+where
+    I: Iterator<Item = String>,
+{
+    match args.next() {
+        Some(arg) => Err(ArgsError::UnknownArgument(arg)),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,915 @@
+use crate::cli::{
+    ArgsError, CompactArgs, GenerateArgs, ReplayArgs, RetryArgs, RunArgs, RuntimeArgs,
+    MAX_GENERATE_COUNT, THREADS_ENV,
+};
+use crate::client::{
+    ChargedBackResolvePolicy, CorruptStatePolicy, Policy, ValueCodec, WithdrawalDisputePolicy,
+    ZeroDisputePolicy,
+};
+use crate::io::IoConfig;
+use crate::parser::reader::{ReaderConfig, TxRange, DEFAULT_BUFFER_CAPACITY};
+use crate::parser::writer::{LineEnding, OutputFormat, SortBy, StatusGroup, WriterConfig};
+use std::path::PathBuf;
+use std::time::Duration;
+
+fn parse_run(args: &[&str]) -> Result<RunArgs, ArgsError> {
+    RunArgs::parse(
+        "input.csv".to_string(),
+        args.iter().map(|arg| arg.to_string()),
+    )
+}
+
+#[test]
+fn defaults() {
+    let args = parse_run(&[]).unwrap();
+    assert_eq!(
+        args,
+        RunArgs {
+            inputs: vec![PathBuf::from("input.csv")],
+            reader: ReaderConfig::default(),
+            policy: Policy::default(),
+            io: IoConfig::default(),
+            writer: WriterConfig::default(),
+            client_metadata: None,
+            rejects: None,
+            manifest: None,
+            warnings: None,
+            output: None,
+            latency: false,
+            metrics: false,
+            check_books: false,
+            verify_workers: 1,
+            value_codec: ValueCodec::Plain,
+            store_dir: None,
+            commit_every: None,
+        }
+    );
+    assert_eq!(args.reader.buffer_capacity, DEFAULT_BUFFER_CAPACITY);
+}
+
+#[test]
+fn buffer_capacity() {
+    let args = parse_run(&["--buffer-capacity", "1024"]).unwrap();
+    assert_eq!(args.reader.buffer_capacity, 1024);
+}
+
+#[test]
+fn invalid_buffer_capacity() {
+    assert_eq!(
+        parse_run(&["--buffer-capacity", "lots"]),
+        Err(ArgsError::InvalidValue {
+            arg: "--buffer-capacity".to_string(),
+            value: "lots".to_string()
+        })
+    );
+    assert_eq!(
+        parse_run(&["--buffer-capacity", "0"]),
+        Err(ArgsError::InvalidValue {
+            arg: "--buffer-capacity".to_string(),
+            value: "0".to_string()
+        })
+    );
+    assert_eq!(
+        parse_run(&["--buffer-capacity"]),
+        Err(ArgsError::MissingValue("--buffer-capacity".to_string()))
+    );
+}
+
+#[test]
+fn strict_freeze() {
+    let args = parse_run(&["--strict-freeze", "--buffer-capacity", "16"]).unwrap();
+    assert!(args.policy.strict_freeze);
+    assert_eq!(args.reader.buffer_capacity, 16);
+}
+
+#[test]
+fn client_metadata() {
+    let args = parse_run(&["--client-metadata", "metadata.csv"]).unwrap();
+    assert_eq!(args.client_metadata, Some(PathBuf::from("metadata.csv")));
+}
+
+#[test]
+fn rejects() {
+    let args = parse_run(&["--rejects", "rejects.csv"]).unwrap();
+    assert_eq!(args.rejects, Some(PathBuf::from("rejects.csv")));
+}
+
+#[test]
+fn manifest() {
+    let args = parse_run(&["--manifest", "manifest.json"]).unwrap();
+    assert_eq!(args.manifest, Some(PathBuf::from("manifest.json")));
+}
+
+#[test]
+fn warnings() {
+    let args = parse_run(&["--warnings", "warnings.json"]).unwrap();
+    assert_eq!(args.warnings, Some(PathBuf::from("warnings.json")));
+}
+
+#[test]
+fn output() {
+    let args = parse_run(&["--output", "balances.csv"]).unwrap();
+    assert_eq!(args.output, Some(PathBuf::from("balances.csv")));
+    assert_eq!(
+        parse_run(&["--output"]),
+        Err(ArgsError::MissingValue("--output".to_string()))
+    );
+}
+
+#[test]
+fn check_books() {
+    assert!(!parse_run(&[]).unwrap().check_books);
+    assert!(parse_run(&["--check-books"]).unwrap().check_books);
+
+    let args = parse_run(&["--check-books", "--verify-workers", "8"]).unwrap();
+    assert_eq!(args.verify_workers, 8);
+    assert_eq!(
+        parse_run(&["--verify-workers", "0"]),
+        Err(ArgsError::InvalidValue {
+            arg: "--verify-workers".to_string(),
+            value: "0".to_string()
+        })
+    );
+}
+
+#[test]
+fn skip_malformed() {
+    assert!(!parse_run(&[]).unwrap().reader.skip_malformed);
+    assert!(
+        parse_run(&["--skip-malformed"])
+            .unwrap()
+            .reader
+            .skip_malformed
+    );
+}
+
+#[cfg(feature = "latency")]
+#[test]
+fn latency() {
+    assert!(!parse_run(&[]).unwrap().latency);
+    assert!(parse_run(&["--latency"]).unwrap().latency);
+}
+
+#[cfg(not(feature = "latency"))]
+#[test]
+fn latency_requires_feature() {
+    assert!(matches!(
+        parse_run(&["--latency"]),
+        Err(ArgsError::UnknownArgument(_))
+    ));
+}
+
+#[test]
+fn metrics() {
+    assert!(!parse_run(&[]).unwrap().metrics);
+    assert!(parse_run(&["--metrics"]).unwrap().metrics);
+}
+
+#[test]
+fn warn_precision_loss() {
+    let args = parse_run(&["--warn-precision-loss"]).unwrap();
+    assert!(args.reader.warn_precision_loss);
+}
+
+#[test]
+fn flush_on_error() {
+    let args = parse_run(&["--flush-on-error"]).unwrap();
+    assert!(args.io.flush_on_error);
+}
+
+#[test]
+fn flag_drain_pattern() {
+    let args = parse_run(&["--flag-drain-pattern", "5"]).unwrap();
+    assert_eq!(args.io.drain_window, Some(5));
+
+    assert_eq!(
+        parse_run(&["--flag-drain-pattern", "0"]),
+        Err(ArgsError::InvalidValue {
+            arg: "--flag-drain-pattern".to_string(),
+            value: "0".to_string()
+        })
+    );
+}
+
+#[test]
+fn micro_batch() {
+    let args = parse_run(&["--micro-batch", "64"]).unwrap();
+    assert_eq!(args.io.micro_batch, Some(64));
+
+    assert_eq!(
+        parse_run(&["--micro-batch", "0"]),
+        Err(ArgsError::InvalidValue {
+            arg: "--micro-batch".to_string(),
+            value: "0".to_string()
+        })
+    );
+}
+
+#[test]
+fn delimiter() {
+    assert_eq!(parse_run(&[]).unwrap().reader.delimiter, b',');
+    for (value, delimiter) in [("\\t", b'\t'), ("\t", b'\t'), (";", b';'), ("|", b'|')] {
+        let args = parse_run(&["--delimiter", value]).unwrap();
+        assert_eq!(args.reader.delimiter, delimiter);
+    }
+
+    for value in ["", "tab", "\"", "\n", "\u{a7}"] {
+        assert_eq!(
+            parse_run(&["--delimiter", value]),
+            Err(ArgsError::InvalidValue {
+                arg: "--delimiter".to_string(),
+                value: value.to_string()
+            })
+        );
+    }
+}
+
+#[test]
+fn max_clients() {
+    let args = parse_run(&["--max-clients", "128"]).unwrap();
+    assert_eq!(args.io.max_clients, Some(128));
+
+    assert_eq!(
+        parse_run(&["--max-clients", "0"]),
+        Err(ArgsError::InvalidValue {
+            arg: "--max-clients".to_string(),
+            value: "0".to_string()
+        })
+    );
+}
+
+#[test]
+fn shard_workers() {
+    let args = parse_run(&["--shard-workers", "8"]).unwrap();
+    assert_eq!(args.io.shard_workers, Some(8));
+
+    assert_eq!(
+        parse_run(&["--shard-workers", "0"]),
+        Err(ArgsError::InvalidValue {
+            arg: "--shard-workers".to_string(),
+            value: "0".to_string()
+        })
+    );
+}
+
+#[test]
+fn sort_by() {
+    let args = parse_run(&["--sort-by", "total"]).unwrap();
+    assert_eq!(args.writer.sort_by, SortBy::Total);
+
+    assert_eq!(
+        parse_run(&["--sort-by", "name"]),
+        Err(ArgsError::InvalidValue {
+            arg: "--sort-by".to_string(),
+            value: "name".to_string()
+        })
+    );
+}
+
+#[test]
+fn group_by_status() {
+    let args = parse_run(&[]).unwrap();
+    assert_eq!(args.writer.group_by_status, None);
+    assert!(!args.writer.group_separator);
+
+    let args = parse_run(&["--group-by-status", "frozen-first", "--group-separator"]).unwrap();
+    assert_eq!(args.writer.group_by_status, Some(StatusGroup::FrozenFirst));
+    assert!(args.writer.group_separator);
+
+    assert_eq!(
+        parse_run(&["--group-by-status", "locked"]),
+        Err(ArgsError::InvalidValue {
+            arg: "--group-by-status".to_string(),
+            value: "locked".to_string()
+        })
+    );
+}
+
+#[test]
+fn output_format() {
+    let args = parse_run(&[]).unwrap();
+    assert_eq!(args.writer.line_ending, LineEnding::Lf);
+    assert!(!args.writer.bom);
+
+    assert_eq!(args.writer.format, OutputFormat::Csv);
+
+    let args = parse_run(&["--line-ending", "crlf", "--bom"]).unwrap();
+    assert_eq!(args.writer.line_ending, LineEnding::Crlf);
+    assert!(args.writer.bom);
+
+    assert_eq!(
+        parse_run(&["--line-ending", "cr"]),
+        Err(ArgsError::InvalidValue {
+            arg: "--line-ending".to_string(),
+            value: "cr".to_string()
+        })
+    );
+}
+
+#[test]
+fn format() {
+    let args = parse_run(&["--format", "csv"]).unwrap();
+    assert_eq!(args.writer.format, OutputFormat::Csv);
+
+    #[cfg(feature = "parquet")]
+    assert_eq!(
+        parse_run(&["--format", "parquet"]).unwrap().writer.format,
+        OutputFormat::Parquet
+    );
+    #[cfg(not(feature = "parquet"))]
+    assert!(parse_run(&["--format", "parquet"]).is_err());
+
+    assert_eq!(
+        parse_run(&["--format", "json"]).unwrap().writer.format,
+        OutputFormat::Json
+    );
+
+    assert_eq!(
+        parse_run(&["--format", "xml"]),
+        Err(ArgsError::InvalidValue {
+            arg: "--format".to_string(),
+            value: "xml".to_string()
+        })
+    );
+}
+
+#[test]
+fn skip_corrupt_records() {
+    let args = parse_run(&["--skip-corrupt-records"]).unwrap();
+    assert!(args.writer.skip_corrupt);
+}
+
+#[test]
+fn follow() {
+    assert!(!parse_run(&[]).unwrap().reader.follow);
+    assert!(parse_run(&["--follow"]).unwrap().reader.follow);
+}
+
+#[test]
+fn stdin() {
+    let args = RunArgs::parse("-".to_string(), std::iter::empty()).unwrap();
+    assert_eq!(args.inputs, vec![PathBuf::from("-")]);
+
+    let args = RunArgs::parse("-".to_string(), vec!["--follow".to_string()].into_iter());
+    assert!(matches!(args, Err(ArgsError::FollowStdin)));
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn mmap() {
+    assert!(!parse_run(&[]).unwrap().reader.mmap);
+    assert!(parse_run(&["--mmap"]).unwrap().reader.mmap);
+    assert!(matches!(
+        parse_run(&["--mmap", "--follow"]),
+        Err(ArgsError::MmapUnsupported)
+    ));
+
+    let args = RunArgs::parse("-".to_string(), vec!["--mmap".to_string()].into_iter());
+    assert!(matches!(args, Err(ArgsError::MmapUnsupported)));
+}
+
+#[cfg(not(feature = "mmap"))]
+#[test]
+fn mmap_requires_feature() {
+    assert!(matches!(
+        parse_run(&["--mmap"]),
+        Err(ArgsError::UnknownArgument(_))
+    ));
+}
+
+#[cfg(any(feature = "rocks", feature = "sqlite"))]
+#[test]
+fn store_dir() {
+    assert_eq!(parse_run(&[]).unwrap().store_dir, None);
+    let args = parse_run(&["--store-dir", "stores/a"]).unwrap();
+    assert_eq!(args.store_dir, Some(PathBuf::from("stores/a")));
+}
+
+#[cfg(not(any(feature = "rocks", feature = "sqlite")))]
+#[test]
+fn store_dir_requires_store() {
+    assert_eq!(
+        parse_run(&["--store-dir", "stores/a"]),
+        Err(ArgsError::RequiresStore("--store-dir".to_string()))
+    );
+}
+
+#[cfg(any(feature = "rocks", feature = "sqlite"))]
+#[test]
+fn commit_every() {
+    assert_eq!(parse_run(&[]).unwrap().commit_every, None);
+    let args = parse_run(&["--commit-every", "100"]).unwrap();
+    assert_eq!(args.commit_every, Some(100));
+
+    assert_eq!(
+        parse_run(&["--commit-every", "0"]),
+        Err(ArgsError::InvalidValue {
+            arg: "--commit-every".to_string(),
+            value: "0".to_string()
+        })
+    );
+}
+
+#[cfg(not(any(feature = "rocks", feature = "sqlite")))]
+#[test]
+fn commit_every_requires_store() {
+    assert_eq!(
+        parse_run(&["--commit-every", "100"]),
+        Err(ArgsError::RequiresStore("--commit-every".to_string()))
+    );
+}
+
+#[test]
+fn skip_untouched() {
+    assert!(!parse_run(&[]).unwrap().writer.skip_untouched);
+    assert!(
+        parse_run(&["--skip-untouched"])
+            .unwrap()
+            .writer
+            .skip_untouched
+    );
+}
+
+#[test]
+fn tx_range() {
+    let args = parse_run(&["--tx-range", "5:10"]).unwrap();
+    assert_eq!(args.reader.tx_range, Some(TxRange { lo: 5, hi: 10 }));
+
+    for value in ["10:5", "5", "5:", "a:b"] {
+        assert_eq!(
+            parse_run(&["--tx-range", value]),
+            Err(ArgsError::InvalidValue {
+                arg: "--tx-range".to_string(),
+                value: value.to_string()
+            })
+        );
+    }
+}
+
+#[test]
+fn assume_sorted() {
+    let args = parse_run(&["--assume-sorted-by-client"]).unwrap();
+    assert!(args.io.assume_sorted);
+}
+
+#[test]
+fn index_clients() {
+    assert!(!parse_run(&[]).unwrap().io.index_clients);
+    assert!(parse_run(&["--index-clients"]).unwrap().io.index_clients);
+}
+
+#[test]
+fn value_codec() {
+    let args = parse_run(&["--value-codec", "compact"]).unwrap();
+    assert_eq!(args.value_codec, ValueCodec::Compact);
+
+    let args = parse_run(&["--value-codec", "plain"]).unwrap();
+    assert_eq!(args.value_codec, ValueCodec::Plain);
+
+    assert_eq!(
+        parse_run(&["--value-codec", "gzip"]),
+        Err(ArgsError::InvalidValue {
+            arg: "--value-codec".to_string(),
+            value: "gzip".to_string()
+        })
+    );
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn compressed_value_codec() {
+    let args = parse_run(&["--value-codec", "zstd"]).unwrap();
+    assert_eq!(args.value_codec, ValueCodec::Zstd);
+
+    let args = parse_run(&["--value-codec", "lz4"]).unwrap();
+    assert_eq!(args.value_codec, ValueCodec::Lz4);
+}
+
+#[test]
+fn on_corrupt_state() {
+    let args = parse_run(&["--on-corrupt-state", "treat-as-new"]).unwrap();
+    assert_eq!(args.policy.corrupt_state, CorruptStatePolicy::TreatAsNew);
+
+    let args = parse_run(&["--on-corrupt-state", "fail"]).unwrap();
+    assert_eq!(args.policy.corrupt_state, CorruptStatePolicy::Fail);
+
+    assert_eq!(
+        parse_run(&["--on-corrupt-state", "ignore"]),
+        Err(ArgsError::InvalidValue {
+            arg: "--on-corrupt-state".to_string(),
+            value: "ignore".to_string()
+        })
+    );
+}
+
+#[test]
+fn withdrawal_disputes() {
+    let args = parse_run(&[]).unwrap();
+    assert_eq!(
+        args.policy.withdrawal_dispute,
+        WithdrawalDisputePolicy::Reject
+    );
+
+    let args = parse_run(&["--withdrawal-disputes", "credit-to-available"]).unwrap();
+    assert_eq!(
+        args.policy.withdrawal_dispute,
+        WithdrawalDisputePolicy::CreditToAvailable
+    );
+
+    let args = parse_replay(&["--policy", "withdrawal-disputes=hold"]).unwrap();
+    assert_eq!(
+        args.policy.withdrawal_dispute,
+        WithdrawalDisputePolicy::Hold
+    );
+
+    assert_eq!(
+        parse_run(&["--withdrawal-disputes", "credit"]),
+        Err(ArgsError::InvalidValue {
+            arg: "--withdrawal-disputes".to_string(),
+            value: "credit".to_string()
+        })
+    );
+}
+
+#[test]
+fn resolve_charged_back() {
+    let args = parse_run(&[]).unwrap();
+    assert_eq!(
+        args.policy.resolve_charged_back,
+        ChargedBackResolvePolicy::Reject
+    );
+
+    let args = parse_run(&["--resolve-charged-back", "ignore"]).unwrap();
+    assert_eq!(
+        args.policy.resolve_charged_back,
+        ChargedBackResolvePolicy::Ignore
+    );
+
+    let args = parse_replay(&["--policy", "resolve-charged-back=ignore"]).unwrap();
+    assert_eq!(
+        args.policy.resolve_charged_back,
+        ChargedBackResolvePolicy::Ignore
+    );
+}
+
+#[test]
+fn zero_disputes() {
+    let args = parse_run(&[]).unwrap();
+    assert_eq!(args.policy.zero_dispute, ZeroDisputePolicy::Reject);
+
+    let args = parse_run(&["--zero-disputes", "ignore"]).unwrap();
+    assert_eq!(args.policy.zero_dispute, ZeroDisputePolicy::Ignore);
+
+    let args = parse_replay(&["--policy", "zero-disputes=ignore"]).unwrap();
+    assert_eq!(args.policy.zero_dispute, ZeroDisputePolicy::Ignore);
+
+    assert_eq!(
+        parse_run(&["--zero-disputes", "allow"]),
+        Err(ArgsError::InvalidValue {
+            arg: "--zero-disputes".to_string(),
+            value: "allow".to_string()
+        })
+    );
+}
+
+#[test]
+fn max_disputed_total() {
+    let args = parse_run(&["--max-disputed-total", "1000.5"]).unwrap();
+    assert_eq!(args.io.max_disputed_total, Some(1000.5));
+
+    for value in ["-1", "inf", "none"] {
+        assert_eq!(
+            parse_run(&["--max-disputed-total", value]),
+            Err(ArgsError::InvalidValue {
+                arg: "--max-disputed-total".to_string(),
+                value: value.to_string()
+            })
+        );
+    }
+}
+
+#[test]
+fn top() {
+    let args = parse_run(&["--top", "3", "--sort-by", "total"]).unwrap();
+    assert_eq!(args.writer.top, Some(3));
+    assert_eq!(args.writer.sort_by, SortBy::Total);
+
+    assert_eq!(
+        parse_run(&["--top", "0"]),
+        Err(ArgsError::InvalidValue {
+            arg: "--top".to_string(),
+            value: "0".to_string()
+        })
+    );
+}
+
+#[test]
+fn dispute_window() {
+    let args = parse_run(&["--dispute-window", "120"]).unwrap();
+    assert_eq!(
+        args.policy.dispute_window,
+        Some(Duration::from_secs(120 * 24 * 60 * 60))
+    );
+
+    // A window that overflows when converted to seconds is rejected rather than wrapping.
+    let overflow = (u64::MAX / 2).to_string();
+    for value in ["0", overflow.as_str()] {
+        assert_eq!(
+            parse_run(&["--dispute-window", value]),
+            Err(ArgsError::InvalidValue {
+                arg: "--dispute-window".to_string(),
+                value: value.to_string()
+            })
+        );
+    }
+}
+
+#[test]
+fn limits_are_per_client() {
+    for arg in ["--min-balance", "--max-held"] {
+        assert_eq!(
+            parse_run(&[arg, "5"]),
+            Err(ArgsError::UnknownArgument(arg.to_string()))
+        );
+    }
+}
+
+#[test]
+fn defer_disputes() {
+    let args = parse_run(&["--defer-disputes", "64"]).unwrap();
+    assert_eq!(args.io.defer_disputes, Some(64));
+
+    assert_eq!(
+        parse_run(&["--defer-disputes", "0"]),
+        Err(ArgsError::InvalidValue {
+            arg: "--defer-disputes".to_string(),
+            value: "0".to_string()
+        })
+    );
+}
+
+#[test]
+fn idle_timeout() {
+    let args = parse_run(&["--idle-timeout", "30"]).unwrap();
+    assert_eq!(args.io.idle_timeout, Some(Duration::from_secs(30)));
+
+    for value in ["0", "-1", "1.5"] {
+        assert!(matches!(
+            parse_run(&["--idle-timeout", value]),
+            Err(ArgsError::InvalidValue { .. })
+        ));
+    }
+}
+
+#[test]
+fn max_open_disputes() {
+    let args = parse_run(&["--max-open-disputes", "3"]).unwrap();
+    assert_eq!(args.policy.max_open_disputes, Some(3));
+
+    assert_eq!(
+        parse_run(&["--max-open-disputes", "-1"]),
+        Err(ArgsError::InvalidValue {
+            arg: "--max-open-disputes".to_string(),
+            value: "-1".to_string()
+        })
+    );
+}
+
+#[test]
+fn multiple_inputs() {
+    let args = parse_run(&["second.csv", "--strict-freeze", "third.csv", "-"]).unwrap();
+    assert_eq!(
+        args.inputs,
+        vec![
+            PathBuf::from("input.csv"),
+            PathBuf::from("second.csv"),
+            PathBuf::from("third.csv"),
+            PathBuf::from("-"),
+        ]
+    );
+
+    let args = parse_run(&["second.csv", "--follow"]).unwrap();
+    assert_eq!(args.inputs.len(), 2);
+    assert!(args.reader.follow);
+    assert!(matches!(
+        parse_run(&["-", "--follow"]),
+        Err(ArgsError::FollowStdin)
+    ));
+}
+
+#[test]
+fn unknown_argument() {
+    assert_eq!(
+        parse_run(&["--unknown"]),
+        Err(ArgsError::UnknownArgument("--unknown".to_string()))
+    );
+}
+
+fn parse_generate(args: &[&str]) -> Result<GenerateArgs, ArgsError> {
+    GenerateArgs::parse(args.iter().map(|arg| arg.to_string()))
+}
+
+#[test]
+fn error_rate() {
+    assert_eq!(
+        parse_generate(&["100"]),
+        Ok(GenerateArgs {
+            count: 100,
+            error_rate: 0.0
+        })
+    );
+    assert_eq!(
+        parse_generate(&["100", "--error-rate", "0.25"]),
+        Ok(GenerateArgs {
+            count: 100,
+            error_rate: 0.25
+        })
+    );
+    assert_eq!(
+        parse_generate(&["100", "--error-rate", "1.5"]),
+        Err(ArgsError::InvalidValue {
+            arg: "--error-rate".to_string(),
+            value: "1.5".to_string()
+        })
+    );
+}
+
+#[test]
+fn generate_count() {
+    assert_eq!(parse_generate(&[]), Err(ArgsError::MissingCount));
+    assert_eq!(
+        parse_generate(&["ten"]),
+        Err(ArgsError::InvalidValue {
+            arg: "count".to_string(),
+            value: "ten".to_string()
+        })
+    );
+    assert_eq!(
+        parse_generate(&["-1"]),
+        Err(ArgsError::InvalidValue {
+            arg: "count".to_string(),
+            value: "-1".to_string()
+        })
+    );
+
+    assert_eq!(parse_generate(&["0"]), Err(ArgsError::EmptyCount));
+    assert_eq!(
+        parse_generate(&["0", "--allow-empty"]),
+        Ok(GenerateArgs::default())
+    );
+
+    let max = MAX_GENERATE_COUNT.to_string();
+    assert_eq!(parse_generate(&[&max]).unwrap().count, MAX_GENERATE_COUNT);
+    assert_eq!(
+        parse_generate(&["99999999999999"]),
+        Err(ArgsError::CountTooLarge {
+            count: 99999999999999,
+            max: MAX_GENERATE_COUNT
+        })
+    );
+}
+
+fn parse_replay(args: &[&str]) -> Result<ReplayArgs, ArgsError> {
+    ReplayArgs::parse("store".to_string(), args.iter().map(|arg| arg.to_string()))
+}
+
+#[test]
+fn replay_policy() {
+    let args = parse_replay(&[]).unwrap();
+    assert_eq!(args.store, PathBuf::from("store"));
+    assert_eq!(args.policy, Policy::default());
+    assert!(args.policy.freeze_on_chargeback);
+
+    let args = parse_replay(&[
+        "--policy",
+        "freeze-on-chargeback=false,max-open-disputes=3,on-corrupt-state=treat-as-new,\
+         dispute-window=1",
+    ])
+    .unwrap();
+    assert_eq!(
+        args.policy,
+        Policy {
+            freeze_on_chargeback: false,
+            max_open_disputes: Some(3),
+            corrupt_state: CorruptStatePolicy::TreatAsNew,
+            dispute_window: Some(Duration::from_secs(24 * 60 * 60)),
+            ..Default::default()
+        }
+    );
+
+    for value in [
+        "freeze-on-chargeback",
+        "freeze-on-chargeback=no",
+        "min-balance=5",
+        "unknown=true",
+        "dispute-window=0",
+        "dispute-window=18446744073709551615",
+    ] {
+        assert_eq!(
+            parse_replay(&["--policy", value]),
+            Err(ArgsError::InvalidValue {
+                arg: "--policy".to_string(),
+                value: value.to_string()
+            })
+        );
+    }
+}
+
+#[test]
+fn retry() {
+    let args = RetryArgs::parse(
+        "rejects.csv".to_string(),
+        "store".to_string(),
+        std::iter::empty(),
+    )
+    .unwrap();
+    assert_eq!(
+        args,
+        RetryArgs {
+            input: PathBuf::from("rejects.csv"),
+            store: PathBuf::from("store"),
+            policy: Policy::default(),
+            rejects: None,
+        }
+    );
+
+    let args = RetryArgs::parse(
+        "rejects.csv".to_string(),
+        "store".to_string(),
+        ["--rejects", "retry-rejects.csv"]
+            .iter()
+            .map(|arg| arg.to_string()),
+    )
+    .unwrap();
+    assert_eq!(args.rejects, Some(PathBuf::from("retry-rejects.csv")));
+}
+
+#[test]
+fn compact_policy() {
+    let args = CompactArgs::parse("store".to_string(), std::iter::empty()).unwrap();
+    assert_eq!(
+        args,
+        CompactArgs {
+            store: PathBuf::from("store"),
+            policy: None,
+        }
+    );
+
+    let args = CompactArgs::parse(
+        "store".to_string(),
+        ["--policy", "freeze-on-chargeback=false"]
+            .iter()
+            .map(|arg| arg.to_string()),
+    )
+    .unwrap();
+    assert!(!args.policy.unwrap().freeze_on_chargeback);
+}
+
+fn parse_runtime(
+    args: &[&str],
+    env: Option<&str>,
+) -> Result<(RuntimeArgs, Vec<String>), ArgsError> {
+    RuntimeArgs::parse(
+        args.iter().map(|arg| arg.to_string()),
+        env.map(str::to_string),
+    )
+}
+
+#[test]
+fn runtime_threads() {
+    let (runtime, args) = parse_runtime(&["--threads", "4", "input.csv"], None).unwrap();
+    assert_eq!(runtime.threads, Some(4));
+    assert_eq!(args, vec!["input.csv".to_string()]);
+
+    let (runtime, args) = parse_runtime(&["input.csv", "--latency"], None).unwrap();
+    assert_eq!(runtime.threads, None);
+    assert_eq!(args, vec!["input.csv".to_string(), "--latency".to_string()]);
+
+    // The option takes precedence over the environment variable.
+    let (runtime, _) = parse_runtime(&["input.csv"], Some("2")).unwrap();
+    assert_eq!(runtime.threads, Some(2));
+    let (runtime, _) = parse_runtime(&["--threads", "3", "input.csv"], Some("2")).unwrap();
+    assert_eq!(runtime.threads, Some(3));
+}
+
+#[test]
+fn invalid_runtime_threads() {
+    assert_eq!(
+        parse_runtime(&["--threads", "0", "input.csv"], None),
+        Err(ArgsError::InvalidValue {
+            arg: "--threads".to_string(),
+            value: "0".to_string()
+        })
+    );
+    assert_eq!(
+        parse_runtime(&["--threads"], None),
+        Err(ArgsError::MissingValue("--threads".to_string()))
+    );
+    assert_eq!(
+        parse_runtime(&["input.csv"], Some("many")),
+        Err(ArgsError::InvalidValue {
+            arg: THREADS_ENV.to_string(),
+            value: "many".to_string()
+        })
+    );
+}
@@ -1,40 +1,130 @@
-use serde::{Deserialize, Serialize};
-use std::ops::{Add, Sub};
+use crate::scale::DECIMAL_PLACES;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
 use thiserror::Error;
 
+/// An amount held as a whole number of the smallest unit that amounts are output to, so that
+/// arithmetic on it is exact. With four decimal places, this is ten-thousandths.
+///
+/// An amount is serialized as an `f64`, so that a serialized account is unchanged from when its
+/// funds were held as floats.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Fixed(i128);
+
+impl Fixed {
+    /// The number of units in one.
+    fn scale() -> f64 {
+        10f64.powi(DECIMAL_PLACES as i32)
+    }
+
+    /// Converts `amount` to the nearest whole number of units.
+    pub fn from_f64(amount: f64) -> Fixed {
+        Fixed((amount * Fixed::scale()).round() as i128)
+    }
+
+    /// Converts this amount to a float. Zero is always positive zero, so a balance that has been
+    /// emptied is never output as `-0.0000`.
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / Fixed::scale()
+    }
+
+    fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    /// Adds `other` to this amount. If the sum cannot be held, then an error is returned.
+    pub fn checked_add(self, other: Fixed) -> Result<Fixed, UpdateError> {
+        self.0
+            .checked_add(other.0)
+            .map(Fixed)
+            .ok_or(UpdateError::Overflow)
+    }
+
+    /// Subtracts `other` from this amount. If the difference cannot be held, then an error is
+    /// returned.
+    pub fn checked_sub(self, other: Fixed) -> Result<Fixed, UpdateError> {
+        self.0
+            .checked_sub(other.0)
+            .map(Fixed)
+            .ok_or(UpdateError::Overflow)
+    }
+
+    /// Adds `other` to this amount, saturating at the bounds of the range that can be held. This
+    /// is only used for totals, as every update to an account is checked.
+    pub fn saturating_add(self, other: Fixed) -> Fixed {
+        Fixed(self.0.saturating_add(other.0))
+    }
+
+    /// Subtracts `other` from this amount, saturating at the bounds of the range that can be held.
+    /// This is only used for totals, as every update to an account is checked.
+    pub fn saturating_sub(self, other: Fixed) -> Fixed {
+        Fixed(self.0.saturating_sub(other.0))
+    }
+}
+
+impl Serialize for Fixed {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_f64(self.to_f64())
+    }
+}
+
+impl<'de> Deserialize<'de> for Fixed {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        f64::deserialize(deserializer).map(Fixed::from_f64)
+    }
+}
+
 /// An account associated with a client's state.
 ///
 /// This is its own structure to prevent direct operations on the internal values that may violate
-/// any contracts.
-#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+/// any contracts. Held funds are tracked per disputed transaction so that releasing or charging
+/// them is always bounded by what was actually held for that transaction.
+///
+/// Funds are held in fixed point, to the number of decimal places that amounts are output to, so
+/// that they do not drift over many transactions. Any amount with more decimal places is rounded
+/// to the nearest unit. An update that would take the funds outside of the range that can be held
+/// is rejected, and leaves the account unchanged.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Account {
-    available: f64,
-    held: f64,
+    available: Fixed,
+    held: BTreeMap<u32, Fixed>,
 }
 
 impl Account {
     /// Returns the available funds in this account.
     pub fn get_available(&self) -> f64 {
-        self.available
+        self.available.to_f64()
     }
 
     /// Returns any held funds in this account.
     pub fn get_frozen(&self) -> f64 {
-        self.held
+        self.frozen().to_f64()
     }
 
     /// Returns the total value of the available and frozen funds.
     pub fn get_total(&self) -> f64 {
-        self.available + self.held
+        self.available.saturating_add(self.frozen()).to_f64()
     }
 
-    /// Attempts to deposit `amount` in this account. If `amount` is negative, then an error is
-    /// returned.
+    fn frozen(&self) -> Fixed {
+        self.held.values().fold(Fixed::default(), |held, amount| {
+            held.saturating_add(*amount)
+        })
+    }
+
+    /// Attempts to deposit `amount` in this account. If `amount` is negative, or the available
+    /// funds would overflow, then an error is returned.
     pub fn deposit(&mut self, amount: f64) -> Result<(), UpdateError> {
         if amount.is_sign_negative() {
             Err(UpdateError::NegativeValue)
         } else {
-            self.available = self.available.add(amount);
+            self.available = self.available.checked_add(Fixed::from_f64(amount))?;
             Ok(())
         }
     }
@@ -42,12 +132,22 @@ impl Account {
     /// Attempts to withdraw `amount` in this account. If `amount` is negative, then an error is
     /// returned.
     pub fn withdraw(&mut self, amount: f64) -> Result<(), UpdateError> {
+        self.withdraw_leaving(amount, 0.0)
+    }
+
+    /// Attempts to withdraw `amount` in this account while leaving at least `minimum` available.
+    /// If `amount` is negative, then an error is returned. A withdrawal that would leave the
+    /// available funds negative is rejected for insufficient funds, while one that would only leave
+    /// them below `minimum` is rejected for breaching the minimum balance.
+    pub fn withdraw_leaving(&mut self, amount: f64, minimum: f64) -> Result<(), UpdateError> {
         if amount.is_sign_negative() {
             Err(UpdateError::NegativeValue)
         } else {
-            let result = self.available.sub(amount);
-            if result.is_sign_negative() {
+            let result = self.available.checked_sub(Fixed::from_f64(amount))?;
+            if result.is_negative() {
                 Err(UpdateError::InsufficientFunds)
+            } else if result < Fixed::from_f64(minimum) {
+                Err(UpdateError::BelowMinimumBalance)
             } else {
                 self.available = result;
                 Ok(())
@@ -55,34 +155,105 @@ impl Account {
         }
     }
 
-    /// Attempts to move `amount` from the available funds to the held funds. If `amount` is
-    /// negative, then an error is returned.
-    pub fn hold(&mut self, amount: f64) -> Result<(), UpdateError> {
+    /// Attempts to move `amount` from the available funds to the funds held for the transaction
+    /// `tx`. If `amount` is negative, then an error is returned.
+    pub fn hold(&mut self, tx: u32, amount: f64) -> Result<(), UpdateError> {
+        self.hold_within(tx, amount, None)
+    }
+
+    /// Attempts to move `amount` from the available funds to the funds held for the transaction
+    /// `tx`, while keeping the total held funds at or below `maximum`. If `amount` is negative, or
+    /// holding it would exceed `maximum`, then an error is returned.
+    pub fn hold_within(
+        &mut self,
+        tx: u32,
+        amount: f64,
+        maximum: Option<f64>,
+    ) -> Result<(), UpdateError> {
+        let amount = self.check_held(amount, maximum)?;
+        let available = self.available.checked_sub(amount)?;
+        let held = self.held_for(tx).checked_add(amount)?;
+        self.available = available;
+        self.held.insert(tx, held);
+        Ok(())
+    }
+
+    /// Attempts to credit `amount` to the funds held for the transaction `tx`, without removing it
+    /// from the available funds. If `amount` is negative, then an error is returned.
+    pub fn credit_held(&mut self, tx: u32, amount: f64) -> Result<(), UpdateError> {
+        self.credit_held_within(tx, amount, None)
+    }
+
+    /// Attempts to credit `amount` to the funds held for the transaction `tx`, without removing it
+    /// from the available funds, while keeping the total held funds at or below `maximum`. If
+    /// `amount` is negative, or crediting it would exceed `maximum`, then an error is returned.
+    pub fn credit_held_within(
+        &mut self,
+        tx: u32,
+        amount: f64,
+        maximum: Option<f64>,
+    ) -> Result<(), UpdateError> {
+        let amount = self.check_held(amount, maximum)?;
+        let held = self.held_for(tx).checked_add(amount)?;
+        self.held.insert(tx, held);
+        Ok(())
+    }
+
+    /// Checks that `amount` may be added to the held funds without exceeding `maximum`, returning
+    /// it in fixed point.
+    fn check_held(&self, amount: f64, maximum: Option<f64>) -> Result<Fixed, UpdateError> {
         if amount.is_sign_negative() {
-            Err(UpdateError::NegativeValue)
-        } else {
-            self.available = self.available.sub(amount);
-            self.held += amount;
-            Ok(())
+            return Err(UpdateError::NegativeValue);
         }
+        let amount = Fixed::from_f64(amount);
+        match maximum {
+            Some(maximum) if self.frozen().checked_add(amount)? > Fixed::from_f64(maximum) => {
+                Err(UpdateError::HeldLimitExceeded)
+            }
+            _ => Ok(amount),
+        }
+    }
+
+    /// Returns the funds held for the transaction `tx`, which are zero if none are held.
+    fn held_for(&self, tx: u32) -> Fixed {
+        self.held.get(&tx).copied().unwrap_or_default()
+    }
+
+    /// Removes `amount` from the available funds, regardless of whether this leaves them negative.
+    /// This reverses a credit that was made while a withdrawal was disputed. If the available funds
+    /// would overflow, then an error is returned.
+    pub fn debit(&mut self, amount: f64) -> Result<(), UpdateError> {
+        self.available = self.available.checked_sub(Fixed::from_f64(amount))?;
+        Ok(())
     }
 
-    /// Releases `amount` from the available funds.
-    pub fn release(&mut self, amount: f64) {
-        self.available = self.available.add(amount);
-        self.held -= amount;
+    /// Releases the funds held for the transaction `tx` back to the available funds. Returns the
+    /// amount released, which is zero if no funds were held for `tx`. If the available funds would
+    /// overflow, then an error is returned and the funds remain held.
+    pub fn release(&mut self, tx: u32) -> Result<f64, UpdateError> {
+        let amount = self.held_for(tx);
+        self.available = self.available.checked_add(amount)?;
+        self.held.remove(&tx);
+        Ok(amount.to_f64())
     }
 
-    /// Removes `amount` from the held funds.
-    pub fn charge(&mut self, amount: f64) {
-        self.held -= amount;
+    /// Removes the funds held for the transaction `tx`. Returns the amount removed, which is zero
+    /// if no funds were held for `tx`.
+    pub fn charge(&mut self, tx: u32) -> f64 {
+        self.held.remove(&tx).unwrap_or_default().to_f64()
     }
 }
 
-#[derive(Debug, Error, Clone, PartialEq)]
+#[derive(Debug, Error, Clone, Copy, PartialEq)]
 pub enum UpdateError {
     #[error("A negative amount was provided")]
     NegativeValue,
     #[error("The account has insufficient funds")]
     InsufficientFunds,
+    #[error("The account would be left below its minimum balance")]
+    BelowMinimumBalance,
+    #[error("The account's held funds would exceed their limit")]
+    HeldLimitExceeded,
+    #[error("The account's funds would exceed the range that they can be held in")]
+    Overflow,
 }
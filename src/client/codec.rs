@@ -0,0 +1,107 @@
+use crate::client::Keyspace;
+use crate::db::StoreError;
+use bincode::{ErrorKind, Options};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::str::FromStr;
+
+/// The zstd level that values are compressed at. Values are small and written once per event, so
+/// a fast level is preferred over a slightly smaller store.
+#[cfg(feature = "compression")]
+const ZSTD_LEVEL: i32 = 3;
+
+/// How the values in a store are encoded. A store must be read with the codec that it was written
+/// with.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ValueCodec {
+    /// Fixed width bincode, which every store was written with before the codec was configurable.
+    #[default]
+    Plain,
+    /// Bincode with variable length integers. Client and transaction IDs, enum tags and lengths
+    /// are mostly small, so they are written in fewer bytes than their fixed width. This reduces
+    /// the memory used by a large in-memory run.
+    Compact,
+    /// The compact encoding, compressed with zstd. Each value is compressed on its own, so the
+    /// frame overhead outweighs the saving on small values, such as a client with few held
+    /// transactions. It pays off for larger values.
+    #[cfg(feature = "compression")]
+    Zstd,
+    /// The compact encoding, compressed with lz4, which is faster than zstd but compresses less.
+    #[cfg(feature = "compression")]
+    Lz4,
+}
+
+impl ValueCodec {
+    /// Encodes `obj` as a stored value.
+    pub fn serialize<S>(&self, obj: &S) -> Result<Vec<u8>, StoreError>
+    where
+        S: Serialize,
+    {
+        let result = match self {
+            ValueCodec::Plain => bincode::serialize(obj),
+            ValueCodec::Compact => bincode::options().serialize(obj),
+            #[cfg(feature = "compression")]
+            ValueCodec::Zstd => bincode::options().serialize(obj).and_then(|encoded| {
+                zstd::bulk::compress(&encoded, ZSTD_LEVEL).map_err(|e| ErrorKind::Io(e).into())
+            }),
+            #[cfg(feature = "compression")]
+            ValueCodec::Lz4 => bincode::options()
+                .serialize(obj)
+                .map(|encoded| lz4_flex::compress_prepend_size(&encoded)),
+        };
+        result.map_err(|e| StoreError::Serialize(Box::new(e)))
+    }
+
+    /// Decodes the `value` that is stored under `key` in `keyspace`. A value that ends before it
+    /// has been fully decoded is reported as a truncated record, rather than as invalid data.
+    pub fn deserialize<S>(
+        &self,
+        keyspace: Keyspace,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<S, StoreError>
+    where
+        S: DeserializeOwned,
+    {
+        let result = match self {
+            ValueCodec::Plain => bincode::deserialize(value),
+            ValueCodec::Compact => bincode::options().deserialize(value),
+            #[cfg(feature = "compression")]
+            ValueCodec::Zstd => zstd::stream::decode_all(value)
+                .map_err(|e| ErrorKind::Io(e).into())
+                .and_then(|decoded| bincode::options().deserialize(&decoded)),
+            #[cfg(feature = "compression")]
+            ValueCodec::Lz4 => lz4_flex::decompress_size_prepended(value)
+                .map_err(|e| ErrorKind::Custom(e.to_string()).into())
+                .and_then(|decoded| bincode::options().deserialize(&decoded)),
+        };
+        result.map_err(|e| match *e {
+            ErrorKind::Io(ref io) if io.kind() == io::ErrorKind::UnexpectedEof => {
+                StoreError::TruncatedRecord {
+                    keyspace: keyspace.name().to_string(),
+                    key: key.to_vec(),
+                    len: value.len(),
+                }
+            }
+            _ => StoreError::Deserialize(Box::new(e)),
+        })
+    }
+}
+
+impl FromStr for ValueCodec {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(ValueCodec::Plain),
+            "compact" => Ok(ValueCodec::Compact),
+            #[cfg(feature = "compression")]
+            "zstd" => Ok(ValueCodec::Zstd),
+            #[cfg(feature = "compression")]
+            "lz4" => Ok(ValueCodec::Lz4),
+            _ => Err(()),
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use crate::client::{ClientError, ClientLimits, ClientState, ClientStore, Policy};
+use crate::clock::MockClock;
+use crate::data::mem_store_with_policy;
+use crate::db::MemStore;
+use crate::scale::DECIMAL_PLACES;
+use crate::transaction::{DisputeStatus, Transaction};
+use std::sync::Arc;
+
+/// Asserts that the amounts `actual` and `expected` are equal to within the precision that amounts
+/// are output to. This tolerates float noise that would never be visible in the output.
+#[track_caller]
+pub fn assert_money_eq(actual: f64, expected: f64) {
+    let tolerance = 10f64.powi(-(DECIMAL_PLACES as i32));
+    assert!(
+        (actual - expected).abs() < tolerance,
+        "Expected an amount of `{}`, found `{}`",
+        expected,
+        actual
+    );
+}
+
+/// A fluent harness for executing transactions against a single client and asserting on the
+/// resulting state. Every transaction that is executed is expected to succeed unless it is
+/// executed using `reject`.
+#[derive(Debug)]
+pub struct ClientTestHarness {
+    store: ClientStore<MemStore>,
+    client: ClientState,
+}
+
+impl ClientTestHarness {
+    /// Constructs a harness for a new client with `id` and an empty store that uses the default
+    /// policies.
+    pub fn new(id: u16) -> ClientTestHarness {
+        ClientTestHarness::with_policy(id, Policy::default())
+    }
+
+    /// Constructs a harness for a new client with `id` and an empty store that uses `policy`.
+    pub fn with_policy(id: u16, policy: Policy) -> ClientTestHarness {
+        ClientTestHarness {
+            store: mem_store_with_policy(policy),
+            client: ClientState::new(id),
+        }
+    }
+
+    /// Holds the client to `limits`.
+    pub fn with_limits(mut self, limits: ClientLimits) -> Self {
+        self.client.set_limits(limits);
+        self
+    }
+
+    /// Reads the time from `clock`, rather than the system's clock.
+    pub fn with_clock(mut self, clock: MockClock) -> Self {
+        self.store.set_clock(Arc::new(clock));
+        self
+    }
+
+    /// Persists `transactions` in the store without executing them against the client.
+    pub fn seed(self, transactions: Vec<Transaction>) -> Self {
+        for transaction in transactions {
+            self.store.put_transaction(transaction).unwrap();
+        }
+        self
+    }
+
+    /// Executes `transaction` against the client and asserts that it succeeded.
+    pub fn execute(mut self, transaction: Transaction) -> Self {
+        let result = self.client.execute_transaction(transaction, &self.store);
+        assert_eq!(result, Ok(()), "Failed to execute `{:?}`", transaction);
+        self
+    }
+
+    /// Executes `transaction` against the client and asserts that it was rejected with `error`.
+    pub fn reject(mut self, transaction: Transaction, error: ClientError) -> Self {
+        let result = self.client.execute_transaction(transaction, &self.store);
+        assert_eq!(result, Err(error));
+        self
+    }
+
+    /// Executes a deposit of `amount` with the ID `tx` against the client.
+    pub fn deposit(self, tx: u32, amount: f64) -> Self {
+        let id = self.client.id;
+        self.execute(Transaction::deposit(id, tx, amount))
+    }
+
+    /// Executes a withdrawal of `amount` with the ID `tx` against the client.
+    pub fn withdrawal(self, tx: u32, amount: f64) -> Self {
+        let id = self.client.id;
+        self.execute(Transaction::withdrawal(id, tx, amount))
+    }
+
+    /// Executes a dispute of the transaction `tx` against the client.
+    pub fn dispute(self, tx: u32) -> Self {
+        let id = self.client.id;
+        self.execute(Transaction::dispute(id, tx))
+    }
+
+    /// Executes a dispute of only `amount` of the transaction `tx` against the client.
+    pub fn partial_dispute(self, tx: u32, amount: f64) -> Self {
+        let id = self.client.id;
+        self.execute(Transaction::partial_dispute(id, tx, amount))
+    }
+
+    /// Executes a resolve of the transaction `tx` against the client.
+    pub fn resolve(self, tx: u32) -> Self {
+        let id = self.client.id;
+        self.execute(Transaction::resolve(id, tx))
+    }
+
+    /// Executes a chargeback of the transaction `tx` against the client.
+    pub fn chargeback(self, tx: u32) -> Self {
+        let id = self.client.id;
+        self.execute(Transaction::chargeback(id, tx))
+    }
+
+    /// Asserts that the client's available funds are `expected`.
+    pub fn assert_available(self, expected: f64) -> Self {
+        assert_money_eq(self.client.balance.get_available(), expected);
+        self
+    }
+
+    /// Asserts that the client's held funds are `expected`.
+    pub fn assert_held(self, expected: f64) -> Self {
+        assert_money_eq(self.client.balance.get_frozen(), expected);
+        self
+    }
+
+    /// Asserts whether the client's account is frozen.
+    pub fn assert_frozen(self, expected: bool) -> Self {
+        assert_eq!(self.client.frozen, expected);
+        self
+    }
+
+    /// Asserts that the stored transfer transaction `tx` has a dispute status of `expected`.
+    pub fn assert_dispute_status(self, tx: u32, expected: DisputeStatus) -> Self {
+        match self.store.get_transaction(self.client.id, tx) {
+            Ok(Some(Transaction::Transfer(transfer))) => assert_eq!(transfer.disputed, expected),
+            r => panic!("Expected a transfer transaction, found: `{:?}`", r),
+        }
+        self
+    }
+
+    /// Asserts that the dispute log of transaction `tx` holds the transitions `expected`, in
+    /// order.
+    pub fn assert_dispute_log(self, tx: u32, expected: &[(DisputeStatus, DisputeStatus)]) -> Self {
+        let log = self.store.get_dispute_log(self.client.id, tx).unwrap();
+        let transitions = log
+            .iter()
+            .map(|event| (event.from, event.to))
+            .collect::<Vec<_>>();
+        assert_eq!(transitions, expected);
+        let sequences = log.iter().map(|event| event.sequence).collect::<Vec<_>>();
+        assert_eq!(sequences, (0..expected.len() as u64).collect::<Vec<_>>());
+        self
+    }
+
+    /// Asserts that the client's state in the store matches the harness's client.
+    pub fn assert_stored(self) -> Self {
+        match self.store.get_client_state(self.client.id) {
+            Ok(Some(stored)) => assert_eq!(stored, self.client),
+            r => panic!("Expected a client, found: `{:?}`", r),
+        }
+        self
+    }
+}
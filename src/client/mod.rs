@@ -2,9 +2,22 @@
 mod tests;
 
 mod balance;
+mod codec;
+#[cfg(test)]
+pub mod harness;
+mod policy;
+mod report;
 mod store;
-pub use store::deserialize;
-pub use store::Keyspace;
+mod validator;
+pub use balance::Fixed;
+pub use codec::ValueCodec;
+pub use policy::{
+    ChargedBackResolvePolicy, CorruptStatePolicy, Policy, WithdrawalDisputePolicy,
+    ZeroDisputePolicy, SECONDS_PER_DAY,
+};
+pub use report::State;
+pub use store::{Books, ClientBooks, Keyspace};
+pub use validator::{NoopValidator, Validator};
 
 use crate::client::balance::{Account, UpdateError};
 pub use crate::client::store::ClientStore;
@@ -22,19 +35,61 @@ use tracing::{event, Level};
 
 const DISPUTE_MISMATCH: &str = "Only a transfer can be disputed";
 const DISPUTE_WITHDRAWAL: &str = "Cannot dispute a withdrawal";
+const DISPUTE_EXCEEDS_AMOUNT: &str = "Cannot dispute more than the transaction's amount";
+const ZERO_DISPUTE_AMOUNT: &str = "zero dispute amount";
 const NOT_DISPUTED: &str = "Transaction is not disputed";
-const ALREADY_DISPUTED: &str = "Transaction is already disputed";
-const DISPUTE_RESOLVED: &str = "Dispute already resolved";
+pub const ALREADY_DISPUTED: &str = "Transaction is already disputed";
+const ALREADY_CHARGED_BACK: &str = "Transaction is already charged back";
+const RESOLVE_CHARGED_BACK: &str = "Cannot resolve a charged-back transaction";
 const EXEC_TRANSACTION: &str = "Executing transaction";
 const TRANSACTION_ERR: &str = "An error was produced when executing a transaction";
 
-/// An asynchronous request for this client to execute the provided transaction.
+/// An asynchronous request for a client to serve.
 #[derive(Debug)]
-pub struct ClientRequest {
-    /// The transaction to execute against this client.
-    pub transaction: Transaction,
-    /// A callback to provide the result of executing the transaction.
-    pub callback: oneshot::Sender<Result<(), ClientError>>,
+pub enum ClientRequest {
+    /// A request for this client to execute the provided transaction.
+    Execute {
+        /// The transaction to execute against this client.
+        transaction: Transaction,
+        /// A callback to provide the result of executing the transaction.
+        callback: oneshot::Sender<Result<Effect, ClientError>>,
+    },
+    /// A request for this client to execute the provided transactions in order. Execution stops
+    /// after the first transaction that produces a fatal error.
+    ExecuteBatch {
+        /// The transactions to execute against this client.
+        transactions: Vec<Transaction>,
+        /// A callback to provide the result of executing each transaction, in order.
+        callback: oneshot::Sender<Vec<Result<Effect, ClientError>>>,
+    },
+    /// A request for this client to persist its current state.
+    Flush {
+        /// A callback to provide the result of persisting the state.
+        callback: oneshot::Sender<Result<(), StoreError>>,
+    },
+    /// A request for a copy of this client's current state, which is left unaltered.
+    Snapshot {
+        /// A callback to provide the copy of the state.
+        callback: oneshot::Sender<ClientState>,
+    },
+    /// A request for this client to revert a withdrawal that it executed, as the transfer that it
+    /// was part of could not be completed.
+    Revert {
+        /// The ID of the withdrawal to revert.
+        tx: u32,
+        /// A callback to provide the result of reverting the withdrawal.
+        callback: oneshot::Sender<Result<(), ClientError>>,
+    },
+}
+
+/// The effect of a transaction that a client accepted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Effect {
+    /// The transaction was applied to the client.
+    Applied,
+    /// The transaction was accepted without effect, as a policy ignores it rather than rejecting
+    /// it.
+    Ignored,
 }
 
 /// A client model for this transaction machine to execute transactions against.
@@ -56,15 +111,6 @@ impl<D> Client<D>
 where
     D: StoreEngine,
 {
-    /// Initialise a new client with a default state.
-    pub fn new(id: u16, rx: mpsc::Receiver<ClientRequest>, store: ClientStore<D>) -> Self {
-        Client {
-            state: ClientState::new(id),
-            rx,
-            store,
-        }
-    }
-
     /// Load a new client with `state`.
     pub fn with_state(
         state: ClientState,
@@ -85,19 +131,13 @@ where
 
         let mut requests = ReceiverStream::new(rx);
         while let Some(request) = requests.next().await {
-            let ClientRequest {
-                transaction,
-                callback,
-            } = request;
-
-            let result = state.execute_transaction(transaction, &store);
-            let _ = callback.send(result);
+            state.serve(request, &store);
         }
     }
 }
 
 /// The internal state of a client.
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ClientState {
     /// A unique identifier that has been assigned to this client.
     id: u16,
@@ -105,6 +145,36 @@ pub struct ClientState {
     balance: Account,
     /// Whether the client's account has been frozen and it should stop executing transactions.
     frozen: bool,
+    /// The number of this client's transactions that are currently disputed.
+    open_disputes: u32,
+    /// Optional reporting metadata that has been attached to this client. This does not affect
+    /// the processing of any transactions.
+    metadata: Option<ClientMetadata>,
+    /// The limits that transactions executed against this client are held to.
+    limits: ClientLimits,
+}
+
+/// Reporting metadata that may be attached to a client.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ClientMetadata {
+    /// The name of the account holder.
+    pub name: String,
+    /// The region that the account belongs to.
+    pub region: String,
+    /// The account's tier.
+    pub tier: String,
+}
+
+/// Limits on a client's balance that transactions executed against it are held to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ClientLimits {
+    /// If set, then a withdrawal that would leave the client's available funds below this amount
+    /// is rejected. Disputes are unaffected and may still leave the available funds below it, or
+    /// negative.
+    pub min_balance: Option<f64>,
+    /// If set, then a dispute that would take the client's total held funds above this amount is
+    /// rejected.
+    pub max_held: Option<f64>,
 }
 
 impl ClientState {
@@ -114,17 +184,22 @@ impl ClientState {
             id,
             balance: Default::default(),
             frozen: false,
+            open_disputes: 0,
+            metadata: None,
+            limits: ClientLimits::default(),
         }
     }
 
     /// Split this client in to its internal parts.
-    pub fn split(self) -> (u16, Account, bool) {
+    pub fn split(self) -> (u16, Account, bool, Option<ClientMetadata>) {
         let ClientState {
             id,
             balance,
             frozen,
+            metadata,
+            ..
         } = self;
-        (id, balance, frozen)
+        (id, balance, frozen, metadata)
     }
 
     /// Returns the unique identifier assigned to this client.
@@ -132,26 +207,103 @@ impl ClientState {
         self.id
     }
 
+    /// Serves `request` against this `ClientState`, persisting any update in `store`, and sends
+    /// the result to the request's callback.
+    pub fn serve<D>(&mut self, request: ClientRequest, store: &ClientStore<D>)
+    where
+        D: StoreEngine,
+    {
+        match request {
+            ClientRequest::Execute {
+                transaction,
+                callback,
+            } => {
+                let result = self.execute(transaction, store);
+                let _ = callback.send(result);
+            }
+            ClientRequest::ExecuteBatch {
+                transactions,
+                callback,
+            } => {
+                let mut results = Vec::with_capacity(transactions.len());
+                for transaction in transactions {
+                    let result = self.execute(transaction, store);
+                    let fatal = matches!(&result, Err(e) if e.is_fatal());
+                    results.push(result);
+                    if fatal {
+                        break;
+                    }
+                }
+                let _ = callback.send(results);
+            }
+            ClientRequest::Flush { callback } => {
+                let _ = callback.send(store.put_client_state(self));
+            }
+            ClientRequest::Snapshot { callback } => {
+                let _ = callback.send(self.clone());
+            }
+            ClientRequest::Revert { tx, callback } => {
+                let _ = callback.send(self.revert_withdrawal(tx, store));
+            }
+        }
+    }
+
+    /// Attaches `metadata` to this client, replacing any existing metadata.
+    pub fn set_metadata(&mut self, metadata: ClientMetadata) {
+        self.metadata = Some(metadata);
+    }
+
+    /// Returns the limits that transactions executed against this client are held to.
+    pub fn limits(&self) -> ClientLimits {
+        self.limits
+    }
+
+    /// Holds the transactions executed against this client to `limits`, replacing any existing
+    /// limits.
+    pub fn set_limits(&mut self, limits: ClientLimits) {
+        self.limits = limits;
+    }
+
     /// Execute a `TransferTransaction` against this `ClientState`. If the operation is successful,
-    /// then the result of the operation is persisted in `store`.
+    /// then the transaction is persisted in `store` with the time that it was executed, in the same
+    /// batch as this `ClientState`'s updated state. If the batch cannot be written, then the update
+    /// is discarded, so that a later flush cannot persist a balance that includes a transaction
+    /// that was never stored. A transfer that reuses the ID of one of this client's stored
+    /// transactions is rejected, rather than overwriting the stored transaction that disputes
+    /// reference.
     fn execute_transfer<D>(
         &mut self,
-        transaction: TransferTransaction,
+        mut transaction: TransferTransaction,
         store: &ClientStore<D>,
     ) -> Result<(), ClientError>
     where
         D: StoreEngine,
     {
-        let TransferTransaction { kind, amount, .. } = &transaction;
+        let TransferTransaction {
+            kind, tx, amount, ..
+        } = &transaction;
+        if store.transaction_exists(self.id, *tx)? {
+            return Err(ClientError::DuplicateTransaction);
+        }
+
+        let previous = self.balance.clone();
         let result = match kind {
             TransferTransactionKind::Deposit => self.balance.deposit(*amount).map_err(Into::into),
-            TransferTransactionKind::Withdrawal => {
-                self.balance.withdraw(*amount).map_err(Into::into)
+            TransferTransactionKind::Withdrawal => match self.limits.min_balance {
+                Some(minimum) => self.balance.withdraw_leaving(*amount, minimum),
+                None => self.balance.withdraw(*amount),
             }
+            .map_err(Into::into),
         };
 
         if result.is_ok() {
-            store.put_transaction(Transaction::Transfer(transaction))?;
+            transaction.executed_at = Some(store.clock().now());
+            if let Err(e) =
+                store.put_transaction_with_state(Transaction::Transfer(transaction), self)
+            {
+                self.balance = previous;
+                return Err(e.into());
+            }
         }
 
         result
@@ -163,188 +315,378 @@ impl ClientState {
         &mut self,
         transaction: DisputedTransaction,
         store: &ClientStore<D>,
-    ) -> Result<(), ClientError>
+    ) -> Result<Effect, ClientError>
     where
         D: StoreEngine,
     {
-        let DisputedTransaction { kind, tx, .. } = transaction;
+        let DisputedTransaction {
+            kind, tx, amount, ..
+        } = transaction;
 
         match kind {
-            DisputedTransactionKind::Dispute => self.execute_dispute(tx, store),
+            DisputedTransactionKind::Dispute => self.execute_dispute(tx, amount, store),
             DisputedTransactionKind::Resolve => self.execute_resolve(tx, store),
             DisputedTransactionKind::Chargeback => self.execute_chargeback(tx, store),
         }
     }
 
-    /// Attempts to dispute a transaction that this state object has previously processed. If the
-    /// transaction has not been processed previously then an error is returned. The funds are
-    /// disputed are moved to being in a held state.
-    fn execute_dispute<D>(&mut self, tx_id: u32, store: &ClientStore<D>) -> Result<(), ClientError>
+    /// Returns the stored transfer with `tx_id` that a dispute, resolve or chargeback against this
+    /// `ClientState` references. An error is returned if it does not exist, is not a transfer or
+    /// belongs to another client.
+    fn disputed_transfer<D>(
+        &self,
+        tx_id: u32,
+        store: &ClientStore<D>,
+    ) -> Result<TransferTransaction, ClientError>
     where
         D: StoreEngine,
     {
-        match store.get_transaction(tx_id)? {
-            Some(Transaction::Transfer(transfer)) => {
-                let TransferTransaction {
-                    kind,
-                    client,
-                    tx,
-                    amount,
-                    disputed,
-                } = transfer;
-                match kind {
-                    TransferTransactionKind::Deposit => {
-                        if matches!(disputed, DisputeStatus::Disputed) {
-                            return Err(ClientError::DisputeError(ALREADY_DISPUTED.to_string()));
-                        }
-
-                        let processed = TransferTransaction {
-                            kind,
-                            client,
-                            tx,
-                            amount,
-                            disputed: DisputeStatus::Disputed,
-                        };
-
-                        store
-                            .put_transaction(Transaction::Transfer(processed))
-                            .map_err(ClientError::StoreError)?;
-
-                        self.balance
-                            .hold(amount)
-                            .map_err::<ClientError, _>(Into::into)
-                    }
-                    TransferTransactionKind::Withdrawal => {
-                        Err(ClientError::DisputeError(DISPUTE_WITHDRAWAL.to_string()))
-                    }
-                }
+        match store.get_transaction(self.id, tx_id)? {
+            // Transaction IDs are scoped to a client, so the stored transaction can only belong to
+            // another client if its record is corrupt.
+            Some(Transaction::Transfer(transfer)) if transfer.client != self.id => {
+                Err(ClientError::ClientMismatchOnDispute)
             }
-            Some(Transaction::Disputed(_)) => {
+            Some(Transaction::Transfer(transfer)) => Ok(transfer),
+            Some(Transaction::Disputed(_) | Transaction::Internal(_)) => {
                 Err(ClientError::DisputeError(DISPUTE_MISMATCH.to_string()))
             }
             None => Err(ClientError::TransactionNotFound),
         }
     }
 
-    /// Attempts to resolve a transaction that has previously been marked as disputed. If the
-    /// corresponding transaction does not exist then an error is returned. Any held funds are
-    /// released if the operation is successful.
-    fn execute_resolve<D>(&mut self, tx_id: u32, store: &ClientStore<D>) -> Result<(), ClientError>
+    /// Attempts to dispute a transaction that this state object has previously processed. If the
+    /// transaction has not been processed previously, or it was executed longer ago than the
+    /// store's dispute window, then an error is returned. The funds of a
+    /// disputed deposit are moved to being in a held state, while a disputed withdrawal is
+    /// executed according to the store's `WithdrawalDisputePolicy`.
+    ///
+    /// Only `disputed_amount` of the transaction is disputed if it is provided, which may not
+    /// exceed the transaction's amount. Otherwise, the whole amount is disputed. A partial dispute
+    /// of an amount of zero is executed according to the store's `ZeroDisputePolicy`.
+    fn execute_dispute<D>(
+        &mut self,
+        tx_id: u32,
+        disputed_amount: Option<f64>,
+        store: &ClientStore<D>,
+    ) -> Result<Effect, ClientError>
     where
         D: StoreEngine,
     {
-        match store.get_transaction(tx_id)? {
-            Some(Transaction::Transfer(transfer)) => {
-                let TransferTransaction {
-                    kind,
-                    client,
-                    tx,
-                    amount,
-                    disputed,
-                } = transfer;
-
-                if disputed == DisputeStatus::NotDisputed {
-                    return Err(ClientError::DisputeError(NOT_DISPUTED.to_string()));
+        let transfer = self.disputed_transfer(tx_id, store)?;
+        let TransferTransaction {
+            kind,
+            client,
+            tx,
+            amount,
+            disputed,
+            executed_at,
+            ..
+        } = transfer;
+        let withdrawal_dispute = store.policy().withdrawal_dispute;
+
+        if matches!(kind, TransferTransactionKind::Withdrawal)
+            && withdrawal_dispute == WithdrawalDisputePolicy::Reject
+        {
+            return Err(ClientError::DisputeError(DISPUTE_WITHDRAWAL.to_string()));
+        }
+        match disputed {
+            DisputeStatus::Disputed => {
+                return Err(ClientError::DisputeError(ALREADY_DISPUTED.to_string()));
+            }
+            DisputeStatus::ChargedBack => {
+                return Err(ClientError::DisputeError(ALREADY_CHARGED_BACK.to_string()));
+            }
+            DisputeStatus::NotDisputed => {}
+        }
+        if let (Some(window), Some(executed_at)) = (store.policy().dispute_window, executed_at) {
+            let age = store.clock().now().duration_since(executed_at);
+            if age.unwrap_or_default() > window {
+                return Err(ClientError::DisputeWindowExpired);
+            }
+        }
+        if let Some(max_open_disputes) = store.policy().max_open_disputes {
+            if self.open_disputes >= max_open_disputes {
+                return Err(ClientError::TooManyDisputes);
+            }
+        }
+        if disputed_amount.is_some_and(|disputed_amount| disputed_amount > amount) {
+            return Err(ClientError::DisputeError(
+                DISPUTE_EXCEEDS_AMOUNT.to_string(),
+            ));
+        }
+        if disputed_amount == Some(0.0) {
+            return match store.policy().zero_dispute {
+                ZeroDisputePolicy::Reject => {
+                    Err(ClientError::DisputeError(ZERO_DISPUTE_AMOUNT.to_string()))
                 }
+                ZeroDisputePolicy::Ignore => Ok(Effect::Ignored),
+            };
+        }
+
+        let processed = TransferTransaction {
+            kind,
+            client,
+            tx,
+            amount,
+            disputed: DisputeStatus::Disputed,
+            executed_at,
+            disputed_amount,
+        };
+
+        // The balance is updated first so that a dispute which would exceed the held limit is
+        // rejected before the transaction is marked as disputed.
+        let max_held = self.limits.max_held;
+        let held = processed.amount_under_dispute();
+        match (kind, withdrawal_dispute) {
+            (TransferTransactionKind::Deposit, _) => self.balance.hold_within(tx, held, max_held),
+            (_, WithdrawalDisputePolicy::CreditToAvailable) => self.balance.deposit(held),
+            (_, _) => self.balance.credit_held_within(tx, held, max_held),
+        }
+        .map_err::<ClientError, _>(Into::into)?;
+
+        store
+            .put_transaction(Transaction::Transfer(processed))
+            .map_err(ClientError::StoreError)?;
+        store
+            .append_dispute_event(client, tx, disputed, DisputeStatus::Disputed)
+            .map_err(ClientError::StoreError)?;
+        self.open_disputes += 1;
 
-                let processed = TransferTransaction {
-                    kind,
-                    client,
-                    tx,
-                    amount,
-                    disputed: DisputeStatus::NotDisputed,
+        Ok(Effect::Applied)
+    }
+
+    /// Attempts to resolve a transaction that has previously been marked as disputed. If the
+    /// corresponding transaction does not exist then an error is returned. Any funds held for a
+    /// deposit are released if the operation is successful, while a withdrawal stands and so any
+    /// funds credited by its dispute are removed.
+    fn execute_resolve<D>(
+        &mut self,
+        tx_id: u32,
+        store: &ClientStore<D>,
+    ) -> Result<Effect, ClientError>
+    where
+        D: StoreEngine,
+    {
+        let transfer = self.disputed_transfer(tx_id, store)?;
+        let TransferTransaction {
+            kind,
+            client,
+            tx,
+            amount,
+            disputed,
+            executed_at,
+            ..
+        } = transfer;
+
+        match disputed {
+            DisputeStatus::NotDisputed => {
+                return Err(ClientError::DisputeError(NOT_DISPUTED.to_string()));
+            }
+            DisputeStatus::ChargedBack => {
+                return match store.policy().resolve_charged_back {
+                    ChargedBackResolvePolicy::Reject => {
+                        Err(ClientError::DisputeError(RESOLVE_CHARGED_BACK.to_string()))
+                    }
+                    ChargedBackResolvePolicy::Ignore => Ok(Effect::Ignored),
                 };
+            }
+            DisputeStatus::Disputed => {}
+        }
 
-                store
-                    .put_transaction(Transaction::Transfer(processed))
-                    .map_err(ClientError::StoreError)?;
+        let processed = TransferTransaction {
+            kind,
+            client,
+            tx,
+            amount,
+            disputed: DisputeStatus::NotDisputed,
+            executed_at,
+            disputed_amount: None,
+        };
 
-                self.balance.release(amount);
-                Ok(())
+        // The balance is updated first so that a resolve which would overflow the funds is rejected
+        // before the transaction is marked as resolved.
+        match (kind, store.policy().withdrawal_dispute) {
+            (TransferTransactionKind::Deposit, _) => self.balance.release(tx).map(|_| ()),
+            (_, WithdrawalDisputePolicy::CreditToAvailable) => {
+                self.balance.debit(transfer.amount_under_dispute())
             }
-            Some(Transaction::Disputed(_)) => {
-                Err(ClientError::DisputeError(DISPUTE_MISMATCH.to_string()))
+            (_, _) => {
+                self.balance.charge(tx);
+                Ok(())
             }
-            None => Err(ClientError::TransactionNotFound),
         }
+        .map_err::<ClientError, _>(Into::into)?;
+
+        store
+            .put_transaction(Transaction::Transfer(processed))
+            .map_err(ClientError::StoreError)?;
+        store
+            .append_dispute_event(client, tx, disputed, DisputeStatus::NotDisputed)
+            .map_err(ClientError::StoreError)?;
+        self.open_disputes = self.open_disputes.saturating_sub(1);
+        Ok(Effect::Applied)
     }
 
     /// Executes a chargeback against this `ClientState` instance. If the corresponding transaction
-    /// does not exist then an error is returned. Otherwise, the held funds are removed from this
-    /// client.
+    /// does not exist then an error is returned. Otherwise, the funds held for a deposit are
+    /// removed from this client, while the funds credited by the dispute of a withdrawal are
+    /// finalized.
     fn execute_chargeback<D>(
         &mut self,
         tx_id: u32,
         store: &ClientStore<D>,
-    ) -> Result<(), ClientError>
+    ) -> Result<Effect, ClientError>
     where
         D: StoreEngine,
     {
-        match store.get_transaction(tx_id)? {
-            Some(Transaction::Transfer(transfer)) => {
-                let TransferTransaction {
-                    kind,
-                    client,
-                    tx,
-                    amount,
-                    disputed,
-                } = transfer;
-
-                if matches!(disputed, DisputeStatus::NotDisputed) {
-                    return Err(ClientError::DisputeError(NOT_DISPUTED.to_string()));
-                } else if matches!(disputed, DisputeStatus::Resolved) {
-                    return Err(ClientError::DisputeError(DISPUTE_RESOLVED.to_string()));
-                }
+        let transfer = self.disputed_transfer(tx_id, store)?;
+        let TransferTransaction {
+            kind,
+            client,
+            tx,
+            amount,
+            disputed,
+            executed_at,
+            disputed_amount,
+        } = transfer;
+
+        if matches!(disputed, DisputeStatus::NotDisputed) {
+            return Err(ClientError::DisputeError(NOT_DISPUTED.to_string()));
+        } else if matches!(disputed, DisputeStatus::ChargedBack) {
+            return Err(ClientError::DisputeError(ALREADY_CHARGED_BACK.to_string()));
+        }
 
-                let processed = TransferTransaction {
-                    kind,
-                    client,
-                    tx,
-                    amount,
-                    disputed: DisputeStatus::Resolved,
-                };
+        // The disputed amount is kept, as it is the amount that was charged back.
+        let processed = TransferTransaction {
+            kind,
+            client,
+            tx,
+            amount,
+            disputed: DisputeStatus::ChargedBack,
+            executed_at,
+            disputed_amount,
+        };
 
-                store
-                    .put_transaction(Transaction::Transfer(processed))
-                    .map_err(ClientError::StoreError)?;
+        // The balance is updated first so that a chargeback which would overflow the funds is
+        // rejected before the transaction is marked as charged back.
+        match (kind, store.policy().withdrawal_dispute) {
+            (TransferTransactionKind::Deposit, _) => {
+                self.balance.charge(tx);
+            }
+            // The credit was already made to the available funds when it was disputed.
+            (_, WithdrawalDisputePolicy::CreditToAvailable) => {}
+            (_, _) => {
+                self.balance.release(tx)?;
+            }
+        }
 
-                self.balance.charge(amount);
-                self.frozen = true;
+        store
+            .put_transaction(Transaction::Transfer(processed))
+            .map_err(ClientError::StoreError)?;
+        store
+            .append_dispute_event(client, tx, disputed, DisputeStatus::ChargedBack)
+            .map_err(ClientError::StoreError)?;
+        self.open_disputes = self.open_disputes.saturating_sub(1);
+        if store.policy().freeze_on_chargeback {
+            self.frozen = true;
+        }
 
-                Ok(())
-            }
-            Some(Transaction::Disputed(_)) => {
-                Err(ClientError::DisputeError(DISPUTE_MISMATCH.to_string()))
+        Ok(Effect::Applied)
+    }
+
+    /// Reverts the withdrawal with `tx_id` that this `ClientState` executed, as though it had never
+    /// been executed. Its amount is credited back to the available funds, and it is removed from
+    /// `store` in the same batch as the updated state is persisted. If the batch cannot be written,
+    /// then the credit is discarded. Only a withdrawal that has never been disputed can be
+    /// reverted, as this is the rollback of the first leg of a transfer.
+    ///
+    /// The transfer as a whole is only atomic within this process, not across a crash: the
+    /// withdrawal is persisted before the deposit is attempted, so a crash before it is reverted
+    /// leaves the source debited.
+    pub fn revert_withdrawal<D>(
+        &mut self,
+        tx_id: u32,
+        store: &ClientStore<D>,
+    ) -> Result<(), ClientError>
+    where
+        D: StoreEngine,
+    {
+        match store.get_transaction(self.id, tx_id)? {
+            Some(Transaction::Transfer(TransferTransaction {
+                kind: TransferTransactionKind::Withdrawal,
+                amount,
+                disputed: DisputeStatus::NotDisputed,
+                ..
+            })) => {
+                let previous = self.balance.clone();
+                self.balance.deposit(amount)?;
+                store
+                    .delete_transaction_with_state(tx_id, self)
+                    .map_err(|e| {
+                        self.balance = previous;
+                        ClientError::StoreError(e)
+                    })
             }
-            None => Err(ClientError::TransactionNotFound),
+            _ => Err(ClientError::TransactionNotFound),
         }
     }
 
     /// Executes `transaction` against this `ClientState`. If the operation is successful, then this
-    /// `ClientState`'s updated state is persisted.
+    /// `ClientState`'s updated state is persisted. A deposit or withdrawal is persisted in the same
+    /// batch as its updated state, so a crash cannot persist one without the other.
     pub fn execute_transaction<D>(
         &mut self,
         transaction: Transaction,
         store: &ClientStore<D>,
     ) -> Result<(), ClientError>
+    where
+        D: StoreEngine,
+    {
+        self.execute(transaction, store).map(|_| ())
+    }
+
+    /// Executes `transaction` as `execute_transaction` does. Returning whether it was applied or
+    /// accepted without effect if it is successful.
+    pub fn execute<D>(
+        &mut self,
+        transaction: Transaction,
+        store: &ClientStore<D>,
+    ) -> Result<Effect, ClientError>
     where
         D: StoreEngine,
     {
         if self.id != transaction.client_id() {
             Err(ClientError::MismatchedClientId)
         } else if self.frozen {
-            Err(ClientError::AccountFrozen)
+            if store.policy().strict_freeze {
+                Err(ClientError::TransactionAfterFreeze)
+            } else {
+                Err(ClientError::AccountFrozen)
+            }
         } else {
             event!(Level::TRACE, EXEC_TRANSACTION, ?transaction);
+            store.validator().validate(&transaction, self)?;
 
             let result = match transaction {
-                Transaction::Transfer(tx) => self.execute_transfer(tx, store),
-                Transaction::Disputed(tx) => self.execute_disputed_transaction(tx, store),
+                Transaction::Transfer(tx) => {
+                    self.execute_transfer(tx, store).map(|()| Effect::Applied)
+                }
+                Transaction::Disputed(tx) => {
+                    self.execute_disputed_transaction(tx, store)
+                        .and_then(|effect| {
+                            store.put_client_state(self)?;
+                            Ok(effect)
+                        })
+                }
+                // Each leg of a transfer is executed by its own client.
+                Transaction::Internal(_) => Err(ClientError::UncoordinatedTransfer),
             };
 
             match result {
-                Ok(()) => store
-                    .put_client_state(self)
+                Ok(effect) => store
+                    .record_applied()
+                    .map(|()| effect)
                     .map_err(ClientError::StoreError),
                 Err(error) => {
                     event!(Level::ERROR, TRANSACTION_ERR, ?error);
@@ -361,12 +703,38 @@ pub enum ClientError {
     NegativeValue,
     #[error("The client has insufficient funds")]
     InsufficientFunds,
+    #[error("The withdrawal would leave the client's available funds below the minimum balance")]
+    BelowMinimumBalance,
+    #[error("The dispute would take the client's held funds above their limit")]
+    HeldLimitExceeded,
+    #[error("The transaction would take the client's funds outside of the range that can be held")]
+    BalanceOverflow,
     #[error("Attempted to execute a transaction that was not for this client")]
     MismatchedClientId,
+    #[error(
+        "The transaction referenced by a dispute, resolve or chargeback belongs to another client"
+    )]
+    ClientMismatchOnDispute,
     #[error("Cannot execute a transaction against this client as its account is frozen")]
     AccountFrozen,
+    #[error("A transaction was received for a client whose account has been frozen")]
+    TransactionAfterFreeze,
     #[error("A reference to a transaction was provided that does not exist")]
     TransactionNotFound,
+    #[error("A transaction with the same ID has already been executed for this client")]
+    DuplicateTransaction,
+    #[error("The client has reached its limit of open disputes")]
+    TooManyDisputes,
+    #[error("The disputed transaction was executed longer ago than the dispute window")]
+    DisputeWindowExpired,
+    #[error(
+        "The dispute would exceed the limit on the total funds under dispute across all clients"
+    )]
+    SystemDisputeCapExceeded,
+    #[error("The transaction failed validation: `{0}`")]
+    ValidationFailed(String),
+    #[error("A transfer must be executed between its two clients rather than by a single client")]
+    UncoordinatedTransfer,
     #[error("Dispute error: `{0}`")]
     DisputeError(String),
     #[error("Store error: `{0}`")]
@@ -375,7 +743,10 @@ pub enum ClientError {
 
 impl ClientError {
     pub fn is_fatal(&self) -> bool {
-        matches!(self, ClientError::StoreError(_))
+        matches!(
+            self,
+            ClientError::StoreError(_) | ClientError::TransactionAfterFreeze
+        )
     }
 }
 
@@ -384,6 +755,9 @@ impl From<UpdateError> for ClientError {
         match e {
             UpdateError::NegativeValue => ClientError::NegativeValue,
             UpdateError::InsufficientFunds => ClientError::InsufficientFunds,
+            UpdateError::BelowMinimumBalance => ClientError::BelowMinimumBalance,
+            UpdateError::HeldLimitExceeded => ClientError::HeldLimitExceeded,
+            UpdateError::Overflow => ClientError::BalanceOverflow,
         }
     }
 }
@@ -0,0 +1,188 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The number of seconds in a day, which a dispute window is specified in.
+pub const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Policies that alter how transactions are executed against a client.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Policy {
+    /// Whether any transaction that is received for a frozen client should be treated as a fatal
+    /// feed error rather than being rejected.
+    pub strict_freeze: bool,
+    /// The action to take when a client's persisted state cannot be deserialized.
+    pub corrupt_state: CorruptStatePolicy,
+    /// The maximum number of transactions that a client may have disputed at once. Further
+    /// disputes are rejected until an open dispute is resolved or charged back.
+    pub max_open_disputes: Option<u32>,
+    /// Whether a client's account is frozen when a chargeback is executed against it.
+    pub freeze_on_chargeback: bool,
+    /// How a dispute that references a withdrawal is executed.
+    pub withdrawal_dispute: WithdrawalDisputePolicy,
+    /// If set, then a transaction may only be disputed within this long of when it was executed.
+    /// A transaction that has no execution time may always be disputed.
+    pub dispute_window: Option<Duration>,
+    /// How a resolve that references a transaction which has been charged back is executed.
+    pub resolve_charged_back: ChargedBackResolvePolicy,
+    /// How a dispute that would dispute an amount of zero is executed.
+    pub zero_dispute: ZeroDisputePolicy,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            strict_freeze: false,
+            corrupt_state: CorruptStatePolicy::default(),
+            max_open_disputes: None,
+            freeze_on_chargeback: true,
+            withdrawal_dispute: WithdrawalDisputePolicy::default(),
+            dispute_window: None,
+            resolve_charged_back: ChargedBackResolvePolicy::default(),
+            zero_dispute: ZeroDisputePolicy::default(),
+        }
+    }
+}
+
+/// Parses a comma-separated list of `name=value` settings that are applied to the default
+/// policies. For example, `freeze-on-chargeback=false,max-open-disputes=3`.
+impl FromStr for Policy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut policy = Policy::default();
+
+        for setting in s.split(',') {
+            let (name, value) = setting.split_once('=').ok_or(())?;
+            match name {
+                "strict-freeze" => policy.strict_freeze = value.parse().map_err(|_| ())?,
+                "on-corrupt-state" => policy.corrupt_state = value.parse()?,
+                "max-open-disputes" => {
+                    policy.max_open_disputes = match value {
+                        "none" => None,
+                        value => Some(value.parse().map_err(|_| ())?),
+                    }
+                }
+                "freeze-on-chargeback" => {
+                    policy.freeze_on_chargeback = value.parse().map_err(|_| ())?
+                }
+                "withdrawal-disputes" => policy.withdrawal_dispute = value.parse()?,
+                "dispute-window" => {
+                    policy.dispute_window = match value {
+                        "none" => None,
+                        value => {
+                            let days = value.parse::<u64>().map_err(|_| ())?;
+                            let seconds = days.checked_mul(SECONDS_PER_DAY).filter(|_| days != 0);
+                            Some(Duration::from_secs(seconds.ok_or(())?))
+                        }
+                    }
+                }
+                "resolve-charged-back" => policy.resolve_charged_back = value.parse()?,
+                "zero-disputes" => policy.zero_dispute = value.parse()?,
+                _ => return Err(()),
+            }
+        }
+
+        Ok(policy)
+    }
+}
+
+/// The action to take when a client's persisted state cannot be deserialized when it is loaded.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CorruptStatePolicy {
+    /// Fail with the deserialization error.
+    #[default]
+    Fail,
+    /// Log the error and start the client with a new state.
+    TreatAsNew,
+}
+
+impl FromStr for CorruptStatePolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(CorruptStatePolicy::Fail),
+            "treat-as-new" => Ok(CorruptStatePolicy::TreatAsNew),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How a dispute that references a withdrawal is executed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WithdrawalDisputePolicy {
+    /// The dispute is rejected. Only deposits may be disputed.
+    #[default]
+    Reject,
+    /// The withdrawn funds are credited to the client's held funds. A resolve removes them, as the
+    /// withdrawal stands, and a chargeback releases them to the available funds.
+    Hold,
+    /// The withdrawn funds are credited straight back to the client's available funds pending
+    /// investigation. A resolve withdraws them again and a chargeback finalizes the credit.
+    CreditToAvailable,
+}
+
+impl FromStr for WithdrawalDisputePolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(WithdrawalDisputePolicy::Reject),
+            "hold" => Ok(WithdrawalDisputePolicy::Hold),
+            "credit-to-available" => Ok(WithdrawalDisputePolicy::CreditToAvailable),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How a resolve that references a transaction which has been charged back is executed. A
+/// chargeback is terminal, so the resolve can never be applied.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChargedBackResolvePolicy {
+    /// The resolve is rejected with a dispute error.
+    #[default]
+    Reject,
+    /// The resolve is accepted but has no effect, for feeds that send a resolve after every
+    /// chargeback.
+    Ignore,
+}
+
+impl FromStr for ChargedBackResolvePolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(ChargedBackResolvePolicy::Reject),
+            "ignore" => Ok(ChargedBackResolvePolicy::Ignore),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How a partial dispute with a disputed amount of zero is executed. Disputing nothing would only
+/// change the transaction's dispute status.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ZeroDisputePolicy {
+    /// The dispute is rejected with a dispute error.
+    #[default]
+    Reject,
+    /// The dispute is accepted but has no effect, so the transaction is left undisputed.
+    Ignore,
+}
+
+impl FromStr for ZeroDisputePolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(ZeroDisputePolicy::Reject),
+            "ignore" => Ok(ZeroDisputePolicy::Ignore),
+            _ => Err(()),
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use crate::client::{ClientMetadata, ClientState};
+use serde::Serialize;
+
+/// A reporting view of a client's state.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct State {
+    /// The client's unique identifier.
+    pub client: u16,
+    /// The funds that are available for trading.
+    pub available: f64,
+    /// The funds that are held due to disputes.
+    pub held: f64,
+    /// The sum of the available and held funds.
+    pub total: f64,
+    /// Whether the client's account is frozen.
+    pub locked: bool,
+    /// Optional reporting metadata that has been attached to the client.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<ClientMetadata>,
+}
+
+impl From<ClientState> for State {
+    fn from(client: ClientState) -> Self {
+        let (id, balance, frozen, metadata) = client.split();
+        let available = balance.get_available();
+        let held = balance.get_frozen();
+        let total = balance.get_total();
+
+        State {
+            client: id,
+            available,
+            held,
+            total,
+            locked: frozen,
+            metadata,
+        }
+    }
+}
@@ -1,11 +1,40 @@
-use crate::client::ClientState;
-use crate::db::{StoreEngine, StoreError};
-use crate::transaction::Transaction;
+use crate::client::{
+    ClientError, ClientLimits, ClientMetadata, ClientState, NoopValidator, Policy, State,
+    Validator, ValueCodec, WithdrawalDisputePolicy,
+};
+use crate::clock::{Clock, SystemClock};
+use crate::db::{BatchWrite, KeyValue, StoreEngine, StoreError};
+use crate::meta::RunMeta;
+use crate::scale::DECIMAL_PLACES;
+use crate::transaction::{
+    DisputeStatus, Transaction, TransferTransaction, TransferTransactionKind,
+};
+use fnv::FnvHashSet;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::collections::BTreeMap;
+use std::panic;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::thread;
+use std::time::SystemTime;
+use tracing::{event, Level};
+
+/// The number of shards in a store's client lock map.
+const LOCK_SHARDS: usize = 64;
 
 pub const CLIENTS_KS: &str = "clients";
 pub const TRANSACTIONS_KS: &str = "transactions";
+pub const RUN_META_KS: &str = "run_meta";
+pub const DISPUTE_LOG_KS: &str = "dispute_log";
+pub const COMPACTED_KS: &str = "compacted";
+/// The value of a compacted transaction's tombstone, which only records that it existed.
+const TOMBSTONE: &[u8] = &[1];
+
+/// The key that the run metadata is stored under in the `RunMeta` keyspace.
+const RUN_META_KEY: &[u8] = b"run";
+
+/// The message logged when a scan skips a corrupt client state record.
+const SKIPPED_RECORD: &str = "Skipped a client state record that could not be deserialized";
 
 /// A store for a client to lookup transactions, store transactions and persist its state.
 #[derive(Debug, Clone)]
@@ -15,6 +44,26 @@ where
 {
     /// The store to delegate operations to.
     delegate: Arc<D>,
+    /// The policies to apply when executing transactions.
+    policy: Policy,
+    /// Business rules to check before a transaction is executed.
+    validator: Arc<dyn Validator>,
+    /// A lock map, sharded by client ID, that serializes `apply_transaction` calls for the same
+    /// client.
+    locks: Arc<Vec<Mutex<()>>>,
+    /// The clients that have been loaded to execute transactions through this store.
+    touched: Arc<Mutex<FnvHashSet<u16>>>,
+    /// If the clients have been indexed, then the clients that may have a state in the delegate.
+    /// The state of a client that is not in the index is not read, as it is known to be new.
+    index: Arc<RwLock<Option<FnvHashSet<u16>>>>,
+    /// The clock that the time is read from.
+    clock: Arc<dyn Clock>,
+    /// If set, then the delegate is committed after every `n` transactions that are applied.
+    commit_every: Option<usize>,
+    /// The number of transactions that have been applied through this store.
+    applied: Arc<AtomicUsize>,
+    /// How client states and transactions are encoded.
+    codec: ValueCodec,
 }
 
 impl<D> ClientStore<D>
@@ -23,8 +72,137 @@ where
 {
     /// Construct a new `ClientStore` that will delegate operations to `delegate`.
     pub fn new(delegate: D) -> Self {
+        ClientStore::with_policy(delegate, Policy::default())
+    }
+
+    /// Construct a new `ClientStore` that will delegate operations to `delegate` and execute
+    /// transactions using `policy`.
+    pub fn with_policy(delegate: D, policy: Policy) -> Self {
+        ClientStore::with_validator(delegate, policy, Arc::new(NoopValidator))
+    }
+
+    /// Construct a new `ClientStore` that will delegate operations to `delegate` and execute
+    /// transactions using `policy` once they have been accepted by `validator`.
+    pub fn with_validator(delegate: D, policy: Policy, validator: Arc<dyn Validator>) -> Self {
         ClientStore {
             delegate: Arc::new(delegate),
+            policy,
+            validator,
+            locks: Arc::new((0..LOCK_SHARDS).map(|_| Mutex::new(())).collect()),
+            touched: Arc::new(Mutex::new(FnvHashSet::default())),
+            index: Arc::new(RwLock::new(None)),
+            clock: Arc::new(SystemClock),
+            commit_every: None,
+            applied: Arc::new(AtomicUsize::new(0)),
+            codec: ValueCodec::default(),
+        }
+    }
+
+    /// Returns the policies that transactions are executed with.
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    /// Returns the validator that transactions are checked with before they are executed.
+    pub fn validator(&self) -> &dyn Validator {
+        self.validator.as_ref()
+    }
+
+    /// Returns the clock that the time is read from.
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    /// Replaces the clock that the time is read from with `clock`. This should be set before the
+    /// store is cloned, as clones do not share a replaced clock.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Commits the delegate after every `n` transactions that are applied through this store, if
+    /// `commit_every` is set, rather than leaving durability to the delegate. A crash then loses at
+    /// most the transactions applied since the last commit. This should be set before the store is
+    /// cloned, as clones do not share a replaced setting.
+    pub fn set_commit_every(&mut self, commit_every: Option<usize>) {
+        self.commit_every = commit_every;
+    }
+
+    /// Encodes client states and transactions with `codec`. This must match the codec that any
+    /// existing values were written with and should be set before the store is cloned, as clones
+    /// do not share a replaced codec. The run metadata is always written with the default codec, so
+    /// that it can be read without knowing the codec.
+    pub fn set_value_codec(&mut self, codec: ValueCodec) {
+        self.codec = codec;
+    }
+
+    /// Returns the codec that client states and transactions are encoded with.
+    pub fn value_codec(&self) -> ValueCodec {
+        self.codec
+    }
+
+    /// Records that a transaction has been applied and the state of its client persisted. The
+    /// delegate is committed if this completes a run of `commit_every` transactions.
+    pub fn record_applied(&self) -> Result<(), StoreError> {
+        if let Some(n) = self.commit_every {
+            let applied = self.applied.fetch_add(1, Ordering::SeqCst) + 1;
+            if applied.is_multiple_of(n) {
+                self.delegate.commit()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Records that the client with `client_id` has been loaded to execute transactions through
+    /// this store.
+    pub fn mark_touched(&self, client_id: u16) {
+        self.touched
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(client_id);
+    }
+
+    /// Returns whether the client with `client_id` has been loaded to execute transactions through
+    /// this store. A client whose state was only seeded, or persisted by a previous run, has not.
+    pub fn is_touched(&self, client_id: u16) -> bool {
+        self.touched
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains(&client_id)
+    }
+
+    /// Indexes the clients that have a state in the delegate, replacing any previous index. Once
+    /// indexed, looking up the state of a client that is not in the index returns `None` without
+    /// reading the delegate. Every state that is then put through this store, or a clone of it,
+    /// is added to the index, so a state that is written to the delegate by any other means may
+    /// not be found.
+    pub fn index_clients(&self) -> Result<(), StoreError> {
+        let keys = self.delegate.scan_keys(Keyspace::Clients)?;
+        let index = keys
+            .iter()
+            .map(|key| key_client(Keyspace::Clients, key))
+            .collect::<Result<_, _>>()?;
+
+        *self.index.write().unwrap_or_else(PoisonError::into_inner) = Some(index);
+        Ok(())
+    }
+
+    /// Returns whether the client with `client_id` may have a state in the delegate. This is
+    /// always true if the clients have not been indexed.
+    fn may_be_stored(&self, client_id: u16) -> bool {
+        match &*self.index.read().unwrap_or_else(PoisonError::into_inner) {
+            Some(index) => index.contains(&client_id),
+            None => true,
+        }
+    }
+
+    /// Adds the client with `client_id` to the index, if the clients have been indexed. This must
+    /// be called before its state is written, so that the state is never stored without being
+    /// indexed.
+    fn add_to_index(&self, client_id: u16) {
+        if !self.may_be_stored(client_id) {
+            if let Some(index) = &mut *self.index.write().unwrap_or_else(PoisonError::into_inner) {
+                index.insert(client_id);
+            }
         }
     }
 
@@ -34,10 +212,136 @@ where
     }
 }
 
+/// The client states that were read by a scan of a store.
+#[derive(Debug, Default, PartialEq)]
+pub struct ScannedStates {
+    /// Every client state that was read, ordered by client ID.
+    pub states: Vec<ClientState>,
+    /// The keys of any records that were skipped because they could not be deserialized.
+    pub skipped: Vec<Vec<u8>>,
+}
+
+/// The result of checking that the funds held by every client match the stored transactions.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Books {
+    /// The net of every stored deposit and withdrawal. A deposit that was charged back, or a
+    /// withdrawal that is disputed or was charged back, is excluded as its funds were reversed.
+    pub ledger: f64,
+    /// The total funds, available and held, across every client state.
+    pub supply: f64,
+    /// Every client whose funds do not match the net of its own stored transactions, ordered by
+    /// client ID.
+    pub unbalanced: Vec<ClientBooks>,
+}
+
+impl Books {
+    /// Returns whether the ledger and supply are equal, overall and for every client, to the
+    /// number of decimal places that amounts are represented to.
+    pub fn is_balanced(&self) -> bool {
+        self.unbalanced.is_empty() && balances(self.ledger, self.supply)
+    }
+}
+
+/// A transition of a transaction's dispute status, as recorded in the dispute log.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DisputeEvent {
+    pub tx: u32,
+    pub from: DisputeStatus,
+    pub to: DisputeStatus,
+    /// The position of this event in the transaction's log, starting from zero.
+    pub sequence: u64,
+    /// The time, read from the store's clock, that the transition was recorded at.
+    pub recorded_at: SystemTime,
+}
+
+/// The net of a single client's stored transactions and the total funds in its state.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ClientBooks {
+    pub client: u16,
+    pub ledger: f64,
+    pub supply: f64,
+}
+
+/// Returns whether `ledger` and `supply` are equal to the number of decimal places that amounts
+/// are represented to.
+fn balances(ledger: f64, supply: f64) -> bool {
+    let unit = 10f64.powi(-(DECIMAL_PLACES as i32));
+    (supply - ledger).abs() < unit / 2.0
+}
+
+/// Totals the stored `transactions` and the client states under `state_keys` of a set of clients,
+/// which are decoded with `codec`. The client states are read from `engine` in a single batch.
+fn client_books<D>(
+    engine: &D,
+    codec: ValueCodec,
+    transactions: Vec<KeyValue>,
+    state_keys: Vec<Vec<u8>>,
+) -> Result<BTreeMap<u16, ClientBooks>, StoreError>
+where
+    D: StoreEngine,
+{
+    let mut books = BTreeMap::new();
+
+    for (key, value) in transactions {
+        let transaction = codec.deserialize::<Transaction>(Keyspace::Transactions, &key, &value)?;
+        if let Transaction::Transfer(transfer) = transaction {
+            let TransferTransaction {
+                kind,
+                client,
+                amount,
+                disputed,
+                ..
+            } = transfer;
+            // Only the part of a transaction that is under dispute is charged back, or credited
+            // back for a withdrawal.
+            let undisputed = amount - transfer.amount_under_dispute();
+            let client_books = books_entry(&mut books, client);
+            match (kind, disputed) {
+                (TransferTransactionKind::Deposit, DisputeStatus::ChargedBack) => {
+                    client_books.ledger += undisputed
+                }
+                (TransferTransactionKind::Deposit, _) => client_books.ledger += amount,
+                (TransferTransactionKind::Withdrawal, DisputeStatus::NotDisputed) => {
+                    client_books.ledger -= amount
+                }
+                (TransferTransactionKind::Withdrawal, _) => client_books.ledger -= undisputed,
+            }
+        }
+    }
+    let keys = state_keys.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    let states = engine.get_batch(Keyspace::Clients, &keys)?;
+    for (key, value) in keys.into_iter().zip(states) {
+        // A state that was removed after its key was read holds no funds.
+        if let Some(value) = value {
+            let state =
+                State::from(codec.deserialize::<ClientState>(Keyspace::Clients, key, &value)?);
+            books_entry(&mut books, state.client).supply += state.total;
+        }
+    }
+
+    Ok(books)
+}
+
+/// Returns the totals of `client` in `books`, inserting empty totals if it has none.
+fn books_entry(books: &mut BTreeMap<u16, ClientBooks>, client: u16) -> &mut ClientBooks {
+    books.entry(client).or_insert(ClientBooks {
+        client,
+        ..Default::default()
+    })
+}
+
 /// Keyspaces (column families in RocksDB).
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Keyspace {
     Clients,
     Transactions,
+    /// Describes how the store was produced.
+    RunMeta,
+    /// An append-only log of every transition of a transaction's dispute status.
+    DisputeLog,
+    /// A tombstone for every transaction that has been removed by compaction, so that its ID is
+    /// not reused.
+    Compacted,
 }
 
 impl Keyspace {
@@ -45,37 +349,64 @@ impl Keyspace {
         match self {
             Keyspace::Clients => CLIENTS_KS,
             Keyspace::Transactions => TRANSACTIONS_KS,
+            Keyspace::RunMeta => RUN_META_KS,
+            Keyspace::DisputeLog => DISPUTE_LOG_KS,
+            Keyspace::Compacted => COMPACTED_KS,
         }
     }
 }
 
+/// Serializes a key, or the run metadata, with the default codec.
 fn serialize<S>(obj: &S) -> Result<Vec<u8>, StoreError>
 where
     S: Serialize,
 {
-    bincode::serialize(obj).map_err(|e| StoreError::Serialize(Box::new(e)))
+    ValueCodec::Plain.serialize(obj)
 }
 
-pub fn deserialize<'de, S>(obj: &'de [u8]) -> Result<S, StoreError>
-where
-    S: Deserialize<'de>,
-{
-    bincode::deserialize(obj).map_err(|e| StoreError::Deserialize(Box::new(e)))
+/// Returns the key that a transaction is stored under: the composite of its client and
+/// transaction IDs.
+fn transaction_key(client_id: u16, transaction_id: u32) -> Result<Vec<u8>, StoreError> {
+    serialize(&(client_id, transaction_id))
+}
+
+/// Returns the key that the dispute event at `sequence` in a transaction's log is stored under.
+fn dispute_event_key(
+    client_id: u16,
+    transaction_id: u32,
+    sequence: u64,
+) -> Result<Vec<u8>, StoreError> {
+    serialize(&(client_id, transaction_id, sequence))
+}
+
+/// Returns the ID of the client that a `key` in `keyspace` belongs to. Client state and
+/// transaction keys both begin with the client's ID.
+fn key_client(keyspace: Keyspace, key: &[u8]) -> Result<u16, StoreError> {
+    ValueCodec::Plain.deserialize(keyspace, key, key)
 }
 
 impl<D> ClientStore<D>
 where
     D: StoreEngine,
 {
-    /// Lookup a transaction in the store by `transaction_id`.
-    pub fn get_transaction(&self, transaction_id: u32) -> Result<Option<Transaction>, StoreError> {
-        let serialized_key = serialize(&transaction_id)?;
+    /// Lookup a transaction in the store by `client_id` and `transaction_id`. Transaction IDs are
+    /// scoped to a client, so another client's transaction with the same ID is never returned.
+    pub fn get_transaction(
+        &self,
+        client_id: u16,
+        transaction_id: u32,
+    ) -> Result<Option<Transaction>, StoreError> {
+        let serialized_key = transaction_key(client_id, transaction_id)?;
         match self
             .delegate
             .get(Keyspace::Transactions, serialized_key.as_slice())
         {
             Ok(Some(value)) => {
-                let transaction = deserialize::<Transaction>(value.as_slice())?;
+                let transaction = self.codec.deserialize::<Transaction>(
+                    Keyspace::Transactions,
+                    serialized_key.as_slice(),
+                    value.as_slice(),
+                )?;
                 Ok(Some(transaction))
             }
             Ok(None) => Ok(None),
@@ -83,10 +414,37 @@ where
         }
     }
 
+    /// Lookup the transactions with each client and transaction ID in `ids` in a single batch. The
+    /// transactions are returned in the same order as `ids`.
+    pub fn get_transactions(
+        &self,
+        ids: &[(u16, u32)],
+    ) -> Result<Vec<Option<Transaction>>, StoreError> {
+        let keys = ids
+            .iter()
+            .map(|(client_id, transaction_id)| transaction_key(*client_id, *transaction_id))
+            .collect::<Result<Vec<_>, _>>()?;
+        let keys = keys.iter().map(Vec::as_slice).collect::<Vec<_>>();
+
+        self.delegate
+            .get_batch(Keyspace::Transactions, &keys)?
+            .into_iter()
+            .zip(keys)
+            .map(|(value, key)| {
+                value
+                    .map(|value| {
+                        self.codec
+                            .deserialize::<Transaction>(Keyspace::Transactions, key, &value)
+                    })
+                    .transpose()
+            })
+            .collect()
+    }
+
     /// Insert or update `transaction`.
     pub fn put_transaction(&self, transaction: Transaction) -> Result<(), StoreError> {
-        let serialized_key = serialize(&transaction.id())?;
-        let serialized_transaction = serialize(&transaction)?;
+        let serialized_key = transaction_key(transaction.client_id(), transaction.id())?;
+        let serialized_transaction = self.codec.serialize(&transaction)?;
 
         self.delegate.put(
             Keyspace::Transactions,
@@ -95,8 +453,106 @@ where
         )
     }
 
-    /// Lookup a client's state in the store by `client_id`.
+    /// Returns whether the transaction has been executed, including if compaction has removed it.
+    pub fn transaction_exists(
+        &self,
+        client_id: u16,
+        transaction_id: u32,
+    ) -> Result<bool, StoreError> {
+        let serialized_key = transaction_key(client_id, transaction_id)?;
+        if self
+            .delegate
+            .get(Keyspace::Transactions, serialized_key.as_slice())?
+            .is_some()
+        {
+            return Ok(true);
+        }
+        match self
+            .delegate
+            .get(Keyspace::Compacted, serialized_key.as_slice())
+        {
+            Ok(tombstone) => Ok(tombstone.is_some()),
+            // A store that was created before compaction left tombstones has none.
+            Err(StoreError::KeyspaceNotFound) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Removes the transaction with `transaction_id` for the client with `client_id` from the
+    /// store. Removing a transaction that is not stored is not an error.
+    pub fn delete_transaction(
+        &self,
+        client_id: u16,
+        transaction_id: u32,
+    ) -> Result<(), StoreError> {
+        let serialized_key = transaction_key(client_id, transaction_id)?;
+        self.delegate
+            .delete(Keyspace::Transactions, serialized_key.as_slice())
+    }
+
+    /// Appends the transition of the transaction with `transaction_id`, for the client with
+    /// `client_id`, from the dispute status `from` to `to` to the dispute log. Existing events
+    /// are never rewritten.
+    pub fn append_dispute_event(
+        &self,
+        client_id: u16,
+        transaction_id: u32,
+        from: DisputeStatus,
+        to: DisputeStatus,
+    ) -> Result<(), StoreError> {
+        let sequence = self.get_dispute_log(client_id, transaction_id)?.len() as u64;
+        let event = DisputeEvent {
+            tx: transaction_id,
+            from,
+            to,
+            sequence,
+            recorded_at: self.clock.now(),
+        };
+        let serialized_key = dispute_event_key(client_id, transaction_id, sequence)?;
+        let serialized_event = self.codec.serialize(&event)?;
+
+        self.delegate.put(
+            Keyspace::DisputeLog,
+            serialized_key.as_slice(),
+            serialized_event.as_slice(),
+        )
+    }
+
+    /// Returns every recorded transition of the dispute status of the transaction with
+    /// `transaction_id`, for the client with `client_id`, in the order that they occurred.
+    /// Transaction IDs are scoped to a client, so another client's events are never returned.
+    pub fn get_dispute_log(
+        &self,
+        client_id: u16,
+        transaction_id: u32,
+    ) -> Result<Vec<DisputeEvent>, StoreError> {
+        let mut events = Vec::new();
+
+        // Events are keyed by their sequence number, so they are read in order until the first
+        // that has not been written.
+        loop {
+            let sequence = events.len() as u64;
+            let serialized_key = dispute_event_key(client_id, transaction_id, sequence)?;
+            match self
+                .delegate
+                .get(Keyspace::DisputeLog, serialized_key.as_slice())?
+            {
+                Some(value) => events.push(self.codec.deserialize::<DisputeEvent>(
+                    Keyspace::DisputeLog,
+                    serialized_key.as_slice(),
+                    value.as_slice(),
+                )?),
+                None => return Ok(events),
+            }
+        }
+    }
+
+    /// Lookup a client's state in the store by `client_id`. If the clients have been indexed, then
+    /// the delegate is only read if the client is in the index.
     pub fn get_client_state(&self, client_id: u16) -> Result<Option<ClientState>, StoreError> {
+        if !self.may_be_stored(client_id) {
+            return Ok(None);
+        }
         let serialized_key = serialize(&client_id)?;
 
         match self
@@ -104,7 +560,11 @@ where
             .get(Keyspace::Clients, serialized_key.as_slice())
         {
             Ok(Some(value)) => {
-                let state = deserialize::<ClientState>(value.as_slice())?;
+                let state = self.codec.deserialize::<ClientState>(
+                    Keyspace::Clients,
+                    serialized_key.as_slice(),
+                    value.as_slice(),
+                )?;
                 Ok(Some(state))
             }
             Ok(None) => Ok(None),
@@ -115,12 +575,371 @@ where
     /// Insert or update `state`.
     pub fn put_client_state(&self, state: &ClientState) -> Result<(), StoreError> {
         let serialized_key = serialize(&state.id())?;
-        let serialized_client = serialize(&state)?;
+        let serialized_client = self.codec.serialize(&state)?;
 
+        self.add_to_index(state.id());
         self.delegate.put(
             Keyspace::Clients,
             serialized_key.as_slice(),
             serialized_client.as_slice(),
         )
     }
+
+    /// Inserts or updates `transaction` and `state` in a single batch, so that neither is persisted
+    /// without the other.
+    pub fn put_transaction_with_state(
+        &self,
+        transaction: Transaction,
+        state: &ClientState,
+    ) -> Result<(), StoreError> {
+        let transaction_key = transaction_key(transaction.client_id(), transaction.id())?;
+        let serialized_transaction = self.codec.serialize(&transaction)?;
+        let client_key = serialize(&state.id())?;
+        let serialized_client = self.codec.serialize(&state)?;
+
+        self.add_to_index(state.id());
+        self.delegate.write_batch(&[
+            BatchWrite::Put(
+                Keyspace::Transactions,
+                transaction_key.as_slice(),
+                serialized_transaction.as_slice(),
+            ),
+            BatchWrite::Put(
+                Keyspace::Clients,
+                client_key.as_slice(),
+                serialized_client.as_slice(),
+            ),
+        ])
+    }
+
+    /// Deletes the transaction with `tx_id` of `state`'s client and updates `state` in a single
+    /// batch, so that neither is persisted without the other.
+    pub fn delete_transaction_with_state(
+        &self,
+        tx_id: u32,
+        state: &ClientState,
+    ) -> Result<(), StoreError> {
+        let transaction_key = transaction_key(state.id(), tx_id)?;
+        let client_key = serialize(&state.id())?;
+        let serialized_client = self.codec.serialize(&state)?;
+
+        self.add_to_index(state.id());
+        self.delegate.write_batch(&[
+            BatchWrite::Delete(Keyspace::Transactions, transaction_key.as_slice()),
+            BatchWrite::Put(
+                Keyspace::Clients,
+                client_key.as_slice(),
+                serialized_client.as_slice(),
+            ),
+        ])
+    }
+
+    /// Attaches `metadata` and `limits` to the client with `client_id`. If the client has not
+    /// previously been persisted then a new client state is created. Otherwise, its balances and
+    /// frozen status are left untouched, so that seeding metadata can never unfreeze an account.
+    pub fn put_client_metadata(
+        &self,
+        client_id: u16,
+        metadata: ClientMetadata,
+        limits: ClientLimits,
+    ) -> Result<(), StoreError> {
+        let mut state = self
+            .get_client_state(client_id)?
+            .unwrap_or_else(|| ClientState::new(client_id));
+        state.set_metadata(metadata);
+        state.set_limits(limits);
+        self.put_client_state(&state)
+    }
+
+    /// Writes `meta` to the store, replacing the metadata of any previous run.
+    pub fn put_run_meta(&self, meta: &RunMeta) -> Result<(), StoreError> {
+        let value = serialize(meta)?;
+        self.delegate.put(Keyspace::RunMeta, RUN_META_KEY, &value)
+    }
+
+    /// Returns the metadata of the run that produced the store, if any.
+    pub fn get_run_meta(&self) -> Result<Option<RunMeta>, StoreError> {
+        match self.delegate.get(Keyspace::RunMeta, RUN_META_KEY)? {
+            Some(value) => ValueCodec::Plain
+                .deserialize(Keyspace::RunMeta, RUN_META_KEY, value.as_slice())
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Removes every stored transaction that can never be referenced by a dispute, resolve or
+    /// chargeback executed using `policy`. Returns the number of transactions that were removed.
+    /// A tombstone is kept under each removed transaction's key, so that a later transaction with
+    /// the same ID is still rejected as a duplicate.
+    ///
+    /// A transfer that is under dispute is always preserved, so that it can be resolved or charged
+    /// back. Otherwise, withdrawals cannot be disputed by default and transfers that have been
+    /// charged back have reached a terminal state. If `policy` has a dispute window, then a
+    /// transfer that is not under dispute and was executed longer than the window before the
+    /// store's clock reads now can no longer be disputed either. Any other transfer, including one
+    /// whose dispute was resolved, may still be disputed and so it is preserved.
+    pub fn compact(&self, policy: &Policy) -> Result<usize, StoreError> {
+        let mut removed = 0;
+        let now = self.clock.now();
+
+        for entry in self.delegate.scan(Keyspace::Transactions) {
+            let (key, value) = entry?;
+            let transaction = self.codec.deserialize::<Transaction>(
+                Keyspace::Transactions,
+                key.as_slice(),
+                value.as_slice(),
+            )?;
+            if is_compactable(&transaction, policy, now) {
+                self.delegate.write_batch(&[
+                    BatchWrite::Delete(Keyspace::Transactions, key.as_slice()),
+                    BatchWrite::Put(Keyspace::Compacted, key.as_slice(), TOMBSTONE),
+                ])?;
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Returns the client, ID and amount under dispute of every stored transaction that is
+    /// currently disputed, ordered by client and then by transaction ID.
+    ///
+    /// This scans every stored transaction, so its cost grows with the size of the store rather
+    /// than with the number of open disputes. A secondary index of the disputed transactions,
+    /// maintained as disputes are opened and closed, would avoid the full scan.
+    pub fn disputed_transactions(&self) -> Result<Vec<(u16, u32, f64)>, StoreError> {
+        let mut disputed = Vec::new();
+
+        for entry in self.delegate.scan(Keyspace::Transactions) {
+            let (key, value) = entry?;
+            let transaction = self.codec.deserialize::<Transaction>(
+                Keyspace::Transactions,
+                key.as_slice(),
+                value.as_slice(),
+            )?;
+            if let Transaction::Transfer(
+                transfer @ TransferTransaction {
+                    disputed: DisputeStatus::Disputed,
+                    ..
+                },
+            ) = transaction
+            {
+                disputed.push((
+                    transfer.client,
+                    transfer.tx,
+                    transfer.amount_under_dispute(),
+                ));
+            }
+        }
+
+        disputed.sort_by_key(|(client, tx, _)| (*client, *tx));
+        Ok(disputed)
+    }
+
+    /// Returns the client and transaction ID of every stored transaction, ordered by client and
+    /// then by transaction ID. Only the keys of the transactions are read.
+    pub fn transaction_ids(&self) -> Result<Vec<(u16, u32)>, StoreError> {
+        let keys = self.delegate.scan_keys(Keyspace::Transactions)?;
+        let mut ids = keys
+            .iter()
+            .map(|key| ValueCodec::Plain.deserialize(Keyspace::Transactions, key, key))
+            .collect::<Result<Vec<(u16, u32)>, _>>()?;
+
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    /// Returns every stored transaction of the client with `client_id`, ordered by transaction
+    /// ID, along with its dispute status.
+    ///
+    /// This scans every stored transaction, as they are not held in ID order. Only the values of
+    /// the transactions whose key has `client_id` as its prefix are deserialized.
+    pub fn transactions_for_client(&self, client_id: u16) -> Result<Vec<Transaction>, StoreError> {
+        let mut transactions = Vec::new();
+
+        for entry in self.delegate.scan(Keyspace::Transactions) {
+            let (key, value) = entry?;
+            if key_client(Keyspace::Transactions, &key)? != client_id {
+                continue;
+            }
+            let transaction = self.codec.deserialize::<Transaction>(
+                Keyspace::Transactions,
+                key.as_slice(),
+                value.as_slice(),
+            )?;
+            transactions.push(transaction);
+        }
+
+        transactions.sort_by_key(Transaction::id);
+        Ok(transactions)
+    }
+
+    /// Computes the net of every stored deposit and withdrawal and the total funds across every
+    /// client state, overall and for each client, so that they may be checked to balance.
+    ///
+    /// Every executed deposit and withdrawal is stored, so the two are equal unless a client
+    /// state or transaction has been lost or altered. A store that has been compacted no longer
+    /// holds every withdrawal and so will not balance.
+    ///
+    /// The stored transactions and the keys of the client states are read once and partitioned by
+    /// client ID across `workers` threads, which each read the states of a disjoint set of clients
+    /// in a single batch, then deserialize and total them. The totals are summed in client
+    /// order, so the result does not depend on the number of workers.
+    pub fn check_books(&self, workers: usize) -> Result<Books, StoreError> {
+        let workers = workers.max(1);
+        let mut partitions = vec![(Vec::new(), Vec::new()); workers];
+
+        for entry in self.delegate.scan(Keyspace::Transactions) {
+            let (key, value) = entry?;
+            let client = key_client(Keyspace::Transactions, &key)?;
+            partitions[client as usize % workers].0.push((key, value));
+        }
+        for key in self.delegate.scan_keys(Keyspace::Clients)? {
+            let client = key_client(Keyspace::Clients, &key)?;
+            partitions[client as usize % workers].1.push(key);
+        }
+
+        let codec = self.codec;
+        let engine = &*self.delegate;
+        let totals = thread::scope(|scope| {
+            let handles = partitions
+                .into_iter()
+                .map(|(transactions, state_keys)| {
+                    scope.spawn(move || client_books(engine, codec, transactions, state_keys))
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+
+        let mut books = Books::default();
+        let totals = totals.into_iter().flatten().collect::<BTreeMap<_, _>>();
+        for client_books in totals.into_values() {
+            books.ledger += client_books.ledger;
+            books.supply += client_books.supply;
+            if !balances(client_books.ledger, client_books.supply) {
+                books.unbalanced.push(client_books);
+            }
+        }
+
+        Ok(books)
+    }
+
+    /// Deserializes every client state record in the `Clients` keyspace and returns their
+    /// reporting views, ordered by client ID. A store without a `Clients` keyspace has no clients.
+    pub fn collect_states(&self) -> Result<Vec<State>, StoreError> {
+        let states = self.collect_client_states()?;
+        Ok(states.into_iter().map(State::from).collect())
+    }
+
+    /// Deserializes every client state record in the `Clients` keyspace, ordered by client ID. A
+    /// store without a `Clients` keyspace has no clients.
+    pub fn collect_client_states(&self) -> Result<Vec<ClientState>, StoreError> {
+        let ScannedStates { states, .. } = self.scan_client_states(false)?;
+        Ok(states)
+    }
+
+    /// Deserializes every client state record in the `Clients` keyspace, ordered by client ID. If
+    /// `skip_corrupt` is set, then a record that is invalid or truncated is skipped and its key is
+    /// reported, rather than the scan failing.
+    pub fn scan_client_states(&self, skip_corrupt: bool) -> Result<ScannedStates, StoreError> {
+        let mut scanned = ScannedStates::default();
+        scanned.states = self
+            .client_states(skip_corrupt, &mut scanned.skipped)
+            .collect::<Result<_, _>>()?;
+
+        scanned.states.sort_by_key(|state| state.id);
+        scanned.skipped.sort();
+        Ok(scanned)
+    }
+
+    /// Returns an iterator that deserializes each client state record in the `Clients` keyspace
+    /// as it is scanned, in no particular order. If `skip_corrupt` is set, then a record that is
+    /// invalid or truncated is skipped and its key is pushed to `skipped`, rather than an error
+    /// being yielded. A store without a `Clients` keyspace has no clients.
+    pub fn client_states<'a>(
+        &'a self,
+        skip_corrupt: bool,
+        skipped: &'a mut Vec<Vec<u8>>,
+    ) -> impl Iterator<Item = Result<ClientState, StoreError>> + 'a {
+        self.delegate
+            .scan(Keyspace::Clients)
+            .filter_map(move |entry| {
+                let (key, value) = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => return Some(Err(e)),
+                };
+                match self
+                    .codec
+                    .deserialize::<ClientState>(Keyspace::Clients, &key, &value)
+                {
+                    Ok(state) => Some(Ok(state)),
+                    Err(
+                        error @ (StoreError::Deserialize(_) | StoreError::TruncatedRecord { .. }),
+                    ) if skip_corrupt => {
+                        event!(Level::WARN, SKIPPED_RECORD, ?key, ?error);
+                        skipped.push(key);
+                        None
+                    }
+                    Err(e) => Some(Err(e)),
+                }
+            })
+    }
+
+    /// Executes `transaction` against the state of the client that it is for. Creating a new
+    /// client state if the client has not previously been persisted. If the operation is
+    /// successful, then the updated state is persisted and returned.
+    ///
+    /// Concurrent calls for the same client are serialized, while calls for clients in different
+    /// lock shards proceed in parallel.
+    pub fn apply_transaction(&self, transaction: Transaction) -> Result<State, ClientError> {
+        let client_id = transaction.client_id();
+        // The lock guards no data, so a panic while it was held cannot leave it inconsistent.
+        let _guard = self.locks[client_id as usize % self.locks.len()]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        self.mark_touched(client_id);
+
+        let mut state = self
+            .get_client_state(client_id)?
+            .unwrap_or_else(|| ClientState::new(client_id));
+
+        state.execute_transaction(transaction, self)?;
+        Ok(State::from(state))
+    }
+}
+
+/// Returns whether `transaction` can never be referenced again when transactions are executed
+/// using `policy` at `now` or later. No policy currently allows a charged back transfer to be
+/// disputed.
+fn is_compactable(transaction: &Transaction, policy: &Policy, now: SystemTime) -> bool {
+    match transaction {
+        // A transfer under dispute may still be resolved or charged back, however old it is and
+        // whatever policy it was disputed under.
+        Transaction::Transfer(TransferTransaction {
+            disputed: DisputeStatus::Disputed,
+            ..
+        }) => false,
+        Transaction::Transfer(TransferTransaction {
+            kind: TransferTransactionKind::Withdrawal,
+            ..
+        }) if policy.withdrawal_dispute == WithdrawalDisputePolicy::Reject => true,
+        Transaction::Transfer(TransferTransaction {
+            disputed: DisputeStatus::ChargedBack,
+            ..
+        }) => true,
+        // A transfer that is older than the dispute window can no longer be disputed.
+        Transaction::Transfer(TransferTransaction { executed_at, .. }) => {
+            match (policy.dispute_window, executed_at) {
+                (Some(window), Some(executed_at)) => executed_at
+                    .checked_add(window)
+                    .is_some_and(|closes_at| closes_at < now),
+                _ => false,
+            }
+        }
+        // Only transfers are stored but any other transaction cannot be disputed.
+        Transaction::Disputed(_) | Transaction::Internal(_) => true,
+    }
 }
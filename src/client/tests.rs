@@ -1,11 +1,24 @@
-use crate::client::store::{ClientStore, TRANSACTIONS_KS};
+use crate::client::balance::{Account, UpdateError};
+use crate::client::harness::{assert_money_eq, ClientTestHarness};
+use crate::client::store::{ClientStore, DisputeEvent, TRANSACTIONS_KS};
 use crate::client::{
-    ClientError, ClientState, ALREADY_DISPUTED, DISPUTE_MISMATCH, DISPUTE_WITHDRAWAL,
+    ChargedBackResolvePolicy, ClientError, ClientLimits, ClientMetadata, ClientState, Keyspace,
+    Policy, State, Validator, ValueCodec, WithdrawalDisputePolicy, ZeroDisputePolicy,
+    ALREADY_CHARGED_BACK, ALREADY_DISPUTED, DISPUTE_EXCEEDS_AMOUNT, DISPUTE_MISMATCH,
+    DISPUTE_WITHDRAWAL, NOT_DISPUTED, RESOLVE_CHARGED_BACK, SECONDS_PER_DAY, ZERO_DISPUTE_AMOUNT,
 };
-use crate::db::MemStore;
-use crate::transaction::Transaction;
-use bincode::serialize;
+use crate::clock::{Clock, MockClock};
+use crate::db::{MemStore, StoreEngine, StoreError};
+use crate::scale::DECIMAL_PLACES;
+use crate::transaction::{
+    DisputeStatus, Transaction, TransferTransaction, TransferTransactionKind,
+};
+use bincode::{deserialize, serialize};
 use fnv::FnvHashMap;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
 
 fn store() -> ClientStore<MemStore> {
     ClientStore::new(MemStore::default())
@@ -17,7 +30,7 @@ fn deposit() {
     let result = client.execute_transaction(Transaction::deposit(1, 1, 100.0), &store());
 
     assert!(result.is_ok());
-    assert_eq!(client.balance.get_available(), 100.0);
+    assert_money_eq(client.balance.get_available(), 100.0);
 }
 
 #[test]
@@ -27,6 +40,57 @@ fn invalid_client() {
     assert_eq!(result, Err(ClientError::MismatchedClientId));
 }
 
+#[test]
+fn cross_client_dispute() {
+    let store = store();
+    let mut owner = ClientState::new(1);
+    let mut other = ClientState::new(2);
+    assert!(owner
+        .execute_transaction(Transaction::deposit(1, 1, 10.0), &store)
+        .is_ok());
+    assert!(other
+        .execute_transaction(Transaction::deposit(2, 2, 5.0), &store)
+        .is_ok());
+
+    // Transaction IDs are scoped to a client, so another client's transaction is not found.
+    let result = other.execute_transaction(Transaction::dispute(2, 1), &store);
+    assert_eq!(result, Err(ClientError::TransactionNotFound));
+
+    // A record of the owner's transaction that is stored under the other client is rejected,
+    // rather than disputed on the owner's behalf.
+    let value = store
+        .inner()
+        .get(Keyspace::Transactions, &serialize(&(1u16, 1u32)).unwrap())
+        .unwrap()
+        .unwrap();
+    store
+        .inner()
+        .put(
+            Keyspace::Transactions,
+            &serialize(&(2u16, 1u32)).unwrap(),
+            &value,
+        )
+        .unwrap();
+    for transaction in [
+        Transaction::dispute(2, 1),
+        Transaction::resolve(2, 1),
+        Transaction::chargeback(2, 1),
+    ] {
+        let result = other.execute_transaction(transaction, &store);
+        assert_eq!(result, Err(ClientError::ClientMismatchOnDispute));
+    }
+    assert_money_eq(other.balance.get_frozen(), 0.0);
+    assert_money_eq(other.balance.get_available(), 5.0);
+    let original = store.get_transaction(1, 1).unwrap().unwrap();
+    assert!(matches!(
+        original,
+        Transaction::Transfer(TransferTransaction {
+            disputed: DisputeStatus::NotDisputed,
+            ..
+        })
+    ));
+}
+
 #[test]
 fn negative_deposit() {
     let mut client = ClientState::new(1);
@@ -40,11 +104,11 @@ fn withdraw() {
     let deposit_result = client.execute_transaction(Transaction::deposit(1, 1, 100.0), &store());
 
     assert!(deposit_result.is_ok());
-    assert_eq!(client.balance.get_available(), 100.0);
+    assert_money_eq(client.balance.get_available(), 100.0);
 
     let withdraw_result = client.execute_transaction(Transaction::withdrawal(1, 1, 50.0), &store());
     assert!(withdraw_result.is_ok());
-    assert_eq!(client.balance.get_available(), 50.0);
+    assert_money_eq(client.balance.get_available(), 50.0);
 }
 
 #[test]
@@ -61,14 +125,36 @@ fn insufficient_funds() {
     assert_eq!(result, Err(ClientError::InsufficientFunds));
 }
 
+#[test]
+fn withdraw_exact_balance() {
+    let mut client = ClientState::new(1);
+    let store = store();
+    let result = client.execute_transaction(Transaction::deposit(1, 1, 100.0), &store);
+    assert!(result.is_ok());
+    let result = client.execute_transaction(Transaction::withdrawal(1, 2, 100.0), &store);
+    assert!(result.is_ok());
+
+    let available = client.balance.get_available();
+    assert_eq!(available, 0.0);
+    assert!(available.is_sign_positive());
+    assert!(client.balance.get_total().is_sign_positive());
+
+    // As floats, `0.3 - 0.1 - 0.2` is slightly below zero.
+    let mut account = Account::default();
+    account.deposit(0.3).unwrap();
+    account.withdraw(0.1).unwrap();
+    assert_eq!(account.withdraw(0.2), Ok(()));
+    assert!(account.get_available().is_sign_positive());
+}
+
 #[test]
 fn insufficient_funds_after_deposit() {
     let mut client = ClientState::new(1);
 
     let deposit_result = client.execute_transaction(Transaction::deposit(1, 1, 50.0), &store());
     assert!(deposit_result.is_ok());
-    assert_eq!(client.balance.get_available(), 50.0);
-    assert_eq!(client.balance.get_frozen(), 0.0);
+    assert_money_eq(client.balance.get_available(), 50.0);
+    assert_money_eq(client.balance.get_frozen(), 0.0);
 
     let result = client.execute_transaction(Transaction::withdrawal(1, 1, 100.0), &store());
     assert_eq!(result, Err(ClientError::InsufficientFunds));
@@ -88,7 +174,10 @@ fn load_store(txs: Vec<Transaction>) -> ClientStore<MemStore> {
     let mut inner = FnvHashMap::default();
 
     for tx in txs {
-        inner.insert(serialize(&tx.id()).unwrap(), serialize(&tx).unwrap());
+        inner.insert(
+            serialize(&(tx.client_id(), tx.id())).unwrap(),
+            serialize(&tx).unwrap(),
+        );
     }
     keyspaces.insert(TRANSACTIONS_KS.to_string(), inner);
 
@@ -97,26 +186,19 @@ fn load_store(txs: Vec<Transaction>) -> ClientStore<MemStore> {
 
 #[test]
 fn dispute_single() {
-    let store = load_store(vec![Transaction::deposit(1, 1, 1.0)]);
-    let mut client = ClientState::new(1);
-
-    let deposit_result = client.execute_transaction(Transaction::deposit(1, 1, 1.0), &store);
-    assert!(deposit_result.is_ok());
-    assert_eq!(client.balance.get_available(), 1.0);
-
-    let dispute_result = client.execute_transaction(Transaction::dispute(1, 1), &store);
-    assert!(dispute_result.is_ok());
-    assert_eq!(client.balance.get_available(), 0.0);
-    assert_eq!(client.balance.get_frozen(), 1.0);
-
-    assert_store_client(&store, &client);
+    ClientTestHarness::new(1)
+        .deposit(1, 1.0)
+        .assert_available(1.0)
+        .dispute(1)
+        .assert_available(0.0)
+        .assert_held(1.0)
+        .assert_stored();
 }
 
 #[test]
 fn dispute_multiple() {
     let mut total = 0.0;
     let transactions = (1..=5)
-        .into_iter()
         .map(|i| {
             let amount = i as f64 * 10.0;
             total += amount;
@@ -124,7 +206,7 @@ fn dispute_multiple() {
         })
         .collect::<Vec<_>>();
 
-    let store = load_store(transactions.clone());
+    let store = store();
     let mut client = ClientState::new(1);
 
     for tx in transactions {
@@ -132,19 +214,19 @@ fn dispute_multiple() {
         assert!(deposit_result.is_ok());
     }
 
-    assert_eq!(client.balance.get_available(), total);
+    assert_money_eq(client.balance.get_available(), total);
 
     let dispute_result = client.execute_transaction(Transaction::dispute(1, 3), &store);
     assert!(dispute_result.is_ok());
 
-    assert_eq!(client.balance.get_available(), total - 30.0);
-    assert_eq!(client.balance.get_frozen(), 30.0);
+    assert_money_eq(client.balance.get_available(), total - 30.0);
+    assert_money_eq(client.balance.get_frozen(), 30.0);
 
     let dispute_result = client.execute_transaction(Transaction::dispute(1, 5), &store);
     assert!(dispute_result.is_ok());
 
-    assert_eq!(client.balance.get_available(), total - 80.0);
-    assert_eq!(client.balance.get_frozen(), 80.0);
+    assert_money_eq(client.balance.get_available(), total - 80.0);
+    assert_money_eq(client.balance.get_frozen(), 80.0);
 
     assert_store_client(&store, &client);
 }
@@ -177,15 +259,15 @@ fn dispute_withdrawal() {
         Transaction::withdrawal(1, 2, 5.0),
     ];
 
-    let store = load_store(transactions.clone());
+    let store = store();
 
     for tx in transactions {
         let exec_result = client.execute_transaction(tx, &store);
         assert!(exec_result.is_ok());
     }
 
-    assert_eq!(client.balance.get_available(), 5.0);
-    assert_eq!(client.balance.get_frozen(), 0.0);
+    assert_money_eq(client.balance.get_available(), 5.0);
+    assert_money_eq(client.balance.get_frozen(), 0.0);
 
     let dispute_result = client.execute_transaction(Transaction::dispute(1, 2), &store);
     assert_eq!(
@@ -203,7 +285,7 @@ fn dispute_then_deposit() {
         Transaction::withdrawal(1, 2, 5.0),
     ];
 
-    let store = load_store(transactions.clone());
+    let store = store();
     let mut client = ClientState::new(1);
 
     for tx in transactions {
@@ -211,25 +293,25 @@ fn dispute_then_deposit() {
         assert!(exec_result.is_ok());
     }
 
-    assert_eq!(client.balance.get_available(), 5.0);
-    assert_eq!(client.balance.get_frozen(), 0.0);
+    assert_money_eq(client.balance.get_available(), 5.0);
+    assert_money_eq(client.balance.get_frozen(), 0.0);
 
     let dispute_result = client.execute_transaction(Transaction::dispute(1, 1), &store);
     assert!(dispute_result.is_ok());
     assert_disputed(1, &store);
-    assert_eq!(client.balance.get_available(), -5.0);
-    assert_eq!(client.balance.get_frozen(), 10.0);
+    assert_money_eq(client.balance.get_available(), -5.0);
+    assert_money_eq(client.balance.get_frozen(), 10.0);
 
-    let deposit_result = client.execute_transaction(Transaction::deposit(1, 2, 10.0), &store);
+    let deposit_result = client.execute_transaction(Transaction::deposit(1, 3, 10.0), &store);
     assert!(deposit_result.is_ok());
-    assert_eq!(client.balance.get_available(), 5.0);
-    assert_eq!(client.balance.get_frozen(), 10.0);
+    assert_money_eq(client.balance.get_available(), 5.0);
+    assert_money_eq(client.balance.get_frozen(), 10.0);
 
     assert_store_client(&store, &client);
 }
 
 fn assert_disputed(tx_id: u32, store: &ClientStore<MemStore>) {
-    match store.get_transaction(tx_id) {
+    match store.get_transaction(1, tx_id) {
         Ok(Some(tx)) => match tx {
             Transaction::Transfer(e) if e.is_disputed() => {}
             t => panic!("Expected a disputed deposit transaction. Found `{:?}`", t),
@@ -244,7 +326,7 @@ fn assert_disputed(tx_id: u32, store: &ClientStore<MemStore>) {
 }
 
 fn assert_not_disputed(tx_id: u32, store: &ClientStore<MemStore>) {
-    match store.get_transaction(tx_id) {
+    match store.get_transaction(1, tx_id) {
         Ok(Some(tx)) => match tx {
             Transaction::Transfer(e) if !e.is_disputed() => {}
             t => panic!(
@@ -261,21 +343,6 @@ fn assert_not_disputed(tx_id: u32, store: &ClientStore<MemStore>) {
     }
 }
 
-fn assert_resolved(tx_id: u32, store: &ClientStore<MemStore>) {
-    match store.get_transaction(tx_id) {
-        Ok(Some(tx)) => match tx {
-            Transaction::Transfer(e) if e.is_resolved() => {}
-            t => panic!("Expected an resolved deposit transaction. Found `{:?}`", t),
-        },
-        Ok(None) => {
-            panic!("Missing transaction")
-        }
-        Err(e) => {
-            panic!("{:?}", e)
-        }
-    }
-}
-
 #[test]
 fn dispute_negative() {
     let transactions = vec![
@@ -283,7 +350,7 @@ fn dispute_negative() {
         Transaction::withdrawal(1, 2, 10.0),
     ];
 
-    let store = load_store(transactions.clone());
+    let store = store();
     let mut client = ClientState::new(1);
 
     for tx in transactions {
@@ -291,15 +358,15 @@ fn dispute_negative() {
         assert!(exec_result.is_ok());
     }
 
-    assert_eq!(client.balance.get_available(), 0.0);
-    assert_eq!(client.balance.get_frozen(), 0.0);
+    assert_money_eq(client.balance.get_available(), 0.0);
+    assert_money_eq(client.balance.get_frozen(), 0.0);
 
     let dispute_result = client.execute_transaction(Transaction::dispute(1, 1), &store);
     assert!(dispute_result.is_ok());
     assert_disputed(1, &store);
 
-    assert_eq!(client.balance.get_available(), -10.0);
-    assert_eq!(client.balance.get_frozen(), 10.0);
+    assert_money_eq(client.balance.get_available(), -10.0);
+    assert_money_eq(client.balance.get_frozen(), 10.0);
 
     let dispute_result = client.execute_transaction(Transaction::withdrawal(1, 3, 10.0), &store);
     assert_eq!(dispute_result, Err(ClientError::InsufficientFunds));
@@ -309,140 +376,1477 @@ fn dispute_negative() {
 
 #[test]
 fn resolve_dispute() {
-    let store = load_store(vec![Transaction::deposit(1, 1, 10.0)]);
+    ClientTestHarness::new(1)
+        .deposit(1, 10.0)
+        .assert_available(10.0)
+        .assert_held(0.0)
+        .dispute(1)
+        .assert_dispute_status(1, DisputeStatus::Disputed)
+        .assert_available(0.0)
+        .assert_held(10.0)
+        .resolve(1)
+        .assert_dispute_status(1, DisputeStatus::NotDisputed)
+        .assert_available(10.0)
+        .assert_held(0.0)
+        .assert_stored();
+}
+
+#[test]
+fn dispute_withdrawn_funds() {
+    ClientTestHarness::new(1)
+        .deposit(1, 10.0)
+        .withdrawal(2, 10.0)
+        .assert_available(0.0)
+        .assert_held(0.0)
+        .dispute(1)
+        .assert_dispute_status(1, DisputeStatus::Disputed)
+        .assert_available(-10.0)
+        .assert_held(10.0)
+        .deposit(3, 10.0)
+        .assert_available(0.0)
+        .assert_held(10.0)
+        .resolve(1)
+        .assert_dispute_status(1, DisputeStatus::NotDisputed)
+        .assert_available(10.0)
+        .assert_held(0.0)
+        .assert_stored();
+}
+
+#[test]
+fn chargeback() {
+    ClientTestHarness::new(1)
+        .deposit(1, 10.0)
+        .assert_available(10.0)
+        .assert_held(0.0)
+        .dispute(1)
+        .assert_dispute_status(1, DisputeStatus::Disputed)
+        .assert_available(0.0)
+        .assert_held(10.0)
+        .chargeback(1)
+        .assert_dispute_status(1, DisputeStatus::ChargedBack)
+        .assert_available(0.0)
+        .assert_held(0.0)
+        .assert_frozen(true)
+        .reject(Transaction::deposit(1, 1, 10.0), ClientError::AccountFrozen)
+        .assert_stored();
+}
+
+#[test]
+fn partial_dispute_resolve() {
+    ClientTestHarness::new(1)
+        .deposit(1, 10.0)
+        .partial_dispute(1, 4.0)
+        .assert_dispute_status(1, DisputeStatus::Disputed)
+        .assert_available(6.0)
+        .assert_held(4.0)
+        .resolve(1)
+        .assert_dispute_status(1, DisputeStatus::NotDisputed)
+        .assert_available(10.0)
+        .assert_held(0.0)
+        // Once resolved, the transaction may be disputed again in full.
+        .dispute(1)
+        .assert_available(0.0)
+        .assert_held(10.0)
+        .assert_stored();
+}
+
+#[test]
+fn partial_dispute_chargeback() {
+    ClientTestHarness::new(1)
+        .deposit(1, 10.0)
+        .partial_dispute(1, 2.5)
+        .assert_available(7.5)
+        .assert_held(2.5)
+        .chargeback(1)
+        .assert_dispute_status(1, DisputeStatus::ChargedBack)
+        .assert_available(7.5)
+        .assert_held(0.0)
+        .assert_frozen(true)
+        .assert_stored();
+}
+
+#[test]
+fn partial_dispute_withdrawal() {
+    let policy = Policy {
+        withdrawal_dispute: WithdrawalDisputePolicy::CreditToAvailable,
+        ..Default::default()
+    };
+    ClientTestHarness::with_policy(1, policy)
+        .deposit(1, 10.0)
+        .withdrawal(2, 6.0)
+        .partial_dispute(2, 1.5)
+        .assert_available(5.5)
+        .assert_held(0.0)
+        .resolve(2)
+        .assert_available(4.0)
+        .assert_stored();
+}
+
+#[test]
+fn partial_dispute_exceeds_amount() {
+    ClientTestHarness::new(1)
+        .deposit(1, 10.0)
+        .reject(
+            Transaction::partial_dispute(1, 1, 10.0001),
+            ClientError::DisputeError(DISPUTE_EXCEEDS_AMOUNT.to_string()),
+        )
+        .assert_dispute_status(1, DisputeStatus::NotDisputed)
+        .assert_available(10.0)
+        // Disputing the whole amount is the same as disputing none of it.
+        .partial_dispute(1, 10.0)
+        .assert_available(0.0)
+        .assert_held(10.0)
+        .assert_stored();
+}
+
+#[test]
+fn zero_dispute_rejected() {
+    ClientTestHarness::new(1)
+        .deposit(1, 10.0)
+        .deposit(2, 0.0)
+        .reject(
+            Transaction::partial_dispute(1, 1, 0.0),
+            ClientError::DisputeError(ZERO_DISPUTE_AMOUNT.to_string()),
+        )
+        .assert_dispute_status(1, DisputeStatus::NotDisputed)
+        // A dispute without a disputed amount is executed as usual, even of a deposit of zero.
+        .dispute(2)
+        .assert_dispute_status(2, DisputeStatus::Disputed)
+        .assert_available(10.0)
+        .assert_held(0.0)
+        .assert_stored();
+}
+
+#[test]
+fn zero_dispute_ignored() {
+    let policy = Policy {
+        zero_dispute: ZeroDisputePolicy::Ignore,
+        ..Default::default()
+    };
+    ClientTestHarness::with_policy(1, policy)
+        .deposit(1, 10.0)
+        .deposit(2, 0.0)
+        .partial_dispute(1, 0.0)
+        .dispute(2)
+        .assert_dispute_status(1, DisputeStatus::NotDisputed)
+        .assert_dispute_status(2, DisputeStatus::Disputed)
+        .assert_available(10.0)
+        .assert_held(0.0)
+        // The transaction was left undisputed, so a resolve of it is rejected.
+        .reject(
+            Transaction::resolve(1, 1),
+            ClientError::DisputeError(NOT_DISPUTED.to_string()),
+        )
+        .assert_stored();
+}
+
+#[test]
+fn double_dispute() {
+    ClientTestHarness::new(1)
+        .deposit(1, 10.0)
+        .assert_available(10.0)
+        .assert_held(0.0)
+        .dispute(1)
+        .assert_dispute_status(1, DisputeStatus::Disputed)
+        .assert_available(0.0)
+        .assert_held(10.0)
+        .reject(
+            Transaction::dispute(1, 1),
+            ClientError::DisputeError(ALREADY_DISPUTED.to_string()),
+        )
+        .assert_stored();
+}
+
+#[test]
+fn duplicate_transfer() {
+    ClientTestHarness::new(1)
+        .deposit(1, 10.0)
+        .withdrawal(2, 4.0)
+        .reject(
+            Transaction::deposit(1, 1, 5.0),
+            ClientError::DuplicateTransaction,
+        )
+        .reject(
+            Transaction::withdrawal(1, 2, 1.0),
+            ClientError::DuplicateTransaction,
+        )
+        .reject(
+            Transaction::withdrawal(1, 1, 1.0),
+            ClientError::DuplicateTransaction,
+        )
+        .assert_available(6.0)
+        // The original deposit is still stored and may be disputed.
+        .dispute(1)
+        .assert_available(-4.0)
+        .assert_held(10.0)
+        .resolve(1)
+        .assert_available(6.0)
+        .assert_stored();
+}
+
+#[test]
+fn duplicate_id_other_client() {
+    let store = store();
+    let mut first = ClientState::new(1);
+    let mut second = ClientState::new(2);
+
+    assert_eq!(
+        first.execute_transaction(Transaction::deposit(1, 1, 10.0), &store),
+        Ok(())
+    );
+    assert_eq!(
+        second.execute_transaction(Transaction::deposit(2, 1, 5.0), &store),
+        Ok(())
+    );
+    assert_money_eq(second.balance.get_available(), 5.0);
+}
+
+fn assert_store_client(store: &ClientStore<MemStore>, expected: &ClientState) {
+    match store.get_client_state(expected.id) {
+        Ok(Some(store_client)) => {
+            assert_eq!(expected, &store_client)
+        }
+        r => panic!("Expected a client, found: `{:?}`", r),
+    }
+}
+
+#[test]
+fn store_updates() {
+    let store = store();
     let mut client = ClientState::new(1);
 
     let exec_result = client.execute_transaction(Transaction::deposit(1, 1, 10.0), &store);
     assert!(exec_result.is_ok());
-    assert_eq!(client.balance.get_available(), 10.0);
-    assert_eq!(client.balance.get_frozen(), 0.0);
 
-    let dispute_result = client.execute_transaction(Transaction::dispute(1, 1), &store);
-    assert!(dispute_result.is_ok());
-    assert_disputed(1, &store);
-    assert_eq!(client.balance.get_available(), 0.0);
-    assert_eq!(client.balance.get_frozen(), 10.0);
+    assert_store_client(&store, &client);
+}
 
-    let dispute_result = client.execute_transaction(Transaction::resolve(1, 1), &store);
-    assert!(dispute_result.is_ok());
-    assert_not_disputed(1, &store);
+#[test]
+fn strict_freeze() {
+    let store = ClientStore::with_policy(
+        MemStore::default(),
+        Policy {
+            strict_freeze: true,
+            ..Default::default()
+        },
+    );
+    let mut client = ClientState::new(1);
+    client.frozen = true;
 
-    assert_eq!(client.balance.get_available(), 10.0);
-    assert_eq!(client.balance.get_frozen(), 0.0);
+    let result = client.execute_transaction(Transaction::deposit(1, 1, 10.0), &store);
+    assert_eq!(result, Err(ClientError::TransactionAfterFreeze));
+    assert!(ClientError::TransactionAfterFreeze.is_fatal());
+}
 
-    assert_store_client(&store, &client);
+#[test]
+fn collect_states() {
+    let store = crate::data::mem_store();
+
+    let mut client = ClientState::new(2);
+    let result = client.execute_transaction(Transaction::deposit(2, 1, 10.0), &store);
+    assert!(result.is_ok());
+    let result = client.execute_transaction(Transaction::dispute(2, 1), &store);
+    assert!(result.is_ok());
+
+    let mut client = ClientState::new(1);
+    let result = client.execute_transaction(Transaction::deposit(1, 2, 5.0), &store);
+    assert!(result.is_ok());
+    let result = client.execute_transaction(Transaction::withdrawal(1, 3, 1.5), &store);
+    assert!(result.is_ok());
+
+    assert_eq!(
+        store.collect_states(),
+        Ok(vec![
+            State {
+                client: 1,
+                available: 3.5,
+                held: 0.0,
+                total: 3.5,
+                locked: false,
+                metadata: None,
+            },
+            State {
+                client: 2,
+                available: 0.0,
+                held: 10.0,
+                total: 10.0,
+                locked: false,
+                metadata: None,
+            }
+        ])
+    );
 }
 
 #[test]
-fn dispute_withdrawn_funds() {
-    let transactions = vec![
-        Transaction::deposit(1, 1, 10.0),
-        Transaction::withdrawal(1, 2, 10.0),
-    ];
+fn apply_transaction() {
+    let store = crate::data::mem_store();
+
+    let state = store.apply_transaction(Transaction::deposit(1, 1, 10.0));
+    assert_eq!(
+        state,
+        Ok(State {
+            client: 1,
+            available: 10.0,
+            held: 0.0,
+            total: 10.0,
+            locked: false,
+            metadata: None,
+        })
+    );
 
-    let store = load_store(transactions.clone());
+    let state = store.apply_transaction(Transaction::dispute(1, 1));
+    assert_eq!(
+        state,
+        Ok(State {
+            client: 1,
+            available: 0.0,
+            held: 10.0,
+            total: 10.0,
+            locked: false,
+            metadata: None,
+        })
+    );
+    assert_disputed(1, &store);
+
+    let state = store.apply_transaction(Transaction::dispute(1, 2));
+    assert_eq!(state, Err(ClientError::TransactionNotFound));
+}
+
+#[test]
+fn apply_transaction_concurrently() {
+    const THREADS: u32 = 8;
+    const DEPOSITS: u32 = 250;
+
+    let store = crate::data::mem_store();
+    let handles = (0..THREADS)
+        .map(|thread| {
+            let store = store.clone();
+            std::thread::spawn(move || {
+                for i in 0..DEPOSITS {
+                    let tx_id = thread * DEPOSITS + i;
+                    let result = store.apply_transaction(Transaction::deposit(1, tx_id, 1.0));
+                    assert!(result.is_ok());
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    let client = store.get_client_state(1).unwrap().unwrap();
+    assert_eq!(
+        client.balance.get_available(),
+        f64::from(THREADS * DEPOSITS)
+    );
+}
+
+#[test]
+fn max_open_disputes() {
+    let store = ClientStore::with_policy(
+        MemStore::default(),
+        Policy {
+            max_open_disputes: Some(2),
+            ..Default::default()
+        },
+    );
     let mut client = ClientState::new(1);
 
-    for tx in transactions {
-        let exec_result = client.execute_transaction(tx, &store);
-        assert!(exec_result.is_ok());
+    for tx_id in 1..=3 {
+        let result = client.execute_transaction(Transaction::deposit(1, tx_id, 10.0), &store);
+        assert!(result.is_ok());
     }
 
-    assert_eq!(client.balance.get_available(), 0.0);
-    assert_eq!(client.balance.get_frozen(), 0.0);
+    let result = client.execute_transaction(Transaction::dispute(1, 1), &store);
+    assert!(result.is_ok());
+    let result = client.execute_transaction(Transaction::dispute(1, 2), &store);
+    assert!(result.is_ok());
+    assert_eq!(client.open_disputes, 2);
 
-    let dispute_result = client.execute_transaction(Transaction::dispute(1, 1), &store);
-    assert!(dispute_result.is_ok());
-    assert_disputed(1, &store);
-    assert_eq!(client.balance.get_available(), -10.0);
-    assert_eq!(client.balance.get_frozen(), 10.0);
+    let result = client.execute_transaction(Transaction::dispute(1, 3), &store);
+    assert_eq!(result, Err(ClientError::TooManyDisputes));
+    assert_not_disputed(3, &store);
+    assert_money_eq(client.balance.get_frozen(), 20.0);
 
-    let deposit_result = client.execute_transaction(Transaction::deposit(1, 3, 10.0), &store);
-    assert!(deposit_result.is_ok());
-    assert_eq!(client.balance.get_available(), 0.0);
-    assert_eq!(client.balance.get_frozen(), 10.0);
+    let result = client.execute_transaction(Transaction::resolve(1, 1), &store);
+    assert!(result.is_ok());
+    assert_eq!(client.open_disputes, 1);
 
-    let dispute_result = client.execute_transaction(Transaction::resolve(1, 1), &store);
-    assert!(dispute_result.is_ok());
-    assert_not_disputed(1, &store);
-    assert_eq!(client.balance.get_available(), 10.0);
-    assert_eq!(client.balance.get_frozen(), 0.0);
+    let result = client.execute_transaction(Transaction::dispute(1, 3), &store);
+    assert!(result.is_ok());
+    assert_disputed(3, &store);
+    assert_eq!(client.open_disputes, 2);
+
+    let result = client.execute_transaction(Transaction::chargeback(1, 2), &store);
+    assert!(result.is_ok());
+    assert_eq!(client.open_disputes, 1);
 
     assert_store_client(&store, &client);
 }
 
+/// Blocks withdrawals over a threshold.
+#[derive(Debug)]
+struct WithdrawalLimit(f64);
+
+impl Validator for WithdrawalLimit {
+    fn validate(&self, transaction: &Transaction, _: &ClientState) -> Result<(), ClientError> {
+        match transaction {
+            Transaction::Transfer(TransferTransaction {
+                kind: TransferTransactionKind::Withdrawal,
+                amount,
+                ..
+            }) if *amount > self.0 => Err(ClientError::ValidationFailed(format!(
+                "Withdrawal of {} exceeds the limit",
+                amount
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
 #[test]
-fn chargeback() {
-    let store = load_store(vec![Transaction::deposit(1, 1, 10.0)]);
+fn validator() {
+    let store = ClientStore::with_validator(
+        MemStore::default(),
+        Policy::default(),
+        Arc::new(WithdrawalLimit(50.0)),
+    );
     let mut client = ClientState::new(1);
 
-    let exec_result = client.execute_transaction(Transaction::deposit(1, 1, 10.0), &store);
-    assert!(exec_result.is_ok());
+    let result = client.execute_transaction(Transaction::deposit(1, 1, 100.0), &store);
+    assert!(result.is_ok());
+    let result = client.execute_transaction(Transaction::withdrawal(1, 2, 50.0), &store);
+    assert!(result.is_ok());
 
-    assert_eq!(client.balance.get_available(), 10.0);
-    assert_eq!(client.balance.get_frozen(), 0.0);
+    let result = client.execute_transaction(Transaction::withdrawal(1, 3, 50.5), &store);
+    assert_eq!(
+        result,
+        Err(ClientError::ValidationFailed(
+            "Withdrawal of 50.5 exceeds the limit".to_string()
+        ))
+    );
+    assert_money_eq(client.balance.get_available(), 50.0);
+    assert_eq!(store.get_transaction(1, 3), Ok(None));
+    assert_store_client(&store, &client);
+}
 
-    let dispute_result = client.execute_transaction(Transaction::dispute(1, 1), &store);
-    assert!(dispute_result.is_ok());
-    assert_disputed(1, &store);
-    assert_eq!(client.balance.get_available(), 0.0);
-    assert_eq!(client.balance.get_frozen(), 10.0);
+#[test]
+fn dispute_log() {
+    let clock = MockClock::new();
+    let mut store = crate::data::mem_store();
+    store.set_clock(Arc::new(clock.clone()));
+    let opened = clock.now();
 
-    let chargeback_result = client.execute_transaction(Transaction::chargeback(1, 1), &store);
-    assert!(chargeback_result.is_ok());
-    assert_resolved(1, &store);
-    assert_eq!(client.balance.get_available(), 0.0);
-    assert_eq!(client.balance.get_frozen(), 0.0);
-    assert!(client.frozen);
+    for transaction in [
+        Transaction::deposit(1, 1, 10.0),
+        Transaction::deposit(2, 1, 10.0),
+        Transaction::dispute(1, 1),
+    ] {
+        assert!(store.apply_transaction(transaction).is_ok());
+    }
+    // A rejected transition is not recorded.
+    assert!(store.apply_transaction(Transaction::dispute(1, 1)).is_err());
+    clock.advance(Duration::from_secs(60));
+    assert!(store
+        .apply_transaction(Transaction::chargeback(1, 1))
+        .is_ok());
 
-    let dispute_result = client.execute_transaction(Transaction::deposit(1, 1, 10.0), &store);
-    assert_eq!(dispute_result, Err(ClientError::AccountFrozen));
+    assert_eq!(
+        store.get_dispute_log(1, 1),
+        Ok(vec![
+            DisputeEvent {
+                tx: 1,
+                from: DisputeStatus::NotDisputed,
+                to: DisputeStatus::Disputed,
+                sequence: 0,
+                recorded_at: opened,
+            },
+            DisputeEvent {
+                tx: 1,
+                from: DisputeStatus::Disputed,
+                to: DisputeStatus::ChargedBack,
+                sequence: 1,
+                recorded_at: opened + Duration::from_secs(60),
+            },
+        ])
+    );
+    assert_eq!(store.get_dispute_log(2, 1), Ok(Vec::new()));
+}
 
-    assert_store_client(&store, &client);
+#[test]
+fn dispute_log_resolved() {
+    ClientTestHarness::new(1)
+        .deposit(1, 10.0)
+        .dispute(1)
+        .resolve(1)
+        .dispute(1)
+        .assert_dispute_log(
+            1,
+            &[
+                (DisputeStatus::NotDisputed, DisputeStatus::Disputed),
+                (DisputeStatus::Disputed, DisputeStatus::NotDisputed),
+                (DisputeStatus::NotDisputed, DisputeStatus::Disputed),
+            ],
+        );
 }
 
 #[test]
-fn double_dispute() {
-    let store = load_store(vec![Transaction::deposit(1, 1, 10.0)]);
+fn tx_id_shared_between_clients() {
+    let clock = MockClock::new();
+    let mut store = crate::data::mem_store();
+    store.set_clock(Arc::new(clock.clone()));
+
+    let first = store.apply_transaction(Transaction::deposit(1, 5, 10.0));
+    assert!(first.is_ok());
+    let second = store.apply_transaction(Transaction::deposit(2, 5, 20.0));
+    assert!(second.is_ok());
+
+    let first = store.apply_transaction(Transaction::dispute(1, 5)).unwrap();
+    assert_eq!((first.available, first.held), (0.0, 10.0));
+    let second = store.apply_transaction(Transaction::dispute(2, 5)).unwrap();
+    assert_eq!((second.available, second.held), (0.0, 20.0));
+
+    assert_eq!(
+        store.get_transaction(1, 5),
+        Ok(Some(Transaction::Transfer(TransferTransaction {
+            kind: TransferTransactionKind::Deposit,
+            client: 1,
+            tx: 5,
+            amount: 10.0,
+            disputed: DisputeStatus::Disputed,
+            executed_at: Some(clock.now()),
+            disputed_amount: None,
+        })))
+    );
+    assert_eq!(
+        store.apply_transaction(Transaction::dispute(3, 5)),
+        Err(ClientError::TransactionNotFound)
+    );
+}
+
+#[test]
+fn held_per_transaction() {
+    let mut account = Account::default();
+    account.deposit(20.0).unwrap();
+    account.hold(1, 10.0).unwrap();
+    account.hold(2, 5.0).unwrap();
+    account.hold(3, 2.5).unwrap();
+    assert_money_eq(account.get_frozen(), 17.5);
+    assert_money_eq(account.get_available(), 2.5);
+
+    assert_money_eq(account.release(2).unwrap(), 5.0);
+    assert_money_eq(account.get_frozen(), 12.5);
+    assert_money_eq(account.get_available(), 7.5);
+
+    // Nothing remains held for a transaction that has already been released or charged.
+    assert_money_eq(account.release(2).unwrap(), 0.0);
+    assert_money_eq(account.charge(1), 10.0);
+    assert_money_eq(account.charge(1), 0.0);
+    assert_money_eq(account.release(4).unwrap(), 0.0);
+    assert_money_eq(account.get_frozen(), 2.5);
+    assert_money_eq(account.get_available(), 7.5);
+    assert_money_eq(account.get_total(), 10.0);
+}
+
+#[test]
+fn exact_arithmetic() {
+    let mut account = Account::default();
+    account.deposit(0.1).unwrap();
+    account.deposit(0.2).unwrap();
+    assert_eq!(account.get_available(), 0.3);
+
+    account.hold(1, 0.3).unwrap();
+    assert_eq!(account.get_available(), 0.0);
+    assert_eq!(account.get_frozen(), 0.3);
+    assert_eq!(account.release(1).unwrap(), 0.3);
+    account.withdraw(0.3).unwrap();
+    assert_eq!(account.get_available(), 0.0);
+    assert_eq!(
+        account.withdraw(0.0001),
+        Err(UpdateError::InsufficientFunds)
+    );
+}
+
+#[test]
+fn overflow() {
+    // Three quarters of the largest amount that can be held, so that two of them overflow.
+    let amount = i128::MAX as f64 / 10f64.powi(DECIMAL_PLACES as i32) * 0.75;
+    let mut account = Account::default();
+    account.deposit(amount).unwrap();
+    let available = account.get_available();
+
+    assert_eq!(account.deposit(amount), Err(UpdateError::Overflow));
+    assert_eq!(account.credit_held(1, amount), Ok(()));
+    // The held funds cannot be released while the available funds would overflow.
+    assert_eq!(account.release(1), Err(UpdateError::Overflow));
+    assert_eq!(account.get_available(), available);
+
+    ClientTestHarness::new(1)
+        .deposit(1, amount)
+        .reject(
+            Transaction::deposit(1, 2, amount),
+            ClientError::BalanceOverflow,
+        )
+        .assert_available(available)
+        .assert_stored();
+}
+
+#[test]
+fn no_drift() {
+    let mut account = Account::default();
+    for _ in 0..1_000_000 {
+        account.deposit(0.1).unwrap();
+    }
+    assert_eq!(account.get_total(), 100_000.0);
+}
+
+/// An account with its funds held as floats, as they were before they were held in fixed point.
+#[derive(Serialize)]
+struct FloatAccount {
+    available: f64,
+    held: BTreeMap<u32, f64>,
+}
+
+#[test]
+fn account_serialization() {
+    let mut account = Account::default();
+    account.deposit(10.5).unwrap();
+    account.hold(3, 2.25).unwrap();
+
+    let float_account = FloatAccount {
+        available: 8.25,
+        held: vec![(3, 2.25)].into_iter().collect(),
+    };
+    let bytes = serialize(&account).unwrap();
+    assert_eq!(bytes, serialize(&float_account).unwrap());
+    assert_eq!(deserialize::<Account>(&bytes).unwrap(), account);
+}
+
+/// Executes a deposit, a withdrawal and a dispute for the client with `client_id` against `store`.
+/// Returns the resulting client state.
+fn disputed_client<D: StoreEngine>(client_id: u16, store: &ClientStore<D>) -> ClientState {
+    let mut client = ClientState::new(client_id);
+    for transaction in [
+        Transaction::deposit(client_id, 1, 10.5),
+        Transaction::withdrawal(client_id, 2, 2.25),
+        Transaction::deposit(client_id, 3, 1.0),
+        Transaction::dispute(client_id, 3),
+    ] {
+        client.execute_transaction(transaction, store).unwrap();
+    }
+    client
+}
+
+#[test]
+fn value_codec_round_trip() {
+    let store = store();
+    let client = disputed_client(7, &store);
+    let transaction = store.get_transaction(7, 3).unwrap().unwrap();
+
+    let mut sizes = Vec::new();
+    for codec in [ValueCodec::Plain, ValueCodec::Compact] {
+        let state_bytes = codec.serialize(&client).unwrap();
+        let state = codec.deserialize::<ClientState>(Keyspace::Clients, b"7", &state_bytes);
+        assert_eq!(state.unwrap(), client);
+
+        let tx_bytes = codec.serialize(&transaction).unwrap();
+        let tx = codec.deserialize::<Transaction>(Keyspace::Transactions, b"3", &tx_bytes);
+        assert_eq!(tx.unwrap(), transaction);
+
+        sizes.push((state_bytes.len(), tx_bytes.len()));
+    }
+
+    let (plain, compact) = (sizes[0], sizes[1]);
+    assert!(compact.0 < plain.0, "{:?} < {:?}", compact, plain);
+    assert!(compact.1 < plain.1, "{:?} < {:?}", compact, plain);
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn compressed_value_codec_round_trip() {
+    let store = store();
+    let client = disputed_client(7, &store);
+    let transaction = store.get_transaction(7, 3).unwrap().unwrap();
+
+    for codec in [ValueCodec::Zstd, ValueCodec::Lz4] {
+        let state_bytes = codec.serialize(&client).unwrap();
+        let state = codec.deserialize::<ClientState>(Keyspace::Clients, b"7", &state_bytes);
+        assert_eq!(state.unwrap(), client);
+
+        let tx_bytes = codec.serialize(&transaction).unwrap();
+        let tx = codec.deserialize::<Transaction>(Keyspace::Transactions, b"3", &tx_bytes);
+        assert_eq!(tx.unwrap(), transaction);
+
+        // A value written with another codec is rejected rather than misread.
+        let plain = ValueCodec::Plain.serialize(&client).unwrap();
+        assert!(matches!(
+            codec.deserialize::<ClientState>(Keyspace::Clients, b"7", &plain),
+            Err(StoreError::Deserialize(_))
+        ));
+    }
+}
+
+#[test]
+fn value_codec_truncated() {
+    let codec = ValueCodec::Compact;
+    let bytes = codec.serialize(&ClientState::new(300)).unwrap();
+    let truncated = &bytes[..bytes.len() - 1];
+
+    assert!(matches!(
+        codec.deserialize::<ClientState>(Keyspace::Clients, b"300", truncated),
+        Err(StoreError::TruncatedRecord { .. })
+    ));
+}
+
+#[test]
+fn compact_store() {
+    let mut store = store();
+    store.set_value_codec(ValueCodec::Compact);
+    let client = disputed_client(7, &store);
+    store.put_client_state(&client).unwrap();
+
+    assert_eq!(store.get_client_state(7).unwrap(), Some(client));
+    let transaction = store.get_transaction(7, 3).unwrap().unwrap();
+    assert_eq!(transaction.id(), 3);
+
+    // Values are compact, while keys are unchanged so that existing keys still match.
+    let key = serialize(&(7u16, 3u32)).unwrap();
+    let value = store.inner().get(Keyspace::Transactions, &key).unwrap();
+    assert_eq!(
+        value,
+        Some(ValueCodec::Compact.serialize(&transaction).unwrap())
+    );
+}
+
+fn dispute_window_harness(clock: &MockClock) -> ClientTestHarness {
+    let policy = Policy {
+        dispute_window: Some(Duration::from_secs(120 * SECONDS_PER_DAY)),
+        ..Default::default()
+    };
+    ClientTestHarness::with_policy(1, policy)
+        .with_clock(clock.clone())
+        .deposit(1, 10.0)
+}
+
+#[test]
+fn dispute_within_window() {
+    let clock = MockClock::new();
+    let harness = dispute_window_harness(&clock);
+    clock.advance(Duration::from_secs(120 * SECONDS_PER_DAY));
+
+    harness
+        .dispute(1)
+        .assert_available(0.0)
+        .assert_held(10.0)
+        .resolve(1)
+        .assert_available(10.0);
+}
+
+#[test]
+fn dispute_outside_window() {
+    let clock = MockClock::new();
+    let harness = dispute_window_harness(&clock);
+    clock.advance(Duration::from_secs(120 * SECONDS_PER_DAY + 1));
+
+    harness
+        .reject(
+            Transaction::dispute(1, 1),
+            ClientError::DisputeWindowExpired,
+        )
+        .assert_available(10.0)
+        .assert_held(0.0)
+        .assert_dispute_status(1, DisputeStatus::NotDisputed);
+}
+
+#[test]
+fn dispute_untimed_transaction() {
+    let clock = MockClock::new();
+    let harness = dispute_window_harness(&clock).seed(vec![Transaction::deposit(1, 2, 5.0)]);
+    clock.advance(Duration::from_secs(365 * SECONDS_PER_DAY));
+
+    harness
+        .reject(
+            Transaction::dispute(1, 1),
+            ClientError::DisputeWindowExpired,
+        )
+        .dispute(2)
+        .assert_held(5.0);
+}
+
+fn min_balance_harness() -> ClientTestHarness {
+    let limits = ClientLimits {
+        min_balance: Some(5.0),
+        ..Default::default()
+    };
+    ClientTestHarness::new(1)
+        .with_limits(limits)
+        .deposit(1, 10.0)
+}
+
+#[test]
+fn withdraw_to_min_balance() {
+    min_balance_harness()
+        .withdrawal(2, 5.0)
+        .assert_available(5.0)
+        .assert_stored();
+}
+
+#[test]
+fn withdraw_below_min_balance() {
+    min_balance_harness()
+        .reject(
+            Transaction::withdrawal(1, 2, 5.0001),
+            ClientError::BelowMinimumBalance,
+        )
+        .assert_available(10.0)
+        .reject(
+            Transaction::withdrawal(1, 3, 20.0),
+            ClientError::InsufficientFunds,
+        )
+        .withdrawal(4, 4.0)
+        .assert_available(6.0);
+}
+
+#[test]
+fn dispute_below_min_balance() {
+    min_balance_harness()
+        .withdrawal(2, 5.0)
+        .dispute(1)
+        .assert_available(-5.0)
+        .assert_held(10.0)
+        .reject(
+            Transaction::withdrawal(1, 3, 1.0),
+            ClientError::InsufficientFunds,
+        )
+        .resolve(1)
+        .assert_available(5.0);
+}
+
+fn max_held_harness() -> ClientTestHarness {
+    let policy = Policy {
+        withdrawal_dispute: WithdrawalDisputePolicy::Hold,
+        ..Default::default()
+    };
+    let limits = ClientLimits {
+        max_held: Some(10.0),
+        ..Default::default()
+    };
+    ClientTestHarness::with_policy(1, policy)
+        .with_limits(limits)
+        .deposit(1, 6.0)
+        .deposit(2, 4.0)
+        .deposit(3, 0.0001)
+        .withdrawal(4, 1.0)
+}
+
+#[test]
+fn dispute_to_max_held() {
+    max_held_harness()
+        .dispute(1)
+        .dispute(2)
+        .assert_held(10.0)
+        .assert_available(-0.9999)
+        .assert_dispute_status(2, DisputeStatus::Disputed)
+        .assert_stored();
+}
+
+#[test]
+fn dispute_beyond_max_held() {
+    max_held_harness()
+        .dispute(1)
+        .dispute(2)
+        .reject(Transaction::dispute(1, 3), ClientError::HeldLimitExceeded)
+        .reject(Transaction::dispute(1, 4), ClientError::HeldLimitExceeded)
+        .assert_held(10.0)
+        .assert_dispute_status(3, DisputeStatus::NotDisputed)
+        .assert_dispute_status(4, DisputeStatus::NotDisputed)
+        .resolve(2)
+        .dispute(3)
+        .dispute(4)
+        .assert_held(7.0001)
+        .assert_stored();
+}
+
+#[test]
+fn resolve_multiple_disputes() {
+    ClientTestHarness::new(1)
+        .deposit(1, 10.0)
+        .deposit(2, 5.0)
+        .deposit(3, 2.5)
+        .dispute(1)
+        .dispute(3)
+        .assert_available(5.0)
+        .assert_held(12.5)
+        .resolve(3)
+        .assert_available(7.5)
+        .assert_held(10.0)
+        .dispute(2)
+        .assert_held(15.0)
+        .resolve(1)
+        .resolve(2)
+        .assert_available(17.5)
+        .assert_held(0.0)
+        .dispute(3)
+        .assert_held(2.5)
+        .assert_stored();
+}
+
+#[test]
+fn compact() {
+    let store = store();
     let mut client = ClientState::new(1);
+    let transactions = vec![
+        Transaction::deposit(1, 1, 10.0),
+        Transaction::deposit(1, 2, 10.0),
+        Transaction::deposit(1, 3, 10.0),
+        Transaction::deposit(1, 4, 10.0),
+        Transaction::withdrawal(1, 5, 5.0),
+        Transaction::dispute(1, 2),
+        Transaction::dispute(1, 3),
+        Transaction::resolve(1, 3),
+        Transaction::dispute(1, 4),
+        Transaction::chargeback(1, 4),
+    ];
+    for transaction in transactions {
+        assert_eq!(client.execute_transaction(transaction, &store), Ok(()));
+    }
 
-    let exec_result = client.execute_transaction(Transaction::deposit(1, 1, 10.0), &store);
-    assert!(exec_result.is_ok());
+    assert_eq!(store.compact(&Policy::default()), Ok(2));
 
-    assert_eq!(client.balance.get_available(), 10.0);
-    assert_eq!(client.balance.get_frozen(), 0.0);
+    // Untouched, disputed and resolved deposits may all still be disputed.
+    for tx in 1..=3 {
+        assert!(store.get_transaction(1, tx).unwrap().is_some());
+    }
+    assert_eq!(store.get_transaction(1, 4), Ok(None));
+    assert_eq!(store.get_transaction(1, 5), Ok(None));
+
+    assert_eq!(store.compact(&Policy::default()), Ok(0));
+
+    // The IDs of compacted transactions cannot be reused. The client was frozen by the
+    // chargeback, so the transactions are executed against a new state.
+    for transaction in [
+        Transaction::deposit(1, 4, 1.0),
+        Transaction::withdrawal(1, 5, 1.0),
+    ] {
+        assert_eq!(
+            ClientState::new(1).execute_transaction(transaction, &store),
+            Err(ClientError::DuplicateTransaction)
+        );
+    }
+    assert_eq!(store.get_transaction(1, 4), Ok(None));
+}
 
-    let dispute_result = client.execute_transaction(Transaction::dispute(1, 1), &store);
-    assert!(dispute_result.is_ok());
-    assert_disputed(1, &store);
-    assert_eq!(client.balance.get_available(), 0.0);
-    assert_eq!(client.balance.get_frozen(), 10.0);
+#[test]
+fn disputed_transactions() {
+    let store = store();
+    for client_id in [2, 1] {
+        let mut client = ClientState::new(client_id);
+        let transactions = vec![
+            Transaction::deposit(client_id, 1, 10.0),
+            Transaction::deposit(client_id, 2, 2.5),
+            Transaction::deposit(client_id, 3, 3.0),
+            Transaction::deposit(client_id, 4, 4.0),
+            Transaction::dispute(client_id, 4),
+            Transaction::dispute(client_id, 2),
+            Transaction::dispute(client_id, 3),
+            Transaction::resolve(client_id, 3),
+        ];
+        for transaction in transactions {
+            assert_eq!(client.execute_transaction(transaction, &store), Ok(()));
+        }
+    }
+    let mut client = ClientState::new(3);
+    for transaction in [
+        Transaction::deposit(3, 1, 1.0),
+        Transaction::dispute(3, 1),
+        Transaction::chargeback(3, 1),
+    ] {
+        assert_eq!(client.execute_transaction(transaction, &store), Ok(()));
+    }
 
-    let dispute_result = client.execute_transaction(Transaction::dispute(1, 1), &store);
     assert_eq!(
-        dispute_result,
-        Err(ClientError::DisputeError(ALREADY_DISPUTED.to_string()))
+        store.disputed_transactions(),
+        Ok(vec![(1, 2, 2.5), (1, 4, 4.0), (2, 2, 2.5), (2, 4, 4.0)])
     );
+    let empty = ClientStore::new(MemStore::default());
+    assert_eq!(empty.disputed_transactions(), Ok(vec![]));
+}
 
-    assert_store_client(&store, &client);
+#[test]
+fn get_transactions() {
+    let store = store();
+    let mut client = ClientState::new(1);
+    for transaction in [
+        Transaction::deposit(1, 9, 10.0),
+        Transaction::deposit(1, 2, 5.0),
+        Transaction::dispute(1, 2),
+    ] {
+        assert_eq!(client.execute_transaction(transaction, &store), Ok(()));
+    }
+
+    let ids = [(1, 2), (1, 3), (2, 9), (1, 9)];
+    let individual = ids
+        .iter()
+        .map(|(client, tx)| store.get_transaction(*client, *tx).unwrap())
+        .collect::<Vec<_>>();
+    let batch = store.get_transactions(&ids).unwrap();
+    assert_eq!(batch, individual);
+    assert_eq!(
+        batch.iter().map(Option::is_some).collect::<Vec<_>>(),
+        vec![true, false, false, true]
+    );
+    assert_eq!(store.transaction_ids().unwrap(), vec![(1, 2), (1, 9)]);
 }
 
-fn assert_store_client(store: &ClientStore<MemStore>, expected: &ClientState) {
-    match store.get_client_state(expected.id) {
-        Ok(Some(store_client)) => {
-            assert_eq!(expected, &store_client)
+#[test]
+fn transactions_for_client() {
+    let store = store();
+    for client_id in [2, 1, 3] {
+        let mut client = ClientState::new(client_id);
+        let transactions = vec![
+            Transaction::deposit(client_id, 9, 10.0),
+            Transaction::deposit(client_id, 2, 5.0),
+            Transaction::withdrawal(client_id, 4, 1.5),
+            Transaction::dispute(client_id, 2),
+        ];
+        for transaction in transactions {
+            assert_eq!(client.execute_transaction(transaction, &store), Ok(()));
         }
-        r => panic!("Expected a client, found: `{:?}`", r),
     }
+
+    let transactions = store
+        .transactions_for_client(1)
+        .unwrap()
+        .into_iter()
+        .map(|transaction| match transaction {
+            Transaction::Transfer(TransferTransaction {
+                kind,
+                client,
+                tx,
+                amount,
+                disputed,
+                ..
+            }) => (kind, client, tx, amount, disputed),
+            transaction => panic!("Expected a transfer, found: `{:?}`", transaction),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        transactions,
+        vec![
+            (
+                TransferTransactionKind::Deposit,
+                1,
+                2,
+                5.0,
+                DisputeStatus::Disputed
+            ),
+            (
+                TransferTransactionKind::Withdrawal,
+                1,
+                4,
+                1.5,
+                DisputeStatus::NotDisputed
+            ),
+            (
+                TransferTransactionKind::Deposit,
+                1,
+                9,
+                10.0,
+                DisputeStatus::NotDisputed
+            ),
+        ]
+    );
+    assert_eq!(store.transactions_for_client(4), Ok(vec![]));
+}
+
+fn books_store(clients: u16) -> ClientStore<MemStore> {
+    let policy = Policy {
+        withdrawal_dispute: WithdrawalDisputePolicy::Hold,
+        freeze_on_chargeback: false,
+        ..Default::default()
+    };
+    let store = ClientStore::with_policy(MemStore::default(), policy);
+    for client_id in 1..=clients {
+        let mut client = ClientState::new(client_id);
+        for transaction in [
+            Transaction::deposit(client_id, 1, 10.0),
+            Transaction::deposit(client_id, 2, 5.5),
+            Transaction::deposit(client_id, 3, 2.25),
+            Transaction::withdrawal(client_id, 4, 3.0),
+            Transaction::withdrawal(client_id, 5, 1.0),
+            Transaction::withdrawal(client_id, 6, 0.5),
+            Transaction::dispute(client_id, 2),
+            Transaction::chargeback(client_id, 2),
+            Transaction::dispute(client_id, 3),
+            Transaction::dispute(client_id, 5),
+            Transaction::dispute(client_id, 6),
+            Transaction::chargeback(client_id, 6),
+        ] {
+            assert_eq!(client.execute_transaction(transaction, &store), Ok(()));
+        }
+    }
+    store
 }
 
 #[test]
-fn store_updates() {
-    let store = load_store(vec![Transaction::deposit(1, 1, 10.0)]);
+fn check_books_balanced() {
+    let books = store().check_books(1).unwrap();
+    assert!(books.is_balanced());
+
+    let books = books_store(2).check_books(1).unwrap();
+    assert_money_eq(books.ledger, 2.0 * 9.25);
+    assert_money_eq(books.supply, 2.0 * 9.25);
+    assert!(books.unbalanced.is_empty());
+    assert!(books.is_balanced());
+}
+
+#[test]
+fn check_books_partial_disputes() {
+    let policy = Policy {
+        withdrawal_dispute: WithdrawalDisputePolicy::Hold,
+        freeze_on_chargeback: false,
+        ..Default::default()
+    };
+    let store = ClientStore::with_policy(MemStore::default(), policy);
     let mut client = ClientState::new(1);
+    for transaction in [
+        Transaction::deposit(1, 1, 10.0),
+        Transaction::withdrawal(1, 2, 3.0),
+        Transaction::partial_dispute(1, 1, 4.0),
+        Transaction::chargeback(1, 1),
+        Transaction::partial_dispute(1, 2, 1.0),
+    ] {
+        assert_eq!(client.execute_transaction(transaction, &store), Ok(()));
+    }
 
-    let exec_result = client.execute_transaction(Transaction::deposit(1, 1, 10.0), &store);
-    assert!(exec_result.is_ok());
+    assert_eq!(store.disputed_transactions(), Ok(vec![(1, 2, 1.0)]));
+    let books = store.check_books(1).unwrap();
+    assert_money_eq(books.ledger, 4.0);
+    assert_money_eq(books.supply, 4.0);
+    assert!(books.is_balanced());
+}
 
-    assert_store_client(&store, &client);
+#[test]
+fn index_clients() {
+    let engine = MemStore::default();
+    let store = ClientStore::new(engine.clone());
+    let mut client = ClientState::new(1);
+    assert!(client.balance.deposit(5.0).is_ok());
+    store.put_client_state(&client).unwrap();
+
+    store.index_clients().unwrap();
+    assert_eq!(store.get_client_state(1).unwrap(), Some(client.clone()));
+    assert_eq!(store.get_client_state(2).unwrap(), None);
+
+    // A state that is put through a clone of the store is indexed.
+    let mut client = ClientState::new(2);
+    assert!(client.balance.deposit(1.0).is_ok());
+    store.clone().put_client_state(&client).unwrap();
+    assert_eq!(store.get_client_state(2).unwrap(), Some(client.clone()));
+
+    // A state that is written to the delegate directly is not found until the clients are indexed
+    // again.
+    let client = ClientState::new(3);
+    ClientStore::new(engine).put_client_state(&client).unwrap();
+    assert_eq!(store.get_client_state(3).unwrap(), None);
+    store.index_clients().unwrap();
+    assert_eq!(store.get_client_state(3).unwrap(), Some(client));
+}
+
+#[test]
+fn check_books_tampered() {
+    let store = books_store(2);
+    let mut client = store.get_client_state(2).unwrap().unwrap();
+    assert!(client.balance.deposit(0.0001).is_ok());
+    store.put_client_state(&client).unwrap();
+
+    let books = store.check_books(1).unwrap();
+    assert_money_eq(books.ledger, 18.5);
+    assert_money_eq(books.supply, 18.5001);
+    assert_eq!(books.unbalanced.len(), 1);
+    assert_eq!(books.unbalanced[0].client, 2);
+    assert!(!books.is_balanced());
+}
+
+#[test]
+fn check_books_parallel() {
+    let store = books_store(50);
+    // Tamper with a state, lose another, and lose a transaction of a third client.
+    let mut client = store.get_client_state(7).unwrap().unwrap();
+    assert!(client.balance.deposit(1.0).is_ok());
+    store.put_client_state(&client).unwrap();
+    let key = bincode::serialize(&12u16).unwrap();
+    store.inner().delete(Keyspace::Clients, &key).unwrap();
+    store.delete_transaction(33, 1).unwrap();
+
+    let serial = store.check_books(1).unwrap();
+    let unbalanced = serial
+        .unbalanced
+        .iter()
+        .map(|client| client.client)
+        .collect::<Vec<_>>();
+    assert_eq!(unbalanced, vec![7, 12, 33]);
+    assert!(!serial.is_balanced());
+
+    for workers in [2, 3, 8, 64] {
+        assert_eq!(store.check_books(workers).unwrap(), serial);
+    }
+}
+
+#[test]
+fn metadata_preserves_frozen() {
+    let store = store();
+    let mut client = ClientState::new(1);
+    for transaction in [
+        Transaction::deposit(1, 1, 10.0),
+        Transaction::deposit(1, 2, 5.0),
+        Transaction::dispute(1, 1),
+        Transaction::chargeback(1, 1),
+    ] {
+        assert_eq!(client.execute_transaction(transaction, &store), Ok(()));
+    }
+    store.put_client_state(&client).unwrap();
+
+    let metadata = ClientMetadata {
+        name: "Ada".to_string(),
+        region: "EU".to_string(),
+        tier: "gold".to_string(),
+    };
+    let limits = ClientLimits {
+        min_balance: Some(5.0),
+        max_held: None,
+    };
+    store
+        .put_client_metadata(1, metadata.clone(), limits)
+        .unwrap();
+
+    let seeded = store.get_client_state(1).unwrap().unwrap();
+    assert!(seeded.frozen);
+    assert_eq!(seeded.balance, client.balance);
+    assert_eq!(seeded.metadata, Some(metadata));
+    assert_eq!(seeded.limits(), limits);
+}
+
+fn charged_back_harness(resolve_charged_back: ChargedBackResolvePolicy) -> ClientTestHarness {
+    ClientTestHarness::with_policy(
+        1,
+        Policy {
+            freeze_on_chargeback: false,
+            resolve_charged_back,
+            ..Default::default()
+        },
+    )
+    .deposit(1, 10.0)
+    .deposit(2, 5.0)
+    .dispute(1)
+    .chargeback(1)
+    .assert_available(5.0)
+    .assert_held(0.0)
+}
+
+#[test]
+fn resolve_charged_back() {
+    charged_back_harness(ChargedBackResolvePolicy::Reject)
+        .reject(
+            Transaction::resolve(1, 1),
+            ClientError::DisputeError(RESOLVE_CHARGED_BACK.to_string()),
+        )
+        .assert_dispute_status(1, DisputeStatus::ChargedBack)
+        .assert_available(5.0)
+        .assert_held(0.0)
+        .reject(
+            Transaction::dispute(1, 1),
+            ClientError::DisputeError(ALREADY_CHARGED_BACK.to_string()),
+        )
+        .assert_stored();
+}
+
+#[test]
+fn resolve_charged_back_ignored() {
+    charged_back_harness(ChargedBackResolvePolicy::Ignore)
+        .resolve(1)
+        .assert_dispute_status(1, DisputeStatus::ChargedBack)
+        .assert_available(5.0)
+        .assert_held(0.0)
+        .dispute(2)
+        .assert_held(5.0)
+        .assert_stored();
+}
+
+fn withdrawal_dispute_harness(withdrawal_dispute: WithdrawalDisputePolicy) -> ClientTestHarness {
+    ClientTestHarness::with_policy(
+        1,
+        Policy {
+            withdrawal_dispute,
+            ..Default::default()
+        },
+    )
+    .deposit(1, 10.0)
+    .withdrawal(2, 4.0)
+}
+
+#[test]
+fn withdrawal_dispute_hold() {
+    withdrawal_dispute_harness(WithdrawalDisputePolicy::Hold)
+        .dispute(2)
+        .assert_available(6.0)
+        .assert_held(4.0)
+        .assert_dispute_status(2, DisputeStatus::Disputed)
+        .resolve(2)
+        .assert_available(6.0)
+        .assert_held(0.0)
+        .dispute(2)
+        .chargeback(2)
+        .assert_available(10.0)
+        .assert_held(0.0)
+        .assert_frozen(true)
+        .assert_stored();
+}
+
+#[test]
+fn withdrawal_dispute_credit_to_available() {
+    withdrawal_dispute_harness(WithdrawalDisputePolicy::CreditToAvailable)
+        .dispute(2)
+        .assert_available(10.0)
+        .assert_held(0.0)
+        .assert_dispute_status(2, DisputeStatus::Disputed)
+        .reject(
+            Transaction::dispute(1, 2),
+            ClientError::DisputeError(ALREADY_DISPUTED.to_string()),
+        )
+        // The withdrawal stands, so the credit is reversed.
+        .resolve(2)
+        .assert_available(6.0)
+        .assert_dispute_status(2, DisputeStatus::NotDisputed)
+        .dispute(2)
+        .assert_available(10.0)
+        // The chargeback finalizes the credit.
+        .chargeback(2)
+        .assert_available(10.0)
+        .assert_held(0.0)
+        .assert_dispute_status(2, DisputeStatus::ChargedBack)
+        .assert_frozen(true)
+        .assert_stored();
+}
+
+#[test]
+fn withdrawal_dispute_credit_after_spending() {
+    // Reversing the credit may leave the available funds negative if they were spent meanwhile.
+    withdrawal_dispute_harness(WithdrawalDisputePolicy::CreditToAvailable)
+        .dispute(2)
+        .withdrawal(3, 8.0)
+        .assert_available(2.0)
+        .resolve(2)
+        .assert_available(-2.0)
+        .assert_stored();
+}
+
+#[test]
+fn compact_expired() {
+    let clock = MockClock::new();
+    let policy = Policy {
+        dispute_window: Some(Duration::from_secs(60)),
+        ..Default::default()
+    };
+    let mut store = ClientStore::with_policy(MemStore::default(), policy);
+    store.set_clock(Arc::new(clock.clone()));
+    let mut client = ClientState::new(1);
+    for transaction in [
+        Transaction::deposit(1, 1, 10.0),
+        Transaction::deposit(1, 2, 10.0),
+    ] {
+        assert_eq!(client.execute_transaction(transaction, &store), Ok(()));
+    }
+    clock.advance(Duration::from_secs(30));
+    for transaction in [Transaction::deposit(1, 3, 10.0), Transaction::dispute(1, 2)] {
+        assert_eq!(client.execute_transaction(transaction, &store), Ok(()));
+    }
+
+    // No transaction has been held for longer than the window.
+    clock.advance(Duration::from_secs(30));
+    assert_eq!(store.compact(&policy), Ok(0));
+
+    // The first deposit has expired, while the second is still disputed and the third is within
+    // the window.
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(store.compact(&policy), Ok(1));
+    assert_eq!(store.get_transaction(1, 1), Ok(None));
+    assert!(store.get_transaction(1, 2).unwrap().is_some());
+    assert!(store.get_transaction(1, 3).unwrap().is_some());
+
+    // Without a window, no deposit expires.
+    clock.advance(Duration::from_secs(60));
+    assert_eq!(store.compact(&Policy::default()), Ok(0));
+    assert_eq!(store.compact(&policy), Ok(1));
+    assert!(store.get_transaction(1, 2).unwrap().is_some());
+}
+
+#[test]
+fn compact_disputable_withdrawals() {
+    let policy = Policy {
+        withdrawal_dispute: WithdrawalDisputePolicy::CreditToAvailable,
+        ..Default::default()
+    };
+    let store = ClientStore::with_policy(MemStore::default(), policy);
+    let mut client = ClientState::new(1);
+    let transactions = vec![
+        Transaction::deposit(1, 1, 10.0),
+        Transaction::withdrawal(1, 2, 2.0),
+        Transaction::withdrawal(1, 3, 2.0),
+        Transaction::dispute(1, 3),
+        Transaction::chargeback(1, 3),
+    ];
+    for transaction in transactions {
+        assert_eq!(client.execute_transaction(transaction, &store), Ok(()));
+    }
+
+    assert_eq!(store.compact(&policy), Ok(1));
+    assert!(store.get_transaction(1, 2).unwrap().is_some());
+    assert_eq!(store.get_transaction(1, 3), Ok(None));
+}
+
+#[test]
+fn compact_disputed_withdrawal() {
+    let policy = Policy {
+        withdrawal_dispute: WithdrawalDisputePolicy::Hold,
+        ..Default::default()
+    };
+    let store = ClientStore::with_policy(MemStore::default(), policy);
+    let mut client = ClientState::new(1);
+    let transactions = vec![
+        Transaction::deposit(1, 1, 10.0),
+        Transaction::withdrawal(1, 2, 2.0),
+        Transaction::withdrawal(1, 3, 2.0),
+        Transaction::dispute(1, 3),
+    ];
+    for transaction in transactions {
+        assert_eq!(client.execute_transaction(transaction, &store), Ok(()));
+    }
+
+    // Withdrawals cannot be disputed under the default policy, but the open dispute must still be
+    // closed, so only the undisputed withdrawal is removed.
+    assert_eq!(store.compact(&Policy::default()), Ok(1));
+    assert_eq!(store.get_transaction(1, 2), Ok(None));
+    assert!(store.get_transaction(1, 3).unwrap().is_some());
+
+    assert_eq!(
+        client.execute_transaction(Transaction::chargeback(1, 3), &store),
+        Ok(())
+    );
+    assert_money_eq(client.balance.get_available(), 8.0);
+    assert_money_eq(client.balance.get_frozen(), 0.0);
+}
+
+#[test]
+fn money_eq() {
+    assert_ne!(0.1 + 0.2, 0.3);
+    assert_money_eq(0.1 + 0.2, 0.3);
+    assert_money_eq(-5.0, -5.0);
+}
+
+#[test]
+#[should_panic(expected = "Expected an amount of `1`, found `1.001`")]
+fn money_ne() {
+    assert_money_eq(1.001, 1.0);
 }
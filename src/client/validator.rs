@@ -0,0 +1,20 @@
+use crate::client::{ClientError, ClientState};
+use crate::transaction::Transaction;
+use std::fmt::Debug;
+
+/// Business rules that are checked before a transaction is applied to a client. A transaction
+/// that fails validation is rejected with the validator's error and the client is not mutated.
+pub trait Validator: Debug + Send + Sync {
+    /// Validates that `transaction` may be applied to `state`.
+    fn validate(&self, transaction: &Transaction, state: &ClientState) -> Result<(), ClientError>;
+}
+
+/// A validator that accepts every transaction.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoopValidator;
+
+impl Validator for NoopValidator {
+    fn validate(&self, _: &Transaction, _: &ClientState) -> Result<(), ClientError> {
+        Ok(())
+    }
+}
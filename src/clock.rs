@@ -0,0 +1,55 @@
+//! A source of the current time. Anything that reads the time does so through a `Clock` so that
+//! time-based behaviour can be tested deterministically by advancing a `MockClock`.
+
+use std::fmt::Debug;
+use std::time::SystemTime;
+
+#[cfg(test)]
+use std::sync::{Arc, Mutex, PoisonError};
+#[cfg(test)]
+use std::time::Duration;
+
+/// A source of the current time.
+pub trait Clock: Debug + Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> SystemTime;
+}
+
+/// A clock that reads the system's clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that only moves when it is advanced. Clones share the same time.
+#[cfg(test)]
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<SystemTime>>,
+}
+
+#[cfg(test)]
+impl MockClock {
+    /// Constructs a clock that is stopped at the current time.
+    pub fn new() -> MockClock {
+        MockClock {
+            now: Arc::new(Mutex::new(SystemTime::now())),
+        }
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner) += duration;
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
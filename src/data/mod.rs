@@ -1,29 +1,58 @@
-use crate::client::{ClientState, ClientStore, Keyspace};
+#[cfg(test)]
+mod tests;
+
+use crate::client::{ClientState, ClientStore, Keyspace, Policy};
 use crate::db::MemStore;
 use crate::parser::CsvTransaction;
 use crate::transaction::Transaction;
-use csv::Writer;
+use csv::{Writer, WriterBuilder};
 use fnv::FnvHashMap;
 use rand::Rng;
 use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::path::Path;
 
 const OUT_FILE_NAME: &str = "generated.csv";
+const HEADERS: [&str; 4] = ["type", "client", "tx", "amount"];
 
 /// Produces an empty memory store initialised with the default keyspaces
 pub fn mem_store() -> ClientStore<MemStore> {
+    ClientStore::new(MemStore::new(default_keyspaces()))
+}
+
+/// Produces an empty memory store initialised with the default keyspaces that will execute
+/// transactions using `policy`.
+pub fn mem_store_with_policy(policy: Policy) -> ClientStore<MemStore> {
+    ClientStore::with_policy(MemStore::new(default_keyspaces()), policy)
+}
+
+fn default_keyspaces() -> FnvHashMap<String, FnvHashMap<Vec<u8>, Vec<u8>>> {
     let mut keyspaces = FnvHashMap::default();
     let transactions = FnvHashMap::default();
     let clients = FnvHashMap::default();
+    let run_meta = FnvHashMap::default();
+    let dispute_log = FnvHashMap::default();
+    let compacted = FnvHashMap::default();
 
     keyspaces.insert(Keyspace::Transactions.name().to_string(), transactions);
     keyspaces.insert(Keyspace::Clients.name().to_string(), clients);
+    keyspaces.insert(Keyspace::RunMeta.name().to_string(), run_meta);
+    keyspaces.insert(Keyspace::DisputeLog.name().to_string(), dispute_log);
+    keyspaces.insert(Keyspace::Compacted.name().to_string(), compacted);
+
+    keyspaces
+}
 
-    ClientStore::new(MemStore::new(keyspaces))
+/// Generates `count` transactions and writes them to `generated.csv`. Before each transaction, a
+/// malformed row is injected with a probability of `error_rate`. Returns the number of malformed
+/// rows that were injected.
+pub fn generate_csv(count: usize, error_rate: f64) -> usize {
+    generate(OUT_FILE_NAME, count, error_rate)
 }
 
-/// Generates `count` transactions and writes them to `generated.csv`.
-pub fn generate_csv(count: usize) {
+/// Generates `count` transactions and writes them to `path`, injecting malformed rows with a
+/// probability of `error_rate`. Returns the number of malformed rows that were injected.
+fn generate<P: AsRef<Path>>(path: P, count: usize, error_rate: f64) -> usize {
     let mut rng = rand::thread_rng();
 
     let mut states: HashMap<u16, ClientState> = HashMap::new();
@@ -41,9 +70,9 @@ pub fn generate_csv(count: usize) {
                 let disputed = Transaction::dispute(tx.client_id(), tx.id());
 
                 let client = states.get_mut(&disputed.client_id()).unwrap();
-                let _ = client.execute_transaction(disputed.clone(), &store);
+                let _ = client.execute_transaction(disputed, &store);
 
-                disputed_transactions.push(disputed.clone());
+                disputed_transactions.push(disputed);
                 transactions.push(disputed)
             } else {
                 let num = rng.gen_range(0..10);
@@ -54,9 +83,9 @@ pub fn generate_csv(count: usize) {
                         let disputed = Transaction::dispute(tx.client_id(), tx.id());
 
                         let client = states.get_mut(&disputed.client_id()).unwrap();
-                        let _ = client.execute_transaction(disputed.clone(), &store);
+                        let _ = client.execute_transaction(disputed, &store);
 
-                        disputed_transactions.push(disputed.clone());
+                        disputed_transactions.push(disputed);
                         transactions.push(disputed)
                     }
                     6..=8 => {
@@ -65,7 +94,7 @@ pub fn generate_csv(count: usize) {
                         let disputed = Transaction::resolve(tx.client_id(), tx.id());
 
                         let client = states.get_mut(&disputed.client_id()).unwrap();
-                        let _ = client.execute_transaction(disputed.clone(), &store);
+                        let _ = client.execute_transaction(disputed, &store);
 
                         disputed_transactions.remove(idx);
                         transactions.push(disputed)
@@ -76,7 +105,7 @@ pub fn generate_csv(count: usize) {
                         let disputed = Transaction::chargeback(tx.client_id(), tx.id());
 
                         let client = states.get_mut(&disputed.client_id()).unwrap();
-                        let _ = client.execute_transaction(disputed.clone(), &store);
+                        let _ = client.execute_transaction(disputed, &store);
 
                         disputed_transactions.remove(idx);
                         transactions.push(disputed)
@@ -107,20 +136,48 @@ pub fn generate_csv(count: usize) {
             let amount = rng.gen_range(0.0..1000.0);
             if withdrawal {
                 let tx = Transaction::withdrawal(client.id(), i as u32, amount);
-                let _ = client.execute_transaction(tx.clone(), &store);
+                let _ = client.execute_transaction(tx, &store);
                 transactions.push(tx);
             } else {
                 let tx = Transaction::deposit(client.id(), i as u32, amount);
-                let _ = client.execute_transaction(tx.clone(), &store);
+                let _ = client.execute_transaction(tx, &store);
                 transactions.push(tx);
             }
         }
     }
 
-    let mut wtr = Writer::from_path(OUT_FILE_NAME).unwrap();
+    let mut wtr = WriterBuilder::new()
+        .has_headers(false)
+        .from_path(path)
+        .unwrap();
+    wtr.write_record(HEADERS).unwrap();
 
+    let mut invalid_count = 0;
     for tx in transactions {
+        if rng.gen_bool(error_rate) {
+            write_invalid_row(&mut wtr, &mut rng, tx.id());
+            invalid_count += 1;
+        }
+
         let csv = CsvTransaction::from(tx);
         wtr.serialize(csv).unwrap();
     }
+
+    invalid_count
+}
+
+/// Writes a malformed row to `wtr`. The row has either an unknown type, a missing amount or an
+/// out-of-range client ID.
+fn write_invalid_row<W, R>(wtr: &mut Writer<W>, rng: &mut R, tx: u32)
+where
+    W: std::io::Write,
+    R: Rng,
+{
+    let tx = tx.to_string();
+    let row = match rng.gen_range(0..3) {
+        0 => ["refund", "1", &tx, "1.0"],
+        1 => ["deposit", "1", &tx, ""],
+        _ => ["deposit", "70000", &tx, "1.0"],
+    };
+    wtr.write_record(row).unwrap();
 }
@@ -0,0 +1,53 @@
+use crate::data::generate;
+use crate::parser::reader::{reader_task, ReaderConfig};
+use crate::parser::rejects::{RejectReason, Rejection, RejectsWriter};
+use tempdir::TempDir;
+use tokio::sync::mpsc;
+
+const COUNT: usize = 2000;
+const ERROR_RATE: f64 = 0.1;
+
+#[tokio::test]
+async fn error_rate() {
+    let dir = TempDir::new("generate").unwrap();
+    let input = dir.path().join("generated.csv");
+    let rejects_path = dir.path().join("rejects.csv");
+
+    let invalid_count = generate(&input, COUNT, ERROR_RATE);
+    let expected = COUNT as f64 * ERROR_RATE;
+    assert!((invalid_count as f64 - expected).abs() < expected * 0.5);
+
+    let rejects = RejectsWriter::create(rejects_path.clone()).unwrap();
+    let (tx, mut rx) = mpsc::channel(COUNT);
+    let config = ReaderConfig {
+        skip_malformed: true,
+        ..Default::default()
+    };
+    let result = reader_task(vec![input], config, tx, Some(rejects.clone()), None).await;
+    assert!(result.is_ok());
+    rejects.flush().unwrap();
+
+    let mut valid_count = 0;
+    while rx.recv().await.is_some() {
+        valid_count += 1;
+    }
+    assert_eq!(valid_count, COUNT);
+
+    let rejections = csv::Reader::from_path(rejects_path)
+        .unwrap()
+        .into_deserialize::<Rejection>()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(rejections.len(), invalid_count);
+    assert!(rejections
+        .iter()
+        .all(|rejection| rejection.reason == RejectReason::Parse));
+}
+
+#[test]
+fn no_errors() {
+    let dir = TempDir::new("generate").unwrap();
+    let input = dir.path().join("generated.csv");
+
+    assert_eq!(generate(&input, 100, 0.0), 0);
+}
@@ -1,13 +1,28 @@
 use crate::client::Keyspace;
-use crate::db::{StoreEngine, StoreError};
-use rocksdb::{ColumnFamily, ColumnFamilyDescriptor, Options, DB};
+use crate::db::{BatchWrite, Scan, StoreEngine, StoreError};
+use rocksdb::{ColumnFamily, ColumnFamilyDescriptor, Options, WriteBatch, WriteOptions, DB};
+use std::iter;
 use std::path::Path;
 use std::sync::Arc;
 
+/// The keyspaces that every store is opened with.
+const DEFAULT_KEYSPACES: [Keyspace; 5] = [
+    Keyspace::Clients,
+    Keyspace::Transactions,
+    Keyspace::RunMeta,
+    Keyspace::DisputeLog,
+    Keyspace::Compacted,
+];
+/// The size, in megabytes, of the block cache of each keyspace.
+const BLOCK_CACHE_SIZE_MB: u64 = 64;
+
 /// A persistent disk store which is backed by a Rocks Database.
 #[derive(Debug, Clone)]
 pub struct DiskStore {
     delegate: Arc<DB>,
+    /// Whether writes are recorded in the write ahead log, so that they survive a crash before the
+    /// memtables are flushed.
+    write_ahead_log: bool,
 }
 
 impl DiskStore {
@@ -16,17 +31,68 @@ impl DiskStore {
     where
         P: AsRef<Path>,
     {
+        let keyspaces = DEFAULT_KEYSPACES
+            .iter()
+            .map(Keyspace::name)
+            .collect::<Vec<_>>();
+        DiskStore::with_keyspaces(path, &keyspaces, 1)
+    }
+
+    /// Attempts to open a new `DiskStore` at the provided `path` with a column family for each of
+    /// `keyspaces`. Every column family is opened in a single operation, using up to `parallelism`
+    /// background threads, and only the families that do not already exist are created.
+    pub fn with_keyspaces<P>(
+        path: P,
+        keyspaces: &[&str],
+        parallelism: i32,
+    ) -> Result<DiskStore, StoreError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
         let mut opts = Options::default();
         opts.create_if_missing(true);
         opts.create_missing_column_families(true);
+        if parallelism > 1 {
+            opts.increase_parallelism(parallelism);
+        }
+
+        // Every existing column family must be opened, so they are merged with the requested
+        // keyspaces. Listing fails if there is no database at `path` yet.
+        let mut names = DB::list_cf(&opts, path).unwrap_or_default();
+        for keyspace in keyspaces {
+            if !names.iter().any(|name| name == keyspace) {
+                names.push(keyspace.to_string());
+            }
+        }
+
+        let descriptors = names
+            .into_iter()
+            .map(|name| ColumnFamilyDescriptor::new(name, keyspace_options()));
+
+        DB::open_cf_descriptors(&opts, path, descriptors)
+            .map(|db| DiskStore {
+                delegate: Arc::new(db),
+                write_ahead_log: true,
+            })
+            .map_err(|e| open_error(path, e))
+    }
 
-        let clients = ColumnFamilyDescriptor::new(Keyspace::Clients.name(), Options::default());
-        let transactions =
-            ColumnFamilyDescriptor::new(Keyspace::Transactions.name(), Options::default());
+    /// Attempts to open the existing `DiskStore` at the provided `path` in read-only mode. Every
+    /// column family in the store is opened and any write to the store fails. A read-only store
+    /// does not lock the database, so it may be opened while another process is writing to it.
+    pub fn open_read_only<P>(path: P) -> Result<DiskStore, StoreError>
+    where
+        P: AsRef<Path>,
+    {
+        let opts = Options::default();
+        let names =
+            DB::list_cf(&opts, &path).map_err(|e| StoreError::InitialisationError(Box::new(e)))?;
 
-        DB::open_cf_descriptors(&opts, path, vec![clients, transactions])
+        DB::open_cf_for_read_only(&opts, path, names, false)
             .map(|db| DiskStore {
                 delegate: Arc::new(db),
+                write_ahead_log: true,
             })
             .map_err(|e| StoreError::InitialisationError(Box::new(e)))
     }
@@ -34,6 +100,39 @@ impl DiskStore {
     pub fn delegate(&self) -> Arc<DB> {
         self.delegate.clone()
     }
+
+    /// Sets whether writes are recorded in the write ahead log. Without it, a write is only made
+    /// durable by the next `commit`, which flushes the memtables, so a crash loses every write
+    /// since then. This should be set before the store is cloned, as clones do not share it.
+    pub fn set_write_ahead_log(&mut self, enabled: bool) {
+        self.write_ahead_log = enabled;
+    }
+
+    /// Returns the options that every write to the store is made with.
+    fn write_options(&self) -> WriteOptions {
+        let mut opts = WriteOptions::default();
+        opts.disable_wal(!self.write_ahead_log);
+        opts
+    }
+}
+
+/// Returns the error for a store at `path` that failed to open with `e`. RocksDB reports a store
+/// that is already open in another process as an IO error on its lock file, which is surfaced as a
+/// locked store rather than a generic initialisation error.
+fn open_error(path: &Path, e: rocksdb::Error) -> StoreError {
+    if e.to_string().contains("LOCK") {
+        StoreError::Locked(path.to_path_buf())
+    } else {
+        StoreError::InitialisationError(Box::new(e))
+    }
+}
+
+/// Returns the options that each keyspace is opened with. Values are only ever read by their key,
+/// so the keyspaces are tuned for point lookups.
+fn keyspace_options() -> Options {
+    let mut opts = Options::default();
+    opts.optimize_for_point_lookup(BLOCK_CACHE_SIZE_MB);
+    opts
 }
 
 fn resolve_keyspace(store: &Arc<DB>, keyspace: Keyspace) -> Result<&ColumnFamily, StoreError> {
@@ -46,7 +145,25 @@ impl StoreEngine for DiskStore {
     fn put(&self, keyspace: Keyspace, key: &[u8], value: &[u8]) -> Result<(), StoreError> {
         let keyspace = resolve_keyspace(&self.delegate, keyspace)?;
         self.delegate
-            .put_cf(keyspace, key, value)
+            .put_cf_opt(keyspace, key, value, &self.write_options())
+            .map_err(|e| StoreError::Write(Box::new(e)))
+    }
+
+    /// Applies every write in a single `WriteBatch`, which RocksDB applies atomically.
+    fn write_batch(&self, writes: &[BatchWrite<'_>]) -> Result<(), StoreError> {
+        let mut batch = WriteBatch::default();
+        for write in writes {
+            match *write {
+                BatchWrite::Put(keyspace, key, value) => {
+                    batch.put_cf(resolve_keyspace(&self.delegate, keyspace)?, key, value);
+                }
+                BatchWrite::Delete(keyspace, key) => {
+                    batch.delete_cf(resolve_keyspace(&self.delegate, keyspace)?, key);
+                }
+            }
+        }
+        self.delegate
+            .write_opt(batch, &self.write_options())
             .map_err(|e| StoreError::Write(Box::new(e)))
     }
 
@@ -58,4 +175,97 @@ impl StoreEngine for DiskStore {
             Err(e) => Err(StoreError::Read(Box::new(e))),
         }
     }
+
+    fn delete(&self, keyspace: Keyspace, key: &[u8]) -> Result<(), StoreError> {
+        let keyspace = resolve_keyspace(&self.delegate, keyspace)?;
+        self.delegate
+            .delete_cf_opt(keyspace, key, &self.write_options())
+            .map_err(|e| StoreError::Write(Box::new(e)))
+    }
+
+    /// Streams the keyspace from a raw RocksDB iterator, which reads from an implicit snapshot, so
+    /// writes made while it is iterated are not seen. A raw iterator is used, rather than
+    /// `iterator_cf`, as that stops without an error if a read fails. Once the iterator stops, any
+    /// error that it stopped on is yielded.
+    fn scan(&self, keyspace: Keyspace) -> Scan<'_> {
+        let keyspace = match self.delegate.cf_handle(keyspace.name()) {
+            Some(keyspace) => keyspace,
+            None => return Box::new(iter::empty()),
+        };
+        let mut iterator = self.delegate.raw_iterator_cf(keyspace);
+        iterator.seek_to_first();
+
+        let mut finished = false;
+        Box::new(iter::from_fn(move || {
+            if finished {
+                return None;
+            }
+            match (iterator.key(), iterator.value()) {
+                (Some(key), Some(value)) => {
+                    let entry = (key.to_vec(), value.to_vec());
+                    iterator.next();
+                    Some(Ok(entry))
+                }
+                _ => {
+                    finished = true;
+                    iterator
+                        .status()
+                        .err()
+                        .map(|e| Err(StoreError::Read(Box::new(e))))
+                }
+            }
+        }))
+    }
+
+    /// Walks the keyspace with a raw iterator, so that no value is copied out of the store.
+    fn scan_keys(&self, keyspace: Keyspace) -> Result<Vec<Vec<u8>>, StoreError> {
+        let keyspace = match self.delegate.cf_handle(keyspace.name()) {
+            Some(keyspace) => keyspace,
+            None => return Ok(Vec::new()),
+        };
+        let mut iterator = self.delegate.raw_iterator_cf(keyspace);
+        iterator.seek_to_first();
+
+        let mut keys = Vec::new();
+        while let Some(key) = iterator.key() {
+            keys.push(key.to_vec());
+            iterator.next();
+        }
+        iterator
+            .status()
+            .map_err(|e| StoreError::Read(Box::new(e)))?;
+        Ok(keys)
+    }
+
+    fn get_batch(
+        &self,
+        keyspace: Keyspace,
+        keys: &[&[u8]],
+    ) -> Result<Vec<Option<Vec<u8>>>, StoreError> {
+        let keyspace = resolve_keyspace(&self.delegate, keyspace)?;
+        let values = self
+            .delegate
+            .multi_get_cf(keys.iter().map(|key| (keyspace, *key)))
+            .map_err(|e| StoreError::Read(Box::new(e)))?;
+
+        // RocksDB returns an empty value for any key that was not found. Every value that is
+        // written to the store is non-empty so these can be safely treated as missing.
+        let values = values
+            .into_iter()
+            .map(|value| if value.is_empty() { None } else { Some(value) })
+            .collect();
+
+        Ok(values)
+    }
+
+    /// Flushes the memtables of every default keyspace to SST files on the disk.
+    fn commit(&self) -> Result<(), StoreError> {
+        for keyspace in DEFAULT_KEYSPACES.iter() {
+            let keyspace = resolve_keyspace(&self.delegate, *keyspace)?;
+            self.delegate
+                .flush_cf(keyspace)
+                .map_err(|e| StoreError::Write(Box::new(e)))?;
+        }
+        Ok(())
+    }
 }
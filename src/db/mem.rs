@@ -1,5 +1,5 @@
 use crate::client::Keyspace;
-use crate::db::{StoreEngine, StoreError};
+use crate::db::{scan_of, BatchWrite, Scan, StoreEngine, StoreError};
 use fnv::FnvHashMap;
 use std::collections::hash_map::Entry;
 use std::sync::{Arc, RwLock};
@@ -54,6 +54,33 @@ impl StoreEngine for MemStore {
         Ok(())
     }
 
+    /// Applies every write while holding the write lock once, so no reader sees part of the batch.
+    /// This is only atomic within the process, as nothing is persisted.
+    fn write_batch(&self, writes: &[BatchWrite<'_>]) -> Result<(), StoreError> {
+        let mut guard = self
+            .keyspaces
+            .write()
+            .map_err(|_| StoreError::Write(Box::new(Poisoned)))?;
+
+        for write in writes {
+            match *write {
+                BatchWrite::Put(keyspace, key, value) => {
+                    guard
+                        .entry(keyspace.name().to_string())
+                        .or_default()
+                        .insert(key.to_vec(), value.to_vec());
+                }
+                BatchWrite::Delete(keyspace, key) => {
+                    if let Some(entries) = guard.get_mut(keyspace.name()) {
+                        entries.remove(key);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     fn get(&self, keyspace: Keyspace, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
         let guard = self
             .keyspaces
@@ -67,4 +94,67 @@ impl StoreEngine for MemStore {
 
         Ok(value)
     }
+
+    fn delete(&self, keyspace: Keyspace, key: &[u8]) -> Result<(), StoreError> {
+        let mut guard = self
+            .keyspaces
+            .write()
+            .map_err(|_| StoreError::Write(Box::new(Poisoned)))?;
+
+        if let Some(entries) = guard.get_mut(keyspace.name()) {
+            entries.remove(key);
+        }
+
+        Ok(())
+    }
+
+    /// Copies the keyspace's entries while holding the read lock, so that writes are not blocked
+    /// while they are iterated.
+    fn scan(&self, keyspace: Keyspace) -> Scan<'_> {
+        let entries = self
+            .keyspaces
+            .read()
+            .map_err(|_| StoreError::Read(Box::new(Poisoned)))
+            .map(|guard| match guard.get(keyspace.name()) {
+                Some(entries) => entries
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect(),
+                None => Vec::new(),
+            });
+        scan_of(entries)
+    }
+
+    fn scan_keys(&self, keyspace: Keyspace) -> Result<Vec<Vec<u8>>, StoreError> {
+        let guard = self
+            .keyspaces
+            .read()
+            .map_err(|_| StoreError::Read(Box::new(Poisoned)))?;
+
+        let keys = match guard.get(keyspace.name()) {
+            Some(entries) => entries.keys().cloned().collect(),
+            None => Vec::new(),
+        };
+
+        Ok(keys)
+    }
+
+    fn get_batch(
+        &self,
+        keyspace: Keyspace,
+        keys: &[&[u8]],
+    ) -> Result<Vec<Option<Vec<u8>>>, StoreError> {
+        let guard = self
+            .keyspaces
+            .read()
+            .map_err(|_| StoreError::Read(Box::new(Poisoned)))?;
+        let map = &*guard;
+
+        let values = match map.get(keyspace.name()) {
+            Some(entries) => keys.iter().map(|key| entries.get(*key).cloned()).collect(),
+            None => vec![None; keys.len()],
+        };
+
+        Ok(values)
+    }
 }
@@ -7,12 +7,33 @@ mod disk;
 pub use crate::db::disk::DiskStore;
 
 mod mem;
-pub use crate::db::mem::{MemStore, Poisoned};
+pub use crate::db::mem::MemStore;
+#[cfg(test)]
+pub use crate::db::mem::Poisoned;
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
 
 use crate::client::Keyspace;
 use std::error::Error;
+use std::iter;
 use thiserror::Error;
 
+/// A key and its value.
+pub type KeyValue = (Vec<u8>, Vec<u8>);
+
+/// An iterator over the key-value pairs of a keyspace, which yields any error reading them.
+pub type Scan<'a> = Box<dyn Iterator<Item = Result<KeyValue, StoreError>> + 'a>;
+
+/// A single write in a batch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BatchWrite<'a> {
+    /// Put the key-value pair in to the keyspace.
+    Put(Keyspace, &'a [u8], &'a [u8]),
+    /// Delete the key from the keyspace. Deleting a key that does not exist is not an error.
+    Delete(Keyspace, &'a [u8]),
+}
+
 #[derive(Error, Debug)]
 pub enum StoreError {
     #[error("An error was produced when reading from the store: `{0}`")]
@@ -23,6 +44,24 @@ pub enum StoreError {
     Serialize(Box<dyn Error + Send>),
     #[error("An error was produced when deserializing a value: `{0}`")]
     Deserialize(Box<dyn Error + Send>),
+    /// A stored value ended before it could be fully deserialized. For example, if a write was
+    /// interrupted.
+    #[error(
+        "A truncated record of `{len}` bytes was read from keyspace `{keyspace}` for key `{key:?}`"
+    )]
+    TruncatedRecord {
+        keyspace: String,
+        key: Vec<u8>,
+        len: usize,
+    },
+    #[error("An error was produced when opening the store: `{0}`")]
+    InitialisationError(Box<dyn Error + Send>),
+    /// The store is already open in another process, which holds its lock.
+    #[cfg(feature = "rocks")]
+    #[error("The store at `{}` is locked by another process", .0.display())]
+    Locked(std::path::PathBuf),
+    /// A keyspace that a write or lookup requires does not exist. A scan treats a missing
+    /// keyspace as empty instead.
     #[error("The requested keyspace was not found")]
     KeyspaceNotFound,
 }
@@ -36,7 +75,22 @@ impl PartialEq for StoreError {
             (StoreError::Write(left), StoreError::Write(right)) => {
                 left.to_string().eq(&right.to_string())
             }
+            (StoreError::InitialisationError(left), StoreError::InitialisationError(right)) => {
+                left.to_string().eq(&right.to_string())
+            }
             (StoreError::KeyspaceNotFound, StoreError::KeyspaceNotFound) => true,
+            (
+                StoreError::TruncatedRecord {
+                    keyspace: left_keyspace,
+                    key: left_key,
+                    len: left_len,
+                },
+                StoreError::TruncatedRecord {
+                    keyspace: right_keyspace,
+                    key: right_key,
+                    len: right_len,
+                },
+            ) => left_keyspace == right_keyspace && left_key == right_key && left_len == right_len,
             _ => false,
         }
     }
@@ -47,6 +101,69 @@ pub trait StoreEngine: Clone + Send + Sync {
     /// Attempt to put the key-value pair in to `keyspace`.
     fn put(&self, keyspace: Keyspace, key: &[u8], value: &[u8]) -> Result<(), StoreError>;
 
+    /// Attempt to apply every put and delete in `writes`, in order, as a single write. Either
+    /// every write is applied or, if an error is returned, none of them are.
+    ///
+    /// The default implementation performs a `put` or `delete` for each write, so a failure may
+    /// leave the earlier writes applied; implementations should override this if they can write
+    /// atomically.
+    fn write_batch(&self, writes: &[BatchWrite<'_>]) -> Result<(), StoreError> {
+        writes.iter().try_for_each(|write| match *write {
+            BatchWrite::Put(keyspace, key, value) => self.put(keyspace, key, value),
+            BatchWrite::Delete(keyspace, key) => self.delete(keyspace, key),
+        })
+    }
+
     /// Attempt to get `key` from the keyspace `keyspace`.
     fn get(&self, keyspace: Keyspace, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError>;
+
+    /// Attempt to delete `key` from the keyspace `keyspace`. Deleting a key that does not exist is
+    /// not an error.
+    fn delete(&self, keyspace: Keyspace, key: &[u8]) -> Result<(), StoreError>;
+
+    /// Returns an iterator over every key-value pair in the keyspace `keyspace`, in no particular
+    /// order. A keyspace that does not exist is empty; any other error is yielded by the iterator.
+    fn scan(&self, keyspace: Keyspace) -> Scan<'_>;
+
+    /// Attempt to get every key in the keyspace `keyspace`, in no particular order. A keyspace
+    /// that does not exist has no keys.
+    ///
+    /// The default implementation scans every key-value pair and discards the values;
+    /// implementations should override this if they can read the keys alone.
+    fn scan_keys(&self, keyspace: Keyspace) -> Result<Vec<Vec<u8>>, StoreError> {
+        self.scan(keyspace)
+            .map(|entry| entry.map(|(key, _)| key))
+            .collect()
+    }
+
+    /// Attempt to get every key in `keys` from the keyspace `keyspace`. The values are returned in
+    /// the same order as `keys`.
+    ///
+    /// The default implementation performs a `get` for each key; implementations should override
+    /// this if they can perform the lookup more efficiently.
+    fn get_batch(
+        &self,
+        keyspace: Keyspace,
+        keys: &[&[u8]],
+    ) -> Result<Vec<Option<Vec<u8>>>, StoreError> {
+        keys.iter().map(|key| self.get(keyspace, key)).collect()
+    }
+
+    /// Attempt to make every write to the store durable, so that it survives a crash of the
+    /// process or the host.
+    ///
+    /// The default implementation does nothing, as a store that is not persistent has nothing to
+    /// make durable.
+    fn commit(&self) -> Result<(), StoreError> {
+        Ok(())
+    }
+}
+
+/// Returns a scan over `entries` that have already been read, or that yields only the error that
+/// reading them failed with.
+fn scan_of<'a>(entries: Result<Vec<KeyValue>, StoreError>) -> Scan<'a> {
+    match entries {
+        Ok(entries) => Box::new(entries.into_iter().map(Ok)),
+        Err(e) => Box::new(iter::once(Err(e))),
+    }
 }
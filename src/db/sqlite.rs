@@ -0,0 +1,197 @@
+use crate::client::Keyspace;
+use crate::db::mem::Poisoned;
+use crate::db::{scan_of, BatchWrite, KeyValue, Scan, StoreEngine, StoreError};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// The keyspaces that every store is opened with.
+const DEFAULT_KEYSPACES: [Keyspace; 5] = [
+    Keyspace::Clients,
+    Keyspace::Transactions,
+    Keyspace::RunMeta,
+    Keyspace::DisputeLog,
+    Keyspace::Compacted,
+];
+
+/// A persistent disk store which is backed by a SQLite database. Each keyspace is held in its own
+/// table, keyed by a `BLOB` key column.
+#[derive(Debug, Clone)]
+pub struct SqliteStore {
+    delegate: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    /// Attempts to open a new `SqliteStore` in the database file at the provided `path`, creating
+    /// it and a table for each keyspace if they do not already exist.
+    pub fn new<P>(path: P) -> Result<SqliteStore, StoreError>
+    where
+        P: AsRef<Path>,
+    {
+        let connection =
+            Connection::open(path).map_err(|e| StoreError::InitialisationError(Box::new(e)))?;
+        // Write ahead logging lets a reader, such as `meta`, open the database while a run is
+        // writing to it.
+        connection
+            .pragma_update(None, "journal_mode", "WAL")
+            .map_err(|e| StoreError::InitialisationError(Box::new(e)))?;
+        for keyspace in DEFAULT_KEYSPACES.iter() {
+            let sql = format!(
+                "CREATE TABLE IF NOT EXISTS \"{}\" (key BLOB PRIMARY KEY, value BLOB NOT NULL)",
+                keyspace.name()
+            );
+            connection
+                .execute(&sql, [])
+                .map_err(|e| StoreError::InitialisationError(Box::new(e)))?;
+        }
+
+        Ok(SqliteStore::from_connection(connection))
+    }
+
+    /// Attempts to open the existing `SqliteStore` at the provided `path` in read-only mode. Any
+    /// write to the store fails.
+    pub fn open_read_only<P>(path: P) -> Result<SqliteStore, StoreError>
+    where
+        P: AsRef<Path>,
+    {
+        Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map(SqliteStore::from_connection)
+            .map_err(|e| StoreError::InitialisationError(Box::new(e)))
+    }
+
+    fn from_connection(connection: Connection) -> SqliteStore {
+        SqliteStore {
+            delegate: Arc::new(Mutex::new(connection)),
+        }
+    }
+
+    fn read(&self) -> Result<MutexGuard<'_, Connection>, StoreError> {
+        self.delegate
+            .lock()
+            .map_err(|_| StoreError::Read(Box::new(Poisoned)))
+    }
+
+    fn write(&self) -> Result<MutexGuard<'_, Connection>, StoreError> {
+        self.delegate
+            .lock()
+            .map_err(|_| StoreError::Write(Box::new(Poisoned)))
+    }
+
+    /// Reads every key-value pair in `keyspace`.
+    fn read_all(&self, keyspace: Keyspace) -> Result<Vec<KeyValue>, StoreError> {
+        let sql = format!("SELECT key, value FROM \"{}\"", keyspace.name());
+        let connection = self.read()?;
+        if !has_table(&connection, keyspace)? {
+            return Ok(Vec::new());
+        }
+        let mut statement = connection
+            .prepare_cached(&sql)
+            .map_err(|e| StoreError::Read(Box::new(e)))?;
+        let entries = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .and_then(|rows| rows.collect())
+            .map_err(|e| StoreError::Read(Box::new(e)))?;
+        Ok(entries)
+    }
+}
+
+/// Returns whether the database that `connection` is open on has a table for `keyspace`. A store
+/// opened read-only is not given the tables that it is missing.
+fn has_table(connection: &Connection, keyspace: Keyspace) -> Result<bool, StoreError> {
+    connection
+        .prepare_cached("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1")
+        .and_then(|mut statement| statement.exists([keyspace.name()]))
+        .map_err(|e| StoreError::Read(Box::new(e)))
+}
+
+impl StoreEngine for SqliteStore {
+    fn put(&self, keyspace: Keyspace, key: &[u8], value: &[u8]) -> Result<(), StoreError> {
+        let sql = format!(
+            "INSERT OR REPLACE INTO \"{}\" (key, value) VALUES (?1, ?2)",
+            keyspace.name()
+        );
+        self.write()?
+            .prepare_cached(&sql)
+            .and_then(|mut statement| statement.execute(params![key, value]))
+            .map(|_| ())
+            .map_err(|e| StoreError::Write(Box::new(e)))
+    }
+
+    /// Applies every write within a single SQLite transaction, which is rolled back if any write
+    /// fails.
+    fn write_batch(&self, writes: &[BatchWrite<'_>]) -> Result<(), StoreError> {
+        let mut connection = self.write()?;
+        let transaction = connection
+            .transaction()
+            .map_err(|e| StoreError::Write(Box::new(e)))?;
+        for write in writes {
+            let result = match *write {
+                BatchWrite::Put(keyspace, key, value) => {
+                    let sql = format!(
+                        "INSERT OR REPLACE INTO \"{}\" (key, value) VALUES (?1, ?2)",
+                        keyspace.name()
+                    );
+                    transaction
+                        .prepare_cached(&sql)
+                        .and_then(|mut statement| statement.execute(params![key, value]))
+                }
+                BatchWrite::Delete(keyspace, key) => {
+                    let sql = format!("DELETE FROM \"{}\" WHERE key = ?1", keyspace.name());
+                    transaction
+                        .prepare_cached(&sql)
+                        .and_then(|mut statement| statement.execute([key]))
+                }
+            };
+            result.map_err(|e| StoreError::Write(Box::new(e)))?;
+        }
+        transaction
+            .commit()
+            .map_err(|e| StoreError::Write(Box::new(e)))
+    }
+
+    fn get(&self, keyspace: Keyspace, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        let sql = format!("SELECT value FROM \"{}\" WHERE key = ?1", keyspace.name());
+        self.read()?
+            .prepare_cached(&sql)
+            .and_then(|mut statement| statement.query_row([key], |row| row.get(0)).optional())
+            .map_err(|e| StoreError::Read(Box::new(e)))
+    }
+
+    fn delete(&self, keyspace: Keyspace, key: &[u8]) -> Result<(), StoreError> {
+        let sql = format!("DELETE FROM \"{}\" WHERE key = ?1", keyspace.name());
+        self.write()?
+            .prepare_cached(&sql)
+            .and_then(|mut statement| statement.execute([key]))
+            .map(|_| ())
+            .map_err(|e| StoreError::Write(Box::new(e)))
+    }
+
+    /// Reads every row before iterating them, as the rows of a query cannot outlive the lock on
+    /// the connection that they are read through.
+    fn scan(&self, keyspace: Keyspace) -> Scan<'_> {
+        scan_of(self.read_all(keyspace))
+    }
+
+    fn scan_keys(&self, keyspace: Keyspace) -> Result<Vec<Vec<u8>>, StoreError> {
+        let sql = format!("SELECT key FROM \"{}\"", keyspace.name());
+        let connection = self.read()?;
+        if !has_table(&connection, keyspace)? {
+            return Ok(Vec::new());
+        }
+        let mut statement = connection
+            .prepare_cached(&sql)
+            .map_err(|e| StoreError::Read(Box::new(e)))?;
+        let keys = statement
+            .query_map([], |row| row.get(0))
+            .and_then(|rows| rows.collect())
+            .map_err(|e| StoreError::Read(Box::new(e)))?;
+        Ok(keys)
+    }
+
+    /// Checkpoints the write ahead log in to the database file.
+    fn commit(&self) -> Result<(), StoreError> {
+        self.write()?
+            .query_row("PRAGMA wal_checkpoint(FULL)", [], |_| Ok(()))
+            .map_err(|e| StoreError::Write(Box::new(e)))
+    }
+}
@@ -1 +1,722 @@
+use crate::client::{Books, ClientError, ClientState, ClientStore, Keyspace, Policy, State};
+#[cfg(feature = "sqlite")]
+use crate::db::sqlite::SqliteStore;
+#[cfg(feature = "rocks")]
+use crate::db::DiskStore;
+use crate::db::{BatchWrite, KeyValue, MemStore, Poisoned, Scan, StoreEngine, StoreError};
+use crate::io::{IoConfig, IoTask, Tally};
+#[cfg(all(feature = "sqlite", not(feature = "scale-8")))]
+use crate::parser::writer::{write_state, WriterConfig};
+use crate::transaction::Transaction;
+#[cfg(feature = "sqlite")]
+use std::fs::File;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+#[cfg(any(feature = "rocks", feature = "sqlite"))]
+use tempdir::TempDir;
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc;
 
+fn seeded_store() -> MemStore {
+    let store = MemStore::default();
+    for i in 0..10u8 {
+        store.put(Keyspace::Clients, &[i], &[i, i]).unwrap();
+    }
+    store
+}
+
+/// Scans `keyspace` of `store`, sorted by key.
+fn scanned<D: StoreEngine>(store: &D, keyspace: Keyspace) -> Vec<KeyValue> {
+    let mut entries = store.scan(keyspace).collect::<Result<Vec<_>, _>>().unwrap();
+    entries.sort();
+    entries
+}
+
+#[test]
+fn scan() {
+    let store = seeded_store();
+    let expected = (0..10u8).map(|i| (vec![i], vec![i, i])).collect::<Vec<_>>();
+
+    assert_eq!(scanned(&store, Keyspace::Clients), expected);
+    assert_eq!(scanned(&store, Keyspace::Transactions), vec![]);
+}
+
+#[test]
+fn scan_while_writing() {
+    let store = seeded_store();
+    // The scan does not hold the store's lock, so the store may be written while it is iterated.
+    for entry in store.scan(Keyspace::Clients) {
+        let (key, _) = entry.unwrap();
+        store.delete(Keyspace::Clients, &key).unwrap();
+    }
+    assert_eq!(scanned(&store, Keyspace::Clients), vec![]);
+}
+
+/// Writes a key-value pair for each of ten keys to `engine`, and asserts that scanning its keys
+/// returns exactly those keys.
+fn assert_scan_keys<D: StoreEngine>(engine: D) {
+    for i in 0..10u8 {
+        engine.put(Keyspace::Clients, &[i], &[i, i]).unwrap();
+    }
+    engine.put(Keyspace::Transactions, &[42], &[1]).unwrap();
+
+    let mut keys = engine.scan_keys(Keyspace::Clients).unwrap();
+    keys.sort();
+    assert_eq!(keys, (0..10u8).map(|i| vec![i]).collect::<Vec<_>>());
+    assert_eq!(engine.scan_keys(Keyspace::Transactions), Ok(vec![vec![42]]));
+}
+
+#[test]
+fn scan_keys() {
+    assert_scan_keys(MemStore::default());
+    assert_eq!(
+        MemStore::default().scan_keys(Keyspace::Clients),
+        Ok(Vec::new())
+    );
+}
+
+/// Asserts that every reader of a client store over `engine`, which has none of the keyspaces,
+/// finds the store empty rather than failing.
+fn assert_missing_keyspaces<D: StoreEngine>(engine: D) {
+    assert_eq!(scanned(&engine, Keyspace::Transactions), Vec::new());
+    assert_eq!(engine.scan_keys(Keyspace::Clients), Ok(Vec::new()));
+
+    let store = ClientStore::new(engine);
+    assert_eq!(store.compact(&Policy::default()), Ok(0));
+    assert_eq!(store.disputed_transactions(), Ok(Vec::new()));
+    assert_eq!(store.transaction_ids(), Ok(Vec::new()));
+    assert_eq!(store.transactions_for_client(1), Ok(Vec::new()));
+    assert_eq!(store.collect_states(), Ok(Vec::new()));
+    assert_eq!(store.check_books(2), Ok(Books::default()));
+}
+
+#[test]
+fn missing_keyspaces() {
+    assert_missing_keyspaces(MemStore::default());
+}
+
+/// Deletes transactions from a client store over `engine`, asserting that only the deleted
+/// transaction is removed and that deleting a missing transaction succeeds.
+fn assert_delete<D: StoreEngine>(engine: D) {
+    let store = ClientStore::new(engine);
+    store
+        .put_transaction(Transaction::deposit(1, 1, 1.0))
+        .unwrap();
+    store
+        .put_transaction(Transaction::deposit(1, 2, 2.0))
+        .unwrap();
+    store
+        .put_transaction(Transaction::deposit(2, 1, 3.0))
+        .unwrap();
+
+    assert_eq!(store.delete_transaction(1, 1), Ok(()));
+    assert_eq!(store.get_transaction(1, 1), Ok(None));
+    assert_eq!(
+        store.get_transaction(1, 2),
+        Ok(Some(Transaction::deposit(1, 2, 2.0)))
+    );
+    assert_eq!(
+        store.get_transaction(2, 1),
+        Ok(Some(Transaction::deposit(2, 1, 3.0)))
+    );
+
+    assert_eq!(store.delete_transaction(1, 1), Ok(()));
+    assert_eq!(store.delete_transaction(3, 1), Ok(()));
+}
+
+#[test]
+fn delete() {
+    let store = seeded_store();
+    assert_eq!(store.delete(Keyspace::Clients, &[3]), Ok(()));
+    assert_eq!(store.get(Keyspace::Clients, &[3]), Ok(None));
+    assert_eq!(store.get(Keyspace::Clients, &[4]), Ok(Some(vec![4, 4])));
+
+    assert_eq!(store.delete(Keyspace::Clients, &[3]), Ok(()));
+    assert_eq!(store.delete(Keyspace::Transactions, &[3]), Ok(()));
+
+    assert_delete(MemStore::default());
+}
+
+/// Writes a batch over two keyspaces to `engine`, including a key that is written twice and a key
+/// that is written and then deleted, and asserts that every write was applied in order.
+fn assert_write_batch<D: StoreEngine>(engine: D) {
+    engine.put(Keyspace::Transactions, &[2], &[5, 5]).unwrap();
+    let writes = [
+        BatchWrite::Put(Keyspace::Transactions, &[1], &[1, 1]),
+        BatchWrite::Put(Keyspace::Clients, &[1], &[2, 2]),
+        BatchWrite::Put(Keyspace::Clients, &[2], &[3, 3]),
+        BatchWrite::Put(Keyspace::Clients, &[1], &[4, 4]),
+        BatchWrite::Put(Keyspace::Clients, &[3], &[6, 6]),
+        BatchWrite::Delete(Keyspace::Clients, &[3]),
+        BatchWrite::Delete(Keyspace::Transactions, &[2]),
+        BatchWrite::Delete(Keyspace::Transactions, &[9]),
+    ];
+    assert_eq!(engine.write_batch(&writes), Ok(()));
+
+    assert_eq!(
+        scanned(&engine, Keyspace::Transactions),
+        vec![(vec![1], vec![1, 1])]
+    );
+    assert_eq!(
+        scanned(&engine, Keyspace::Clients),
+        vec![(vec![1], vec![4, 4]), (vec![2], vec![3, 3])]
+    );
+    assert_eq!(engine.write_batch(&[]), Ok(()));
+}
+
+#[test]
+fn write_batch() {
+    assert_write_batch(MemStore::default());
+}
+
+/// A memory store whose batched writes always fail, without writing anything, while single
+/// writes succeed.
+#[derive(Debug, Default, Clone)]
+struct FailingBatchStore {
+    inner: MemStore,
+}
+
+impl StoreEngine for FailingBatchStore {
+    fn put(&self, keyspace: Keyspace, key: &[u8], value: &[u8]) -> Result<(), StoreError> {
+        self.inner.put(keyspace, key, value)
+    }
+
+    fn write_batch(&self, _writes: &[BatchWrite<'_>]) -> Result<(), StoreError> {
+        Err(StoreError::Write(Box::new(Poisoned)))
+    }
+
+    fn get(&self, keyspace: Keyspace, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        self.inner.get(keyspace, key)
+    }
+
+    fn delete(&self, keyspace: Keyspace, key: &[u8]) -> Result<(), StoreError> {
+        self.inner.delete(keyspace, key)
+    }
+
+    fn scan(&self, keyspace: Keyspace) -> Scan<'_> {
+        self.inner.scan(keyspace)
+    }
+}
+
+#[test]
+fn transfer_written_in_one_batch() {
+    let store = ClientStore::new(FailingBatchStore::default());
+    let mut client = ClientState::new(1);
+
+    let result = client.execute_transaction(Transaction::deposit(1, 1, 1.0), &store);
+    assert!(matches!(result, Err(ClientError::StoreError(_))));
+    // Neither the transaction nor the client's state were written.
+    assert_eq!(store.get_transaction(1, 1), Ok(None));
+    assert_eq!(store.get_client_state(1), Ok(None));
+}
+
+#[test]
+fn revert_written_in_one_batch() {
+    let engine = MemStore::default();
+    let mut client = ClientState::new(1);
+    let writable = ClientStore::new(engine.clone());
+    for transaction in [
+        Transaction::deposit(1, 1, 3.0),
+        Transaction::withdrawal(1, 2, 2.0),
+    ] {
+        client.execute_transaction(transaction, &writable).unwrap();
+    }
+
+    let store = ClientStore::new(FailingBatchStore { inner: engine });
+    let result = client.revert_withdrawal(2, &store);
+    assert!(matches!(result, Err(ClientError::StoreError(_))));
+    // Neither the removal of the withdrawal nor the client's updated state were written.
+    assert!(store.get_transaction(1, 2).unwrap().is_some());
+    assert_eq!(
+        State::from(store.get_client_state(1).unwrap().unwrap()).available,
+        1.0
+    );
+}
+
+#[test]
+fn initialisation_error_eq() {
+    assert_eq!(
+        StoreError::InitialisationError(Box::new(Poisoned)),
+        StoreError::InitialisationError(Box::new(Poisoned))
+    );
+    assert_ne!(
+        StoreError::InitialisationError(Box::new(Poisoned)),
+        StoreError::Read(Box::new(Poisoned))
+    );
+}
+
+#[test]
+fn get_batch_matches_get() {
+    let store = seeded_store();
+    let keys: Vec<&[u8]> = vec![&[0], &[3], &[42], &[9], &[3]];
+
+    let batch = store.get_batch(Keyspace::Clients, &keys).unwrap();
+    let individual = keys
+        .iter()
+        .map(|key| store.get(Keyspace::Clients, key).unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(batch, individual);
+    assert_eq!(
+        batch,
+        vec![
+            Some(vec![0, 0]),
+            Some(vec![3, 3]),
+            None,
+            Some(vec![9, 9]),
+            Some(vec![3, 3])
+        ]
+    );
+}
+
+#[test]
+fn get_batch_missing_keyspace() {
+    let store = seeded_store();
+    let keys: Vec<&[u8]> = vec![&[0], &[1]];
+
+    let batch = store.get_batch(Keyspace::Transactions, &keys).unwrap();
+    assert_eq!(batch, vec![None, None]);
+}
+
+#[test]
+fn get_batch_empty() {
+    let store = seeded_store();
+    let batch = store.get_batch(Keyspace::Clients, &[]).unwrap();
+    assert!(batch.is_empty());
+}
+
+#[cfg(feature = "rocks")]
+#[test]
+fn disk_store_keyspaces() {
+    let dir = TempDir::new("disk_store_keyspaces").unwrap();
+    let keyspaces = [
+        "tenant-a/clients",
+        "tenant-a/transactions",
+        "tenant-b/clients",
+        "tenant-b/transactions",
+    ];
+
+    {
+        let store = DiskStore::with_keyspaces(dir.path(), &keyspaces, 4).unwrap();
+        let db = store.delegate();
+        for (i, keyspace) in keyspaces.iter().enumerate() {
+            let cf = db.cf_handle(keyspace).unwrap();
+            db.put_cf(cf, [i as u8], [i as u8, 1]).unwrap();
+        }
+    }
+
+    // Reopening with the default keyspaces must open the existing families alongside them.
+    let store = DiskStore::new(dir.path()).unwrap();
+    store.put(Keyspace::Clients, &[1], &[2]).unwrap();
+    assert_eq!(store.get(Keyspace::Clients, &[1]), Ok(Some(vec![2])));
+
+    let db = store.delegate();
+    for (i, keyspace) in keyspaces.iter().enumerate() {
+        let cf = db.cf_handle(keyspace).unwrap();
+        assert_eq!(db.get_cf(cf, [i as u8]).unwrap(), Some(vec![i as u8, 1]));
+    }
+}
+
+#[cfg(feature = "rocks")]
+#[test]
+fn disk_store_read_only() {
+    let dir = TempDir::new("disk_store_read_only").unwrap();
+    {
+        let store = DiskStore::new(dir.path()).unwrap();
+        store.put(Keyspace::Clients, &[1], &[2]).unwrap();
+    }
+
+    let store = DiskStore::open_read_only(dir.path()).unwrap();
+    assert_eq!(store.get(Keyspace::Clients, &[1]), Ok(Some(vec![2])));
+    assert_eq!(store.get(Keyspace::Transactions, &[1]), Ok(None));
+    assert!(matches!(
+        store.put(Keyspace::Clients, &[3], &[4]),
+        Err(StoreError::Write(_))
+    ));
+    assert!(matches!(
+        store.delete(Keyspace::Clients, &[1]),
+        Err(StoreError::Write(_))
+    ));
+    assert_eq!(store.get(Keyspace::Clients, &[1]), Ok(Some(vec![2])));
+}
+
+#[cfg(feature = "rocks")]
+#[test]
+fn disk_store_locked() {
+    let dir = TempDir::new("disk_store_locked").unwrap();
+    let _store = DiskStore::new(dir.path()).unwrap();
+
+    assert!(matches!(
+        DiskStore::new(dir.path()),
+        Err(StoreError::Locked(path)) if path == dir.path()
+    ));
+}
+
+#[cfg(feature = "rocks")]
+#[test]
+fn disk_store_read_only_missing() {
+    let dir = TempDir::new("disk_store_read_only_missing").unwrap();
+    assert!(matches!(
+        DiskStore::open_read_only(dir.path().join("missing")),
+        Err(StoreError::InitialisationError(_))
+    ));
+}
+
+#[cfg(feature = "rocks")]
+#[test]
+fn disk_store_scan() {
+    let dir = TempDir::new("disk_store_scan").unwrap();
+    let store = DiskStore::new(dir.path()).unwrap();
+    for i in 0..10u8 {
+        store.put(Keyspace::Clients, &[i], &[i, i]).unwrap();
+    }
+    store.put(Keyspace::Transactions, &[42], &[1]).unwrap();
+
+    let expected = (0..10u8).map(|i| (vec![i], vec![i, i])).collect::<Vec<_>>();
+    assert_eq!(scanned(&store, Keyspace::Clients), expected);
+    assert_eq!(
+        scanned(&store, Keyspace::Transactions),
+        vec![(vec![42], vec![1])]
+    );
+}
+
+#[cfg(feature = "rocks")]
+#[test]
+fn disk_store_missing_keyspaces() {
+    let dir = TempDir::new("disk_store_missing_keyspaces").unwrap();
+    assert_missing_keyspaces(DiskStore::with_keyspaces(dir.path(), &[], 1).unwrap());
+}
+
+#[cfg(feature = "rocks")]
+#[test]
+fn disk_store_delete() {
+    let dir = TempDir::new("disk_store_delete").unwrap();
+    assert_delete(DiskStore::new(dir.path()).unwrap());
+}
+
+#[cfg(feature = "rocks")]
+#[test]
+fn disk_store_scan_keys() {
+    let dir = TempDir::new("disk_store_scan_keys").unwrap();
+    assert_scan_keys(DiskStore::new(dir.path()).unwrap());
+}
+
+#[cfg(feature = "rocks")]
+#[test]
+fn disk_store_write_batch() {
+    let dir = TempDir::new("disk_store_write_batch").unwrap();
+    assert_write_batch(DiskStore::new(dir.path()).unwrap());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_store_get_put() {
+    let dir = TempDir::new("sqlite_store_get_put").unwrap();
+    let store = SqliteStore::new(dir.path().join("store.db")).unwrap();
+
+    assert_eq!(store.get(Keyspace::Clients, &[1]), Ok(None));
+    store.put(Keyspace::Clients, &[1], &[2]).unwrap();
+    store.put(Keyspace::Clients, &[1], &[3]).unwrap();
+    assert_eq!(store.get(Keyspace::Clients, &[1]), Ok(Some(vec![3])));
+    assert_eq!(store.get(Keyspace::Transactions, &[1]), Ok(None));
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_store_scan() {
+    let dir = TempDir::new("sqlite_store_scan").unwrap();
+    let store = SqliteStore::new(dir.path().join("store.db")).unwrap();
+    for i in 0..10u8 {
+        store.put(Keyspace::Clients, &[i], &[i, i]).unwrap();
+    }
+    store.put(Keyspace::Transactions, &[42], &[1]).unwrap();
+
+    let expected = (0..10u8).map(|i| (vec![i], vec![i, i])).collect::<Vec<_>>();
+    assert_eq!(scanned(&store, Keyspace::Clients), expected);
+    assert_eq!(
+        scanned(&store, Keyspace::Transactions),
+        vec![(vec![42], vec![1])]
+    );
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_store_scan_keys() {
+    let dir = TempDir::new("sqlite_store_scan_keys").unwrap();
+    assert_scan_keys(SqliteStore::new(dir.path().join("store.db")).unwrap());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_store_missing_keyspaces() {
+    let dir = TempDir::new("sqlite_store_missing_keyspaces").unwrap();
+    let path = dir.path().join("store.db");
+    // An empty file is a database without any tables.
+    File::create(&path).unwrap();
+    assert_missing_keyspaces(SqliteStore::open_read_only(&path).unwrap());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_store_delete() {
+    let dir = TempDir::new("sqlite_store_delete").unwrap();
+    assert_delete(SqliteStore::new(dir.path().join("store.db")).unwrap());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_store_write_batch() {
+    let dir = TempDir::new("sqlite_store_write_batch").unwrap();
+    assert_write_batch(SqliteStore::new(dir.path().join("store.db")).unwrap());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_store_reopen() {
+    let dir = TempDir::new("sqlite_store_reopen").unwrap();
+    let path = dir.path().join("store.db");
+    {
+        let store = SqliteStore::new(&path).unwrap();
+        store.put(Keyspace::Clients, &[1], &[2]).unwrap();
+        store.commit().unwrap();
+    }
+
+    let store = SqliteStore::new(&path).unwrap();
+    assert_eq!(store.get(Keyspace::Clients, &[1]), Ok(Some(vec![2])));
+
+    let read_only = SqliteStore::open_read_only(&path).unwrap();
+    assert_eq!(read_only.get(Keyspace::Clients, &[1]), Ok(Some(vec![2])));
+    assert!(matches!(
+        read_only.put(Keyspace::Clients, &[3], &[4]),
+        Err(StoreError::Write(_))
+    ));
+}
+
+#[cfg(all(feature = "sqlite", not(feature = "scale-8")))]
+#[test]
+fn sqlite_store_write_state() {
+    let dir = TempDir::new("sqlite_store_write_state").unwrap();
+    let store = ClientStore::new(SqliteStore::new(dir.path().join("store.db")).unwrap());
+    store
+        .apply_transaction(Transaction::deposit(2, 1, 5.0))
+        .unwrap();
+    store
+        .apply_transaction(Transaction::deposit(1, 2, 10.0))
+        .unwrap();
+    store
+        .apply_transaction(Transaction::withdrawal(1, 3, 4.0))
+        .unwrap();
+
+    let mut output = Vec::new();
+    write_state(store, WriterConfig::default(), &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let mut lines = output.lines();
+    assert_eq!(
+        lines.next(),
+        Some("client,\tavailable,\theld,\ttotal,\tlocked")
+    );
+    let mut rows = lines.collect::<Vec<_>>();
+    rows.sort_unstable();
+    assert_eq!(
+        rows,
+        vec![
+            "1, 6.0000, 0.0000, 6.0000, false",
+            "2, 5.0000, 0.0000, 5.0000, false"
+        ]
+    );
+}
+
+/// Executes `transactions` through an IO task against `store`, counting their outcomes in
+/// `tally`. The task runs on its own runtime, which is shut down before returning, so that no
+/// client task still holds the store once this returns.
+fn run_to_completion<D>(store: ClientStore<D>, transactions: Vec<Transaction>, tally: Tally)
+where
+    D: StoreEngine + 'static,
+{
+    let runtime = Runtime::new().unwrap();
+    let result = runtime.block_on(async move {
+        let (tx, rx) = mpsc::channel(transactions.len());
+        for transaction in transactions {
+            tx.send(transaction).await.unwrap();
+        }
+        drop(tx);
+        IoTask::new(rx, store, IoConfig::default(), None)
+            .with_tally(tally)
+            .run(8)
+            .await
+    });
+    assert!(result.is_ok());
+}
+
+/// Freezes client 1 with a chargeback through an IO task against the store returned by `open`,
+/// then restarts against a newly opened store and asserts that the restored client rejects any
+/// further transfers.
+fn assert_frozen_after_restart<D, F>(open: F)
+where
+    D: StoreEngine + 'static,
+    F: Fn() -> D,
+{
+    let freeze = vec![
+        Transaction::deposit(1, 1, 10.0),
+        Transaction::deposit(1, 2, 5.0),
+        Transaction::dispute(1, 1),
+        Transaction::chargeback(1, 1),
+    ];
+    run_to_completion(ClientStore::new(open()), freeze, Tally::default());
+
+    let store = ClientStore::new(open());
+    let tally = Tally::default();
+    let transactions = vec![
+        Transaction::deposit(1, 3, 5.0),
+        Transaction::withdrawal(1, 4, 1.0),
+        Transaction::deposit(2, 5, 1.0),
+    ];
+    run_to_completion(store.clone(), transactions, tally.clone());
+
+    let outcomes = tally.outcomes();
+    assert_eq!(outcomes.rejected.deposit, 1);
+    assert_eq!(outcomes.rejected.withdrawal, 1);
+    assert_eq!(outcomes.applied.deposit, 1);
+    assert_eq!(
+        store.apply_transaction(Transaction::deposit(1, 6, 5.0)),
+        Err(ClientError::AccountFrozen)
+    );
+    let state = State::from(store.get_client_state(1).unwrap().unwrap());
+    assert_eq!((state.available, state.locked), (5.0, true));
+}
+
+#[test]
+fn mem_store_frozen_after_restart() {
+    // Clones of a memory store share its contents, so each clone stands in for a reopened store.
+    let store = MemStore::default();
+    assert_frozen_after_restart(|| store.clone());
+}
+
+#[cfg(feature = "rocks")]
+#[test]
+fn disk_store_frozen_after_restart() {
+    let dir = TempDir::new("disk_store_frozen_after_restart").unwrap();
+    assert_frozen_after_restart(|| DiskStore::new(dir.path()).unwrap());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_store_frozen_after_restart() {
+    let dir = TempDir::new("sqlite_store_frozen_after_restart").unwrap();
+    let path = dir.path().join("store.db");
+    assert_frozen_after_restart(|| SqliteStore::new(&path).unwrap());
+}
+
+/// A memory store that counts the number of times that it is committed.
+#[derive(Debug, Default, Clone)]
+struct CommitCountingStore {
+    inner: MemStore,
+    commits: Arc<AtomicUsize>,
+}
+
+impl StoreEngine for CommitCountingStore {
+    fn put(&self, keyspace: Keyspace, key: &[u8], value: &[u8]) -> Result<(), StoreError> {
+        self.inner.put(keyspace, key, value)
+    }
+
+    fn get(&self, keyspace: Keyspace, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        self.inner.get(keyspace, key)
+    }
+
+    fn delete(&self, keyspace: Keyspace, key: &[u8]) -> Result<(), StoreError> {
+        self.inner.delete(keyspace, key)
+    }
+
+    fn scan(&self, keyspace: Keyspace) -> Scan<'_> {
+        self.inner.scan(keyspace)
+    }
+
+    fn commit(&self) -> Result<(), StoreError> {
+        self.commits.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[test]
+fn commit_every() {
+    let engine = CommitCountingStore::default();
+    let commits = engine.commits.clone();
+    let mut store = ClientStore::new(engine);
+    store.set_commit_every(Some(4));
+
+    let mut client = ClientState::new(1);
+    for tx in 1..=7 {
+        let result = client.execute_transaction(Transaction::deposit(1, tx, 1.0), &store);
+        assert!(result.is_ok());
+    }
+    assert_eq!(commits.load(Ordering::SeqCst), 1);
+
+    // A rejected transaction is not applied, so it does not count towards the next commit.
+    let result = client.execute_transaction(Transaction::withdrawal(1, 8, 100.0), &store);
+    assert_eq!(result, Err(ClientError::InsufficientFunds));
+    assert_eq!(commits.load(Ordering::SeqCst), 1);
+
+    let result = client.execute_transaction(Transaction::deposit(1, 9, 1.0), &store);
+    assert!(result.is_ok());
+    assert_eq!(commits.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn no_commit_every() {
+    let engine = CommitCountingStore::default();
+    let commits = engine.commits.clone();
+    let store = ClientStore::new(engine);
+
+    let mut client = ClientState::new(1);
+    for tx in 1..=10 {
+        let result = client.execute_transaction(Transaction::deposit(1, tx, 1.0), &store);
+        assert!(result.is_ok());
+    }
+    assert_eq!(commits.load(Ordering::SeqCst), 0);
+}
+
+/// Applies a deposit with each ID in `txs` for client one through `store`.
+#[cfg(feature = "rocks")]
+fn apply_deposits<D>(store: &ClientStore<D>, txs: std::ops::RangeInclusive<u32>)
+where
+    D: StoreEngine,
+{
+    let mut client = store
+        .get_client_state(1)
+        .unwrap()
+        .unwrap_or_else(|| ClientState::new(1));
+    for tx in txs {
+        let result = client.execute_transaction(Transaction::deposit(1, tx, 1.0), store);
+        assert!(result.is_ok());
+    }
+}
+
+#[cfg(feature = "rocks")]
+#[test]
+fn disk_store_commit_every() {
+    let dir = TempDir::new("disk_store_commit_every").unwrap();
+    let crashed = TempDir::new("disk_store_commit_every_crashed").unwrap();
+    let mut engine = DiskStore::new(dir.path()).unwrap();
+    engine.set_write_ahead_log(false);
+    let mut store = ClientStore::new(engine);
+    store.set_commit_every(Some(4));
+    apply_deposits(&store, 1..=10);
+
+    // Copying the files of the open store captures what a crash would leave on the disk, as
+    // closing the store would flush the writes made since the last commit.
+    for entry in std::fs::read_dir(dir.path()).unwrap() {
+        let entry = entry.unwrap();
+        std::fs::copy(entry.path(), crashed.path().join(entry.file_name())).unwrap();
+    }
+
+    // The transactions up to the last commit survive, while the two after it are lost.
+    let store = ClientStore::new(DiskStore::new(crashed.path()).unwrap());
+    for tx in 1..=8 {
+        assert!(store.get_transaction(1, tx).unwrap().is_some());
+    }
+    for tx in 9..=10 {
+        assert_eq!(store.get_transaction(1, tx), Ok(None));
+    }
+    let state = State::from(store.get_client_state(1).unwrap().unwrap());
+    assert_eq!(state.available, 8.0);
+}
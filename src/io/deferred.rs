@@ -0,0 +1,76 @@
+use crate::transaction::Transaction;
+use fnv::FnvHashMap;
+
+/// A bounded buffer of disputes, resolves and chargebacks that referenced a transaction which had
+/// not been received yet. Each is held until the referenced transaction is applied, so that a feed
+/// which is slightly out of order does not lose a valid dispute.
+#[derive(Debug)]
+pub struct DeferredDisputes {
+    /// The maximum number of transactions that may be deferred at once.
+    capacity: usize,
+    /// The number of transactions that are currently deferred.
+    len: usize,
+    /// The deferred transactions, in the order that they were received, keyed by their client and
+    /// the ID of the transaction that they reference.
+    pending: FnvHashMap<(u16, u32), Vec<Transaction>>,
+}
+
+impl DeferredDisputes {
+    /// Constructs a new buffer that defers up to `capacity` transactions at once.
+    pub fn new(capacity: usize) -> DeferredDisputes {
+        DeferredDisputes {
+            capacity,
+            len: 0,
+            pending: FnvHashMap::default(),
+        }
+    }
+
+    /// Returns whether `transaction` is a dispute, resolve or chargeback of a transaction that
+    /// already has a transaction deferred on it. Such a transaction must also be deferred so that
+    /// it is executed after those that were received before it.
+    pub fn is_pending(&self, transaction: &Transaction) -> bool {
+        match transaction {
+            Transaction::Disputed(_) => self.pending.contains_key(&key(transaction)),
+            Transaction::Transfer(_) | Transaction::Internal(_) => false,
+        }
+    }
+
+    /// Defers `transaction` until the transaction that it references is applied. Returns the
+    /// transaction if the buffer is full.
+    pub fn defer(&mut self, transaction: Transaction) -> Result<(), Transaction> {
+        if self.len >= self.capacity {
+            return Err(transaction);
+        }
+        self.len += 1;
+        self.pending
+            .entry(key(&transaction))
+            .or_default()
+            .push(transaction);
+        Ok(())
+    }
+
+    /// Removes every transaction that was deferred on the applied transfer `transaction`, in the
+    /// order that they were received.
+    pub fn release(&mut self, transaction: &Transaction) -> Vec<Transaction> {
+        let released = self.pending.remove(&key(transaction)).unwrap_or_default();
+        self.len -= released.len();
+        released
+    }
+
+    /// Removes every transaction that is still deferred, ordered by client and then by the ID of
+    /// the transaction that they reference.
+    pub fn drain(&mut self) -> Vec<Transaction> {
+        let mut pending = self.pending.drain().collect::<Vec<_>>();
+        pending.sort_by_key(|(key, _)| *key);
+        self.len = 0;
+        pending
+            .into_iter()
+            .flat_map(|(_, transactions)| transactions)
+            .collect()
+    }
+}
+
+/// Returns the client of `transaction` and the ID of the transaction that it is or references.
+fn key(transaction: &Transaction) -> (u16, u32) {
+    (transaction.client_id(), transaction.id())
+}
@@ -0,0 +1,115 @@
+use crate::client::{ClientError, ClientStore, Effect, Fixed};
+use crate::db::{StoreEngine, StoreError};
+use crate::io::ClientHandle;
+use crate::transaction::{
+    DisputeStatus, DisputedTransaction, DisputedTransactionKind, Transaction,
+};
+
+/// A system-wide risk limit on the total amount of funds that are under dispute across every
+/// client. This is distinct from any limit on the number of disputes that a single client may
+/// have open.
+///
+/// The total is only tracked for disputes that are executed while the limit is in place. It is held
+/// in the same fixed point units as an account's funds, so that it does not drift as disputes are
+/// opened and closed.
+#[derive(Debug)]
+pub struct DisputeCap<D>
+where
+    D: StoreEngine,
+{
+    /// The maximum total amount of funds that may be under dispute.
+    ceiling: Fixed,
+    /// The total amount of funds that are currently under dispute.
+    disputed: Fixed,
+    /// The store to look up the amount of a referenced transaction in.
+    store: ClientStore<D>,
+}
+
+impl<D> DisputeCap<D>
+where
+    D: StoreEngine,
+{
+    /// Constructs a new limit that rejects any dispute which would push the total amount of funds
+    /// under dispute above `ceiling`. The amount of a disputed transaction is looked up in `store`.
+    pub fn new(ceiling: f64, store: ClientStore<D>) -> DisputeCap<D> {
+        DisputeCap {
+            ceiling: Fixed::from_f64(ceiling),
+            disputed: Fixed::default(),
+            store,
+        }
+    }
+
+    /// Executes `transaction` against `handle`. If it is a dispute that would push the total above
+    /// the ceiling, then it is rejected without being executed. Otherwise, the total is updated if
+    /// the transaction is a dispute, resolve or chargeback that was applied.
+    pub async fn execute(
+        &mut self,
+        handle: &ClientHandle,
+        transaction: Transaction,
+    ) -> Result<Effect, ClientError> {
+        let referenced = self.referenced(&transaction)?;
+
+        if let Some((DisputedTransactionKind::Dispute, amount)) = &referenced {
+            match self.disputed.checked_add(*amount) {
+                Ok(disputed) if disputed <= self.ceiling => {}
+                _ => return Err(ClientError::SystemDisputeCapExceeded),
+            }
+        }
+
+        let result = handle.execute_transaction(transaction).await;
+
+        if let (Ok(Effect::Applied), Some((kind, amount))) = (&result, referenced) {
+            self.disputed = match kind {
+                // The sum was checked against the ceiling before the dispute was executed.
+                DisputedTransactionKind::Dispute => self.disputed.saturating_add(amount),
+                // A dispute that was opened before the limit was in place, such as by an earlier
+                // run against the same store, was never added to the total.
+                DisputedTransactionKind::Resolve | DisputedTransactionKind::Chargeback => {
+                    self.disputed.saturating_sub(amount).max(Fixed::default())
+                }
+            };
+        }
+
+        result
+    }
+
+    /// Returns the kind of `transaction` and the amount of the transfer that it references which is
+    /// disputed, if it is a dispute, resolve or chargeback that references a stored transfer. This
+    /// is the amount that a dispute disputes, or the amount that is under dispute for a resolve or
+    /// chargeback. A resolve or chargeback only references a transfer that is under dispute.
+    fn referenced(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<Option<(DisputedTransactionKind, Fixed)>, StoreError> {
+        match transaction {
+            Transaction::Disputed(DisputedTransaction {
+                kind,
+                client,
+                tx,
+                amount,
+            }) => match self.store.get_transaction(*client, *tx)? {
+                Some(Transaction::Transfer(transfer)) => match kind {
+                    DisputedTransactionKind::Dispute => Ok(Some((
+                        *kind,
+                        Fixed::from_f64(amount.unwrap_or(transfer.amount)),
+                    ))),
+                    DisputedTransactionKind::Resolve | DisputedTransactionKind::Chargeback
+                        if transfer.disputed == DisputeStatus::Disputed =>
+                    {
+                        Ok(Some((
+                            *kind,
+                            Fixed::from_f64(transfer.amount_under_dispute()),
+                        )))
+                    }
+                    // A resolve or chargeback of a transfer that is not under dispute has nothing
+                    // to release, even if its client accepts it without effect.
+                    DisputedTransactionKind::Resolve | DisputedTransactionKind::Chargeback => {
+                        Ok(None)
+                    }
+                },
+                _ => Ok(None),
+            },
+            Transaction::Transfer(_) | Transaction::Internal(_) => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use crate::transaction::{Transaction, TransferTransaction, TransferTransactionKind};
+use crate::warnings::{Warning, WarningCollector};
+use fnv::FnvHashMap;
+use std::collections::VecDeque;
+
+/// A withdrawal that drained a deposit which was made shortly before it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Drain {
+    /// The client that made both transactions.
+    pub client: u16,
+    /// The ID of the deposit that was drained.
+    pub deposit: u32,
+    /// The ID of the withdrawal that drained the deposit.
+    pub withdrawal: u32,
+}
+
+/// A fraud heuristic that tracks each client's recent transactions and flags any withdrawal that
+/// is equal to a deposit made within the client's previous `window` transactions.
+///
+/// This is purely an observability aid; flagged transactions are still executed.
+#[derive(Debug)]
+pub struct DrainDetector {
+    /// The number of a client's previous transactions to search for a matching deposit.
+    window: usize,
+    /// Each client's most recent transactions. Deposits are recorded as their ID and amount.
+    recent: FnvHashMap<u16, VecDeque<Option<(u32, f64)>>>,
+    /// If set, then every drain that is flagged is recorded as a warning.
+    warnings: Option<WarningCollector>,
+}
+
+impl DrainDetector {
+    /// Constructs a new detector that flags withdrawals that drain a deposit made within the
+    /// client's previous `window` transactions. Each flagged drain is recorded in `warnings`, if it
+    /// is provided.
+    pub fn new(window: usize, warnings: Option<WarningCollector>) -> DrainDetector {
+        DrainDetector {
+            window,
+            recent: FnvHashMap::default(),
+            warnings,
+        }
+    }
+
+    /// Records that `transaction` was executed. Returns the deposit that it drained if it is a
+    /// withdrawal that matches a recent deposit. Each deposit is only flagged once.
+    pub fn observe(&mut self, transaction: &Transaction) -> Option<Drain> {
+        let recent = self.recent.entry(transaction.client_id()).or_default();
+
+        let (entry, drain) = match transaction {
+            Transaction::Transfer(TransferTransaction {
+                kind: TransferTransactionKind::Deposit,
+                tx,
+                amount,
+                ..
+            }) => (Some((*tx, *amount)), None),
+            Transaction::Transfer(TransferTransaction {
+                kind: TransferTransactionKind::Withdrawal,
+                client,
+                tx,
+                amount,
+                ..
+            }) => {
+                let drained = recent
+                    .iter_mut()
+                    .rev()
+                    .find(|entry| matches!(entry, Some((_, deposited)) if deposited == amount));
+                let drain = drained.and_then(Option::take).map(|(deposit, _)| Drain {
+                    client: *client,
+                    deposit,
+                    withdrawal: *tx,
+                });
+                (None, drain)
+            }
+            Transaction::Disputed(_) | Transaction::Internal(_) => (None, None),
+        };
+
+        recent.push_back(entry);
+        if recent.len() > self.window {
+            recent.pop_front();
+        }
+
+        if let (Some(drain), Some(warnings)) = (&drain, &self.warnings) {
+            warnings.record(Warning::DrainPattern {
+                client: drain.client,
+                deposit: drain.deposit,
+                withdrawal: drain.withdrawal,
+            });
+        }
+        drain
+    }
+}
@@ -0,0 +1,85 @@
+#[cfg(feature = "latency")]
+use hdrhistogram::Histogram;
+#[cfg(feature = "latency")]
+use std::convert::TryFrom;
+#[cfg(feature = "latency")]
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+/// The number of significant figures that latencies are recorded to, which records each sample to
+/// within 1% of its value.
+#[cfg(feature = "latency")]
+const SIGNIFICANT_FIGURES: u8 = 2;
+
+/// A record of how long each transaction took to execute, from being sent to its client until its
+/// result was received. This includes the time that it waited in the client's channel and the
+/// store IO of executing it. Latencies are recorded in microseconds. Clones share the same
+/// histogram, so that it may be read once the IO task has completed.
+#[cfg(feature = "latency")]
+#[derive(Debug, Clone)]
+pub struct Latency {
+    histogram: Arc<Mutex<Histogram<u64>>>,
+}
+
+#[cfg(feature = "latency")]
+impl Default for Latency {
+    fn default() -> Self {
+        let histogram = Histogram::new_with_bounds(1, u64::MAX, SIGNIFICANT_FIGURES)
+            .expect("The histogram's bounds are valid");
+        Latency {
+            histogram: Arc::new(Mutex::new(histogram)),
+        }
+    }
+}
+
+#[cfg(feature = "latency")]
+impl Latency {
+    /// Records that each of `count` transactions took `latency` to execute.
+    pub fn record(&self, latency: Duration, count: usize) {
+        let micros = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+        self.histogram
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .saturating_record_n(micros, count as u64);
+    }
+
+    /// Returns the percentiles of the latencies that have been recorded so far.
+    pub fn summary(&self) -> LatencySummary {
+        let histogram = self
+            .histogram
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let percentile = |quantile| Duration::from_micros(histogram.value_at_quantile(quantile));
+        LatencySummary {
+            count: histogram.len(),
+            p50: percentile(0.5),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+        }
+    }
+}
+
+/// Latencies can only be recorded with the `latency` feature. Without it, no `Latency` can be
+/// constructed, so a client handle never records its transactions.
+#[cfg(not(feature = "latency"))]
+#[derive(Debug, Clone)]
+pub enum Latency {}
+
+#[cfg(not(feature = "latency"))]
+impl Latency {
+    /// Records that each of `count` transactions took `latency` to execute.
+    pub fn record(&self, _latency: Duration, _count: usize) {
+        match *self {}
+    }
+}
+
+/// Percentiles of the latencies that were recorded in a `Latency`.
+#[cfg(feature = "latency")]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LatencySummary {
+    /// The number of samples that were recorded.
+    pub count: u64,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+}
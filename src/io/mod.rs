@@ -0,0 +1,1357 @@
+#[cfg(test)]
+mod tests;
+
+mod deferred;
+mod dispute_cap;
+mod drain;
+mod latency;
+mod tally;
+mod worker;
+
+pub use latency::Latency;
+#[cfg(feature = "latency")]
+pub use latency::LatencySummary;
+pub use tally::{Outcomes, Tally};
+
+use crate::client::{
+    Client, ClientError, ClientRequest, ClientState, ClientStore, CorruptStatePolicy, Effect,
+};
+use crate::db::{StoreEngine, StoreError};
+use crate::io::deferred::DeferredDisputes;
+use crate::io::dispute_cap::DisputeCap;
+use crate::io::drain::DrainDetector;
+use crate::io::worker::{WorkerPool, WorkerRequest};
+use crate::parser::rejects::{Rejection, RejectsError, RejectsWriter};
+use crate::transaction::{InternalTransfer, Transaction};
+use crate::warnings::{Warning, WarningCollector};
+use fnv::FnvHashMap;
+use futures::future::pending;
+use futures::{Stream, StreamExt};
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use thiserror::Error;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{event, span, Level};
+use tracing_futures::Instrument;
+
+const DEFAULT_MAX_CLIENTS: usize = 2048;
+const CLIENT_TASK: &str = "Client task";
+const CLIENT_STOPPED: &str = "Client stopped unexpectedly";
+const NO_RESPONSE: &str = "No response received from client";
+const CORRUPT_STATE: &str = "Client state is corrupt. Starting a new client";
+const FLUSH_FAILED: &str = "Failed to flush client state";
+const DRAIN_PATTERN: &str = "Withdrawal drained a recent deposit";
+const IDLE_CLIENT: &str = "Stopped an idle client";
+const UNMATCHED_DISPUTE: &str =
+    "The transaction referenced by a deferred dispute was never received";
+
+#[derive(Error, Debug)]
+pub enum IoError {
+    #[error("An error was produced by the store: `{0}`")]
+    Store(StoreError),
+    #[error("A client fatally errored: `{0}`")]
+    Client(ClientError),
+    #[error("Transactions are not sorted by client. Client `{0}` was seen after a higher client")]
+    Unsorted(u16),
+    #[error("An error was produced when recording a rejected transaction: `{0}`")]
+    Rejects(RejectsError),
+}
+
+impl From<StoreError> for IoError {
+    fn from(e: StoreError) -> Self {
+        IoError::Store(e)
+    }
+}
+
+impl From<RejectsError> for IoError {
+    fn from(e: RejectsError) -> Self {
+        IoError::Rejects(e)
+    }
+}
+
+/// A handle to a client that is currently running.
+#[derive(Clone)]
+struct ClientHandle {
+    /// A sender for forwarding transactions to.
+    tx: RequestSender,
+    /// If set, then the time taken to execute each transaction is recorded in it.
+    latency: Option<Latency>,
+}
+
+/// Where the requests that are sent through a client handle are served.
+#[derive(Clone)]
+enum RequestSender {
+    /// The client is run in its own task.
+    Task {
+        _handle: Arc<JoinHandle<()>>,
+        tx: mpsc::Sender<ClientRequest>,
+    },
+    /// The client is held by the worker task whose shard it is in.
+    Worker {
+        id: u16,
+        tx: mpsc::Sender<WorkerRequest>,
+    },
+}
+
+impl RequestSender {
+    /// Sends `request` to the client.
+    async fn send(&self, request: ClientRequest) {
+        match self {
+            RequestSender::Task { tx, .. } => tx.send(request).await.expect(CLIENT_STOPPED),
+            RequestSender::Worker { id, tx } => tx
+                .send(WorkerRequest::Serve {
+                    client: *id,
+                    request,
+                })
+                .await
+                .expect(CLIENT_STOPPED),
+        }
+    }
+}
+
+/// Loads the state of the client with `id` from `store`, or constructs a new state if the client
+/// has not previously been run. If `warnings` is provided, then a corrupt state that is treated as
+/// new is recorded in it.
+///
+/// If the client's previous state cannot be deserialized, either because it is invalid or it has
+/// been truncated, then the store's `CorruptStatePolicy` determines whether an error is returned
+/// or a new state is constructed.
+fn load_state<D>(
+    id: u16,
+    store: &ClientStore<D>,
+    warnings: Option<&WarningCollector>,
+) -> Result<ClientState, StoreError>
+where
+    D: StoreEngine,
+{
+    store.mark_touched(id);
+
+    match store.get_client_state(id) {
+        Ok(state) => Ok(state.unwrap_or_else(|| ClientState::new(id))),
+        Err(error @ (StoreError::Deserialize(_) | StoreError::TruncatedRecord { .. }))
+            if store.policy().corrupt_state == CorruptStatePolicy::TreatAsNew =>
+        {
+            event!(Level::WARN, CORRUPT_STATE, ?id, ?error);
+            if let Some(warnings) = warnings {
+                warnings.record(Warning::CorruptState { client: id });
+            }
+            Ok(ClientState::new(id))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+impl ClientHandle {
+    /// Initialises a new client instance from `store` if it has previously been run. Or constructs
+    /// a new client instance. Returns either a handle that can be used to forward transactions to
+    /// or an initialisation error. If `latency` is provided, then the time taken to execute each
+    /// transaction through the handle is recorded in it. The state is loaded as `load_state` does,
+    /// recording a corrupt state in `warnings` if it is provided.
+    fn new<D>(
+        id: u16,
+        store: ClientStore<D>,
+        channel_size: usize,
+        latency: Option<Latency>,
+        warnings: Option<&WarningCollector>,
+    ) -> Result<ClientHandle, StoreError>
+    where
+        D: StoreEngine + 'static,
+    {
+        let (tx, rx) = mpsc::channel(channel_size);
+        let state = load_state(id, &store, warnings)?;
+        let client = Client::with_state(state, rx, store);
+
+        let task = tokio::spawn(async move {
+            client
+                .run()
+                .instrument(span!(Level::INFO, CLIENT_TASK, ?id))
+                .await;
+        });
+
+        Ok(ClientHandle {
+            tx: RequestSender::Task {
+                _handle: Arc::new(task),
+                tx,
+            },
+            latency,
+        })
+    }
+
+    /// Starts the client with `id` in the shard of its worker in `workers`, loading its state from
+    /// `store` as `new` does. Returns either a handle that forwards transactions to the worker or
+    /// an initialisation error.
+    async fn on_worker<D>(
+        id: u16,
+        workers: &WorkerPool,
+        store: &ClientStore<D>,
+        latency: Option<Latency>,
+        warnings: Option<&WarningCollector>,
+    ) -> Result<ClientHandle, StoreError>
+    where
+        D: StoreEngine,
+    {
+        let state = load_state(id, store, warnings)?;
+        let tx = workers.sender(id);
+        tx.send(WorkerRequest::Start(state))
+            .await
+            .expect(CLIENT_STOPPED);
+
+        Ok(ClientHandle {
+            tx: RequestSender::Worker { id, tx },
+            latency,
+        })
+    }
+
+    /// Executes `transaction` against this client handle. Returning the result of the execution.
+    async fn execute_transaction(&self, transaction: Transaction) -> Result<Effect, ClientError> {
+        let started = Instant::now();
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send(ClientRequest::Execute {
+                transaction,
+                callback: tx,
+            })
+            .await;
+        let result = rx.await.expect(NO_RESPONSE);
+
+        if let Some(latency) = &self.latency {
+            latency.record(started.elapsed(), 1);
+        }
+        result
+    }
+
+    /// Executes `transactions` in order against this client handle. Returning the result of each
+    /// execution, up to and including the first fatal error. Each executed transaction is recorded
+    /// as taking as long as the whole batch, as its result is not received until then.
+    async fn execute_batch(
+        &self,
+        transactions: Vec<Transaction>,
+    ) -> Vec<Result<Effect, ClientError>> {
+        let started = Instant::now();
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send(ClientRequest::ExecuteBatch {
+                transactions,
+                callback: tx,
+            })
+            .await;
+        let results = rx.await.expect(NO_RESPONSE);
+
+        if let Some(latency) = &self.latency {
+            latency.record(started.elapsed(), results.len());
+        }
+        results
+    }
+
+    /// Requests that this client persists its current state. Returning the result of persisting
+    /// it.
+    async fn flush(&self) -> Result<(), StoreError> {
+        let (tx, rx) = oneshot::channel();
+        self.tx.send(ClientRequest::Flush { callback: tx }).await;
+        rx.await.expect(NO_RESPONSE)
+    }
+
+    /// Requests that this client reverts the withdrawal `tx` that it executed. Returning the result
+    /// of reverting it.
+    async fn revert(&self, tx: u32) -> Result<(), ClientError> {
+        let (callback, rx) = oneshot::channel();
+        self.tx.send(ClientRequest::Revert { tx, callback }).await;
+        rx.await.expect(NO_RESPONSE)
+    }
+
+    /// Returns a copy of this client's current state, once it has executed every transaction that
+    /// it was sent before the request.
+    async fn snapshot(&self) -> ClientState {
+        let (tx, rx) = oneshot::channel();
+        self.tx.send(ClientRequest::Snapshot { callback: tx }).await;
+        rx.await.expect(NO_RESPONSE)
+    }
+}
+
+/// A request for the current state of a client, which is served by an IO task without altering
+/// the state.
+#[derive(Debug)]
+pub struct SnapshotRequest {
+    /// The ID of the client to read the state of.
+    pub client: u16,
+    /// A callback to provide the client's state, or `None` if it has never been persisted.
+    pub callback: oneshot::Sender<Result<Option<ClientState>, StoreError>>,
+}
+
+/// An input that is received by an IO task.
+enum Input {
+    Batch(Vec<Transaction>),
+    Snapshot(SnapshotRequest),
+}
+
+/// A part of a batch. Either transactions that may be grouped by client, or a transfer that must
+/// be executed between its two clients on its own.
+enum Segment {
+    Transactions(Vec<Transaction>),
+    Transfer(InternalTransfer),
+}
+
+/// Configuration for an IO task.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct IoConfig {
+    /// Whether the transactions are sorted by client ID. If set, then each client is run to
+    /// completion before the next client is started and only a single client is held in memory.
+    /// An error is returned if a client is seen after a client with a higher ID.
+    pub assume_sorted: bool,
+    /// Whether to make a best-effort attempt to persist the state of every running client before
+    /// a fatal error is returned.
+    pub flush_on_error: bool,
+    /// If set, then a warning is logged whenever a withdrawal is equal to a deposit that was made
+    /// within the client's previous `n` transactions.
+    pub drain_window: Option<usize>,
+    /// If set, then up to `n` transactions that are ready are received at once, grouped by
+    /// client and dispatched to each client as a single batch. Each client's transactions are
+    /// still executed in order.
+    pub micro_batch: Option<usize>,
+    /// If set, then any dispute that would push the total amount of funds under dispute across
+    /// every client above this ceiling is rejected. Transactions are then dispatched one at a time,
+    /// as each dispute must be checked against the total when it is executed.
+    pub max_disputed_total: Option<f64>,
+    /// If set, then any running client that has not been sent a transaction for this long is
+    /// stopped when the next transaction is received, rather than being kept running until it is
+    /// evicted from the cache. Its state has already been persisted, so it is restored from the
+    /// store if it is sent another transaction. This has no effect if the transactions are sorted
+    /// by client.
+    pub idle_timeout: Option<Duration>,
+    /// If set, then a dispute, resolve or chargeback of a transaction that has not been received
+    /// yet is held, up to `n` at once, and executed once the transaction is applied. Any that are
+    /// still held when the input ends are rejected as not found. Transactions are then dispatched
+    /// one at a time, as each result must be checked before the next transaction is executed.
+    pub defer_disputes: Option<usize>,
+    /// If set, then clients are sharded by ID across this many worker tasks, each of which holds
+    /// the state of every client in its shard, rather than each client being run in its own task
+    /// and held in an LRU cache. A client is then never stopped, so `idle_timeout` has no effect.
+    /// Each client's transactions are still executed in order. This has no effect if the
+    /// transactions are sorted by client.
+    pub shard_workers: Option<usize>,
+    /// If set, then up to this many running clients are held in the LRU cache, rather than 2048.
+    /// At least one client is always held. The least recently used client is stopped when the
+    /// cache is full; its state has already been persisted, so it is restored from the store if
+    /// it is sent another transaction. This has no effect if the transactions are sorted by client
+    /// or `shard_workers` is set.
+    pub max_clients: Option<usize>,
+    /// Whether to index the clients that have a state in the store before any transaction is
+    /// received, so that a new client is started without reading the store. The index is built
+    /// by scanning every stored client's key, and every state that is written is then added to it
+    /// under a lock, so this only pays off where a read of a missing state is costly.
+    pub index_clients: bool,
+}
+
+/// A client in the cache of running clients.
+struct CachedClient {
+    handle: ClientHandle,
+    /// When the client was last sent a transaction.
+    last_used: SystemTime,
+}
+
+/// An IO task between a reader (`rx`) and clients.
+#[derive(Debug)]
+pub struct IoTask<D>
+where
+    D: StoreEngine,
+{
+    rx: mpsc::Receiver<Transaction>,
+    snapshots: Option<mpsc::Receiver<SnapshotRequest>>,
+    store: ClientStore<D>,
+    config: IoConfig,
+    rejects: Option<RejectsWriter>,
+    observers: Observers,
+}
+
+/// The optional observers of an IO task's execution.
+#[derive(Debug, Default)]
+struct Observers {
+    tally: Option<Tally>,
+    latency: Option<Latency>,
+    warnings: Option<WarningCollector>,
+}
+
+impl<D> IoTask<D>
+where
+    D: StoreEngine,
+{
+    /// Constructs a new IO task that will listen on `rx`, run clients on demand as specified by
+    /// `config` and execute all transactions that are received. If `rejects` is provided, then
+    /// every transaction that is rejected by a client is written to it.
+    pub fn new(
+        rx: mpsc::Receiver<Transaction>,
+        store: ClientStore<D>,
+        config: IoConfig,
+        rejects: Option<RejectsWriter>,
+    ) -> Self {
+        IoTask {
+            rx,
+            snapshots: None,
+            store,
+            config,
+            rejects,
+            observers: Observers::default(),
+        }
+    }
+
+    /// Counts the outcome of every transaction that is executed in `tally`.
+    pub fn with_tally(mut self, tally: Tally) -> Self {
+        self.observers.tally = Some(tally);
+        self
+    }
+
+    /// Records the time taken to execute every transaction in `latency`.
+    #[cfg(feature = "latency")]
+    pub fn with_latency(mut self, latency: Latency) -> Self {
+        self.observers.latency = Some(latency);
+        self
+    }
+
+    /// Records every warning that is raised while executing transactions in `warnings`.
+    pub fn with_warnings(mut self, warnings: WarningCollector) -> Self {
+        self.observers.warnings = Some(warnings);
+        self
+    }
+
+    /// Serves every request that is received on `snapshots` with the current state of its client,
+    /// while transactions are executed. Transactions that have been received are always executed
+    /// first, so a snapshot reflects every transaction that was sent to the task before it.
+    pub fn with_snapshots(mut self, snapshots: mpsc::Receiver<SnapshotRequest>) -> Self {
+        self.snapshots = Some(snapshots);
+        self
+    }
+}
+
+impl<D> IoTask<D>
+where
+    D: StoreEngine + 'static,
+{
+    /// Runs this IO task until completion or an error is produced. Any fatal error (a store error
+    /// or a fatal client error) will cause this task to terminate.
+    ///
+    /// Any event that is received will be forwarded to either a new client instance that is
+    /// restored from its previous state if it exists, or a new instance is constructed. If
+    /// `index_clients` is set, then the clients that have a state in the store are indexed before
+    /// any event is received, so that a new instance is constructed without reading the store.
+    ///
+    /// Running clients are stored in an LRU cache to reduce the memory footprint of this
+    /// application and to not keep old clients running. If the transactions are assumed to be
+    /// sorted by client, then only the current client is kept running. If `shard_workers` is set,
+    /// then every client is instead held by one of a fixed pool of worker tasks.
+    pub async fn run(self, channel_size: usize) -> Result<(), IoError> {
+        let IoTask {
+            rx,
+            snapshots,
+            store,
+            config,
+            rejects,
+            observers,
+        } = self;
+        let requests = ReceiverStream::new(rx);
+        if config.index_clients {
+            store.index_clients()?;
+        }
+
+        if config.assume_sorted {
+            run_sorted(
+                requests,
+                snapshots,
+                store,
+                channel_size,
+                config,
+                rejects,
+                observers,
+            )
+            .await
+        } else if config.shard_workers.is_some() {
+            run_sharded(
+                requests,
+                snapshots,
+                store,
+                channel_size,
+                config,
+                rejects,
+                observers,
+            )
+            .await
+        } else {
+            run_cached(
+                requests,
+                snapshots,
+                store,
+                channel_size,
+                config,
+                rejects,
+                observers,
+            )
+            .await
+        }
+    }
+}
+
+/// Executes every transaction in `requests` against clients that are held in an LRU cache, and
+/// serves any request on `snapshots` from the cached client or the store.
+async fn run_cached<D>(
+    requests: ReceiverStream<Transaction>,
+    mut snapshots: Option<mpsc::Receiver<SnapshotRequest>>,
+    store: ClientStore<D>,
+    channel_size: usize,
+    config: IoConfig,
+    rejects: Option<RejectsWriter>,
+    observers: Observers,
+) -> Result<(), IoError>
+where
+    D: StoreEngine + 'static,
+{
+    let Observers {
+        tally,
+        latency,
+        warnings,
+    } = observers;
+    let max_clients = config.max_clients.unwrap_or(DEFAULT_MAX_CLIENTS).max(1);
+    let mut clients: LruCache<u16, CachedClient> = LruCache::new(max_clients);
+    let mut drains = config
+        .drain_window
+        .map(|window| DrainDetector::new(window, warnings.clone()));
+    let mut cap = config
+        .max_disputed_total
+        .map(|ceiling| DisputeCap::new(ceiling, store.clone()));
+    let mut deferred = config.defer_disputes.map(DeferredDisputes::new);
+    let mut batches = requests.ready_chunks(config.micro_batch.unwrap_or(1));
+
+    while let Some(input) = next_input(&mut batches, snapshots.as_mut()).await {
+        let batch = match input {
+            Input::Batch(batch) => batch,
+            Input::Snapshot(request) => {
+                // Peeking leaves the client's position in the cache unchanged.
+                let handle = clients.peek(&request.client).map(|client| &client.handle);
+                serve_snapshot(request, handle, &store).await;
+                continue;
+            }
+        };
+        let now = store.clock().now();
+        if let Some(timeout) = config.idle_timeout {
+            stop_idle(&mut clients, now, timeout);
+        }
+
+        for segment in split_transfers(batch) {
+            let transactions = match segment {
+                Segment::Transactions(transactions) => transactions,
+                Segment::Transfer(transfer) => {
+                    let handles = cached_handle(
+                        &mut clients,
+                        transfer.client,
+                        now,
+                        &store,
+                        channel_size,
+                        latency.as_ref(),
+                        warnings.as_ref(),
+                    )
+                    .and_then(|source| {
+                        let destination = cached_handle(
+                            &mut clients,
+                            transfer.to,
+                            now,
+                            &store,
+                            channel_size,
+                            latency.as_ref(),
+                            warnings.as_ref(),
+                        )?;
+                        Ok((source, destination))
+                    });
+                    let result = match handles {
+                        Ok((source, destination)) => {
+                            execute_transfer(
+                                &source,
+                                &destination,
+                                transfer,
+                                rejects.as_ref(),
+                                cap.as_mut(),
+                                tally.as_ref(),
+                                deferred.as_mut(),
+                            )
+                            .await
+                        }
+                        Err(e) => Err(e.into()),
+                    };
+                    if let Err(e) = result {
+                        if config.flush_on_error {
+                            flush_cached(&clients).await;
+                        }
+                        return Err(e);
+                    }
+                    continue;
+                }
+            };
+
+            for (client_id, transactions) in group_consecutive(transactions) {
+                let rejects = rejects.as_ref();
+                let result = match clients.get_mut(&client_id) {
+                    Some(client) => {
+                        client.last_used = now;
+                        execute_all(
+                            &client.handle,
+                            transactions,
+                            rejects,
+                            drains.as_mut(),
+                            cap.as_mut(),
+                            tally.as_ref(),
+                            deferred.as_mut(),
+                        )
+                        .await
+                    }
+                    None => {
+                        let handle = ClientHandle::new(
+                            client_id,
+                            store.clone(),
+                            channel_size,
+                            latency.clone(),
+                            warnings.as_ref(),
+                        );
+                        match handle {
+                            Ok(handle) => {
+                                let result = execute_all(
+                                    &handle,
+                                    transactions,
+                                    rejects,
+                                    drains.as_mut(),
+                                    cap.as_mut(),
+                                    tally.as_ref(),
+                                    deferred.as_mut(),
+                                )
+                                .await;
+                                let client = CachedClient {
+                                    handle,
+                                    last_used: now,
+                                };
+                                let _removed = clients.put(client_id, client);
+                                result
+                            }
+                            Err(e) => Err(e.into()),
+                        }
+                    }
+                };
+
+                if let Err(e) = result {
+                    if config.flush_on_error {
+                        flush_cached(&clients).await;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    reject_unmatched(
+        deferred,
+        rejects.as_ref(),
+        tally.as_ref(),
+        warnings.as_ref(),
+    )
+}
+
+/// Executes every transaction in `requests` against clients that are sharded across the pool of
+/// worker tasks that is set in `config`, and serves any request on `snapshots` from the client's
+/// worker or the store.
+async fn run_sharded<D>(
+    requests: ReceiverStream<Transaction>,
+    mut snapshots: Option<mpsc::Receiver<SnapshotRequest>>,
+    store: ClientStore<D>,
+    channel_size: usize,
+    config: IoConfig,
+    rejects: Option<RejectsWriter>,
+    observers: Observers,
+) -> Result<(), IoError>
+where
+    D: StoreEngine + 'static,
+{
+    let Observers {
+        tally,
+        latency,
+        warnings,
+    } = observers;
+    let workers = WorkerPool::new(
+        config.shard_workers.unwrap_or(1),
+        store.clone(),
+        channel_size,
+    );
+    let mut clients: FnvHashMap<u16, ClientHandle> = FnvHashMap::default();
+    let mut drains = config
+        .drain_window
+        .map(|window| DrainDetector::new(window, warnings.clone()));
+    let mut cap = config
+        .max_disputed_total
+        .map(|ceiling| DisputeCap::new(ceiling, store.clone()));
+    let mut deferred = config.defer_disputes.map(DeferredDisputes::new);
+    let mut batches = requests.ready_chunks(config.micro_batch.unwrap_or(1));
+
+    while let Some(input) = next_input(&mut batches, snapshots.as_mut()).await {
+        let batch = match input {
+            Input::Batch(batch) => batch,
+            Input::Snapshot(request) => {
+                let handle = clients.get(&request.client);
+                serve_snapshot(request, handle, &store).await;
+                continue;
+            }
+        };
+
+        for segment in split_transfers(batch) {
+            let result: Result<(), IoError> = async {
+                match segment {
+                    Segment::Transfer(transfer) => {
+                        let source = sharded_handle(
+                            &mut clients,
+                            transfer.client,
+                            &workers,
+                            &store,
+                            latency.as_ref(),
+                            warnings.as_ref(),
+                        )
+                        .await?;
+                        let destination = sharded_handle(
+                            &mut clients,
+                            transfer.to,
+                            &workers,
+                            &store,
+                            latency.as_ref(),
+                            warnings.as_ref(),
+                        )
+                        .await?;
+                        execute_transfer(
+                            &source,
+                            &destination,
+                            transfer,
+                            rejects.as_ref(),
+                            cap.as_mut(),
+                            tally.as_ref(),
+                            deferred.as_mut(),
+                        )
+                        .await
+                    }
+                    Segment::Transactions(transactions) => {
+                        for (client_id, transactions) in group_consecutive(transactions) {
+                            let handle = sharded_handle(
+                                &mut clients,
+                                client_id,
+                                &workers,
+                                &store,
+                                latency.as_ref(),
+                                warnings.as_ref(),
+                            )
+                            .await?;
+                            execute_all(
+                                &handle,
+                                transactions,
+                                rejects.as_ref(),
+                                drains.as_mut(),
+                                cap.as_mut(),
+                                tally.as_ref(),
+                                deferred.as_mut(),
+                            )
+                            .await?;
+                        }
+                        Ok(())
+                    }
+                }
+            }
+            .await;
+
+            if let Err(e) = result {
+                if config.flush_on_error {
+                    flush_all(clients.values()).await;
+                }
+                return Err(e);
+            }
+        }
+    }
+
+    reject_unmatched(
+        deferred,
+        rejects.as_ref(),
+        tally.as_ref(),
+        warnings.as_ref(),
+    )
+}
+
+/// Returns a handle to the client with `id` from `clients`. If it has not been started, then it is
+/// started on its worker in `workers` from `store` and added to `clients`.
+async fn sharded_handle<D>(
+    clients: &mut FnvHashMap<u16, ClientHandle>,
+    id: u16,
+    workers: &WorkerPool,
+    store: &ClientStore<D>,
+    latency: Option<&Latency>,
+    warnings: Option<&WarningCollector>,
+) -> Result<ClientHandle, StoreError>
+where
+    D: StoreEngine,
+{
+    if let Some(handle) = clients.get(&id) {
+        return Ok(handle.clone());
+    }
+
+    let handle = ClientHandle::on_worker(id, workers, store, latency.cloned(), warnings).await?;
+    clients.insert(id, handle.clone());
+    Ok(handle)
+}
+
+/// Returns a handle to the client with `id` from `clients`, marking it as used at `now`. If it is
+/// not running, then it is started from `store` and added to `clients`.
+fn cached_handle<D>(
+    clients: &mut LruCache<u16, CachedClient>,
+    id: u16,
+    now: SystemTime,
+    store: &ClientStore<D>,
+    channel_size: usize,
+    latency: Option<&Latency>,
+    warnings: Option<&WarningCollector>,
+) -> Result<ClientHandle, StoreError>
+where
+    D: StoreEngine + 'static,
+{
+    if let Some(client) = clients.get_mut(&id) {
+        client.last_used = now;
+        return Ok(client.handle.clone());
+    }
+
+    let handle = ClientHandle::new(id, store.clone(), channel_size, latency.cloned(), warnings)?;
+    let client = CachedClient {
+        handle: handle.clone(),
+        last_used: now,
+    };
+    let _removed = clients.put(id, client);
+    Ok(handle)
+}
+
+/// Makes a best-effort attempt to persist the state of every client in `clients`.
+async fn flush_cached(clients: &LruCache<u16, CachedClient>) {
+    let handles: Vec<&ClientHandle> = clients.iter().map(|(_, client)| &client.handle).collect();
+    flush_all(handles).await;
+}
+
+/// Stops every client in `clients` that has not been sent a transaction within `timeout` of `now`.
+/// Dropping a client's handle closes its channel, which stops the client once it has executed
+/// every transaction that it was sent.
+fn stop_idle(clients: &mut LruCache<u16, CachedClient>, now: SystemTime, timeout: Duration) {
+    while let Some((_, client)) = clients.peek_lru() {
+        if now.duration_since(client.last_used).unwrap_or_default() < timeout {
+            break;
+        }
+        if let Some((id, _client)) = clients.pop_lru() {
+            event!(Level::DEBUG, IDLE_CLIENT, ?id);
+        }
+    }
+}
+
+/// Executes every transaction in `requests`, which are sorted by client, against a single running
+/// client, and serves any request on `snapshots` from the running client or the store. Returns an
+/// error if a transaction is received for a client with a lower ID than the running client.
+async fn run_sorted<D>(
+    requests: ReceiverStream<Transaction>,
+    mut snapshots: Option<mpsc::Receiver<SnapshotRequest>>,
+    store: ClientStore<D>,
+    channel_size: usize,
+    config: IoConfig,
+    rejects: Option<RejectsWriter>,
+    observers: Observers,
+) -> Result<(), IoError>
+where
+    D: StoreEngine + 'static,
+{
+    let Observers {
+        tally,
+        latency,
+        warnings,
+    } = observers;
+    let mut current: Option<(u16, ClientHandle)> = None;
+    let mut drains = config
+        .drain_window
+        .map(|window| DrainDetector::new(window, warnings.clone()));
+    let mut cap = config
+        .max_disputed_total
+        .map(|ceiling| DisputeCap::new(ceiling, store.clone()));
+    let mut deferred = config.defer_disputes.map(DeferredDisputes::new);
+    let mut batches = requests.ready_chunks(config.micro_batch.unwrap_or(1));
+
+    while let Some(input) = next_input(&mut batches, snapshots.as_mut()).await {
+        let batch = match input {
+            Input::Batch(batch) => batch,
+            Input::Snapshot(request) => {
+                let handle = current
+                    .as_ref()
+                    .filter(|(id, _)| *id == request.client)
+                    .map(|(_, handle)| handle);
+                serve_snapshot(request, handle, &store).await;
+                continue;
+            }
+        };
+        for segment in split_transfers(batch) {
+            let transactions = match segment {
+                Segment::Transactions(transactions) => transactions,
+                Segment::Transfer(transfer) => {
+                    let source = switch_client(
+                        &mut current,
+                        transfer.client,
+                        &store,
+                        channel_size,
+                        latency.as_ref(),
+                        warnings.as_ref(),
+                    )?;
+                    // The destination is only run for the transfer. Its state is persisted once
+                    // the deposit is executed, so it is restored from the store if it is run again.
+                    let destination = ClientHandle::new(
+                        transfer.to,
+                        store.clone(),
+                        channel_size,
+                        latency.clone(),
+                        warnings.as_ref(),
+                    )?;
+                    let result = execute_transfer(
+                        source,
+                        &destination,
+                        transfer,
+                        rejects.as_ref(),
+                        cap.as_mut(),
+                        tally.as_ref(),
+                        deferred.as_mut(),
+                    )
+                    .await;
+                    if let Err(e) = result {
+                        if config.flush_on_error {
+                            flush_all(Some(source)).await;
+                        }
+                        return Err(e);
+                    }
+                    continue;
+                }
+            };
+
+            for (client_id, transactions) in group_consecutive(transactions) {
+                let handle = switch_client(
+                    &mut current,
+                    client_id,
+                    &store,
+                    channel_size,
+                    latency.as_ref(),
+                    warnings.as_ref(),
+                )?;
+                let result = execute_all(
+                    handle,
+                    transactions,
+                    rejects.as_ref(),
+                    drains.as_mut(),
+                    cap.as_mut(),
+                    tally.as_ref(),
+                    deferred.as_mut(),
+                )
+                .await;
+                if let Err(e) = result {
+                    if config.flush_on_error {
+                        flush_all(Some(handle)).await;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    reject_unmatched(
+        deferred,
+        rejects.as_ref(),
+        tally.as_ref(),
+        warnings.as_ref(),
+    )
+}
+
+/// Makes the client with `client_id` the `current` client, if it is not already, and returns its
+/// handle. The previous client is stopped, while the new client is started from `store`. Returns
+/// an error if `client_id` is lower than the current client's, as clients must be in ascending
+/// order.
+fn switch_client<'c, D>(
+    current: &'c mut Option<(u16, ClientHandle)>,
+    client_id: u16,
+    store: &ClientStore<D>,
+    channel_size: usize,
+    latency: Option<&Latency>,
+    warnings: Option<&WarningCollector>,
+) -> Result<&'c ClientHandle, IoError>
+where
+    D: StoreEngine + 'static,
+{
+    let current_id = current.as_ref().map(|(id, _)| *id);
+    if current_id != Some(client_id) {
+        if current_id.is_some_and(|id| client_id < id) {
+            return Err(IoError::Unsorted(client_id));
+        }
+        current.take();
+
+        let handle = ClientHandle::new(
+            client_id,
+            store.clone(),
+            channel_size,
+            latency.cloned(),
+            warnings,
+        )?;
+        *current = Some((client_id, handle));
+    }
+
+    match current {
+        Some((_, handle)) => Ok(handle),
+        None => unreachable!("The current client was just set"),
+    }
+}
+
+/// Waits for the next batch of `transactions` or, if `snapshots` is provided, snapshot request.
+/// A batch that is ready is always received first. Returns `None` once the transactions have
+/// ended, even if snapshots may still be requested.
+async fn next_input<S>(
+    transactions: &mut S,
+    snapshots: Option<&mut mpsc::Receiver<SnapshotRequest>>,
+) -> Option<Input>
+where
+    S: Stream<Item = Vec<Transaction>> + Unpin,
+{
+    let snapshot = async {
+        match snapshots {
+            Some(snapshots) => match snapshots.recv().await {
+                Some(request) => request,
+                None => pending().await,
+            },
+            None => pending().await,
+        }
+    };
+
+    tokio::select! {
+        biased;
+        batch = transactions.next() => batch.map(Input::Batch),
+        request = snapshot => Some(Input::Snapshot(request)),
+    }
+}
+
+/// Serves `request` with the state of its client from `handle`, if the client is running.
+/// Otherwise, it is read from `store`, as the state is persisted after every transaction.
+async fn serve_snapshot<D>(
+    request: SnapshotRequest,
+    handle: Option<&ClientHandle>,
+    store: &ClientStore<D>,
+) where
+    D: StoreEngine,
+{
+    let state = match handle {
+        Some(handle) => Ok(Some(handle.snapshot().await)),
+        None => store.get_client_state(request.client),
+    };
+    let _ = request.callback.send(state);
+}
+
+/// Splits `batch` at every transfer, so that the transactions that were received before a transfer
+/// are executed before it and those that were received after it are executed after it.
+fn split_transfers(batch: Vec<Transaction>) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut transactions = Vec::new();
+    for transaction in batch {
+        match transaction {
+            Transaction::Internal(transfer) => {
+                if !transactions.is_empty() {
+                    segments.push(Segment::Transactions(std::mem::take(&mut transactions)));
+                }
+                segments.push(Segment::Transfer(transfer));
+            }
+            transaction => transactions.push(transaction),
+        }
+    }
+    if !transactions.is_empty() {
+        segments.push(Segment::Transactions(transactions));
+    }
+    segments
+}
+
+/// Groups runs of consecutive transactions in `transactions` that are for the same client. The
+/// order across clients is kept, so that rejections are recorded and disputes are capped in input
+/// order.
+fn group_consecutive(transactions: Vec<Transaction>) -> Vec<(u16, Vec<Transaction>)> {
+    let mut groups: Vec<(u16, Vec<Transaction>)> = Vec::new();
+    for transaction in transactions {
+        let client_id = transaction.client_id();
+        match groups.last_mut() {
+            Some((id, group)) if *id == client_id => group.push(transaction),
+            _ => groups.push((client_id, vec![transaction])),
+        }
+    }
+    groups
+}
+
+/// Executes `transactions` in order against `handle`. A single transaction is executed on its
+/// own, while any more are dispatched as one batch. If `cap` or `deferred` is provided, then every
+/// transaction is executed on its own. See `execute` and `execute_deferring` for how the results
+/// are handled.
+async fn execute_all<D>(
+    handle: &ClientHandle,
+    mut transactions: Vec<Transaction>,
+    rejects: Option<&RejectsWriter>,
+    mut drains: Option<&mut DrainDetector>,
+    mut cap: Option<&mut DisputeCap<D>>,
+    tally: Option<&Tally>,
+    deferred: Option<&mut DeferredDisputes>,
+) -> Result<(), IoError>
+where
+    D: StoreEngine,
+{
+    if let Some(deferred) = deferred {
+        for transaction in transactions {
+            execute_deferring(
+                handle,
+                transaction,
+                rejects,
+                drains.as_deref_mut(),
+                cap.as_deref_mut(),
+                tally,
+                deferred,
+            )
+            .await?;
+        }
+        return Ok(());
+    }
+
+    if let Some(cap) = cap {
+        for transaction in transactions {
+            execute(
+                handle,
+                transaction,
+                rejects,
+                drains.as_deref_mut(),
+                Some(&mut *cap),
+                tally,
+            )
+            .await?;
+        }
+        return Ok(());
+    }
+
+    if transactions.len() == 1 {
+        let transaction = transactions.remove(0);
+        return execute::<D>(handle, transaction, rejects, drains, None, tally).await;
+    }
+
+    if rejects.is_none() && drains.is_none() && tally.is_none() {
+        let results = handle.execute_batch(transactions).await;
+        return results.into_iter().try_for_each(on_result);
+    }
+
+    let results = handle.execute_batch(transactions.clone()).await;
+    for (transaction, result) in transactions.into_iter().zip(results) {
+        on_executed(transaction, result, rejects, drains.as_deref_mut(), tally)?;
+    }
+    Ok(())
+}
+
+/// Executes `transaction` against `handle`. If the transaction is rejected by the client and
+/// `rejects` is provided, then the rejection is written to it. If the transaction is executed and
+/// `drains` is provided, then a warning is logged if it drains a recent deposit. If `cap` is
+/// provided, then a dispute that would exceed it is rejected without being executed. If `tally` is
+/// provided, then the outcome is counted in it.
+async fn execute<D>(
+    handle: &ClientHandle,
+    transaction: Transaction,
+    rejects: Option<&RejectsWriter>,
+    drains: Option<&mut DrainDetector>,
+    cap: Option<&mut DisputeCap<D>>,
+    tally: Option<&Tally>,
+) -> Result<(), IoError>
+where
+    D: StoreEngine,
+{
+    if rejects.is_none() && drains.is_none() && tally.is_none() {
+        return on_result(dispatch(handle, transaction, cap).await);
+    }
+
+    let result = dispatch(handle, transaction, cap).await;
+    on_executed(transaction, result, rejects, drains, tally)
+}
+
+/// Executes `transaction` against `handle` as `execute` does, unless it is a dispute, resolve or
+/// chargeback of a transaction that has not been received yet. Such a transaction is deferred in
+/// `deferred`, rather than rejected, and is executed once the transaction that it references is
+/// applied. If `deferred` is full, then it is rejected as not found.
+async fn execute_deferring<D>(
+    handle: &ClientHandle,
+    transaction: Transaction,
+    rejects: Option<&RejectsWriter>,
+    mut drains: Option<&mut DrainDetector>,
+    mut cap: Option<&mut DisputeCap<D>>,
+    tally: Option<&Tally>,
+    deferred: &mut DeferredDisputes,
+) -> Result<(), IoError>
+where
+    D: StoreEngine,
+{
+    if deferred.is_pending(&transaction) {
+        return match deferred.defer(transaction) {
+            Ok(()) => Ok(()),
+            Err(transaction) => {
+                let result = Err(ClientError::TransactionNotFound);
+                on_executed(transaction, result, rejects, drains, tally)
+            }
+        };
+    }
+
+    let result = dispatch(handle, transaction, cap.as_deref_mut()).await;
+    let released = match (&transaction, &result) {
+        (Transaction::Disputed(_), Err(ClientError::TransactionNotFound)) => {
+            return match deferred.defer(transaction) {
+                Ok(()) => Ok(()),
+                Err(transaction) => on_executed(transaction, result, rejects, drains, tally),
+            };
+        }
+        (Transaction::Transfer(_), Ok(_)) => deferred.release(&transaction),
+        _ => Vec::new(),
+    };
+
+    on_executed(transaction, result, rejects, drains.as_deref_mut(), tally)?;
+    for transaction in released {
+        let result = dispatch(handle, transaction, cap.as_deref_mut()).await;
+        on_executed(transaction, result, rejects, drains.as_deref_mut(), tally)?;
+    }
+    Ok(())
+}
+
+/// Executes `transfer` as a withdrawal from `source` followed by a deposit to `destination`, so
+/// that either both are applied or neither is. The result is handled as `on_executed` does for any
+/// other transaction. If the transfer is applied and `deferred` is provided, then every dispute,
+/// resolve or chargeback that was deferred on either leg is executed against its client, through
+/// `cap` if it is provided, as `execute_deferring` does for a deposit or withdrawal.
+///
+/// Each client runs in its own task, so the transfer is executed in two phases. If the withdrawal
+/// is rejected, such as for insufficient funds, then the destination is never sent the deposit. If
+/// the deposit is then rejected, such as for a frozen destination, then the withdrawal is reverted
+/// before the deposit's error is returned. Neither client is sent anything else in between, as
+/// the IO task waits for each phase to complete.
+async fn execute_transfer<D>(
+    source: &ClientHandle,
+    destination: &ClientHandle,
+    transfer: InternalTransfer,
+    rejects: Option<&RejectsWriter>,
+    mut cap: Option<&mut DisputeCap<D>>,
+    tally: Option<&Tally>,
+    deferred: Option<&mut DeferredDisputes>,
+) -> Result<(), IoError>
+where
+    D: StoreEngine,
+{
+    let (withdrawal, deposit) = transfer.legs();
+    let result = match source.execute_transaction(withdrawal).await {
+        Ok(_) => match destination.execute_transaction(deposit).await {
+            Ok(effect) => Ok(effect),
+            Err(e) => source.revert(transfer.tx).await.and(Err(e)),
+        },
+        Err(e) => Err(e),
+    };
+    let applied = result.is_ok();
+    on_executed(transfer.into(), result, rejects, None, tally)?;
+
+    if let (Some(deferred), true) = (deferred, applied) {
+        for (handle, leg) in [(source, withdrawal), (destination, deposit)] {
+            for transaction in deferred.release(&leg) {
+                let result = dispatch(handle, transaction, cap.as_deref_mut()).await;
+                on_executed(transaction, result, rejects, None, tally)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Executes `transaction` against `handle`, through `cap` if it is provided.
+async fn dispatch<D>(
+    handle: &ClientHandle,
+    transaction: Transaction,
+    cap: Option<&mut DisputeCap<D>>,
+) -> Result<Effect, ClientError>
+where
+    D: StoreEngine,
+{
+    match cap {
+        Some(cap) => cap.execute(handle, transaction).await,
+        None => handle.execute_transaction(transaction).await,
+    }
+}
+
+/// Rejects every transaction that is still held in `deferred` once the input has ended, as the
+/// transaction that it references was never received. Each is logged, written to `rejects`,
+/// counted in `tally` as not found and recorded in `warnings`, if they are provided.
+fn reject_unmatched(
+    deferred: Option<DeferredDisputes>,
+    rejects: Option<&RejectsWriter>,
+    tally: Option<&Tally>,
+    warnings: Option<&WarningCollector>,
+) -> Result<(), IoError> {
+    for transaction in deferred
+        .into_iter()
+        .flat_map(|mut deferred| deferred.drain())
+    {
+        event!(Level::WARN, UNMATCHED_DISPUTE, ?transaction);
+        if let Some(warnings) = warnings {
+            warnings.record(Warning::OrphanDispute {
+                client: transaction.client_id(),
+                tx: transaction.id(),
+            });
+        }
+        let result = Err(ClientError::TransactionNotFound);
+        on_executed(transaction, result, rejects, None, tally)?;
+    }
+    Ok(())
+}
+
+/// Handles the `result` of executing `transaction`. Writing a rejection to `rejects`, observing
+/// an applied transaction with `drains` or counting the outcome in `tally`, if they are provided.
+/// Returns an error if the result contains a fatal client error.
+fn on_executed(
+    transaction: Transaction,
+    result: Result<Effect, ClientError>,
+    rejects: Option<&RejectsWriter>,
+    drains: Option<&mut DrainDetector>,
+    tally: Option<&Tally>,
+) -> Result<(), IoError> {
+    if let Some(tally) = tally {
+        tally.record(&transaction, &result);
+    }
+    match &result {
+        Ok(Effect::Applied) => {
+            if let Some(drain) = drains.and_then(|drains| drains.observe(&transaction)) {
+                event!(Level::WARN, DRAIN_PATTERN, ?drain);
+            }
+        }
+        Err(e) if !e.is_fatal() => {
+            if let Some(rejects) = rejects {
+                rejects.write(Rejection::from_transaction(transaction, e.into()))?;
+            }
+        }
+        Ok(Effect::Ignored) | Err(_) => {}
+    }
+    on_result(result)
+}
+
+/// Makes a best-effort attempt to persist the state of every client in `handles`. Any error is
+/// logged rather than returned as a fatal error is already being propagated.
+async fn flush_all<'h, I>(handles: I)
+where
+    I: IntoIterator<Item = &'h ClientHandle>,
+{
+    for handle in handles {
+        if let Err(error) = handle.flush().await {
+            event!(Level::ERROR, FLUSH_FAILED, ?error);
+        }
+    }
+}
+
+/// Returns an error if `result` contains a fatal client error. Non-fatal errors are only a
+/// rejection of the transaction and processing can continue.
+fn on_result<T>(result: Result<T, ClientError>) -> Result<(), IoError> {
+    match result {
+        Err(e) if e.is_fatal() => Err(IoError::Client(e)),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,159 @@
+use crate::client::{ClientError, Effect};
+use crate::parser::rejects::RejectReason;
+use crate::transaction::{Transaction, TransactionType, TypeCounts};
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The number of transaction types that are counted.
+const TYPES: usize = 6;
+/// The number of reasons that rejections are counted by.
+const REASONS: usize = RejectReason::ALL.len();
+
+/// The outcomes of the transactions that were executed by an IO task, by type.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Outcomes {
+    /// The transactions that were applied.
+    pub applied: TypeCounts,
+    /// The transactions that were rejected, either by their client or by a system-wide limit.
+    pub rejected: TypeCounts,
+}
+
+/// The counts that are shared by the clones of a tally, indexed by transaction type or reason.
+#[derive(Debug, Default)]
+struct Counts {
+    applied: [AtomicUsize; TYPES],
+    rejected: [AtomicUsize; TYPES],
+    reasons: [AtomicUsize; REASONS],
+}
+
+/// A count of the outcomes of the transactions that were executed by an IO task. Clones share the
+/// same counts, so that they may be read once the task has completed. Each count is atomic, so
+/// recording an outcome never takes a lock.
+#[derive(Debug, Default, Clone)]
+pub struct Tally {
+    counts: Arc<Counts>,
+}
+
+impl Tally {
+    /// Counts the `result` of executing `transaction`. A transaction that was accepted without
+    /// effect was neither applied nor rejected, and a fatal error halts processing, so neither is
+    /// counted as an outcome.
+    pub fn record(&self, transaction: &Transaction, result: &Result<Effect, ClientError>) {
+        let index = transaction.tx_type() as usize;
+        match result {
+            Ok(Effect::Applied) => {
+                self.counts.applied[index].fetch_add(1, Ordering::Relaxed);
+            }
+            Err(e) if !e.is_fatal() => {
+                self.counts.rejected[index].fetch_add(1, Ordering::Relaxed);
+                let reason = RejectReason::from(e) as usize;
+                self.counts.reasons[reason].fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(Effect::Ignored) | Err(_) => {}
+        }
+    }
+
+    /// Returns the outcomes that have been counted so far.
+    pub fn outcomes(&self) -> Outcomes {
+        Outcomes {
+            applied: type_counts(&self.counts.applied),
+            rejected: type_counts(&self.counts.rejected),
+        }
+    }
+
+    /// Returns the number of transactions that have been rejected for each reason so far, in the
+    /// order that the reasons are declared. A reason that no transaction was rejected for is
+    /// omitted.
+    pub fn rejections(&self) -> Vec<(RejectReason, usize)> {
+        RejectReason::ALL
+            .iter()
+            .map(|&reason| {
+                let count = self.counts.reasons[reason as usize].load(Ordering::Relaxed);
+                (reason, count)
+            })
+            .filter(|(_, count)| *count > 0)
+            .collect()
+    }
+
+    /// Returns the metrics of a run that has counted its outcomes in this tally and taken
+    /// `elapsed` so far.
+    pub fn metrics(&self, elapsed: Duration) -> Metrics {
+        Metrics {
+            outcomes: self.outcomes(),
+            rejections: self.rejections(),
+            elapsed,
+        }
+    }
+}
+
+/// Reads the count of each transaction type from `counts`.
+fn type_counts(counts: &[AtomicUsize; TYPES]) -> TypeCounts {
+    let count = |tx_type: TransactionType| counts[tx_type as usize].load(Ordering::Relaxed);
+
+    TypeCounts {
+        deposit: count(TransactionType::Deposit),
+        withdrawal: count(TransactionType::Withdrawal),
+        dispute: count(TransactionType::Dispute),
+        resolve: count(TransactionType::Resolve),
+        chargeback: count(TransactionType::Chargeback),
+        transfer: count(TransactionType::Transfer),
+    }
+}
+
+/// The processing metrics of a run, which are reported once it completes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metrics {
+    /// The outcomes of the transactions that were executed.
+    pub outcomes: Outcomes,
+    /// The number of transactions that were rejected for each reason that any were.
+    pub rejections: Vec<(RejectReason, usize)>,
+    /// How long the run took.
+    pub elapsed: Duration,
+}
+
+impl Metrics {
+    /// Returns the number of transactions that were executed, whether they were applied or
+    /// rejected.
+    pub fn executed(&self) -> usize {
+        self.outcomes.applied.total() + self.outcomes.rejected.total()
+    }
+
+    /// Returns the number of transactions that were executed per second.
+    pub fn throughput(&self) -> f64 {
+        self.executed() as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl Display for Metrics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let Outcomes { applied, rejected } = &self.outcomes;
+        writeln!(
+            f,
+            "Executed {} transactions in {:?} ({:.0} per second)",
+            self.executed(),
+            self.elapsed,
+            self.throughput()
+        )?;
+        writeln!(
+            f,
+            "Applied {}: {} deposits, {} withdrawals, {} transfers",
+            applied.total(),
+            applied.deposit,
+            applied.withdrawal,
+            applied.transfer
+        )?;
+        writeln!(
+            f,
+            "Disputes: {} opened, {} resolved, {} charged back",
+            applied.dispute, applied.resolve, applied.chargeback
+        )?;
+        write!(f, "Rejected {}", rejected.total())?;
+        for (index, (reason, count)) in self.rejections.iter().enumerate() {
+            let separator = if index == 0 { ": " } else { ", " };
+            write!(f, "{}{} {}", separator, count, reason.name())?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1173 @@
+use crate::client::{
+    ChargedBackResolvePolicy, ClientError, ClientState, ClientStore, CorruptStatePolicy, Keyspace,
+    Policy, State,
+};
+use crate::clock::MockClock;
+use crate::data::{mem_store, mem_store_with_policy};
+use crate::db::{BatchWrite, MemStore, Poisoned, Scan, StoreEngine, StoreError};
+use crate::io::drain::{Drain, DrainDetector};
+use crate::io::{IoConfig, IoError, IoTask, SnapshotRequest, Tally};
+#[cfg(feature = "latency")]
+use crate::io::{Latency, LatencySummary};
+use crate::parser::reader::ReaderConfig;
+use crate::parser::rejects::{RejectReason, Rejection, RejectsWriter};
+use crate::parser::CsvTransaction;
+use crate::transaction::{Transaction, TransactionType};
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tempdir::TempDir;
+use tokio::sync::{mpsc, oneshot};
+
+const CHANNEL_SIZE: usize = 8;
+
+async fn run_transactions(
+    store: ClientStore<MemStore>,
+    transactions: Vec<Transaction>,
+) -> Result<(), IoError> {
+    run_with_config(store, transactions, IoConfig::default()).await
+}
+
+async fn run_with_config<D>(
+    store: ClientStore<D>,
+    transactions: Vec<Transaction>,
+    config: IoConfig,
+) -> Result<(), IoError>
+where
+    D: StoreEngine + 'static,
+{
+    let (tx, rx) = mpsc::channel(transactions.len() + 1);
+    for transaction in transactions {
+        tx.send(transaction).await.unwrap();
+    }
+    drop(tx);
+
+    IoTask::new(rx, store, config, None).run(CHANNEL_SIZE).await
+}
+
+fn frozen_feed() -> Vec<Transaction> {
+    vec![
+        Transaction::deposit(1, 1, 10.0),
+        Transaction::dispute(1, 1),
+        Transaction::chargeback(1, 1),
+        Transaction::deposit(1, 2, 5.0),
+        Transaction::deposit(2, 3, 5.0),
+    ]
+}
+
+#[tokio::test]
+async fn transaction_after_freeze() {
+    let result = run_transactions(mem_store(), frozen_feed()).await;
+    assert!(result.is_ok());
+}
+
+#[tokio::test]
+async fn strict_transaction_after_freeze() {
+    let store = mem_store_with_policy(Policy {
+        strict_freeze: true,
+        ..Default::default()
+    });
+    let result = run_transactions(store.clone(), frozen_feed()).await;
+
+    assert!(matches!(
+        result,
+        Err(IoError::Client(ClientError::TransactionAfterFreeze))
+    ));
+    assert_eq!(store.get_client_state(2), Ok(None));
+}
+
+fn sorted_config() -> IoConfig {
+    IoConfig {
+        assume_sorted: true,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn sorted_by_client() {
+    let transactions = vec![
+        Transaction::deposit(1, 1, 10.0),
+        Transaction::withdrawal(1, 2, 5.0),
+        Transaction::deposit(2, 3, 20.0),
+        Transaction::dispute(2, 3),
+        Transaction::deposit(3, 4, 30.0),
+    ];
+
+    let sorted_store = mem_store();
+    let result = run_with_config(sorted_store.clone(), transactions.clone(), sorted_config()).await;
+    assert!(result.is_ok());
+
+    let cached_store = mem_store();
+    let result = run_transactions(cached_store.clone(), transactions).await;
+    assert!(result.is_ok());
+
+    for client_id in 1..=3 {
+        let sorted_state = sorted_store.get_client_state(client_id).unwrap();
+        assert!(sorted_state.is_some());
+        assert_eq!(
+            sorted_state,
+            cached_store.get_client_state(client_id).unwrap()
+        );
+    }
+}
+
+#[tokio::test]
+async fn unsorted_by_client() {
+    let transactions = vec![
+        Transaction::deposit(1, 1, 10.0),
+        Transaction::deposit(2, 2, 20.0),
+        Transaction::withdrawal(1, 3, 5.0),
+    ];
+
+    let result = run_with_config(mem_store(), transactions, sorted_config()).await;
+    assert!(matches!(result, Err(IoError::Unsorted(1))));
+}
+
+#[tokio::test]
+async fn descending_by_client() {
+    let transactions = vec![
+        Transaction::deposit(2, 1, 20.0),
+        Transaction::deposit(2, 2, 5.0),
+        Transaction::deposit(1, 3, 10.0),
+    ];
+
+    let result = run_with_config(mem_store(), transactions, sorted_config()).await;
+    assert!(matches!(result, Err(IoError::Unsorted(1))));
+}
+
+fn corrupt_client(store: &ClientStore<MemStore>, client_id: u16) {
+    let key = bincode::serialize(&client_id).unwrap();
+    let mut value = bincode::serialize(&ClientState::new(client_id)).unwrap();
+    // The tag of the state's trailing `Option`, which must be either 0 or 1.
+    *value.last_mut().unwrap() = 2;
+    store
+        .inner()
+        .put(Keyspace::Clients, key.as_slice(), &value)
+        .unwrap();
+}
+
+/// Writes all but the last `missing` bytes of a new state for `client_id`. Returns the length of
+/// the truncated record.
+fn truncate_client(store: &ClientStore<MemStore>, client_id: u16, missing: usize) -> usize {
+    let key = bincode::serialize(&client_id).unwrap();
+    let value = bincode::serialize(&ClientState::new(client_id)).unwrap();
+    let len = value.len() - missing;
+    store
+        .inner()
+        .put(Keyspace::Clients, key.as_slice(), &value[..len])
+        .unwrap();
+    len
+}
+
+#[tokio::test]
+async fn corrupt_state_fails() {
+    let store = mem_store();
+    corrupt_client(&store, 1);
+
+    let result = run_transactions(store, vec![Transaction::deposit(1, 1, 10.0)]).await;
+    assert!(matches!(
+        result,
+        Err(IoError::Store(StoreError::Deserialize(_)))
+    ));
+}
+
+#[tokio::test]
+async fn truncated_state_fails() {
+    let store = mem_store();
+    let len = truncate_client(&store, 1, 3);
+
+    assert_eq!(
+        store.get_client_state(1),
+        Err(StoreError::TruncatedRecord {
+            keyspace: Keyspace::Clients.name().to_string(),
+            key: bincode::serialize(&1u16).unwrap(),
+            len,
+        })
+    );
+
+    let result = run_transactions(store, vec![Transaction::deposit(1, 1, 10.0)]).await;
+    assert!(matches!(
+        result,
+        Err(IoError::Store(StoreError::TruncatedRecord { .. }))
+    ));
+}
+
+#[tokio::test]
+async fn truncated_state_treated_as_new() {
+    let store = mem_store_with_policy(Policy {
+        corrupt_state: CorruptStatePolicy::TreatAsNew,
+        ..Default::default()
+    });
+    truncate_client(&store, 1, 1);
+
+    let result = run_transactions(store.clone(), vec![Transaction::deposit(1, 1, 10.0)]).await;
+    assert!(result.is_ok());
+    assert_eq!(stored_available(&store, 1), 10.0);
+}
+
+#[tokio::test]
+async fn corrupt_state_treated_as_new() {
+    let store = mem_store_with_policy(Policy {
+        corrupt_state: CorruptStatePolicy::TreatAsNew,
+        ..Default::default()
+    });
+    corrupt_client(&store, 1);
+
+    let result = run_transactions(store.clone(), vec![Transaction::deposit(1, 1, 10.0)]).await;
+    assert!(result.is_ok());
+
+    let mut expected = ClientState::new(1);
+    let result = expected.execute_transaction(Transaction::deposit(1, 1, 10.0), &mem_store());
+    assert!(result.is_ok());
+    assert_eq!(store.get_client_state(1), Ok(Some(expected)));
+}
+
+/// A store that fails a single write, whether a put or a batch. A failed batch writes nothing, as
+/// with every store that writes batches atomically.
+#[derive(Debug, Clone)]
+struct FailingStore {
+    inner: MemStore,
+    writes: Arc<AtomicUsize>,
+    fail_on: usize,
+}
+
+impl FailingStore {
+    /// Constructs a store that fails the `fail_on`th write.
+    fn new(fail_on: usize) -> FailingStore {
+        FailingStore {
+            inner: MemStore::default(),
+            writes: Arc::new(AtomicUsize::new(0)),
+            fail_on,
+        }
+    }
+
+    /// Records a write, returning an error if it is the one to fail.
+    fn write(&self) -> Result<(), StoreError> {
+        if self.writes.fetch_add(1, Ordering::SeqCst) + 1 == self.fail_on {
+            Err(StoreError::Write(Box::new(Poisoned)))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl StoreEngine for FailingStore {
+    fn put(&self, keyspace: Keyspace, key: &[u8], value: &[u8]) -> Result<(), StoreError> {
+        self.write()?;
+        self.inner.put(keyspace, key, value)
+    }
+
+    fn write_batch(&self, writes: &[BatchWrite<'_>]) -> Result<(), StoreError> {
+        self.write()?;
+        self.inner.write_batch(writes)
+    }
+
+    fn get(&self, keyspace: Keyspace, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        self.inner.get(keyspace, key)
+    }
+
+    fn delete(&self, keyspace: Keyspace, key: &[u8]) -> Result<(), StoreError> {
+        self.inner.delete(keyspace, key)
+    }
+
+    fn scan(&self, keyspace: Keyspace) -> Scan<'_> {
+        self.inner.scan(keyspace)
+    }
+}
+
+async fn run_failing(config: IoConfig) -> (Result<(), IoError>, ClientStore<FailingStore>) {
+    // Each deposit writes the transaction and the client's state in a single batch. So the third
+    // write is client two's second deposit.
+    let store = ClientStore::new(FailingStore::new(3));
+    let transactions = vec![
+        Transaction::deposit(1, 1, 5.0),
+        Transaction::deposit(2, 2, 10.0),
+        Transaction::deposit(2, 3, 5.0),
+    ];
+
+    let result = run_with_config(store.clone(), transactions, config).await;
+    (result, store)
+}
+
+fn stored_available<D: StoreEngine>(store: &ClientStore<D>, client_id: u16) -> f64 {
+    State::from(store.get_client_state(client_id).unwrap().unwrap()).available
+}
+
+#[tokio::test]
+async fn flush_on_error() {
+    for (assume_sorted, shard_workers) in [(false, None), (true, None), (false, Some(2))] {
+        let config = IoConfig {
+            assume_sorted,
+            flush_on_error: true,
+            shard_workers,
+            ..Default::default()
+        };
+        let (result, store) = run_failing(config).await;
+
+        assert!(matches!(
+            result,
+            Err(IoError::Client(ClientError::StoreError(_)))
+        ));
+        // The failed deposit was never stored, so the flushed state does not include it.
+        assert_eq!(stored_available(&store, 1), 5.0);
+        assert_eq!(stored_available(&store, 2), 10.0);
+        assert_eq!(store.get_transaction(2, 3), Ok(None));
+        assert!(store.check_books(1).unwrap().is_balanced());
+    }
+}
+
+#[tokio::test]
+async fn no_flush_on_error() {
+    let (result, store) = run_failing(IoConfig::default()).await;
+
+    assert!(matches!(
+        result,
+        Err(IoError::Client(ClientError::StoreError(_)))
+    ));
+    assert_eq!(stored_available(&store, 1), 5.0);
+    assert_eq!(stored_available(&store, 2), 10.0);
+}
+
+fn observe_all(window: usize, transactions: Vec<Transaction>) -> Vec<Drain> {
+    let mut detector = DrainDetector::new(window, None);
+    transactions
+        .iter()
+        .filter_map(|transaction| detector.observe(transaction))
+        .collect()
+}
+
+#[test]
+fn drain_pattern() {
+    let transactions = vec![
+        Transaction::deposit(1, 1, 50.0),
+        Transaction::deposit(2, 2, 10.0),
+        Transaction::withdrawal(1, 3, 50.0),
+    ];
+    assert_eq!(
+        observe_all(2, transactions),
+        vec![Drain {
+            client: 1,
+            deposit: 1,
+            withdrawal: 3
+        }]
+    );
+
+    let transactions = vec![
+        Transaction::deposit(1, 1, 50.0),
+        Transaction::deposit(1, 2, 20.0),
+        Transaction::withdrawal(1, 3, 20.0),
+        Transaction::withdrawal(1, 4, 20.0),
+    ];
+    assert_eq!(
+        observe_all(2, transactions),
+        vec![Drain {
+            client: 1,
+            deposit: 2,
+            withdrawal: 3
+        }]
+    );
+}
+
+#[test]
+fn no_drain_pattern() {
+    let transactions = vec![
+        Transaction::deposit(1, 1, 50.0),
+        Transaction::withdrawal(1, 2, 20.0),
+        Transaction::deposit(2, 3, 20.0),
+        Transaction::withdrawal(1, 4, 20.0),
+    ];
+    assert!(observe_all(2, transactions).is_empty());
+
+    // The deposit falls outside of the client's previous two transactions.
+    let transactions = vec![
+        Transaction::deposit(1, 1, 50.0),
+        Transaction::deposit(1, 2, 5.0),
+        Transaction::dispute(1, 2),
+        Transaction::withdrawal(1, 3, 50.0),
+    ];
+    assert!(observe_all(2, transactions).is_empty());
+}
+
+/// A feed of deposits, withdrawals and disputes that are interleaved between `clients` clients.
+/// Some of the withdrawals and disputes are rejected.
+fn interleaved_feed(clients: u16, count: u32) -> Vec<Transaction> {
+    (0..count)
+        .map(|tx| {
+            let client = (tx % clients as u32) as u16 + 1;
+            match tx % 7 {
+                3 => Transaction::withdrawal(client, tx, 15.0),
+                5 => Transaction::dispute(client, tx - 5),
+                6 => Transaction::resolve(client, tx - 6),
+                _ => Transaction::deposit(client, tx, 10.0),
+            }
+        })
+        .collect()
+}
+
+async fn final_states(transactions: Vec<Transaction>, config: IoConfig) -> Vec<ClientState> {
+    let store = mem_store();
+    let result = run_with_config(store.clone(), transactions, config).await;
+    assert!(result.is_ok());
+    store.collect_client_states().unwrap()
+}
+
+#[tokio::test]
+async fn micro_batch_parity() {
+    let transactions = interleaved_feed(5, 500);
+    let expected = final_states(transactions.clone(), IoConfig::default()).await;
+    assert_eq!(expected.len(), 5);
+
+    for micro_batch in [2, 7, 64] {
+        let config = IoConfig {
+            micro_batch: Some(micro_batch),
+            ..Default::default()
+        };
+        assert_eq!(final_states(transactions.clone(), config).await, expected);
+    }
+
+    let mut sorted = transactions;
+    sorted.sort_by_key(Transaction::client_id);
+    let config = IoConfig {
+        assume_sorted: true,
+        micro_batch: Some(16),
+        ..Default::default()
+    };
+    assert_eq!(final_states(sorted, config).await, expected);
+}
+
+#[tokio::test]
+async fn micro_batch_rejects_in_order() {
+    let transactions = vec![
+        Transaction::withdrawal(1, 1, 5.0),
+        Transaction::withdrawal(2, 2, 5.0),
+        Transaction::withdrawal(1, 3, 5.0),
+        Transaction::withdrawal(2, 4, 5.0),
+    ];
+    let config = IoConfig {
+        micro_batch: Some(8),
+        ..Default::default()
+    };
+    let (_, rejections) = run_transfer(transactions, config).await;
+
+    let rejected = rejections
+        .iter()
+        .map(|rejection| rejection.tx.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(rejected, vec!["1", "2", "3", "4"]);
+}
+
+#[tokio::test]
+async fn sharded_parity() {
+    let transactions = interleaved_feed(9, 900);
+    let expected = final_states(transactions.clone(), IoConfig::default()).await;
+    assert_eq!(expected.len(), 9);
+
+    for (shard_workers, micro_batch) in [(1, None), (2, None), (4, Some(16)), (16, Some(7))] {
+        let config = IoConfig {
+            shard_workers: Some(shard_workers),
+            micro_batch,
+            ..Default::default()
+        };
+        assert_eq!(final_states(transactions.clone(), config).await, expected);
+    }
+}
+
+#[tokio::test]
+async fn micro_batch_fatal_error() {
+    let store = mem_store_with_policy(Policy {
+        strict_freeze: true,
+        ..Default::default()
+    });
+    let config = IoConfig {
+        micro_batch: Some(8),
+        ..Default::default()
+    };
+    let result = run_with_config(store.clone(), frozen_feed(), config).await;
+
+    assert!(matches!(
+        result,
+        Err(IoError::Client(ClientError::TransactionAfterFreeze))
+    ));
+}
+
+fn held(store: &ClientStore<MemStore>) -> Vec<f64> {
+    store
+        .collect_states()
+        .unwrap()
+        .iter()
+        .map(|state| state.held)
+        .collect()
+}
+
+#[tokio::test]
+async fn max_disputed_total() {
+    let config = IoConfig {
+        max_disputed_total: Some(25.0),
+        ..Default::default()
+    };
+    let deposits = vec![
+        Transaction::deposit(1, 1, 10.0),
+        Transaction::deposit(2, 2, 10.0),
+        Transaction::deposit(3, 3, 10.0),
+    ];
+    let disputes = vec![
+        Transaction::dispute(1, 1),
+        Transaction::dispute(2, 2),
+        Transaction::dispute(3, 3),
+    ];
+
+    let dir = TempDir::new("io").unwrap();
+    let rejects_path = dir.path().join("rejects.csv");
+    let rejects = RejectsWriter::create(rejects_path.clone()).unwrap();
+    let store = mem_store();
+    let (tx, rx) = mpsc::channel(8);
+    for transaction in deposits.iter().chain(&disputes) {
+        tx.send(*transaction).await.unwrap();
+    }
+    drop(tx);
+    let result = IoTask::new(rx, store.clone(), config, Some(rejects.clone()))
+        .run(CHANNEL_SIZE)
+        .await;
+    assert!(result.is_ok());
+    rejects.flush().unwrap();
+
+    // The third dispute would push the total under dispute to 30.
+    assert_eq!(held(&store), vec![10.0, 10.0, 0.0]);
+    let rejections = csv::Reader::from_path(rejects_path)
+        .unwrap()
+        .into_deserialize::<Rejection>()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(rejections.len(), 1);
+    assert_eq!(rejections[0].client, "3");
+    assert_eq!(rejections[0].reason, RejectReason::SystemDisputeCap);
+
+    // Resolving a dispute lowers the total, so the third dispute is then accepted.
+    let mut transactions = deposits;
+    transactions.extend(disputes.clone());
+    transactions.push(Transaction::resolve(1, 1));
+    transactions.push(disputes[2]);
+    let store = mem_store();
+    let result = run_with_config(store.clone(), transactions, config).await;
+    assert!(result.is_ok());
+    assert_eq!(held(&store), vec![0.0, 10.0, 10.0]);
+}
+
+#[tokio::test]
+async fn max_disputed_total_exact() {
+    // As floats, `0.1 + 0.2` is slightly above `0.3`, so the total must be held exactly for the
+    // second dispute to fill the limit.
+    let config = IoConfig {
+        max_disputed_total: Some(0.3),
+        ..Default::default()
+    };
+    let transactions = vec![
+        Transaction::deposit(1, 1, 0.1),
+        Transaction::deposit(2, 2, 0.2),
+        Transaction::deposit(3, 3, 0.0001),
+        Transaction::dispute(1, 1),
+        Transaction::dispute(2, 2),
+        Transaction::dispute(3, 3),
+    ];
+
+    let store = mem_store();
+    let result = run_with_config(store.clone(), transactions, config).await;
+    assert!(result.is_ok());
+    assert_eq!(held(&store), vec![0.1, 0.2, 0.0]);
+}
+
+#[tokio::test]
+async fn max_disputed_total_charged_back() {
+    let store = mem_store_with_policy(Policy {
+        freeze_on_chargeback: false,
+        resolve_charged_back: ChargedBackResolvePolicy::Ignore,
+        ..Default::default()
+    });
+    let config = IoConfig {
+        max_disputed_total: Some(10.0),
+        ..Default::default()
+    };
+    let transactions = vec![
+        Transaction::deposit(1, 1, 10.0),
+        Transaction::deposit(1, 2, 10.0),
+        Transaction::deposit(1, 3, 10.0),
+        Transaction::dispute(1, 1),
+        Transaction::chargeback(1, 1),
+        Transaction::dispute(1, 2),
+        Transaction::resolve(1, 1),
+        Transaction::dispute(1, 3),
+    ];
+
+    let result = run_with_config(store.clone(), transactions, config).await;
+    assert!(result.is_ok());
+    // The ignored resolve of the charged back transfer does not lower the total, so the last
+    // dispute would still push it to 20.
+    assert_eq!(held(&store), vec![10.0]);
+}
+
+#[tokio::test]
+async fn ignored_resolve_of_charged_back() {
+    let store = mem_store_with_policy(Policy {
+        freeze_on_chargeback: false,
+        resolve_charged_back: ChargedBackResolvePolicy::Ignore,
+        ..Default::default()
+    });
+    let config = IoConfig {
+        max_disputed_total: Some(10.0),
+        ..Default::default()
+    };
+    let transactions = vec![
+        Transaction::deposit(1, 1, 10.0),
+        Transaction::deposit(1, 2, 10.0),
+        Transaction::deposit(2, 3, 10.0),
+        Transaction::dispute(1, 1),
+        Transaction::chargeback(1, 1),
+        Transaction::dispute(1, 2),
+        Transaction::resolve(1, 1),
+        Transaction::dispute(2, 3),
+    ];
+
+    let tally = Tally::default();
+    let (tx, rx) = mpsc::channel(transactions.len());
+    for transaction in transactions {
+        tx.send(transaction).await.unwrap();
+    }
+    drop(tx);
+    let result = IoTask::new(rx, store.clone(), config, None)
+        .with_tally(tally.clone())
+        .run(CHANNEL_SIZE)
+        .await;
+    assert!(result.is_ok());
+
+    // The resolve is accepted without effect, so it neither releases the total nor counts as an
+    // applied resolve, and the dispute of the other client's deposit is rejected.
+    assert_eq!(held(&store), vec![10.0, 0.0]);
+    let outcomes = tally.outcomes();
+    assert_eq!(outcomes.applied.resolve, 0);
+    assert_eq!(outcomes.rejected.resolve, 0);
+    assert_eq!(outcomes.applied.dispute, 2);
+    assert_eq!(outcomes.rejected.dispute, 1);
+}
+
+/// A store that counts how many times a client's state has been read, which happens each time a
+/// client is started, unless the clients have been indexed and it has no stored state.
+#[derive(Debug, Clone, Default)]
+struct CountingStore {
+    inner: MemStore,
+    loads: Arc<AtomicUsize>,
+}
+
+impl StoreEngine for CountingStore {
+    fn put(&self, keyspace: Keyspace, key: &[u8], value: &[u8]) -> Result<(), StoreError> {
+        self.inner.put(keyspace, key, value)
+    }
+
+    fn get(&self, keyspace: Keyspace, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        if keyspace == Keyspace::Clients {
+            self.loads.fetch_add(1, Ordering::SeqCst);
+        }
+        self.inner.get(keyspace, key)
+    }
+
+    fn delete(&self, keyspace: Keyspace, key: &[u8]) -> Result<(), StoreError> {
+        self.inner.delete(keyspace, key)
+    }
+
+    fn scan(&self, keyspace: Keyspace) -> Scan<'_> {
+        self.inner.scan(keyspace)
+    }
+}
+
+#[tokio::test]
+async fn idle_timeout() {
+    let clock = MockClock::new();
+    let engine = CountingStore::default();
+    let loads = engine.loads.clone();
+    let mut store = ClientStore::new(engine);
+    store.set_clock(Arc::new(clock.clone()));
+
+    let config = IoConfig {
+        idle_timeout: Some(Duration::from_secs(60)),
+        ..Default::default()
+    };
+    let (tx, rx) = mpsc::channel(1);
+    let task = tokio::spawn(IoTask::new(rx, store.clone(), config, None).run(CHANNEL_SIZE));
+
+    // Sends a deposit for client one and waits until it has been stored, which is after the client
+    // has been started. Returns the number of times that the client has been started.
+    let deposit = |id: u32| {
+        let tx = tx.clone();
+        let store = store.clone();
+        let loads = loads.clone();
+        async move {
+            tx.send(Transaction::deposit(1, id, 1.0)).await.unwrap();
+            while store.get_transaction(1, id).unwrap().is_none() {
+                tokio::task::yield_now().await;
+            }
+            loads.load(Ordering::SeqCst)
+        }
+    };
+
+    assert_eq!(deposit(1).await, 1);
+    clock.advance(Duration::from_secs(59));
+    assert_eq!(deposit(2).await, 1);
+    clock.advance(Duration::from_secs(60));
+    assert_eq!(deposit(3).await, 2);
+
+    drop(tx);
+    assert!(task.await.unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn evicted_client_reloaded() {
+    let engine = CountingStore::default();
+    let loads = engine.loads.clone();
+    let store = ClientStore::new(engine);
+    let config = IoConfig {
+        max_clients: Some(1),
+        ..Default::default()
+    };
+    let transactions = vec![
+        Transaction::deposit(1, 1, 5.0),
+        Transaction::deposit(2, 2, 1.0),
+        Transaction::withdrawal(1, 3, 2.0),
+    ];
+
+    let result = run_with_config(store.clone(), transactions, config).await;
+    assert!(result.is_ok());
+    // Starting client two evicts client one, so client one is loaded again by its withdrawal.
+    assert_eq!(loads.load(Ordering::SeqCst), 3);
+    let state = State::from(store.get_client_state(1).unwrap().unwrap());
+    assert_eq!(state.available, 3.0);
+    assert_eq!(state.total, 3.0);
+}
+
+#[tokio::test]
+async fn new_clients_not_read() {
+    let engine = CountingStore::default();
+    let loads = engine.loads.clone();
+    let store = ClientStore::new(engine);
+    let config = IoConfig {
+        index_clients: true,
+        ..Default::default()
+    };
+
+    let transactions = vec![Transaction::deposit(1, 1, 5.0)];
+    let result = run_with_config(store.clone(), transactions, config).await;
+    assert!(result.is_ok());
+    assert_eq!(loads.load(Ordering::SeqCst), 0);
+
+    // Only the client that was persisted by the previous run is read.
+    let transactions = vec![
+        Transaction::deposit(1, 2, 1.0),
+        Transaction::deposit(2, 3, 2.0),
+    ];
+    let result = run_with_config(store.clone(), transactions, config).await;
+    assert!(result.is_ok());
+    assert_eq!(loads.load(Ordering::SeqCst), 1);
+    let state = State::from(store.get_client_state(1).unwrap().unwrap());
+    assert_eq!(state.available, 6.0);
+}
+
+#[tokio::test]
+async fn max_clients_parity() {
+    let transactions = interleaved_feed(9, 900);
+    let expected = final_states(transactions.clone(), IoConfig::default()).await;
+
+    for max_clients in [1, 2, 8] {
+        let config = IoConfig {
+            max_clients: Some(max_clients),
+            ..Default::default()
+        };
+        assert_eq!(final_states(transactions.clone(), config).await, expected);
+    }
+}
+
+fn defer_config(capacity: usize) -> IoConfig {
+    IoConfig {
+        defer_disputes: Some(capacity),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn deferred_dispute() {
+    let transactions = vec![
+        Transaction::dispute(1, 5),
+        Transaction::deposit(2, 1, 1.0),
+        Transaction::deposit(1, 5, 10.0),
+        Transaction::deposit(1, 6, 2.0),
+    ];
+
+    let mut states = final_states(transactions.clone(), IoConfig::default()).await;
+    assert_eq!(State::from(states.remove(0)).held, 0.0);
+
+    for config in [
+        defer_config(8),
+        IoConfig {
+            micro_batch: Some(4),
+            ..defer_config(8)
+        },
+    ] {
+        let mut states = final_states(transactions.clone(), config).await;
+        let state = State::from(states.remove(0));
+        assert_eq!(state.available, 2.0);
+        assert_eq!(state.held, 10.0);
+    }
+}
+
+#[tokio::test]
+async fn deferred_dispute_order() {
+    let transactions = vec![
+        Transaction::dispute(1, 5),
+        Transaction::chargeback(1, 5),
+        Transaction::deposit(1, 5, 10.0),
+    ];
+
+    let mut states = final_states(transactions, defer_config(8)).await;
+    let state = State::from(states.remove(0));
+    assert_eq!(state.total, 0.0);
+    assert!(state.locked);
+}
+
+#[tokio::test]
+async fn deferred_transfer_dispute() {
+    // The dispute references the deposit leg of the transfer, which has not been received yet.
+    let transactions = vec![
+        Transaction::deposit(1, 1, 10.0),
+        Transaction::dispute(2, 7),
+        Transaction::transfer(1, 2, 7, 4.0),
+    ];
+
+    for config in [
+        defer_config(8),
+        IoConfig {
+            shard_workers: Some(2),
+            ..defer_config(8)
+        },
+        IoConfig {
+            micro_batch: Some(4),
+            ..defer_config(8)
+        },
+    ] {
+        let states = final_states(transactions.clone(), config).await;
+        let states = states.into_iter().map(State::from).collect::<Vec<_>>();
+        assert_eq!(states[0].available, 6.0);
+        assert_eq!(states[1].available, 0.0);
+        assert_eq!(states[1].held, 4.0);
+    }
+
+    // The released dispute is still held to the limit on the total funds under dispute.
+    let config = IoConfig {
+        max_disputed_total: Some(3.0),
+        ..defer_config(8)
+    };
+    let mut states = final_states(transactions, config).await;
+    let state = State::from(states.remove(1));
+    assert_eq!(state.available, 4.0);
+    assert_eq!(state.held, 0.0);
+}
+
+#[tokio::test]
+async fn unmatched_deferred_disputes() {
+    let dir = TempDir::new("io").unwrap();
+    let rejects_path = dir.path().join("rejects.csv");
+    let rejects = RejectsWriter::create(rejects_path.clone()).unwrap();
+    let store = mem_store();
+    let (tx, rx) = mpsc::channel(8);
+    // The second dispute does not fit in the buffer, so it is rejected straight away. The first is
+    // rejected once the input ends.
+    for transaction in [
+        Transaction::dispute(1, 5),
+        Transaction::dispute(2, 6),
+        Transaction::deposit(1, 1, 10.0),
+    ] {
+        tx.send(transaction).await.unwrap();
+    }
+    drop(tx);
+    let result = IoTask::new(rx, store.clone(), defer_config(1), Some(rejects.clone()))
+        .run(CHANNEL_SIZE)
+        .await;
+    assert!(result.is_ok());
+    rejects.flush().unwrap();
+
+    let rejections = csv::Reader::from_path(rejects_path)
+        .unwrap()
+        .into_deserialize::<Rejection>()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let rejected = rejections
+        .iter()
+        .map(|rejection| {
+            (
+                rejection.client.as_str(),
+                rejection.tx.as_str(),
+                rejection.reason,
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rejected,
+        vec![
+            ("2", "6", RejectReason::NotFound),
+            ("1", "5", RejectReason::NotFound)
+        ]
+    );
+}
+
+#[cfg(feature = "latency")]
+#[test]
+fn latency_percentiles() {
+    let latency = Latency::default();
+    assert_eq!(latency.summary(), LatencySummary::default());
+
+    for millis in 1..=1000 {
+        latency.record(Duration::from_millis(millis), 1);
+    }
+    let summary = latency.summary();
+    assert_eq!(summary.count, 1000);
+
+    for (percentile, expected) in [(summary.p50, 500), (summary.p95, 950), (summary.p99, 990)] {
+        let expected = Duration::from_millis(expected);
+        assert!(percentile >= expected, "{:?} >= {:?}", percentile, expected);
+        assert!(
+            percentile <= expected.mul_f64(1.01),
+            "{:?} <= {:?}",
+            percentile,
+            expected
+        );
+    }
+}
+
+#[cfg(feature = "latency")]
+#[test]
+fn latency_small_and_large() {
+    let latency = Latency::default();
+    latency.record(Duration::from_micros(3), 2);
+    latency.record(Duration::from_secs(u64::MAX), 1);
+
+    let summary = latency.summary();
+    assert_eq!(summary.count, 3);
+    assert_eq!(summary.p50, Duration::from_micros(3));
+    assert!(summary.p99 > Duration::from_secs(u64::MAX / 1_000_000 / 2));
+}
+
+#[cfg(feature = "latency")]
+#[tokio::test]
+async fn latency() {
+    for micro_batch in [None, Some(4)] {
+        let transactions = (1..=20)
+            .map(|tx| Transaction::deposit((tx % 3) as u16, tx, 1.0))
+            .collect::<Vec<_>>();
+        let (tx, rx) = mpsc::channel(transactions.len());
+        for transaction in transactions {
+            tx.send(transaction).await.unwrap();
+        }
+        drop(tx);
+
+        let latency = Latency::default();
+        let config = IoConfig {
+            micro_batch,
+            ..Default::default()
+        };
+        let result = IoTask::new(rx, mem_store(), config, None)
+            .with_latency(latency.clone())
+            .run(CHANNEL_SIZE)
+            .await;
+        assert!(result.is_ok());
+
+        let summary = latency.summary();
+        assert_eq!(summary.count, 20, "micro_batch {:?}", micro_batch);
+        assert!(summary.p50 <= summary.p95);
+        assert!(summary.p95 <= summary.p99);
+        assert!(summary.p99 < Duration::from_secs(5));
+    }
+}
+
+#[tokio::test]
+async fn rounded_amounts() {
+    let input = "type, client, tx, amount
+deposit, 1, 1, 1.23456789
+dispute, 1, 1,";
+    let transactions = ReaderConfig::default()
+        .builder()
+        .from_reader(input.as_bytes())
+        .into_deserialize::<CsvTransaction>()
+        .map(|record| Transaction::try_from(record.unwrap()).unwrap())
+        .collect::<Vec<_>>();
+
+    let store = mem_store();
+    let result = run_transactions(store.clone(), transactions).await;
+    assert!(result.is_ok());
+
+    #[cfg(not(feature = "scale-8"))]
+    let expected = 1.2346;
+    #[cfg(feature = "scale-8")]
+    let expected = 1.23456789;
+    assert_eq!(held(&store), vec![expected]);
+    match store.get_transaction(1, 1).unwrap() {
+        Some(Transaction::Transfer(transfer)) => assert_eq!(transfer.amount, expected),
+        other => panic!("Expected a stored deposit. Got `{:?}`", other),
+    }
+}
+
+/// Requests the state of `client` through `snapshots`, returning its reporting view.
+async fn snapshot(snapshots: &mpsc::Sender<SnapshotRequest>, client: u16) -> Option<State> {
+    let (callback, rx) = oneshot::channel();
+    snapshots
+        .send(SnapshotRequest { client, callback })
+        .await
+        .unwrap();
+    rx.await.unwrap().unwrap().map(State::from)
+}
+
+async fn snapshot_during_run(config: IoConfig) {
+    let (tx, rx) = mpsc::channel(CHANNEL_SIZE);
+    let (snapshots, snapshots_rx) = mpsc::channel(CHANNEL_SIZE);
+    let task = tokio::spawn(
+        IoTask::new(rx, mem_store(), config, None)
+            .with_snapshots(snapshots_rx)
+            .run(CHANNEL_SIZE),
+    );
+
+    tx.send(Transaction::deposit(1, 1, 10.0)).await.unwrap();
+    let state = snapshot(&snapshots, 1).await.unwrap();
+    assert_eq!((state.available, state.held), (10.0, 0.0));
+
+    // When sorted, client 1 is no longer running, so its state is read from the store.
+    tx.send(Transaction::deposit(2, 2, 5.0)).await.unwrap();
+    tx.send(Transaction::dispute(2, 2)).await.unwrap();
+    assert_eq!(snapshot(&snapshots, 1).await.unwrap().available, 10.0);
+    let state = snapshot(&snapshots, 2).await.unwrap();
+    assert_eq!((state.available, state.held), (0.0, 5.0));
+    assert_eq!(snapshot(&snapshots, 3).await, None);
+
+    // The task completes once the transactions end, while snapshots may still be requested.
+    drop(tx);
+    assert!(task.await.unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn snapshots() {
+    snapshot_during_run(IoConfig::default()).await;
+    let config = IoConfig {
+        assume_sorted: true,
+        ..Default::default()
+    };
+    snapshot_during_run(config).await;
+    let config = IoConfig {
+        shard_workers: Some(2),
+        ..Default::default()
+    };
+    snapshot_during_run(config).await;
+}
+
+async fn run_transfer(
+    transactions: Vec<Transaction>,
+    config: IoConfig,
+) -> (ClientStore<MemStore>, Vec<Rejection>) {
+    let dir = TempDir::new("io").unwrap();
+    let rejects_path = dir.path().join("rejects.csv");
+    let rejects = RejectsWriter::create(rejects_path.clone()).unwrap();
+    let store = mem_store();
+    let (tx, rx) = mpsc::channel(transactions.len() + 1);
+    for transaction in transactions {
+        tx.send(transaction).await.unwrap();
+    }
+    drop(tx);
+
+    let result = IoTask::new(rx, store.clone(), config, Some(rejects.clone()))
+        .run(CHANNEL_SIZE)
+        .await;
+    assert!(result.is_ok());
+    rejects.flush().unwrap();
+    let rejections = csv::Reader::from_path(rejects_path)
+        .unwrap()
+        .into_deserialize::<Rejection>()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    (store, rejections)
+}
+
+fn transfer_configs() -> [IoConfig; 4] {
+    [
+        IoConfig::default(),
+        sorted_config(),
+        IoConfig {
+            micro_batch: Some(8),
+            ..Default::default()
+        },
+        IoConfig {
+            shard_workers: Some(2),
+            ..Default::default()
+        },
+    ]
+}
+
+#[tokio::test]
+async fn transfer() {
+    let transactions = vec![
+        Transaction::deposit(1, 1, 10.0),
+        Transaction::transfer(1, 2, 2, 4.0),
+        Transaction::withdrawal(2, 3, 1.0),
+    ];
+    for config in transfer_configs() {
+        let (store, rejections) = run_transfer(transactions.clone(), config).await;
+        assert!(rejections.is_empty());
+
+        assert_eq!(stored_available(&store, 1), 6.0);
+        assert_eq!(stored_available(&store, 2), 3.0);
+        assert_eq!(
+            store.get_transaction(1, 2).unwrap().map(|tx| tx.tx_type()),
+            Some(TransactionType::Withdrawal)
+        );
+        assert_eq!(
+            store.get_transaction(2, 2).unwrap().map(|tx| tx.tx_type()),
+            Some(TransactionType::Deposit)
+        );
+        assert!(store.check_books(1).unwrap().is_balanced());
+    }
+}
+
+#[tokio::test]
+async fn transfer_underfunded() {
+    let transactions = vec![
+        Transaction::deposit(1, 1, 3.0),
+        Transaction::transfer(1, 2, 2, 4.0),
+    ];
+    for config in transfer_configs() {
+        let (store, rejections) = run_transfer(transactions.clone(), config).await;
+        assert_eq!(rejections.len(), 1);
+        assert_eq!(rejections[0].tx_type, "transfer");
+        assert_eq!(rejections[0].to, "2");
+        assert_eq!(rejections[0].reason, RejectReason::InsufficientFunds);
+
+        // The destination is never sent the deposit.
+        assert_eq!(stored_available(&store, 1), 3.0);
+        assert_eq!(store.get_client_state(2), Ok(None));
+        assert_eq!(store.get_transaction(2, 2), Ok(None));
+    }
+}
+
+#[tokio::test]
+async fn transfer_rolled_back() {
+    // The destination is frozen, so the deposit is rejected after the withdrawal was applied.
+    let transactions = vec![
+        Transaction::deposit(1, 1, 5.0),
+        Transaction::dispute(1, 1),
+        Transaction::chargeback(1, 1),
+        Transaction::deposit(2, 2, 10.0),
+        Transaction::transfer(2, 1, 3, 4.0),
+    ];
+    for config in transfer_configs() {
+        let (store, rejections) = run_transfer(transactions.clone(), config).await;
+        assert_eq!(rejections.len(), 1);
+        assert_eq!(rejections[0].reason, RejectReason::Frozen);
+
+        assert_eq!(stored_available(&store, 1), 0.0);
+        assert_eq!(stored_available(&store, 2), 10.0);
+        assert_eq!(store.get_transaction(2, 3), Ok(None));
+        assert!(store.check_books(1).unwrap().is_balanced());
+    }
+}
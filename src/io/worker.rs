@@ -0,0 +1,79 @@
+use crate::client::{ClientRequest, ClientState, ClientStore};
+use crate::db::StoreEngine;
+use fnv::FnvHashMap;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{span, Level};
+use tracing_futures::Instrument;
+
+const WORKER_TASK: &str = "Worker task";
+
+/// A request for a worker task to serve.
+#[derive(Debug)]
+pub enum WorkerRequest {
+    /// Adds a client with `state` to the worker's shard. A client that is already in the shard
+    /// keeps its current state.
+    Start(ClientState),
+    /// Serves `request` against the client in the worker's shard with the ID `client`.
+    Serve { client: u16, request: ClientRequest },
+}
+
+/// A fixed pool of worker tasks that clients are sharded across by their ID. Each worker holds the
+/// state of every client in its shard and serves their requests in the order that they are sent,
+/// so no task is spawned per client.
+#[derive(Debug)]
+pub struct WorkerPool {
+    workers: Vec<(JoinHandle<()>, mpsc::Sender<WorkerRequest>)>,
+}
+
+impl WorkerPool {
+    /// Spawns `count` worker tasks, each with a channel of `channel_size` requests, that persist
+    /// their clients in `store`. At least one worker is always spawned.
+    pub fn new<D>(count: usize, store: ClientStore<D>, channel_size: usize) -> WorkerPool
+    where
+        D: StoreEngine + 'static,
+    {
+        let workers = (0..count.max(1))
+            .map(|index| {
+                let (tx, rx) = mpsc::channel(channel_size);
+                let store = store.clone();
+                let task = tokio::spawn(run_worker(rx, store).instrument(span!(
+                    Level::INFO,
+                    WORKER_TASK,
+                    ?index
+                )));
+                (task, tx)
+            })
+            .collect();
+
+        WorkerPool { workers }
+    }
+
+    /// Returns a sender to the worker whose shard holds the client with `client_id`.
+    pub fn sender(&self, client_id: u16) -> mpsc::Sender<WorkerRequest> {
+        let (_, tx) = &self.workers[client_id as usize % self.workers.len()];
+        tx.clone()
+    }
+}
+
+/// Serves every request that is received on `rx` until the channel has no senders. A request for
+/// a client that has not been started is dropped, which drops its callback.
+async fn run_worker<D>(mut rx: mpsc::Receiver<WorkerRequest>, store: ClientStore<D>)
+where
+    D: StoreEngine,
+{
+    let mut states: FnvHashMap<u16, ClientState> = FnvHashMap::default();
+
+    while let Some(request) = rx.recv().await {
+        match request {
+            WorkerRequest::Start(state) => {
+                states.entry(state.id()).or_insert(state);
+            }
+            WorkerRequest::Serve { client, request } => {
+                if let Some(state) = states.get_mut(&client) {
+                    state.serve(request, &store);
+                }
+            }
+        }
+    }
+}
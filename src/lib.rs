@@ -0,0 +1,628 @@
+//! A payments engine that executes deposits, withdrawals, disputes, resolves and chargebacks
+//! against a store of client states. The binary is a thin wrapper over `execute`, while
+//! `process_csv` runs the pipeline of a reader task and an IO task over a single CSV file for
+//! embedding the engine elsewhere. `Processor::run_transactions` executes transactions that are
+//! already in memory.
+
+#![deny(
+    missing_copy_implementations,
+    missing_debug_implementations,
+    trivial_casts,
+    trivial_numeric_casts,
+    unstable_features,
+    unused_must_use,
+    unused_mut,
+    unused_imports,
+    unused_import_braces
+)]
+
+#[cfg(test)]
+mod tests;
+
+mod cli;
+mod client;
+mod clock;
+mod data;
+mod db;
+mod io;
+mod meta;
+mod parser;
+pub mod processor;
+mod scale;
+mod transaction;
+mod warnings;
+
+pub use crate::client::{ClientState, ClientStore, Keyspace, Policy, ValueCodec};
+#[cfg(feature = "sqlite")]
+pub use crate::db::sqlite::SqliteStore;
+#[cfg(feature = "rocks")]
+pub use crate::db::DiskStore;
+pub use crate::db::{BatchWrite, MemStore, StoreEngine, StoreError};
+pub use crate::io::{IoConfig, IoError, IoTask, SnapshotRequest};
+pub use crate::parser::reader::{reader_task, ReaderConfig, ReaderError};
+pub use crate::processor::Processor;
+pub use crate::transaction::Transaction;
+
+pub use crate::cli::{RuntimeArgs, THREADS_ENV};
+
+use crate::cli::{parse_end, ArgsError, CompactArgs, GenerateArgs, ReplayArgs, RetryArgs, RunArgs};
+use crate::client::{Books, ClientBooks, State};
+use crate::io::Tally;
+#[cfg(feature = "latency")]
+use crate::io::{Latency, LatencySummary};
+use crate::meta::{write_manifest, write_warnings, MetaError, RunManifest, RunMeta};
+
+use crate::data::{generate_csv, mem_store_with_policy};
+use crate::parser::lint::lint_file;
+use crate::parser::normalize::normalize_file;
+use crate::parser::reader::read_client_metadata;
+use crate::parser::rejects::{RejectsError, RejectsWriter};
+use crate::parser::writer::{write_state, WriterError};
+use crate::processor::{ReplayError, RetryError};
+use crate::warnings::WarningCollector;
+use futures::future::try_join;
+use futures::TryFutureExt;
+use std::fs::File;
+use std::io::{stdout, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+const IO_BUFFER_SIZE: usize = 256;
+const BRIDGE_BUFFER_SIZE: usize = 1024;
+const GENERATE_COMMAND: &str = "generate";
+const REPLAY_COMMAND: &str = "replay";
+const META_COMMAND: &str = "meta";
+const COMPACT_COMMAND: &str = "compact";
+const DISPUTED_COMMAND: &str = "disputed";
+const NORMALIZE_COMMAND: &str = "normalize";
+const LINT_COMMAND: &str = "lint";
+const RETRY_COMMAND: &str = "retry";
+
+/// A short summary of the commands, which is printed along with any usage error.
+pub const USAGE: &str = "Usage:
+    transaction-machine <input>... [options]
+    transaction-machine generate <count> [--error-rate <rate>] [--allow-empty]
+    transaction-machine replay <store> [--policy <settings>]
+    transaction-machine meta <store>
+    transaction-machine compact <store> [--policy <settings>]
+    transaction-machine disputed <store>
+    transaction-machine retry <rejects> <store> [--policy <settings>] [--rejects <file>]
+    transaction-machine normalize <input> <output>
+    transaction-machine lint <input>
+
+Any command may be preceded by `--threads <n>` to run it on `n` worker threads, rather than one
+for each core. The number of threads may also be set by the TRANSACTION_MACHINE_THREADS variable.";
+
+/// Executes the command in `args`, or runs the payments machine if `args` does not begin with a
+/// command.
+pub async fn execute<I>(mut args: I) -> Result<(), TaskError>
+where
+    I: Iterator<Item = String>,
+{
+    let command = args.next();
+
+    match command.as_deref() {
+        Some(GENERATE_COMMAND) => {
+            let GenerateArgs { count, error_rate } = GenerateArgs::parse(args)?;
+            let invalid_count = generate_csv(count, error_rate);
+            if invalid_count > 0 {
+                println!("Injected {} malformed rows", invalid_count);
+            }
+            Ok(())
+        }
+        Some(REPLAY_COMMAND) => {
+            let path = args
+                .next()
+                .ok_or_else(|| ArgsError::MissingValue(REPLAY_COMMAND.to_string()))?;
+            replay(ReplayArgs::parse(path, args)?).await
+        }
+        Some(META_COMMAND) => {
+            let path = args
+                .next()
+                .ok_or_else(|| ArgsError::MissingValue(META_COMMAND.to_string()))?;
+            parse_end(args)?;
+            meta(path)
+        }
+        Some(COMPACT_COMMAND) => {
+            let path = args
+                .next()
+                .ok_or_else(|| ArgsError::MissingValue(COMPACT_COMMAND.to_string()))?;
+            compact(CompactArgs::parse(path, args)?)
+        }
+        Some(DISPUTED_COMMAND) => {
+            let path = args
+                .next()
+                .ok_or_else(|| ArgsError::MissingValue(DISPUTED_COMMAND.to_string()))?;
+            parse_end(args)?;
+            disputed(path)
+        }
+        Some(RETRY_COMMAND) => {
+            let input = args
+                .next()
+                .ok_or_else(|| ArgsError::MissingValue(RETRY_COMMAND.to_string()))?;
+            let store = args
+                .next()
+                .ok_or_else(|| ArgsError::MissingValue(RETRY_COMMAND.to_string()))?;
+            retry(RetryArgs::parse(input, store, args)?).await
+        }
+        Some(NORMALIZE_COMMAND) => {
+            let input = args
+                .next()
+                .ok_or_else(|| ArgsError::MissingValue(NORMALIZE_COMMAND.to_string()))?;
+            let output = args
+                .next()
+                .ok_or_else(|| ArgsError::MissingValue(NORMALIZE_COMMAND.to_string()))?;
+            parse_end(args)?;
+            let count = normalize_file(PathBuf::from(input), PathBuf::from(output))?;
+            println!("Normalized {} transactions", count);
+            Ok(())
+        }
+        Some(LINT_COMMAND) => {
+            let input = args
+                .next()
+                .ok_or_else(|| ArgsError::MissingValue(LINT_COMMAND.to_string()))?;
+            parse_end(args)?;
+            let problems = lint_file(PathBuf::from(input))?;
+            for problem in &problems {
+                println!("{}", problem);
+            }
+            match problems.len() {
+                0 => Ok(()),
+                count => Err(TaskError::Lint(count)),
+            }
+        }
+        Some(file) => run(RunArgs::parse(file.to_string(), args)?).await,
+        None => Err(TaskError::Usage(
+            "A command or an input file must be provided".to_string(),
+        )),
+    }
+}
+
+/// Reads the transactions in the CSV file at `input` and executes them against `store` with its
+/// policy, using the default reader and IO configuration. The reader and IO tasks are connected as
+/// they are by `run`, and a failure of either is returned. The store is returned once every
+/// transaction has been executed, so that the client states may be read from it.
+pub async fn process_csv<P, D>(input: P, store: ClientStore<D>) -> Result<ClientStore<D>, TaskError>
+where
+    P: AsRef<Path>,
+    D: StoreEngine + 'static,
+{
+    let (tx, rx) = mpsc::channel(IO_BUFFER_SIZE);
+    let io_task = IoTask::new(rx, store.clone(), IoConfig::default(), None)
+        .run(BRIDGE_BUFFER_SIZE)
+        .map_err(TaskError::Io);
+    let reader_task = reader_task(
+        vec![input.as_ref().to_path_buf()],
+        ReaderConfig::default(),
+        tx,
+        None,
+        None,
+    )
+    .map_err(TaskError::Reader);
+
+    try_join(io_task, reader_task).await?;
+    Ok(store)
+}
+
+/// Asynchronously runs the payments machine. Serving the input files in order using a reader
+/// configured by `args` and executing transactions using its policy in an IO task configured by
+/// `args`. If a client metadata file is provided, then the metadata in the file is attached to the
+/// clients before processing begins. If a rejects file is provided, then every transaction that is
+/// read but not applied is written to it. If a manifest file is provided, then a summary of the
+/// run is written to it once processing completes.
+///
+/// If a store directory is provided, then transactions are executed against the persistent store
+/// in it. Without the `rocks` feature, the path is instead the SQLite database file of the store.
+/// Otherwise, they are executed against a new in-memory store.
+async fn run(args: RunArgs) -> Result<(), TaskError> {
+    #[cfg(feature = "rocks")]
+    if let Some(dir) = &args.store_dir {
+        let mut engine = crate::db::DiskStore::new(dir)?;
+        // Writes are only made durable at the commit boundaries, which skip the write ahead log.
+        engine.set_write_ahead_log(args.commit_every.is_none());
+        let store = crate::client::ClientStore::with_policy(engine, args.policy);
+        return run_with_store(args, store).await;
+    }
+    #[cfg(all(feature = "sqlite", not(feature = "rocks")))]
+    if let Some(path) = &args.store_dir {
+        let store = crate::client::ClientStore::with_policy(SqliteStore::new(path)?, args.policy);
+        return run_with_store(args, store).await;
+    }
+
+    let store = mem_store_with_policy(args.policy);
+    run_with_store(args, store).await
+}
+
+/// Runs the payments machine as described by `run`, executing transactions against `store`.
+async fn run_with_store<D>(args: RunArgs, mut store: ClientStore<D>) -> Result<(), TaskError>
+where
+    D: StoreEngine + 'static,
+{
+    let started = Instant::now();
+    let meta = RunMeta::new(&args)?;
+    let RunArgs {
+        inputs,
+        reader: reader_config,
+        policy: _,
+        io: io_config,
+        writer: writer_config,
+        client_metadata,
+        rejects,
+        manifest,
+        warnings: warnings_path,
+        output,
+        latency,
+        metrics,
+        check_books,
+        verify_workers,
+        value_codec,
+        store_dir: _,
+        commit_every,
+    } = args;
+    let (tx, rx) = mpsc::channel(IO_BUFFER_SIZE);
+    // The output file is created before any transactions are processed, so that a path which
+    // cannot be written to fails the run immediately.
+    let output: Box<dyn Write + Send> = match output {
+        Some(path) => match File::create(&path) {
+            Ok(file) => Box::new(file),
+            Err(e) => return Err(TaskError::Output(path, e)),
+        },
+        None => Box::new(stdout()),
+    };
+
+    // A store that was written by an earlier run can only be read with the codec that it used.
+    if let Some(stored) = store.get_run_meta()? {
+        if stored.value_codec != value_codec {
+            return Err(TaskError::CodecMismatch {
+                stored: stored.value_codec,
+                requested: value_codec,
+            });
+        }
+    }
+    store.set_value_codec(value_codec);
+    store.set_commit_every(commit_every);
+    store.put_run_meta(&meta)?;
+    if let Some(path) = client_metadata {
+        for (client_id, metadata, limits) in read_client_metadata(path)? {
+            store.put_client_metadata(client_id, metadata, limits)?;
+        }
+    }
+    let rejects = rejects.map(RejectsWriter::create).transpose()?;
+
+    let tally = (manifest.is_some() || metrics).then(Tally::default);
+    #[cfg(feature = "latency")]
+    let latency = latency.then(Latency::default);
+    // `--latency` is only accepted with the `latency` feature.
+    #[cfg(not(feature = "latency"))]
+    let _ = latency;
+    let warnings = (manifest.is_some() || warnings_path.is_some()).then(WarningCollector::default);
+
+    let mut io_task = IoTask::new(rx, store.clone(), io_config, rejects.clone());
+    if let Some(tally) = &tally {
+        io_task = io_task.with_tally(tally.clone());
+    }
+    #[cfg(feature = "latency")]
+    if let Some(latency) = &latency {
+        io_task = io_task.with_latency(latency.clone());
+    }
+    if let Some(warnings) = &warnings {
+        io_task = io_task.with_warnings(warnings.clone());
+    }
+    let io_task = io_task.run(BRIDGE_BUFFER_SIZE).map_err(TaskError::Io);
+    let reader_task = reader_task(inputs, reader_config, tx, rejects.clone(), warnings.clone())
+        .map_err(TaskError::Reader);
+
+    let report_skipped = reader_config.skip_malformed;
+    // A failure of either task is returned, which stops the other.
+    let (_, read_summary) = try_join(io_task, reader_task).await?;
+    if let Some(rejects) = rejects {
+        rejects.flush()?;
+    }
+    let warnings = warnings
+        .map(|warnings| warnings.warnings())
+        .unwrap_or_default();
+    if let (Some(path), Some(tally)) = (manifest, &tally) {
+        let states = store
+            .scan_client_states(writer_config.skip_corrupt)?
+            .states
+            .into_iter()
+            .map(State::from)
+            .collect::<Vec<_>>();
+        let manifest = RunManifest::new(
+            &meta,
+            read_summary,
+            tally.outcomes(),
+            &states,
+            &warnings,
+            started.elapsed(),
+        );
+        write_manifest(path, &manifest)?;
+    }
+    if let Some(path) = warnings_path {
+        write_warnings(path, &warnings)?;
+    }
+    if check_books {
+        let books = store.check_books(verify_workers)?;
+        for ClientBooks {
+            client,
+            ledger,
+            supply,
+        } in &books.unbalanced
+        {
+            eprintln!(
+                "Client {}: stored transactions net to {}, but the client holds {}",
+                client, ledger, supply
+            );
+        }
+        if !books.is_balanced() {
+            return Err(TaskError::Unbalanced(books));
+        }
+    }
+    if report_skipped && read_summary.unparsed > 0 {
+        eprintln!(
+            "Skipped {} records that could not be parsed",
+            read_summary.unparsed
+        );
+    }
+    let mut output = BufWriter::new(output);
+    let summary = write_state(store, writer_config, &mut output)?;
+    output.flush().map_err(WriterError::from)?;
+    if !summary.skipped.is_empty() {
+        eprintln!(
+            "Skipped {} corrupt client records with the keys: {:?}",
+            summary.skipped.len(),
+            summary.skipped
+        );
+    }
+    #[cfg(feature = "latency")]
+    if let Some(latency) = latency {
+        let LatencySummary {
+            count,
+            p50,
+            p95,
+            p99,
+        } = latency.summary();
+        eprintln!(
+            "Transaction latency over {} transactions: p50 {:?}, p95 {:?}, p99 {:?}",
+            count, p50, p95, p99
+        );
+    }
+    if let Some(tally) = tally.filter(|_| metrics) {
+        eprintln!("{}", tally.metrics(started.elapsed()));
+    }
+    Ok(())
+}
+
+/// Replays the transactions stored in the store at the path in `args` into a new memory store
+/// using the policies in `args` and prints the resulting client states. This shows how the
+/// balances would have differed under those policies. The store is opened in read-only mode and
+/// read with the value codec that it was written with, so it is left untouched.
+#[cfg(feature = "rocks")]
+async fn replay(args: ReplayArgs) -> Result<(), TaskError> {
+    let ReplayArgs { store, policy } = args;
+    let mut store = crate::client::ClientStore::new(crate::db::DiskStore::open_read_only(store)?);
+    if let Some(meta) = store.get_run_meta()? {
+        store.set_value_codec(meta.value_codec);
+    }
+
+    let states = crate::processor::Processor::new(policy, IoConfig::default())
+        .replay_store(&store)
+        .await?;
+    let states = states.into_iter().map(State::from).collect();
+    let mut output = BufWriter::new(stdout());
+    let config = crate::parser::writer::WriterConfig::default();
+    crate::parser::writer::write_states(states, config, &mut output)?;
+    output.flush().map_err(WriterError::from)?;
+
+    Ok(())
+}
+
+/// Replaying the stored transactions requires a persistent store.
+#[cfg(not(feature = "rocks"))]
+async fn replay(_args: ReplayArgs) -> Result<(), TaskError> {
+    Err(ArgsError::RequiresRocks(REPLAY_COMMAND.to_string()).into())
+}
+
+/// Prints the configuration of the run that produced the store at `path`. The store is opened in
+/// read-only mode.
+#[cfg(feature = "rocks")]
+fn meta(path: String) -> Result<(), TaskError> {
+    let store = crate::client::ClientStore::new(crate::db::DiskStore::open_read_only(path)?);
+    let stdout = std::io::stdout();
+    meta::write_meta(&store, &mut stdout.lock())?;
+    Ok(())
+}
+
+/// Reading the run metadata requires a persistent store.
+#[cfg(not(feature = "rocks"))]
+fn meta(_path: String) -> Result<(), TaskError> {
+    Err(ArgsError::RequiresRocks(META_COMMAND.to_string()).into())
+}
+
+/// Removes every transaction that can no longer be disputed under the policies in `args` from the
+/// store at the path in `args`. If `args` has no policies, then those that the store was last run
+/// with are used. The store is read and written with the value codec that it was written with.
+#[cfg(feature = "rocks")]
+fn compact(args: CompactArgs) -> Result<(), TaskError> {
+    let CompactArgs { store, policy } = args;
+    let mut store = crate::client::ClientStore::new(crate::db::DiskStore::new(store)?);
+    let meta = store.get_run_meta()?;
+    if let Some(meta) = &meta {
+        store.set_value_codec(meta.value_codec);
+    }
+    let policy = policy
+        .or_else(|| meta.map(|meta| meta.policy))
+        .unwrap_or_default();
+    let removed = store.compact(&policy)?;
+    println!("Removed {} transactions", removed);
+    Ok(())
+}
+
+/// Compacting a store requires a persistent store.
+#[cfg(not(feature = "rocks"))]
+fn compact(_args: CompactArgs) -> Result<(), TaskError> {
+    Err(ArgsError::RequiresRocks(COMPACT_COMMAND.to_string()).into())
+}
+
+/// Prints the client, ID and amount of every transaction that is disputed in the store at `path`
+/// as CSV. The store is opened in read-only mode and read with the value codec that it was written
+/// with.
+#[cfg(feature = "rocks")]
+fn disputed(path: String) -> Result<(), TaskError> {
+    let mut store = crate::client::ClientStore::new(crate::db::DiskStore::open_read_only(path)?);
+    if let Some(meta) = store.get_run_meta()? {
+        store.set_value_codec(meta.value_codec);
+    }
+
+    println!("client, tx, amount");
+    for (client, tx, amount) in store.disputed_transactions()? {
+        println!(
+            "{}, {}, {:.*}",
+            client,
+            tx,
+            crate::scale::DECIMAL_PLACES,
+            amount
+        );
+    }
+    Ok(())
+}
+
+/// Listing the disputed transactions requires a persistent store.
+#[cfg(not(feature = "rocks"))]
+fn disputed(_path: String) -> Result<(), TaskError> {
+    Err(ArgsError::RequiresRocks(DISPUTED_COMMAND.to_string()).into())
+}
+
+/// Re-applies the transactions in a prior run's rejects file to the store at the path in `args`,
+/// using the policies in `args`. The store is read and written with the value codec that it was
+/// written with. If a rejects file is provided, then every transaction that is rejected again is
+/// written to it.
+#[cfg(feature = "rocks")]
+async fn retry(args: RetryArgs) -> Result<(), TaskError> {
+    let RetryArgs {
+        input,
+        store,
+        policy,
+        rejects,
+    } = args;
+    let mut store =
+        crate::client::ClientStore::with_policy(crate::db::DiskStore::new(store)?, policy);
+    if let Some(meta) = store.get_run_meta()? {
+        store.set_value_codec(meta.value_codec);
+    }
+    let rejects = rejects.map(RejectsWriter::create).transpose()?;
+
+    let summary =
+        crate::processor::retry_rejects(input, store, IoConfig::default(), rejects.clone()).await?;
+    if let Some(rejects) = rejects {
+        rejects.flush()?;
+    }
+    println!(
+        "Applied {} transactions, {} were rejected again",
+        summary.applied, summary.rejected
+    );
+    Ok(())
+}
+
+/// Retrying transactions requires a persistent store.
+#[cfg(not(feature = "rocks"))]
+async fn retry(_args: RetryArgs) -> Result<(), TaskError> {
+    Err(ArgsError::RequiresRocks(RETRY_COMMAND.to_string()).into())
+}
+
+/// An error that stopped a command or run.
+#[derive(Error, Debug)]
+pub enum TaskError {
+    #[error("{0}")]
+    Usage(String),
+    #[error("Invalid arguments provided: `{0}`")]
+    Args(ArgsError),
+    #[error("An error was produced by the reader task: `{0}`")]
+    Reader(ReaderError),
+    #[error("An error was produced by the IO task: `{0}`")]
+    Io(IoError),
+    #[error("An error was produced by the store: `{0}`")]
+    Store(StoreError),
+    #[error("An error was produced by the rejects writer: `{0}`")]
+    Rejects(RejectsError),
+    #[error("An error was produced when writing the output: `{0}`")]
+    Writer(WriterError),
+    #[error("Failed to create the output file `{}`: `{}`", .0.display(), .1)]
+    Output(PathBuf, std::io::Error),
+    #[error("An error was produced when handling the run metadata: `{0}`")]
+    Meta(MetaError),
+    #[error("An error was produced when retrying the rejected transactions: `{0}`")]
+    Retry(RetryError),
+    #[error("An error was produced when replaying the stored transactions: `{0}`")]
+    Replay(ReplayError),
+    #[error(
+        "The books do not balance: the stored transactions net to `{}`, but the clients hold `{}`, \
+         with `{}` clients unbalanced",
+        .0.ledger,
+        .0.supply,
+        .0.unbalanced.len()
+    )]
+    Unbalanced(Books),
+    #[error(
+        "The store was written with the `{stored:?}` value codec, but the `{requested:?}` codec \
+         was requested"
+    )]
+    CodecMismatch {
+        stored: ValueCodec,
+        requested: ValueCodec,
+    },
+    #[error("Found `{0}` problems in the input")]
+    Lint(usize),
+    #[error("Failed to build the runtime: `{0}`")]
+    Runtime(std::io::Error),
+}
+
+impl From<ArgsError> for TaskError {
+    fn from(e: ArgsError) -> Self {
+        TaskError::Args(e)
+    }
+}
+
+impl From<ReaderError> for TaskError {
+    fn from(e: ReaderError) -> Self {
+        TaskError::Reader(e)
+    }
+}
+
+impl From<StoreError> for TaskError {
+    fn from(e: StoreError) -> Self {
+        TaskError::Store(e)
+    }
+}
+
+impl From<RejectsError> for TaskError {
+    fn from(e: RejectsError) -> Self {
+        TaskError::Rejects(e)
+    }
+}
+
+impl From<WriterError> for TaskError {
+    fn from(e: WriterError) -> Self {
+        TaskError::Writer(e)
+    }
+}
+
+impl From<MetaError> for TaskError {
+    fn from(e: MetaError) -> Self {
+        TaskError::Meta(e)
+    }
+}
+
+impl From<RetryError> for TaskError {
+    fn from(e: RetryError) -> Self {
+        TaskError::Retry(e)
+    }
+}
+
+impl From<ReplayError> for TaskError {
+    fn from(e: ReplayError) -> Self {
+        TaskError::Replay(e)
+    }
+}
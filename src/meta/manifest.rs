@@ -0,0 +1,123 @@
+use crate::client::State;
+use crate::io::Outcomes;
+use crate::meta::{InputFile, MetaError, RunMeta};
+use crate::parser::reader::ReadSummary;
+use crate::scale::DECIMAL_PLACES;
+use crate::transaction::TypeCounts;
+use crate::warnings::Warning;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The rows of an input file that were read but never executed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SkippedRows {
+    /// Records that could not be parsed and were skipped, and written to the rejects if they were
+    /// provided.
+    pub unparsed: usize,
+    /// Transactions whose ID is outside of the configured transaction range.
+    pub out_of_range: usize,
+}
+
+/// The outcomes of the disputes, resolves and chargebacks in a run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DisputeOutcomes {
+    /// Disputes that were applied.
+    pub opened: usize,
+    /// Resolves that were applied.
+    pub resolved: usize,
+    /// Chargebacks that were applied.
+    pub charged_back: usize,
+    /// Disputes that were rejected.
+    pub rejected: usize,
+}
+
+/// A summary of a completed run: what was read, what became of it and the resulting clients. This
+/// aggregates the reader's summary, the outcomes of the executed transactions and the client
+/// states into a single artifact.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunManifest {
+    /// The input files, followed by the client metadata file if one was attached.
+    pub inputs: Vec<InputFile>,
+    /// The transactions that were parsed, by type.
+    pub records: TypeCounts,
+    /// The number of transactions that were applied.
+    pub applied: usize,
+    /// The number of transactions that were rejected.
+    pub rejected: usize,
+    pub skipped: SkippedRows,
+    pub disputes: DisputeOutcomes,
+    /// The number of clients in the store once processing completed.
+    pub clients: usize,
+    /// The sum of every client's total funds.
+    pub total_supply: f64,
+    /// The number of warnings that were raised.
+    pub warnings: usize,
+    /// The time taken to process the input, in seconds.
+    pub elapsed_seconds: f64,
+}
+
+impl RunManifest {
+    /// Describes the run `meta` that read `read`, executed transactions with `outcomes`, left the
+    /// client `states`, raised `warnings` and took `elapsed`.
+    pub fn new(
+        meta: &RunMeta,
+        read: ReadSummary,
+        outcomes: Outcomes,
+        states: &[State],
+        warnings: &[Warning],
+        elapsed: Duration,
+    ) -> RunManifest {
+        let mut inputs = meta.inputs.clone();
+        if let (Some(path), Some(hash)) = (&meta.client_metadata, meta.client_metadata_hash) {
+            inputs.push(InputFile {
+                path: path.clone(),
+                hash: Some(hash),
+            });
+        }
+
+        let scale = 10f64.powi(DECIMAL_PLACES as i32);
+        let total_supply = states.iter().map(|state| state.total).sum::<f64>();
+
+        RunManifest {
+            inputs,
+            records: read.records,
+            applied: outcomes.applied.total(),
+            rejected: outcomes.rejected.total(),
+            skipped: SkippedRows {
+                unparsed: read.unparsed,
+                out_of_range: read.out_of_range,
+            },
+            disputes: DisputeOutcomes {
+                opened: outcomes.applied.dispute,
+                resolved: outcomes.applied.resolve,
+                charged_back: outcomes.applied.chargeback,
+                rejected: outcomes.rejected.dispute,
+            },
+            clients: states.len(),
+            total_supply: (total_supply * scale).round() / scale,
+            warnings: warnings.len(),
+            elapsed_seconds: elapsed.as_secs_f64(),
+        }
+    }
+}
+
+/// Writes `manifest` to the file at `path` as JSON, creating or truncating it.
+pub fn write_manifest(path: PathBuf, manifest: &RunManifest) -> Result<(), MetaError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, manifest)?;
+    writeln!(writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes `warnings` to the file at `path` as a JSON array, creating or truncating it.
+pub fn write_warnings(path: PathBuf, warnings: &[Warning]) -> Result<(), MetaError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, warnings)?;
+    writeln!(writer)?;
+    writer.flush()?;
+    Ok(())
+}
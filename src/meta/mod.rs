@@ -0,0 +1,158 @@
+#[cfg(test)]
+mod tests;
+
+mod manifest;
+
+pub use manifest::{write_manifest, write_warnings, RunManifest};
+
+use crate::cli::RunArgs;
+#[cfg(any(feature = "rocks", test))]
+use crate::client::ClientStore;
+use crate::client::{Policy, ValueCodec};
+#[cfg(any(feature = "rocks", test))]
+use crate::db::StoreEngine;
+use crate::db::StoreError;
+use crate::io::IoConfig;
+use crate::parser::reader::{is_stdin, ReaderConfig};
+use crate::scale::DECIMAL_PLACES;
+use fnv::FnvHasher;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::hash::Hasher;
+#[cfg(any(feature = "rocks", test))]
+use std::io::Write;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+// Run metadata is only read back from a persistent store.
+#[cfg(any(feature = "rocks", test))]
+const NO_RUN_META: &str = "No run metadata was found in the store";
+
+#[derive(Error, Debug)]
+pub enum MetaError {
+    #[error("An error was produced by the store: `{0}`")]
+    Store(StoreError),
+    #[error("An IO error was produced: `{0}`")]
+    Io(io::Error),
+    #[error("An error was produced when formatting the run metadata: `{0}`")]
+    Json(serde_json::Error),
+}
+
+impl From<StoreError> for MetaError {
+    fn from(e: StoreError) -> Self {
+        MetaError::Store(e)
+    }
+}
+
+impl From<io::Error> for MetaError {
+    fn from(e: io::Error) -> Self {
+        MetaError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for MetaError {
+    fn from(e: serde_json::Error) -> Self {
+        MetaError::Json(e)
+    }
+}
+
+/// A file that was read by a run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputFile {
+    pub path: PathBuf,
+    /// An FNV-1a hash of the file's contents. The standard input cannot be read twice, so this is
+    /// `None` when it is the file.
+    pub hash: Option<u64>,
+}
+
+impl InputFile {
+    /// Describes the input file at `path`, hashing its contents.
+    fn new(path: &Path) -> Result<InputFile, io::Error> {
+        let hash = if is_stdin(path) {
+            None
+        } else {
+            Some(hash_file(path)?)
+        };
+        Ok(InputFile {
+            path: path.to_path_buf(),
+            hash,
+        })
+    }
+}
+
+/// The effective configuration of a run. This is written to the store at the start of each run so
+/// that it describes how it was produced.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunMeta {
+    /// The CSV files that were processed, in order.
+    pub inputs: Vec<InputFile>,
+    /// The client metadata file that was attached to clients, if any.
+    pub client_metadata: Option<PathBuf>,
+    /// An FNV-1a hash of the client metadata file's contents, if any.
+    pub client_metadata_hash: Option<u64>,
+    /// The number of decimal places that amounts are represented to.
+    pub decimal_places: usize,
+    /// The policies that transactions were executed with.
+    pub policy: Policy,
+    /// The configuration of the CSV reader.
+    pub reader: ReaderConfig,
+    /// The configuration of the IO task.
+    pub io: IoConfig,
+    /// How client states and transactions were encoded in the store.
+    pub value_codec: ValueCodec,
+}
+
+impl RunMeta {
+    /// Describes a run using `args`, hashing any input files that it reads.
+    pub fn new(args: &RunArgs) -> Result<RunMeta, MetaError> {
+        let client_metadata_hash = args.client_metadata.as_ref().map(hash_file).transpose()?;
+        let inputs = args
+            .inputs
+            .iter()
+            .map(|input| InputFile::new(input))
+            .collect::<Result<_, _>>()?;
+
+        Ok(RunMeta {
+            inputs,
+            client_metadata: args.client_metadata.clone(),
+            client_metadata_hash,
+            decimal_places: DECIMAL_PLACES,
+            policy: args.policy,
+            reader: args.reader,
+            io: args.io,
+            value_codec: args.value_codec,
+        })
+    }
+}
+
+/// Returns an FNV-1a hash of the contents of the file at `path`.
+fn hash_file<P: AsRef<Path>>(path: P) -> Result<u64, io::Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = FnvHasher::default();
+
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(hasher.finish());
+        }
+        hasher.write(buffer);
+        let len = buffer.len();
+        reader.consume(len);
+    }
+}
+
+/// Writes the run metadata in `store` to `writer` as JSON. Or a message if the store has none.
+#[cfg(any(feature = "rocks", test))]
+pub fn write_meta<D, W>(store: &ClientStore<D>, writer: &mut W) -> Result<(), MetaError>
+where
+    D: StoreEngine,
+    W: Write,
+{
+    match store.get_run_meta()? {
+        Some(meta) => serde_json::to_writer_pretty(&mut *writer, &meta)?,
+        None => write!(writer, "{}", NO_RUN_META)?,
+    }
+    writeln!(writer)?;
+    Ok(())
+}
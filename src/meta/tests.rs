@@ -0,0 +1,256 @@
+use crate::cli::RunArgs;
+use crate::client::{ClientStore, Policy};
+use crate::data::mem_store;
+use crate::db::MemStore;
+use crate::io::{IoTask, Tally};
+use crate::meta::{write_manifest, write_meta, write_warnings, RunManifest, RunMeta};
+use crate::parser::reader::{reader_task, TxRange};
+use crate::parser::rejects::RejectsWriter;
+use crate::scale::DECIMAL_PLACES;
+use crate::warnings::{Warning, WarningCollector};
+use futures::future::join;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tempdir::TempDir;
+use tokio::sync::mpsc;
+
+fn parse_run(input: &Path, args: &[&str]) -> RunArgs {
+    RunArgs::parse(
+        input.to_str().unwrap().to_string(),
+        args.iter().map(|arg| arg.to_string()),
+    )
+    .unwrap()
+}
+
+fn meta_json(store: &ClientStore<MemStore>) -> Value {
+    let mut bytes = Vec::new();
+    write_meta(store, &mut bytes).unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[test]
+fn run_meta() {
+    let dir = TempDir::new("run_meta").unwrap();
+    let input = dir.path().join("input.csv");
+    fs::write(&input, "type, client, tx, amount\ndeposit, 1, 1, 1.0\n").unwrap();
+
+    let args = parse_run(
+        &input,
+        &[
+            "--strict-freeze",
+            "--max-open-disputes",
+            "3",
+            "--buffer-capacity",
+            "16",
+            "--tx-range",
+            "5:10",
+            "--flush-on-error",
+        ],
+    );
+    let meta = RunMeta::new(&args).unwrap();
+    let store = mem_store();
+    store.put_run_meta(&meta).unwrap();
+    assert_eq!(store.get_run_meta(), Ok(Some(meta.clone())));
+
+    assert!(meta.policy.strict_freeze);
+    assert_eq!(meta.reader.tx_range, Some(TxRange { lo: 5, hi: 10 }));
+
+    let json = meta_json(&store);
+    assert_eq!(json["inputs"][0]["path"], input.to_str().unwrap());
+    assert_eq!(json["inputs"][0]["hash"], meta.inputs[0].hash.unwrap());
+    assert_eq!(json["client_metadata"], Value::Null);
+    assert_eq!(json["decimal_places"], DECIMAL_PLACES);
+    assert_eq!(json["policy"]["strict_freeze"], true);
+    assert_eq!(json["policy"]["max_open_disputes"], 3);
+    assert_eq!(json["policy"]["corrupt_state"], "fail");
+    assert_eq!(json["reader"]["buffer_capacity"], 16);
+    assert_eq!(json["reader"]["tx_range"]["lo"], 5);
+    assert_eq!(json["reader"]["tx_range"]["hi"], 10);
+    assert_eq!(json["io"]["flush_on_error"], true);
+    assert_eq!(json["io"]["assume_sorted"], false);
+    assert_eq!(json["value_codec"], "plain");
+}
+
+#[test]
+fn input_hash() {
+    let dir = TempDir::new("input_hash").unwrap();
+    let input = dir.path().join("input.csv");
+    let metadata = dir.path().join("metadata.csv");
+    fs::write(&input, "type, client, tx, amount\n").unwrap();
+    fs::write(&metadata, "client, name, region, tier\n").unwrap();
+
+    let args = parse_run(&input, &["--client-metadata", metadata.to_str().unwrap()]);
+    let first = RunMeta::new(&args).unwrap();
+    assert_eq!(RunMeta::new(&args).unwrap(), first);
+    assert!(first.client_metadata_hash.is_some());
+
+    fs::write(&input, "type, client, tx, amount\ndeposit, 1, 1, 1.0\n").unwrap();
+    let second = RunMeta::new(&args).unwrap();
+    assert_ne!(second.inputs, first.inputs);
+    assert_eq!(second.client_metadata_hash, first.client_metadata_hash);
+    assert_eq!(second.policy, Policy::default());
+}
+
+#[test]
+fn multiple_input_hashes() {
+    let dir = TempDir::new("input_hash").unwrap();
+    let first = dir.path().join("first.csv");
+    let second = dir.path().join("second.csv");
+    fs::write(&first, "type, client, tx, amount\ndeposit, 1, 1, 1.0\n").unwrap();
+    fs::write(&second, "type, client, tx, amount\ndeposit, 1, 2, 1.0\n").unwrap();
+
+    let args = parse_run(&first, &[second.to_str().unwrap()]);
+    let meta = RunMeta::new(&args).unwrap();
+    assert_eq!(meta.inputs.len(), 2);
+    assert_eq!(meta.inputs[1].path, second);
+    assert_ne!(meta.inputs[0].hash, meta.inputs[1].hash);
+}
+
+#[test]
+fn stdin_input_hash() {
+    let args = RunArgs::parse("-".to_string(), std::iter::empty()).unwrap();
+    let meta = RunMeta::new(&args).unwrap();
+    assert_eq!(meta.inputs[0].hash, None);
+}
+
+#[test]
+fn no_run_meta() {
+    let mut bytes = Vec::new();
+    write_meta(&mem_store(), &mut bytes).unwrap();
+    assert_eq!(
+        String::from_utf8(bytes).unwrap(),
+        "No run metadata was found in the store\n"
+    );
+}
+
+#[tokio::test]
+async fn manifest() {
+    let dir = TempDir::new("manifest").unwrap();
+    let input = dir.path().join("input.csv");
+    let rejects = dir.path().join("rejects.csv");
+    let manifest = dir.path().join("manifest.json");
+    fs::write(
+        &input,
+        "type, client, tx, amount
+deposit, 1, 1, 10.0
+deposit, 2, 2, 5.0
+withdrawal, 1, 3, 20.0
+dispute, 1, 1,
+resolve, 1, 1,
+dispute, 2, 2,
+chargeback, 2, 2,
+dispute, 1, 99,
+unknown, 1, 4, 1.0
+deposit, 3, 100, 1.0
+",
+    )
+    .unwrap();
+
+    let args = parse_run(
+        &input,
+        &[
+            "--tx-range",
+            "1:99",
+            "--skip-malformed",
+            "--rejects",
+            rejects.to_str().unwrap(),
+            "--manifest",
+            manifest.to_str().unwrap(),
+        ],
+    );
+    let meta = RunMeta::new(&args).unwrap();
+    let store = mem_store();
+    let tally = Tally::default();
+    let rejects = RejectsWriter::create(rejects).unwrap();
+    let (tx, rx) = mpsc::channel(16);
+
+    let io_task = IoTask::new(rx, store.clone(), args.io, Some(rejects.clone()))
+        .with_tally(tally.clone())
+        .run(16);
+    let reader_task = reader_task(vec![input.clone()], args.reader, tx, Some(rejects), None);
+    let (io_result, read_summary) = join(io_task, reader_task).await;
+    assert!(io_result.is_ok());
+
+    let states = store.collect_states().unwrap();
+    let run_manifest = RunManifest::new(
+        &meta,
+        read_summary.unwrap(),
+        tally.outcomes(),
+        &states,
+        &[],
+        Duration::from_millis(1500),
+    );
+    write_manifest(manifest.clone(), &run_manifest).unwrap();
+
+    let json: Value = serde_json::from_slice(&fs::read(&manifest).unwrap()).unwrap();
+    assert_eq!(json["inputs"][0]["path"], input.to_str().unwrap());
+    assert_eq!(json["inputs"][0]["hash"], meta.inputs[0].hash.unwrap());
+    assert_eq!(json["inputs"].as_array().unwrap().len(), 1);
+    assert_eq!(json["records"]["deposit"], 3);
+    assert_eq!(json["records"]["withdrawal"], 1);
+    assert_eq!(json["records"]["dispute"], 3);
+    assert_eq!(json["records"]["resolve"], 1);
+    assert_eq!(json["records"]["chargeback"], 1);
+    assert_eq!(json["applied"], 6);
+    assert_eq!(json["rejected"], 2);
+    assert_eq!(json["skipped"]["unparsed"], 1);
+    assert_eq!(json["skipped"]["out_of_range"], 1);
+    assert_eq!(json["disputes"]["opened"], 2);
+    assert_eq!(json["disputes"]["resolved"], 1);
+    assert_eq!(json["disputes"]["charged_back"], 1);
+    assert_eq!(json["disputes"]["rejected"], 1);
+    assert_eq!(json["clients"], 2);
+    assert_eq!(json["total_supply"], 10.0);
+    assert_eq!(json["warnings"], 0);
+    assert_eq!(json["elapsed_seconds"], 1.5);
+}
+
+#[tokio::test]
+async fn warnings() {
+    let dir = TempDir::new("warnings").unwrap();
+    let input = dir.path().join("input.csv");
+    let warnings_path = dir.path().join("warnings.json");
+    fs::write(
+        &input,
+        "type, client, tx, amount
+deposit, 1, 1, 1.1234567891
+dispute, 2, 9,
+",
+    )
+    .unwrap();
+
+    let args = parse_run(&input, &["--warn-precision-loss", "--defer-disputes", "4"]);
+    let warnings = WarningCollector::default();
+    let (tx, rx) = mpsc::channel(16);
+
+    let io_task = IoTask::new(rx, mem_store(), args.io, None)
+        .with_warnings(warnings.clone())
+        .run(16);
+    let reader_task = reader_task(vec![input], args.reader, tx, None, Some(warnings.clone()));
+    let (io_result, reader_result) = join(io_task, reader_task).await;
+    assert!(io_result.is_ok());
+    assert!(reader_result.is_ok());
+
+    let warnings = warnings.warnings();
+    assert_eq!(
+        warnings,
+        vec![
+            Warning::PrecisionLoss {
+                line: Some(2),
+                client: Some(1),
+                tx: Some(1),
+                amount: "1.1234567891".to_string(),
+            },
+            Warning::OrphanDispute { client: 2, tx: 9 },
+        ]
+    );
+
+    write_warnings(warnings_path.clone(), &warnings).unwrap();
+    let json: Value = serde_json::from_slice(&fs::read(&warnings_path).unwrap()).unwrap();
+    assert_eq!(json[0]["kind"], "precision_loss");
+    assert_eq!(json[0]["line"], 2);
+    assert_eq!(json[1]["kind"], "orphan_dispute");
+    assert_eq!(json[1]["tx"], 9);
+}
@@ -0,0 +1,300 @@
+#[cfg(test)]
+mod tests;
+
+use crate::parser::is_representable;
+use crate::parser::reader::{loses_precision, ReaderConfig, ReaderError};
+use crate::transaction::TransactionType;
+use csv::StringRecord;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// The columns that a transactions file must have.
+const HEADERS: [&str; 4] = [TYPE_HEADER, CLIENT_HEADER, TX_HEADER, AMOUNT_HEADER];
+const TYPE_HEADER: &str = "type";
+const CLIENT_HEADER: &str = "client";
+const TX_HEADER: &str = "tx";
+const AMOUNT_HEADER: &str = "amount";
+/// The column of a transfer's destination client, which a transactions file may have.
+const TO_HEADER: &str = "to";
+/// The column of a partial dispute's disputed amount, which a transactions file may have.
+const DISPUTED_AMOUNT_HEADER: &str = "disputed_amount";
+/// The columns that a transactions file may have.
+const OPTIONAL_HEADERS: [&str; 2] = [TO_HEADER, DISPUTED_AMOUNT_HEADER];
+
+/// A problem with the schema of a transactions file.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ProblemKind {
+    #[error("Missing column `{0}`")]
+    MissingColumn(String),
+    #[error("Unexpected column `{0}`")]
+    UnexpectedColumn(String),
+    #[error("Expected at most `{expected}` fields, found `{found}`")]
+    ExtraFields { expected: usize, found: usize },
+    #[error("Missing a value for column `{0}`")]
+    MissingValue(String),
+    #[error("Invalid value `{value}` for column `{column}`")]
+    InvalidValue { column: String, value: String },
+    #[error("Value `{value}` is out of range for column `{column}`")]
+    OutOfRange { column: String, value: String },
+    #[error("Unknown transaction type `{0}`")]
+    UnknownType(String),
+    #[error("A `{0}` must have an amount")]
+    MissingAmount(String),
+    #[error("A `{0}` must not have an amount")]
+    UnexpectedAmount(String),
+    #[error("Amount `{0}` has more decimal places than are output")]
+    PrecisionLoss(String),
+    #[error("A `{0}` must not have a disputed amount")]
+    UnexpectedDisputedAmount(String),
+    #[error("Disputed amount `{0}` must not be negative")]
+    NegativeDisputedAmount(String),
+}
+
+/// A problem with the schema of a transactions file and the line that it was found on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub line: u64,
+    pub kind: ProblemKind,
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.kind)
+    }
+}
+
+/// Checks the CSV file `input` against the transactions schema. Returns every problem that was
+/// found.
+pub fn lint_file(input: PathBuf) -> Result<Vec<Problem>, ReaderError> {
+    let input = File::open(input).map_err(|e| ReaderError::Io(e.to_string()))?;
+    lint(input)
+}
+
+/// Checks `input` against the transactions schema, without executing any of its transactions.
+/// Returns every problem that was found, in the order of the lines that they were found on. An
+/// error is only returned if `input` cannot be read as CSV.
+///
+/// The headers are checked for missing and unexpected columns. Each record is checked for extra
+/// fields, types and IDs that cannot be parsed, amounts that are missing from or provided to a
+/// transaction type, transfers without a destination client, disputed amounts that are provided to
+/// anything but a dispute or are negative, and amounts that have more decimal places than are
+/// output.
+pub fn lint<R>(input: R) -> Result<Vec<Problem>, ReaderError>
+where
+    R: Read,
+{
+    let mut reader = ReaderConfig::default().builder().from_reader(input);
+    let headers = reader
+        .headers()
+        .map_err(|e| ReaderError::Csv(Box::new(e)))?
+        .clone();
+    let mut problems = lint_headers(&headers);
+
+    let columns = Columns::new(&headers);
+    let mut record = StringRecord::new();
+    while reader
+        .read_record(&mut record)
+        .map_err(|e| ReaderError::Csv(Box::new(e)))?
+    {
+        let line = record.position().map_or(0, |position| position.line());
+        problems.extend(
+            columns
+                .lint(&record)
+                .into_iter()
+                .map(|kind| Problem { line, kind }),
+        );
+    }
+
+    Ok(problems)
+}
+
+/// Returns a problem for every column in `HEADERS` that is missing from `headers` and every column
+/// in `headers` that is neither in `HEADERS` nor `OPTIONAL_HEADERS`.
+fn lint_headers(headers: &StringRecord) -> Vec<Problem> {
+    let line = headers.position().map_or(1, |position| position.line());
+    let missing = HEADERS
+        .iter()
+        .filter(|expected| !headers.iter().any(|header| header == **expected))
+        .map(|header| ProblemKind::MissingColumn(header.to_string()));
+    let unexpected = headers
+        .iter()
+        .filter(|header| !HEADERS.contains(header) && !OPTIONAL_HEADERS.contains(header))
+        .map(|header| ProblemKind::UnexpectedColumn(header.to_string()));
+
+    missing
+        .chain(unexpected)
+        .map(|kind| Problem { line, kind })
+        .collect()
+}
+
+/// The index of each column in a file's headers, if it is present.
+struct Columns {
+    len: usize,
+    tx_type: Option<usize>,
+    client: Option<usize>,
+    tx: Option<usize>,
+    amount: Option<usize>,
+    to: Option<usize>,
+    disputed_amount: Option<usize>,
+}
+
+impl Columns {
+    fn new(headers: &StringRecord) -> Self {
+        let index = |name: &str| headers.iter().position(|header| header == name);
+        Columns {
+            len: headers.len(),
+            tx_type: index(TYPE_HEADER),
+            client: index(CLIENT_HEADER),
+            tx: index(TX_HEADER),
+            amount: index(AMOUNT_HEADER),
+            to: index(TO_HEADER),
+            disputed_amount: index(DISPUTED_AMOUNT_HEADER),
+        }
+    }
+
+    /// Returns every problem with `record`. Columns that are missing from the headers have
+    /// already been reported and are not checked.
+    fn lint(&self, record: &StringRecord) -> Vec<ProblemKind> {
+        let mut problems = Vec::new();
+        let field = |index: Option<usize>| index.map(|index| record.get(index).unwrap_or_default());
+
+        if record.len() > self.len {
+            problems.push(ProblemKind::ExtraFields {
+                expected: self.len,
+                found: record.len(),
+            });
+        }
+
+        let tx_type = field(self.tx_type).and_then(|value| match value {
+            "" => {
+                problems.push(ProblemKind::MissingValue(TYPE_HEADER.to_string()));
+                None
+            }
+            value => match parse_type(value) {
+                Some(tx_type) => Some(tx_type),
+                None => {
+                    problems.push(ProblemKind::UnknownType(value.to_string()));
+                    None
+                }
+            },
+        });
+
+        if let Some(value) = field(self.client) {
+            problems.extend(lint_id::<u16>(CLIENT_HEADER, value));
+        }
+        if let Some(value) = field(self.tx) {
+            problems.extend(lint_id::<u32>(TX_HEADER, value));
+        }
+        match field(self.to).filter(|value| !value.is_empty()) {
+            Some(value) => problems.extend(lint_id::<u16>(TO_HEADER, value)),
+            None if tx_type == Some(TransactionType::Transfer) => {
+                problems.push(ProblemKind::MissingValue(TO_HEADER.to_string()));
+            }
+            None => {}
+        }
+
+        let amount = field(self.amount).filter(|value| !value.is_empty());
+        if let Some(value) = amount {
+            problems.extend(lint_amount(AMOUNT_HEADER, value));
+        }
+
+        if let Some(value) = field(self.disputed_amount).filter(|value| !value.is_empty()) {
+            match tx_type {
+                Some(TransactionType::Dispute) => match value.parse::<f64>() {
+                    Ok(amount) if amount < 0.0 => {
+                        problems.push(ProblemKind::NegativeDisputedAmount(value.to_string()))
+                    }
+                    _ => problems.extend(lint_amount(DISPUTED_AMOUNT_HEADER, value)),
+                },
+                Some(tx_type) => {
+                    problems.push(ProblemKind::UnexpectedDisputedAmount(
+                        tx_type.name().to_string(),
+                    ));
+                }
+                None => {}
+            }
+        }
+
+        match (tx_type.filter(|_| self.amount.is_some()), amount) {
+            (
+                Some(
+                    tx_type @ (TransactionType::Deposit
+                    | TransactionType::Withdrawal
+                    | TransactionType::Transfer),
+                ),
+                None,
+            ) => {
+                problems.push(ProblemKind::MissingAmount(tx_type.name().to_string()));
+            }
+            (
+                Some(
+                    tx_type @ (TransactionType::Dispute
+                    | TransactionType::Resolve
+                    | TransactionType::Chargeback),
+                ),
+                Some(_),
+            ) => {
+                problems.push(ProblemKind::UnexpectedAmount(tx_type.name().to_string()));
+            }
+            _ => {}
+        }
+
+        problems
+    }
+}
+
+/// Returns a problem if `value`, from `column`, is not an amount, is out of the range that balances
+/// can hold or has more decimal places than are output.
+fn lint_amount(column: &str, value: &str) -> Option<ProblemKind> {
+    match value.parse::<f64>() {
+        Ok(amount) if !is_representable(amount) => Some(ProblemKind::OutOfRange {
+            column: column.to_string(),
+            value: value.to_string(),
+        }),
+        Ok(_) if loses_precision(value) => Some(ProblemKind::PrecisionLoss(value.to_string())),
+        Ok(_) => None,
+        Err(_) => Some(ProblemKind::InvalidValue {
+            column: column.to_string(),
+            value: value.to_string(),
+        }),
+    }
+}
+
+/// Returns the transaction type named `value`, as it is named in a CSV file.
+fn parse_type(value: &str) -> Option<TransactionType> {
+    match value {
+        "deposit" => Some(TransactionType::Deposit),
+        "withdrawal" => Some(TransactionType::Withdrawal),
+        "dispute" => Some(TransactionType::Dispute),
+        "resolve" => Some(TransactionType::Resolve),
+        "chargeback" => Some(TransactionType::Chargeback),
+        "transfer" => Some(TransactionType::Transfer),
+        _ => None,
+    }
+}
+
+/// Returns a problem if `value`, from `column`, is not a valid ID of type `I`. An integer that is
+/// too large for `I` is out of range, while anything else that does not parse is invalid.
+fn lint_id<I>(column: &str, value: &str) -> Option<ProblemKind>
+where
+    I: FromStr,
+{
+    if value.is_empty() {
+        return Some(ProblemKind::MissingValue(column.to_string()));
+    }
+    if value.parse::<I>().is_ok() {
+        return None;
+    }
+
+    let column = column.to_string();
+    let value = value.to_string();
+    if value.parse::<i128>().is_ok() {
+        Some(ProblemKind::OutOfRange { column, value })
+    } else {
+        Some(ProblemKind::InvalidValue { column, value })
+    }
+}
@@ -0,0 +1,133 @@
+use crate::parser::lint::{lint, Problem, ProblemKind};
+
+fn problems(input: &str) -> Vec<Problem> {
+    lint(input.as_bytes()).unwrap()
+}
+
+fn problem(line: u64, kind: ProblemKind) -> Problem {
+    Problem { line, kind }
+}
+
+#[test]
+fn valid() {
+    let input = "type, client, tx, amount
+deposit, 1, 1, 1.5
+withdrawal, 1, 2, 0.25
+dispute, 1, 1,
+resolve, 1, 1
+chargeback, 1, 1,
+";
+    assert_eq!(problems(input), vec![]);
+}
+
+#[test]
+fn every_problem() {
+    let input = "type,client,tx,note
+deposit,1,1,
+refund,1,2,
+deposit,70000,3,
+withdrawal,1,abc,
+dispute,1,,
+deposit,1,4,,extra
+";
+    assert_eq!(
+        problems(input),
+        vec![
+            problem(1, ProblemKind::MissingColumn("amount".to_string())),
+            problem(1, ProblemKind::UnexpectedColumn("note".to_string())),
+            problem(3, ProblemKind::UnknownType("refund".to_string())),
+            problem(
+                4,
+                ProblemKind::OutOfRange {
+                    column: "client".to_string(),
+                    value: "70000".to_string()
+                }
+            ),
+            problem(
+                5,
+                ProblemKind::InvalidValue {
+                    column: "tx".to_string(),
+                    value: "abc".to_string()
+                }
+            ),
+            problem(6, ProblemKind::MissingValue("tx".to_string())),
+            problem(
+                7,
+                ProblemKind::ExtraFields {
+                    expected: 4,
+                    found: 5
+                }
+            ),
+        ]
+    );
+}
+
+#[test]
+fn amounts() {
+    let input = "type,client,tx,amount
+deposit,1,1,
+withdrawal,1,2,ten
+dispute,1,1,1.0
+deposit,1,3,1.0000000001
+deposit,-1,4,1.0
+deposit,1,5,1e40
+";
+    assert_eq!(
+        problems(input),
+        vec![
+            problem(2, ProblemKind::MissingAmount("deposit".to_string())),
+            problem(
+                3,
+                ProblemKind::InvalidValue {
+                    column: "amount".to_string(),
+                    value: "ten".to_string()
+                }
+            ),
+            problem(4, ProblemKind::UnexpectedAmount("dispute".to_string())),
+            problem(5, ProblemKind::PrecisionLoss("1.0000000001".to_string())),
+            problem(
+                6,
+                ProblemKind::OutOfRange {
+                    column: "client".to_string(),
+                    value: "-1".to_string()
+                }
+            ),
+            problem(
+                7,
+                ProblemKind::OutOfRange {
+                    column: "amount".to_string(),
+                    value: "1e40".to_string()
+                }
+            ),
+        ]
+    );
+}
+
+#[test]
+fn disputed_amounts() {
+    let input = "type,client,tx,amount,disputed_amount
+dispute,1,1,,2.5
+dispute,1,1,,
+resolve,1,1,,2.5
+dispute,1,1,,0
+dispute,1,1,,-1
+dispute,1,1,,half
+";
+    assert_eq!(
+        problems(input),
+        vec![
+            problem(
+                4,
+                ProblemKind::UnexpectedDisputedAmount("resolve".to_string())
+            ),
+            problem(6, ProblemKind::NegativeDisputedAmount("-1".to_string())),
+            problem(
+                7,
+                ProblemKind::InvalidValue {
+                    column: "disputed_amount".to_string(),
+                    value: "half".to_string()
+                }
+            ),
+        ]
+    );
+}
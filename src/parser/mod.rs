@@ -1,8 +1,13 @@
+pub mod lint;
+pub mod normalize;
 pub mod reader;
+pub mod rejects;
 pub mod writer;
 
+use crate::client::{ClientLimits, ClientMetadata};
+use crate::scale::DECIMAL_PLACES;
 use crate::transaction::{
-    DisputedTransaction, DisputedTransactionKind, Transaction, TransactionType,
+    DisputedTransaction, DisputedTransactionKind, InternalTransfer, Transaction, TransactionType,
     TransferTransaction, TransferTransactionKind,
 };
 use serde::{Deserialize, Serialize};
@@ -11,13 +16,22 @@ use thiserror::Error;
 
 // The CSV crate doesn't work well with untagged enums. So this serves as an intermediary parsing
 // step to get to and from the transaction structure.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub struct CsvTransaction {
     #[serde(rename = "type")]
     tx_type: TransactionType,
     client: u16,
     tx: u32,
     amount: Option<f64>,
+    /// The destination client of a transfer. The column is optional, so that files without
+    /// transfers are unchanged, and it is only written for a transfer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    to: Option<u16>,
+    /// The part of the referenced transaction's amount that a dispute disputes. The column is
+    /// optional, so that files without partial disputes are unchanged, and it is only written for
+    /// a partial dispute.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    disputed_amount: Option<f64>,
 }
 
 impl From<Transaction> for CsvTransaction {
@@ -37,28 +51,66 @@ impl From<Transaction> for CsvTransaction {
                     client,
                     tx,
                     amount: Some(amount),
+                    to: None,
+                    disputed_amount: None,
                 }
             }
             Transaction::Disputed(tx) => {
-                let DisputedTransaction { kind, client, tx } = tx;
+                let DisputedTransaction {
+                    kind,
+                    client,
+                    tx,
+                    amount,
+                } = tx;
 
                 CsvTransaction {
                     tx_type: kind.into(),
                     client,
                     tx,
                     amount: None,
+                    to: None,
+                    disputed_amount: amount,
+                }
+            }
+            Transaction::Internal(tx) => {
+                let InternalTransfer {
+                    client,
+                    to,
+                    tx,
+                    amount,
+                } = tx;
+
+                CsvTransaction {
+                    tx_type: TransactionType::Transfer,
+                    client,
+                    tx,
+                    amount: Some(amount),
+                    to: Some(to),
+                    disputed_amount: None,
                 }
             }
         }
     }
 }
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone, Copy)]
 pub enum CsvParseError {
     #[error("Expected no amount to be provided")]
     ExpectedNoAmount,
     #[error("Expected an amount to be provided")]
     ExpectedAnAmount,
+    #[error("Expected a destination client to be provided")]
+    ExpectedADestination,
+    #[error("Expected the destination client to differ from the source client")]
+    TransferToSelf,
+    #[error("Expected no disputed amount to be provided")]
+    ExpectedNoDisputedAmount,
+    #[error("Expected the disputed amount not to be negative")]
+    NegativeDisputedAmount,
+    #[error("Expected a finite amount within the range that balances can hold")]
+    AmountOutOfRange,
+    #[error("Expected a client's limits to be non-negative and finite")]
+    InvalidLimit,
 }
 
 impl TryFrom<CsvTransaction> for Transaction {
@@ -70,22 +122,36 @@ impl TryFrom<CsvTransaction> for Transaction {
             client,
             tx,
             amount,
+            to,
+            disputed_amount,
         } = tx;
+        if disputed_amount.is_some() && tx_type != TransactionType::Dispute {
+            return Err(CsvParseError::ExpectedNoDisputedAmount);
+        }
+
         let tx = match tx_type {
             TransactionType::Deposit => TransferTransaction {
                 kind: TransferTransactionKind::Deposit,
                 client,
                 tx,
-                amount: amount.ok_or(CsvParseError::ExpectedAnAmount)?,
+                amount: amount
+                    .ok_or(CsvParseError::ExpectedAnAmount)
+                    .and_then(round_amount)?,
                 disputed: Default::default(),
+                executed_at: None,
+                disputed_amount: None,
             }
             .into(),
             TransactionType::Withdrawal => TransferTransaction {
                 kind: TransferTransactionKind::Withdrawal,
                 client,
                 tx,
-                amount: amount.ok_or(CsvParseError::ExpectedAnAmount)?,
+                amount: amount
+                    .ok_or(CsvParseError::ExpectedAnAmount)
+                    .and_then(round_amount)?,
                 disputed: Default::default(),
+                executed_at: None,
+                disputed_amount: None,
             }
             .into(),
             TransactionType::Dispute => match amount {
@@ -94,6 +160,7 @@ impl TryFrom<CsvTransaction> for Transaction {
                     kind: DisputedTransactionKind::Dispute,
                     client,
                     tx,
+                    amount: round_disputed_amount(disputed_amount)?,
                 }
                 .into(),
             },
@@ -103,6 +170,7 @@ impl TryFrom<CsvTransaction> for Transaction {
                     kind: DisputedTransactionKind::Resolve,
                     client,
                     tx,
+                    amount: None,
                 }
                 .into(),
             },
@@ -112,11 +180,117 @@ impl TryFrom<CsvTransaction> for Transaction {
                     kind: DisputedTransactionKind::Chargeback,
                     client,
                     tx,
+                    amount: None,
+                }
+                .into(),
+            },
+            TransactionType::Transfer => match to {
+                Some(to) if to == client => return Err(CsvParseError::TransferToSelf),
+                Some(to) => InternalTransfer {
+                    client,
+                    to,
+                    tx,
+                    amount: amount
+                        .ok_or(CsvParseError::ExpectedAnAmount)
+                        .and_then(round_amount)?,
                 }
                 .into(),
+                None => return Err(CsvParseError::ExpectedADestination),
             },
         };
 
         Ok(tx)
     }
 }
+
+/// Rounds `amount` to the number of decimal places that balances are output to, so that the amount
+/// that is stored and disputed is the amount that is displayed. Halves are rounded to even. An
+/// amount that is not representable is rejected.
+fn round_amount(amount: f64) -> Result<f64, CsvParseError> {
+    if !is_representable(amount) {
+        return Err(CsvParseError::AmountOutOfRange);
+    }
+    let scale = 10f64.powi(DECIMAL_PLACES as i32);
+    let scaled = amount * scale;
+    // A halfway amount such as `0.00015` is rarely exactly halfway once it has been scaled, so
+    // halves are found in the shortest decimal form of the amount instead, which is as it was
+    // written.
+    let rounded = if is_halfway(amount) {
+        let lower = (scaled - 0.5).round();
+        if lower % 2.0 == 0.0 {
+            lower
+        } else {
+            lower + 1.0
+        }
+    } else {
+        scaled.round()
+    };
+    Ok(rounded / scale)
+}
+
+/// Returns whether `amount` is finite and, once scaled to the number of decimal places that
+/// balances are held to, fits in the fixed point range that they are held in.
+pub fn is_representable(amount: f64) -> bool {
+    let scaled = amount * 10f64.powi(DECIMAL_PLACES as i32);
+    // `i128::MAX` rounds up to 2^127 as a float, which is itself out of range.
+    scaled.is_finite() && scaled.abs() < i128::MAX as f64
+}
+
+/// Rounds a dispute's `disputed_amount` in the same way as any other amount. A disputed amount must
+/// not be negative, while a disputed amount of zero is executed according to the
+/// `ZeroDisputePolicy` and whether it exceeds the amount of the disputed transaction can only be
+/// checked once that transaction has been looked up.
+fn round_disputed_amount(disputed_amount: Option<f64>) -> Result<Option<f64>, CsvParseError> {
+    match disputed_amount {
+        Some(amount) if amount < 0.0 => Err(CsvParseError::NegativeDisputedAmount),
+        disputed_amount => disputed_amount.map(round_amount).transpose(),
+    }
+}
+
+/// Returns whether `amount` has exactly one more decimal place than amounts are rounded to, and
+/// that place is a five.
+fn is_halfway(amount: f64) -> bool {
+    match amount.to_string().split_once('.') {
+        Some((_, fraction)) => fraction.len() == DECIMAL_PLACES + 1 && fraction.ends_with('5'),
+        None => false,
+    }
+}
+
+/// A row in a client metadata file. The `min_balance` and `max_held` columns are optional, and a
+/// client whose row leaves them empty has no limit.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CsvClientMetadata {
+    client: u16,
+    name: String,
+    region: String,
+    tier: String,
+    #[serde(default)]
+    min_balance: Option<f64>,
+    #[serde(default)]
+    max_held: Option<f64>,
+}
+
+impl TryFrom<CsvClientMetadata> for (u16, ClientMetadata, ClientLimits) {
+    type Error = CsvParseError;
+
+    fn try_from(record: CsvClientMetadata) -> Result<Self, Self::Error> {
+        let CsvClientMetadata {
+            client,
+            name,
+            region,
+            tier,
+            min_balance,
+            max_held,
+        } = record;
+        let is_invalid = |limit: f64| limit.is_sign_negative() || !limit.is_finite();
+        if min_balance.is_some_and(is_invalid) || max_held.is_some_and(is_invalid) {
+            return Err(CsvParseError::InvalidLimit);
+        }
+
+        let limits = ClientLimits {
+            min_balance,
+            max_held,
+        };
+        Ok((client, ClientMetadata { name, region, tier }, limits))
+    }
+}
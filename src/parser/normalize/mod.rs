@@ -0,0 +1,134 @@
+#[cfg(test)]
+mod tests;
+
+use crate::parser::reader::{ReaderConfig, ReaderError};
+use crate::parser::CsvTransaction;
+use crate::scale::DECIMAL_PLACES;
+use crate::transaction::Transaction;
+use csv::{StringRecord, WriterBuilder};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+/// The columns of a normalized file, in the order that they are written.
+const HEADERS: [&str; 4] = ["type", "client", "tx", "amount"];
+const TYPE_HEADER: &str = "type";
+/// The optional column of a transfer's destination client, which is written last if the input
+/// has it.
+const TO_HEADER: &str = "to";
+/// The optional column of a partial dispute's disputed amount, which is written after any
+/// destination column if the input has it.
+const DISPUTED_AMOUNT_HEADER: &str = "disputed_amount";
+
+/// Reads the transactions in the CSV file `input` and writes them to the file `output` in a
+/// canonical form. Returns the number of transactions that were written.
+pub fn normalize_file(input: PathBuf, output: PathBuf) -> Result<usize, ReaderError> {
+    let input = File::open(input).map_err(|e| ReaderError::Io(e.to_string()))?;
+    let output = File::create(output).map_err(|e| ReaderError::Io(e.to_string()))?;
+    normalize(input, output)
+}
+
+/// Reads the transactions in `input` and writes them to `output` in a canonical form, so that two
+/// inputs which contain the same transactions produce identical output. Returns the number of
+/// transactions that were written.
+///
+/// Whitespace around values is trimmed, headers and transaction types are lowercased, the columns
+/// are written in a fixed order and amounts are written to the number of decimal places that
+/// balances are output to. The destination column of transfers and the disputed amount column of
+/// partial disputes are only written if the input has them. The first record that cannot be
+/// parsed produces an error.
+pub fn normalize<R, W>(input: R, output: W) -> Result<usize, ReaderError>
+where
+    R: Read,
+    W: Write,
+{
+    let mut reader = ReaderConfig::default().builder().from_reader(input);
+    let headers = reader
+        .headers()
+        .map_err(|e| ReaderError::Csv(Box::new(e)))?
+        .iter()
+        .map(str::to_lowercase)
+        .collect::<StringRecord>();
+    let type_index = headers.iter().position(|header| header == TYPE_HEADER);
+    let has_to = headers.iter().any(|header| header == TO_HEADER);
+    let has_disputed_amount = headers
+        .iter()
+        .any(|header| header == DISPUTED_AMOUNT_HEADER);
+
+    let mut writer = WriterBuilder::new().has_headers(false).from_writer(output);
+    let mut output_headers = HEADERS.to_vec();
+    if has_to {
+        output_headers.push(TO_HEADER);
+    }
+    if has_disputed_amount {
+        output_headers.push(DISPUTED_AMOUNT_HEADER);
+    }
+    writer
+        .write_record(output_headers)
+        .map_err(|e| ReaderError::Csv(Box::new(e)))?;
+
+    let mut record = StringRecord::new();
+    let mut count = 0;
+
+    while reader
+        .read_record(&mut record)
+        .map_err(|e| ReaderError::Csv(Box::new(e)))?
+    {
+        let record = lowercase_field(&record, type_index);
+        let csv_tx = record
+            .deserialize::<CsvTransaction>(Some(&headers))
+            .map_err(|e| ReaderError::Csv(Box::new(e)))?;
+        // Converting to a transaction and back validates the record.
+        let transaction =
+            Transaction::try_from(csv_tx).map_err(|e| ReaderError::Parse(e.to_string()))?;
+        let CsvTransaction {
+            tx_type,
+            client,
+            tx,
+            amount,
+            to,
+            disputed_amount,
+        } = CsvTransaction::from(transaction);
+        let format_amount = |amount: Option<f64>| {
+            amount
+                .map(|amount| format!("{:.*}", DECIMAL_PLACES, amount))
+                .unwrap_or_default()
+        };
+
+        let mut fields = vec![
+            tx_type.name().to_string(),
+            client.to_string(),
+            tx.to_string(),
+            format_amount(amount),
+        ];
+        if has_to {
+            fields.push(to.map(|to| to.to_string()).unwrap_or_default());
+        }
+        if has_disputed_amount {
+            fields.push(format_amount(disputed_amount));
+        }
+        writer
+            .write_record(fields)
+            .map_err(|e| ReaderError::Csv(Box::new(e)))?;
+        count += 1;
+    }
+
+    writer.flush().map_err(|e| ReaderError::Io(e.to_string()))?;
+    Ok(count)
+}
+
+/// Returns a copy of `record` with the field at `index`, if there is one, lowercased.
+fn lowercase_field(record: &StringRecord, index: Option<usize>) -> StringRecord {
+    record
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            if Some(i) == index {
+                field.to_lowercase()
+            } else {
+                field.to_string()
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,67 @@
+use crate::parser::normalize::normalize;
+use crate::parser::reader::ReaderError;
+
+fn normalized(input: &str) -> String {
+    let mut output = Vec::new();
+    normalize(input.as_bytes(), &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn equivalent_inputs() {
+    let plain = "type,client,tx,amount
+deposit,1,1,1.5
+withdrawal,2,2,0.25
+dispute,1,1,
+";
+    let formatted = " Amount , TX,client,  Type
+1.50,  1,1,DEPOSIT
+ 0.2500 ,2, 2,Withdrawal
+,1,1,Dispute";
+
+    let expected = "type,client,tx,amount
+deposit,1,1,1.5000
+withdrawal,2,2,0.2500
+dispute,1,1,
+";
+    #[cfg(feature = "scale-8")]
+    let expected = expected
+        .replace("1.5000", "1.50000000")
+        .replace("0.2500", "0.25000000");
+
+    assert_eq!(normalized(plain), expected);
+    assert_eq!(
+        normalized(formatted).as_bytes(),
+        normalized(plain).as_bytes()
+    );
+}
+
+#[test]
+fn disputed_amount() {
+    let input = "type,client,tx,amount,disputed_amount
+deposit,1,1,10,
+dispute,1,1,,2.5
+";
+    let expected = "type,client,tx,amount,disputed_amount
+deposit,1,1,10.0000,
+dispute,1,1,,2.5000
+";
+    #[cfg(feature = "scale-8")]
+    let expected = expected
+        .replace("10.0000", "10.00000000")
+        .replace("2.5000", "2.50000000");
+
+    assert_eq!(normalized(input), expected);
+}
+
+#[test]
+fn invalid_record() {
+    let input = "type,client,tx,amount
+deposit,1,1,
+";
+    let mut output = Vec::new();
+    assert!(matches!(
+        normalize(input.as_bytes(), &mut output),
+        Err(ReaderError::Parse(_))
+    ));
+}
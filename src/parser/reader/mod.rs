@@ -1,16 +1,162 @@
 #[cfg(test)]
 mod tests;
 
-use crate::parser::CsvTransaction;
-use crate::transaction::Transaction;
-use csv::{ReaderBuilder, Trim};
+use crate::client::{ClientLimits, ClientMetadata};
+use crate::parser::rejects::{RejectReason, Rejection, RejectsError, RejectsWriter};
+use crate::parser::{CsvClientMetadata, CsvParseError, CsvTransaction};
+use crate::scale::DECIMAL_PLACES;
+use crate::transaction::{Transaction, TypeCounts};
+use crate::warnings::{Warning, WarningCollector};
+use csv::{Position, ReaderBuilder, StringRecord, Trim};
+use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
 use std::error::Error;
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::{stdin, Cursor, Read, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::sync::mpsc;
+use tracing::{event, Level};
 
 const FORWARD_CHANNEL_CLOSED: &str = "Transaction IO closed unexpectedly";
+const PRECISION_LOSS: &str = "Amount has more decimal places than are output";
+const SKIPPED_MALFORMED: &str = "Skipped a record that could not be parsed";
+const AMOUNT_HEADER: &str = "amount";
+/// A UTF-8 byte order mark, which files exported from spreadsheets often begin with.
+const BOM: char = '\u{feff}';
+/// An input path that reads from the standard input rather than from a file.
+pub const STDIN_PATH: &str = "-";
+/// A line that ends the reading of a followed file.
+pub const FOLLOW_SENTINEL: &str = "#end";
+/// How long a followed file is waited on for more data once its end has been reached.
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// The default capacity of the CSV reader's internal buffer. This matches the CSV crate's default.
+pub const DEFAULT_BUFFER_CAPACITY: usize = 8 * (1 << 10);
+/// The default field delimiter of an input file.
+pub const DEFAULT_DELIMITER: u8 = b',';
+
+/// Configuration for the CSV reader task.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ReaderConfig {
+    /// The capacity, in bytes, of the CSV reader's internal buffer.
+    pub buffer_capacity: usize,
+    /// Whether to warn when an amount has more precision than the number of decimal places that
+    /// amounts are output to.
+    pub warn_precision_loss: bool,
+    /// If set, then only transactions whose ID is within the range are forwarded.
+    pub tx_range: Option<TxRange>,
+    /// Whether to wait for more data to be appended to the file once its end has been reached,
+    /// rather than finishing, until a `FOLLOW_SENTINEL` line is read.
+    pub follow: bool,
+    /// Whether to map each input file in to memory and parse it in place, rather than copying it
+    /// through a buffered reader. This requires the `mmap` feature.
+    pub mmap: bool,
+    /// Whether to log and skip any record that cannot be parsed and continue reading, rather than
+    /// failing. A skipped record is also written to the rejects file, if one is provided.
+    pub skip_malformed: bool,
+    /// The byte that separates the fields of each record, such as `b'\t'` for tab-separated
+    /// input. The headers and values are parsed in the same way whatever the delimiter.
+    #[serde(default = "default_delimiter")]
+    pub delimiter: u8,
+}
+
+fn default_delimiter() -> u8 {
+    DEFAULT_DELIMITER
+}
+
+/// An inclusive range of transaction IDs.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TxRange {
+    pub lo: u32,
+    pub hi: u32,
+}
+
+impl TxRange {
+    /// Returns whether `tx` is within this range.
+    pub fn contains(&self, tx: u32) -> bool {
+        (self.lo..=self.hi).contains(&tx)
+    }
+}
+
+impl FromStr for TxRange {
+    type Err = ();
+
+    /// Parses a range in the format `lo:hi`, where `lo` is not greater than `hi`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (lo, hi) = s.split_once(':').ok_or(())?;
+        let lo = lo.parse().map_err(|_| ())?;
+        let hi = hi.parse().map_err(|_| ())?;
+
+        if lo <= hi {
+            Ok(TxRange { lo, hi })
+        } else {
+            Err(())
+        }
+    }
+}
+
+impl Default for ReaderConfig {
+    fn default() -> Self {
+        ReaderConfig {
+            buffer_capacity: DEFAULT_BUFFER_CAPACITY,
+            warn_precision_loss: false,
+            tx_range: None,
+            follow: false,
+            mmap: false,
+            skip_malformed: false,
+            delimiter: DEFAULT_DELIMITER,
+        }
+    }
+}
+
+/// A summary of the records that a reader task has read.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ReadSummary {
+    /// The number of records whose amount has more precision than is output.
+    pub precision_loss: usize,
+    /// The number of transactions that were not forwarded as their ID is outside of the configured
+    /// transaction range.
+    pub out_of_range: usize,
+    /// The number of transactions that were parsed, by type. This includes any that were out of
+    /// range.
+    pub records: TypeCounts,
+    /// The number of records that could not be parsed and were skipped. Each was also written to
+    /// the rejects, if they were provided.
+    pub unparsed: usize,
+}
+
+impl ReadSummary {
+    /// Adds the counts in `other`, which was read from another file, to this summary.
+    pub fn merge(&mut self, other: ReadSummary) {
+        self.precision_loss += other.precision_loss;
+        self.out_of_range += other.out_of_range;
+        self.records.merge(other.records);
+        self.unparsed += other.unparsed;
+    }
+}
+
+impl ReaderConfig {
+    /// Returns a `ReaderBuilder` that is configured using this `ReaderConfig`.
+    ///
+    /// Leading and trailing whitespace, by the Unicode definition, is trimmed from every header and
+    /// value. This includes spaces, tabs and non-breaking spaces. Nothing else is normalized, so a
+    /// transaction type must then exactly match one of the lowercase type names. A type with
+    /// whitespace inside of it, such as `de posit`, or in another case, such as `Deposit`, cannot
+    /// be parsed.
+    pub fn builder(&self) -> ReaderBuilder {
+        let mut builder = ReaderBuilder::new();
+        builder
+            .trim(Trim::All)
+            .flexible(true)
+            .has_headers(true)
+            .delimiter(self.delimiter)
+            .buffer_capacity(self.buffer_capacity);
+        builder
+    }
+}
 
 #[derive(Error, Debug)]
 pub enum ReaderError {
@@ -20,35 +166,434 @@ pub enum ReaderError {
     Parse(String),
     #[error("An error was produced when handling a CSV record: `{0}`")]
     Csv(Box<dyn Error + Send>),
+    #[error("An error was produced when recording a rejected record: `{0}`")]
+    Rejects(RejectsError),
+    /// The size or modification time of a memory mapped input file changed while it was read, so
+    /// the records that were read from it may be inconsistent.
+    #[cfg(feature = "mmap")]
+    #[error("The input file changed while it was memory mapped")]
+    InputChanged,
+    #[error("Failed to read the input file `{}`: {source}", .path.display())]
+    Input {
+        path: PathBuf,
+        source: Box<ReaderError>,
+    },
 }
 
-/// Creates a task which will read the CSV file `path`, deserialize the records and send them over
-/// the `sender` channel.
+impl From<RejectsError> for ReaderError {
+    fn from(e: RejectsError) -> Self {
+        ReaderError::Rejects(e)
+    }
+}
+
+/// Creates a task which will read each of the CSV files in `paths` in order, deserialize the
+/// records and send them over the `sender` channel as a single stream. Every file begins with its
+/// own header row. The CSV reader is built using `config`. An error is reported along with the
+/// path of the file that produced it.
+///
+/// If `warnings` is provided, then any precision loss or skipped record is recorded in it, as well
+/// as being logged.
+///
+/// If `config` skips malformed records, then any record that cannot be parsed is logged with its
+/// line number, written to `rejects` if it is provided, and skipped. Skipped records are counted in
+/// the summary. Otherwise, the first record that cannot be parsed produces an error, whether or
+/// not `rejects` is provided.
+///
+/// If `config` has a transaction range, then transactions outside of it are counted but not
+/// forwarded. Disputes, resolves and chargebacks carry the ID of the transaction that they
+/// reference, so they are filtered along with it.
+///
+/// If a path is `STDIN_PATH`, then the standard input is read in its place. If `config` follows
+/// the files, then the last file is followed once every other file has been read, see
+/// `follow_task`.
 pub async fn reader_task(
-    path: PathBuf,
+    paths: Vec<PathBuf>,
+    config: ReaderConfig,
+    sender: mpsc::Sender<Transaction>,
+    rejects: Option<RejectsWriter>,
+    warnings: Option<WarningCollector>,
+) -> Result<ReadSummary, ReaderError> {
+    let mut summary = ReadSummary::default();
+    let last = paths.len().saturating_sub(1);
+
+    for (i, path) in paths.into_iter().enumerate() {
+        let sender = sender.clone();
+        let rejects = rejects.clone();
+        let warnings = warnings.clone();
+        let result = if config.follow && i == last {
+            follow_task(path.clone(), config, sender, rejects, warnings).await
+        } else {
+            read_input(&path, config, sender, rejects, warnings).await
+        };
+        let read = result.map_err(|e| ReaderError::Input {
+            path,
+            source: Box::new(e),
+        })?;
+        summary.merge(read);
+    }
+
+    Ok(summary)
+}
+
+/// Reads the CSV file `path`, or the standard input if it is `STDIN_PATH`, until its end is
+/// reached.
+async fn read_input(
+    path: &Path,
+    config: ReaderConfig,
     sender: mpsc::Sender<Transaction>,
-) -> Result<(), ReaderError> {
+    rejects: Option<RejectsWriter>,
+    warnings: Option<WarningCollector>,
+) -> Result<ReadSummary, ReaderError> {
+    if is_stdin(path) {
+        return reader_task_from_reader(stdin(), config, sender, rejects, warnings).await;
+    }
+
+    let file = File::open(path).map_err(|e| ReaderError::Io(e.to_string()))?;
+    #[cfg(feature = "mmap")]
+    if config.mmap {
+        return read_mapped(file, config, sender, rejects, warnings).await;
+    }
+    reader_task_from_reader(file, config, sender, rejects, warnings).await
+}
+
+/// Maps `file` in to memory and reads CSV records from its bytes in the same manner as
+/// `reader_task_from_reader`. The map is only valid while the file is unchanged, so its size and
+/// modification time are compared once it has been read and any change is reported as an error.
+/// Truncating the file while it is mapped may still terminate the process.
+#[cfg(feature = "mmap")]
+async fn read_mapped(
+    file: File,
+    config: ReaderConfig,
+    sender: mpsc::Sender<Transaction>,
+    rejects: Option<RejectsWriter>,
+    warnings: Option<WarningCollector>,
+) -> Result<ReadSummary, ReaderError> {
+    let stamp = |file: &File| {
+        file.metadata()
+            .and_then(|metadata| Ok((metadata.len(), metadata.modified()?)))
+            .map_err(|e| ReaderError::Io(e.to_string()))
+    };
+
+    let before = stamp(&file)?;
+    // Safety: the map is read only and is dropped before this function returns. Another process
+    // may still modify the file while it is mapped, which is detected below.
+    let map = unsafe { memmap2::Mmap::map(&file) }.map_err(|e| ReaderError::Io(e.to_string()))?;
+    let input = std::io::Cursor::new(&map[..]);
+    let summary = reader_task_from_reader(input, config, sender, rejects, warnings).await?;
+
+    if stamp(&file)? != before {
+        return Err(ReaderError::InputChanged);
+    }
+    Ok(summary)
+}
+
+/// Reads CSV records from `input` in the same manner as `reader_task` reads a single file, until
+/// its end is reached.
+pub async fn reader_task_from_reader<R: Read>(
+    input: R,
+    config: ReaderConfig,
+    sender: mpsc::Sender<Transaction>,
+    rejects: Option<RejectsWriter>,
+    warnings: Option<WarningCollector>,
+) -> Result<ReadSummary, ReaderError> {
     // Reader performs internal buffering so there's no need to use a BufReader
-    let reader = ReaderBuilder::new()
-        .trim(Trim::All)
-        .flexible(true)
-        .has_headers(true)
-        .from_path(path)
+    let mut reader = config.builder().from_reader(input);
+    let headers = reader
+        .headers()
+        .map_err(|e| ReaderError::Csv(Box::new(e)))?;
+    let headers = strip_bom(headers);
+    let mut forwarder = Forwarder::new(
+        config,
+        headers,
+        &sender,
+        rejects.as_ref(),
+        warnings.as_ref(),
+    );
+    let mut record = StringRecord::new();
+
+    while reader
+        .read_record(&mut record)
         .map_err(|e| ReaderError::Csv(Box::new(e)))?
-        .into_deserialize::<CsvTransaction>();
+    {
+        let line = record.position().map(|position| position.line());
+        forwarder.forward(&record, line).await?;
+    }
+
+    Ok(forwarder.summary)
+}
+
+/// Returns whether `path` is `STDIN_PATH` and so refers to the standard input.
+pub fn is_stdin(path: &Path) -> bool {
+    path == Path::new(STDIN_PATH)
+}
+
+/// Reads the CSV file `path` in the same manner as `reader_task`, but once the end of the file has
+/// been reached, it is waited on for more data to be appended rather than finishing. Reading
+/// finishes when a `FOLLOW_SENTINEL` line is read.
+///
+/// Only complete lines are parsed, so a record that is partially written when it is read is
+/// waited on until it is terminated. A record whose quoted field spans several lines is buffered
+/// until its quotes balance, so a record with an unmatched quote is waited on until a later line
+/// closes it. Blank lines are skipped. Each time the end of the file is reached, any rejects are
+/// flushed so that they can be observed while the file is followed.
+pub async fn follow_task(
+    path: PathBuf,
+    config: ReaderConfig,
+    sender: mpsc::Sender<Transaction>,
+    rejects: Option<RejectsWriter>,
+    warnings: Option<WarningCollector>,
+) -> Result<ReadSummary, ReaderError> {
+    let file = tokio::fs::File::open(path)
+        .await
+        .map_err(|e| ReaderError::Io(e.to_string()))?;
+    let mut file = BufReader::with_capacity(config.buffer_capacity, file);
+    let mut forwarder: Option<Forwarder> = None;
+    let mut parser = LineParser::new(&config);
+    let mut buf = String::new();
+    let mut line = 0;
+    // The line that the buffered record starts on.
+    let mut record_line = 1;
+
+    loop {
+        if buf.is_empty() {
+            record_line = line + 1;
+        }
+        let read = file
+            .read_line(&mut buf)
+            .await
+            .map_err(|e| ReaderError::Io(e.to_string()))?;
+        if read == 0 || !buf.ends_with('\n') {
+            // Any partial line is kept and completed by the next read.
+            if let Some(rejects) = &rejects {
+                rejects.flush()?;
+            }
+            tokio::time::sleep(FOLLOW_POLL_INTERVAL).await;
+            continue;
+        }
+
+        line += 1;
+        // An odd number of quotes leaves a quoted field open, so its record continues on the
+        // next line. An escaped quote is doubled, so it does not change the balance.
+        if buf.matches('"').count() % 2 == 1 {
+            continue;
+        }
+        let text = buf.trim_end_matches(&['\r', '\n'][..]);
+        if text.trim().is_empty() {
+            buf.clear();
+            continue;
+        }
+        if text.trim() == FOLLOW_SENTINEL {
+            break;
+        }
+
+        let record = parser.parse(text)?;
+        match &mut forwarder {
+            Some(forwarder) => forwarder.forward(&record, Some(record_line)).await?,
+            None => {
+                let headers = strip_bom(&record);
+                forwarder = Some(Forwarder::new(
+                    config,
+                    headers,
+                    &sender,
+                    rejects.as_ref(),
+                    warnings.as_ref(),
+                ));
+            }
+        }
+        buf.clear();
+    }
+
+    Ok(forwarder
+        .map(|forwarder| forwarder.summary)
+        .unwrap_or_default())
+}
+
+/// Returns a copy of `headers` without a leading byte order mark. Otherwise, the mark would become
+/// part of the first header and its column could not be found. A mark anywhere else in the file is
+/// left untouched.
+fn strip_bom(headers: &StringRecord) -> StringRecord {
+    headers
+        .iter()
+        .enumerate()
+        .map(|(i, header)| match header.strip_prefix(BOM) {
+            Some(header) if i == 0 => header.trim_start(),
+            _ => header,
+        })
+        .collect()
+}
+
+/// Parses single records of a CSV file, each of which is usually a line, in to records. A single
+/// CSV reader is reused for every record, rather than one being built for each.
+struct LineParser {
+    reader: csv::Reader<Cursor<Vec<u8>>>,
+}
+
+impl LineParser {
+    /// Constructs a parser whose reader is built from `config`.
+    fn new(config: &ReaderConfig) -> Self {
+        let reader = config
+            .builder()
+            .has_headers(false)
+            .from_reader(Cursor::new(Vec::new()));
+        LineParser { reader }
+    }
+
+    /// Parses `line`, which may span several lines in a quoted field, in to a record.
+    fn parse(&mut self, line: &str) -> Result<StringRecord, ReaderError> {
+        let buf = self.reader.get_mut().get_mut();
+        buf.clear();
+        buf.extend_from_slice(line.as_bytes());
+
+        // Seeking back to the start discards anything the reader buffered from the previous line
+        // and clears its end of file state.
+        let mut record = StringRecord::new();
+        self.reader
+            .seek_raw(SeekFrom::Start(0), Position::new())
+            .and_then(|_| self.reader.read_record(&mut record))
+            .map_err(|e| ReaderError::Csv(Box::new(e)))?;
+        Ok(record)
+    }
+}
+
+/// Deserializes records in to transactions and forwards them to a channel.
+struct Forwarder<'r> {
+    config: ReaderConfig,
+    headers: StringRecord,
+    /// The index of the amount column in `headers`, if it exists.
+    amount_index: Option<usize>,
+    sender: &'r mpsc::Sender<Transaction>,
+    rejects: Option<&'r RejectsWriter>,
+    warnings: Option<&'r WarningCollector>,
+    summary: ReadSummary,
+}
+
+impl<'r> Forwarder<'r> {
+    /// Constructs a new forwarder that will deserialize records with `headers` and send them over
+    /// `sender`, as configured by `config`. Any warnings are recorded in `warnings`, if it is
+    /// provided.
+    fn new(
+        config: ReaderConfig,
+        headers: StringRecord,
+        sender: &'r mpsc::Sender<Transaction>,
+        rejects: Option<&'r RejectsWriter>,
+        warnings: Option<&'r WarningCollector>,
+    ) -> Self {
+        let amount_index = headers.iter().position(|header| header == AMOUNT_HEADER);
+        Forwarder {
+            config,
+            headers,
+            amount_index,
+            sender,
+            rejects,
+            warnings,
+            summary: ReadSummary::default(),
+        }
+    }
+
+    /// Deserializes `record`, which was read from `line`, and forwards it if it is within the
+    /// configured transaction range. If the configuration skips malformed records, then a record
+    /// that cannot be parsed is skipped and written to the rejects, if they are provided.
+    /// Otherwise, an error is returned.
+    async fn forward(
+        &mut self,
+        record: &StringRecord,
+        line: Option<u64>,
+    ) -> Result<(), ReaderError> {
+        let lost_precision = self
+            .amount_index
+            .filter(|_| self.config.warn_precision_loss)
+            .and_then(|index| record.get(index))
+            .filter(|amount| loses_precision(amount));
+        if let Some(amount) = lost_precision {
+            event!(Level::WARN, PRECISION_LOSS, ?line, amount);
+            self.summary.precision_loss += 1;
+        }
+
+        let parse_result = record
+            .deserialize::<CsvTransaction>(Some(&self.headers))
+            .map_err(|e| ReaderError::Csv(Box::new(e)))
+            .and_then(|csv_tx| {
+                Transaction::try_from(csv_tx).map_err(|e| ReaderError::Parse(e.to_string()))
+            });
+
+        if let (Some(amount), Some(warnings)) = (lost_precision, self.warnings) {
+            let parsed = parse_result.as_ref().ok();
+            warnings.record(Warning::PrecisionLoss {
+                line,
+                client: parsed.map(Transaction::client_id),
+                tx: parsed.map(Transaction::id),
+                amount: amount.to_string(),
+            });
+        }
+
+        if let Ok(tx) = &parse_result {
+            self.summary.records.add(tx.tx_type());
+        }
 
-    for parse_result in reader {
         match parse_result {
-            Ok(csv_tx) => {
-                let tx =
-                    Transaction::try_from(csv_tx).map_err(|e| ReaderError::Parse(e.to_string()))?;
-                if sender.send(tx).await.is_err() {
+            Ok(tx) if !in_range(&self.config, &tx) => self.summary.out_of_range += 1,
+            Ok(tx) => {
+                if self.sender.send(tx).await.is_err() {
                     return Err(ReaderError::Io(FORWARD_CHANNEL_CLOSED.to_string()));
                 }
             }
-            Err(e) => return Err(ReaderError::Csv(Box::new(e))),
+            Err(error) if self.config.skip_malformed => {
+                event!(Level::WARN, SKIPPED_MALFORMED, ?line, ?error);
+                if let Some(warnings) = self.warnings {
+                    warnings.record(Warning::SkippedRecord {
+                        line,
+                        error: error.to_string(),
+                    });
+                }
+                if let Some(rejects) = self.rejects {
+                    rejects.write(Rejection::from_record(
+                        &self.headers,
+                        record,
+                        RejectReason::Parse,
+                    ))?;
+                }
+                self.summary.unparsed += 1;
+            }
+            Err(e) => return Err(e),
         }
+
+        Ok(())
     }
+}
 
-    Ok(())
+/// Returns whether `transaction` is within the transaction range of `config`, if one is set.
+fn in_range(config: &ReaderConfig, transaction: &Transaction) -> bool {
+    match config.tx_range {
+        Some(range) => range.contains(transaction.id()),
+        None => true,
+    }
+}
+
+/// Returns whether the amount `token` would change if it was rounded to the number of decimal
+/// places that amounts are output to. Tokens that are not a number never lose precision.
+pub fn loses_precision(token: &str) -> bool {
+    match token.parse::<f64>() {
+        Ok(amount) => {
+            let rounded = format!("{:.*}", DECIMAL_PLACES, amount);
+            rounded.parse::<f64>() != Ok(amount)
+        }
+        Err(_) => false,
+    }
+}
+
+/// Reads the client metadata CSV file at `path`. Returning the metadata and limits for each client
+/// in the file.
+pub fn read_client_metadata(
+    path: PathBuf,
+) -> Result<Vec<(u16, ClientMetadata, ClientLimits)>, ReaderError> {
+    ReaderConfig::default()
+        .builder()
+        .from_path(path)
+        .map_err(|e| ReaderError::Csv(Box::new(e)))?
+        .into_deserialize::<CsvClientMetadata>()
+        .map(|record| {
+            let record = record.map_err(|e| ReaderError::Csv(Box::new(e)))?;
+            TryFrom::try_from(record).map_err(|e: CsvParseError| ReaderError::Parse(e.to_string()))
+        })
+        .collect()
 }
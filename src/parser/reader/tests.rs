@@ -1,15 +1,27 @@
-use crate::parser::CsvTransaction;
-use crate::transaction::Transaction;
-use csv::{Reader, ReaderBuilder, Trim};
+use crate::client::{ClientLimits, State};
+use crate::data::mem_store;
+use crate::io::{IoConfig, IoTask};
+use crate::parser::reader::{
+    is_stdin, loses_precision, read_client_metadata, reader_task, reader_task_from_reader,
+    LineParser, ReadSummary, ReaderConfig, ReaderError, TxRange, DEFAULT_BUFFER_CAPACITY,
+};
+use crate::parser::{CsvParseError, CsvTransaction};
+use crate::transaction::{Transaction, TypeCounts};
+use csv::Reader;
+use futures::future::join;
 use std::convert::TryFrom;
-use std::io::Read;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+#[cfg(feature = "mmap")]
+use std::path::PathBuf;
+use std::time::Duration;
+use tempdir::TempDir;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
 
 fn reader<R: Read>(reader: R) -> Reader<R> {
-    ReaderBuilder::new()
-        .trim(Trim::All)
-        .flexible(true)
-        .has_headers(true)
-        .from_reader(reader)
+    ReaderConfig::default().builder().from_reader(reader)
 }
 
 fn read_single(input: &str, expected: Transaction) {
@@ -54,6 +66,40 @@ withdrawal, 1,   1,  1.0";
     read_single(input, Transaction::withdrawal(1, 1, 1.0));
 }
 
+#[test]
+fn transfer() {
+    let input = "type, client, tx, amount, to
+deposit, 1, 1, 5.0
+transfer, 1, 2, 2.5, 3
+withdrawal, 3, 3, 1.0,";
+
+    read_multiple(
+        input,
+        vec![
+            Transaction::deposit(1, 1, 5.0),
+            Transaction::transfer(1, 3, 2, 2.5),
+            Transaction::withdrawal(3, 3, 1.0),
+        ],
+    );
+}
+
+#[test]
+fn invalid_transfer() {
+    for input in [
+        "type,client,tx,amount\ntransfer,1,1,1.0",
+        "type,client,tx,amount,to\ntransfer,1,1,1.0,",
+        "type,client,tx,amount,to\ntransfer,1,1,1.0,1",
+        "type,client,tx,amount,to\ntransfer,1,1,,2",
+    ] {
+        let record = reader(input.as_bytes())
+            .into_deserialize::<CsvTransaction>()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert!(Transaction::try_from(record).is_err());
+    }
+}
+
 #[test]
 fn deposit() {
     let input = "type, client,  tx,amount
@@ -70,6 +116,73 @@ dispute, 1,   1";
     read_single(input, Transaction::dispute(1, 1));
 }
 
+#[test]
+fn partial_dispute() {
+    let input = "type, client, tx, amount, disputed_amount
+dispute, 1, 1, , 2.5
+dispute, 1, 2, ,";
+
+    read_multiple(
+        input,
+        vec![
+            Transaction::partial_dispute(1, 1, 2.5),
+            Transaction::dispute(1, 2),
+        ],
+    );
+}
+
+#[test]
+fn zero_partial_dispute() {
+    // A disputed amount of zero is parsed, including one that rounds to zero, so that it is
+    // executed according to the policy for zero disputes.
+    let input = "type, client, tx, amount, disputed_amount
+dispute, 1, 1, , 0
+dispute, 1, 2, , 0.000000001";
+
+    read_multiple(
+        input,
+        vec![
+            Transaction::partial_dispute(1, 1, 0.0),
+            Transaction::partial_dispute(1, 2, 0.0),
+        ],
+    );
+}
+
+#[test]
+fn invalid_partial_dispute() {
+    for input in [
+        "type,client,tx,amount,disputed_amount\ndispute,1,1,,-1.0",
+        "type,client,tx,amount,disputed_amount\ndispute,1,1,,-0.000000001",
+        "type,client,tx,amount,disputed_amount\nresolve,1,1,,1.0",
+        "type,client,tx,amount,disputed_amount\ndeposit,1,1,1.0,1.0",
+    ] {
+        let record = reader(input.as_bytes())
+            .into_deserialize::<CsvTransaction>()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert!(Transaction::try_from(record).is_err());
+    }
+}
+
+#[test]
+fn unrepresentable_amount() {
+    for amount in ["1e40", "-1e40", "inf", "NaN"] {
+        for tx_type in ["deposit", "withdrawal"] {
+            let input = format!("type,client,tx,amount\n{},1,1,{}", tx_type, amount);
+            let record = reader(input.as_bytes())
+                .into_deserialize::<CsvTransaction>()
+                .next()
+                .unwrap()
+                .unwrap();
+            assert!(matches!(
+                Transaction::try_from(record),
+                Err(CsvParseError::AmountOutOfRange)
+            ));
+        }
+    }
+}
+
 #[test]
 fn resolve() {
     let input = "type, client,  tx,amount
@@ -136,7 +249,12 @@ fn high_precision() {
     let input = "type, client,  tx,amount
 deposit, 1,   1,  1.23456789";
 
-    read_single(input, Transaction::deposit(1, 1, 1.23456789));
+    #[cfg(not(feature = "scale-8"))]
+    let expected = Transaction::deposit(1, 1, 1.2346);
+    #[cfg(feature = "scale-8")]
+    let expected = Transaction::deposit(1, 1, 1.23456789);
+
+    read_single(input, expected);
 }
 
 #[test]
@@ -158,3 +276,635 @@ chargeback, 1,   1";
 
     read_multiple(input, expected);
 }
+
+#[test]
+fn buffer_capacities() {
+    let mut input = "type, client,  tx,amount\n".to_string();
+    let mut expected = Vec::new();
+
+    for i in 1..=1000 {
+        input.push_str(&format!("deposit, {}, {}, {}.5\n", i % 10, i, i));
+        expected.push(Transaction::deposit((i % 10) as u16, i, i as f64 + 0.5));
+    }
+
+    for buffer_capacity in [1, 16, 1024, 64 * 1024] {
+        let config = ReaderConfig {
+            buffer_capacity,
+            ..Default::default()
+        };
+        let records = config
+            .builder()
+            .from_reader(input.as_bytes())
+            .into_deserialize::<CsvTransaction>()
+            .map(|record| Transaction::try_from(record.unwrap()).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(records, expected, "Buffer capacity: {}", buffer_capacity);
+    }
+}
+
+async fn read_summary(input: &str, warn_precision_loss: bool) -> ReadSummary {
+    let dir = TempDir::new("reader").unwrap();
+    let path = dir.path().join("input.csv");
+    fs::write(&path, input).unwrap();
+
+    let config = ReaderConfig {
+        warn_precision_loss,
+        ..Default::default()
+    };
+    let (tx, mut rx) = mpsc::channel(16);
+    let summary = reader_task(vec![path], config, tx, None, None)
+        .await
+        .unwrap();
+    while rx.recv().await.is_some() {}
+
+    summary
+}
+
+#[tokio::test]
+async fn tab_separated() {
+    let dir = TempDir::new("reader").unwrap();
+    let path = dir.path().join("input.tsv");
+    fs::write(
+        &path,
+        "type\tclient\ttx\tamount
+deposit\t1\t1\t1.5
+withdrawal\t1\t2\t0.5
+dispute\t1\t1\t
+deposit, 2\t3\t1.0
+",
+    )
+    .unwrap();
+
+    let config = ReaderConfig {
+        delimiter: b'\t',
+        skip_malformed: true,
+        ..Default::default()
+    };
+    let (tx, mut rx) = mpsc::channel(16);
+    let summary = reader_task(vec![path], config, tx, None, None)
+        .await
+        .unwrap();
+    let mut transactions = Vec::new();
+    while let Some(transaction) = rx.recv().await {
+        transactions.push(transaction);
+    }
+
+    assert_eq!(
+        transactions,
+        vec![
+            Transaction::deposit(1, 1, 1.5),
+            Transaction::withdrawal(1, 2, 0.5),
+            Transaction::dispute(1, 1),
+        ]
+    );
+    // A comma is not a delimiter, so `deposit, 2` is read as the type.
+    assert_eq!(summary.unparsed, 1);
+}
+
+#[test]
+fn type_surrounding_whitespace() {
+    for tx_type in [
+        "deposit ",
+        " deposit",
+        "\tdeposit",
+        "deposit\t",
+        " \t deposit \t ",
+        "\u{a0}deposit",
+    ] {
+        let input = format!("type,client,tx,amount\n{},1,1,1.0", tx_type);
+        read_single(&input, Transaction::deposit(1, 1, 1.0));
+    }
+}
+
+#[test]
+fn type_internal_whitespace() {
+    for tx_type in ["de posit", "de\tposit", " de posit "] {
+        let input = format!("type,client,tx,amount\n{},1,1,1.0", tx_type);
+        read_err(&input);
+    }
+}
+
+#[test]
+fn type_casing() {
+    for tx_type in ["Deposit", " DEPOSIT\t"] {
+        let input = format!("type,client,tx,amount\n{},1,1,1.0", tx_type);
+        read_err(&input);
+    }
+}
+
+#[test]
+fn amount_rounding() {
+    let input = "type, client, tx, amount
+deposit, 1, 1, 1.23456789
+withdrawal, 1, 2, 0.00005
+withdrawal, 1, 3, 0.00015
+withdrawal, 1, 4, -0.00015";
+
+    #[cfg(not(feature = "scale-8"))]
+    let expected = vec![
+        Transaction::deposit(1, 1, 1.2346),
+        Transaction::withdrawal(1, 2, 0.0),
+        Transaction::withdrawal(1, 3, 0.0002),
+        Transaction::withdrawal(1, 4, -0.0002),
+    ];
+    #[cfg(feature = "scale-8")]
+    let expected = vec![
+        Transaction::deposit(1, 1, 1.23456789),
+        Transaction::withdrawal(1, 2, 0.00005),
+        Transaction::withdrawal(1, 3, 0.00015),
+        Transaction::withdrawal(1, 4, -0.00015),
+    ];
+
+    read_multiple(input, expected);
+}
+
+#[test]
+fn precision() {
+    assert!(!loses_precision("1.0"));
+    assert!(!loses_precision("0.1"));
+    assert!(!loses_precision("1.1234"));
+    assert!(!loses_precision("not a number"));
+    assert!(loses_precision("1.123456789"));
+}
+
+#[tokio::test]
+async fn warn_precision_loss() {
+    let input = "type, client, tx, amount
+deposit, 1, 1, 1.123456789
+deposit, 1, 2, 1.1234
+withdrawal, 1, 3, 0.000000001
+dispute, 1, 1,";
+
+    assert_eq!(read_summary(input, true).await.precision_loss, 2);
+    assert_eq!(read_summary(input, false).await.precision_loss, 0);
+}
+
+#[tokio::test]
+async fn no_precision_loss() {
+    let input = "type, client, tx, amount
+deposit, 1, 1, 1.1234
+deposit, 1, 2, 10
+withdrawal, 1, 3, 0.5";
+
+    assert_eq!(read_summary(input, true).await.precision_loss, 0);
+}
+
+#[tokio::test]
+async fn tx_range() {
+    let dir = TempDir::new("reader").unwrap();
+    let path = dir.path().join("input.csv");
+    fs::write(
+        &path,
+        "type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, 1, 2, 2.0
+deposit, 2, 3, 4.0
+withdrawal, 1, 4, 1.0
+deposit, 2, 5, 8.0
+dispute, 1, 1,
+dispute, 2, 3,",
+    )
+    .unwrap();
+
+    let config = ReaderConfig {
+        tx_range: Some(TxRange { lo: 2, hi: 4 }),
+        ..Default::default()
+    };
+    let store = mem_store();
+    let (tx, rx) = mpsc::channel(16);
+
+    let io_task = IoTask::new(rx, store.clone(), IoConfig::default(), None).run(16);
+    let reader_task = reader_task(vec![path], config, tx, None, None);
+    let (io_result, reader_result) = join(io_task, reader_task).await;
+    assert!(io_result.is_ok());
+    assert_eq!(reader_result.unwrap().out_of_range, 3);
+
+    let states = store.collect_states().unwrap();
+    let balances = states
+        .iter()
+        .map(
+            |State {
+                 available, held, ..
+             }| (*available, *held),
+        )
+        .collect::<Vec<_>>();
+    assert_eq!(balances, vec![(1.0, 0.0), (0.0, 4.0)]);
+}
+
+/// Writes `input` to a file and reads every transaction from it with `reader_task`, using a buffer
+/// of `buffer_capacity` bytes.
+async fn read_file(input: &str, buffer_capacity: usize) -> Result<Vec<Transaction>, ReaderError> {
+    let dir = TempDir::new("reader").unwrap();
+    let path = dir.path().join("input.csv");
+    fs::write(&path, input).unwrap();
+
+    let (tx, mut rx) = mpsc::channel(16);
+    let collect = async move {
+        let mut transactions = Vec::new();
+        while let Some(transaction) = rx.recv().await {
+            transactions.push(transaction);
+        }
+        transactions
+    };
+    let config = ReaderConfig {
+        buffer_capacity,
+        ..Default::default()
+    };
+    let (result, transactions) =
+        join(reader_task(vec![path], config, tx, None, None), collect).await;
+    result.map(|_| transactions)
+}
+
+#[tokio::test]
+async fn from_reader() {
+    let input = "type, client, tx, amount
+deposit, 1, 1, 1.0
+withdrawal, 1, 2, 0.5
+dispute, 1, 1,";
+
+    let (tx, mut rx) = mpsc::channel(16);
+    let collect = async move {
+        let mut transactions = Vec::new();
+        while let Some(transaction) = rx.recv().await {
+            transactions.push(transaction);
+        }
+        transactions
+    };
+    let read = reader_task_from_reader(input.as_bytes(), ReaderConfig::default(), tx, None, None);
+    let (summary, transactions) = join(read, collect).await;
+
+    assert_eq!(summary.unwrap().records.total(), 3);
+    assert_eq!(
+        transactions,
+        read_file(input, DEFAULT_BUFFER_CAPACITY).await.unwrap()
+    );
+}
+
+#[tokio::test]
+async fn skip_malformed() {
+    let input = "type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, 1, 2
+transfer, 1, 3, 1.0
+withdrawal, 1, 4, 0.5
+deposit, 70000, 5, 1.0";
+    let read = |config: ReaderConfig| async move {
+        let (tx, mut rx) = mpsc::channel(16);
+        let collect = async move {
+            let mut transactions = Vec::new();
+            while let Some(transaction) = rx.recv().await {
+                transactions.push(transaction);
+            }
+            transactions
+        };
+        join(
+            reader_task_from_reader(input.as_bytes(), config, tx, None, None),
+            collect,
+        )
+        .await
+    };
+
+    let (result, transactions) = read(ReaderConfig::default()).await;
+    assert!(matches!(result, Err(ReaderError::Parse(_))));
+    assert_eq!(transactions, vec![Transaction::deposit(1, 1, 1.0)]);
+
+    let config = ReaderConfig {
+        skip_malformed: true,
+        ..Default::default()
+    };
+    let (result, transactions) = read(config).await;
+    let summary = result.unwrap();
+    assert_eq!(summary.unparsed, 3);
+    assert_eq!(summary.records.total(), 2);
+    assert_eq!(
+        transactions,
+        vec![
+            Transaction::deposit(1, 1, 1.0),
+            Transaction::withdrawal(1, 4, 0.5)
+        ]
+    );
+}
+
+#[tokio::test]
+async fn missing_file() {
+    let dir = TempDir::new("reader").unwrap();
+    let (tx, _rx) = mpsc::channel(1);
+    let path = dir.path().join("missing.csv");
+    let result = reader_task(vec![path.clone()], ReaderConfig::default(), tx, None, None).await;
+
+    match result {
+        Err(ReaderError::Input {
+            path: failed,
+            source,
+        }) => {
+            assert_eq!(failed, path);
+            assert!(matches!(*source, ReaderError::Io(_)));
+        }
+        result => panic!("Unexpected result: {:?}", result),
+    }
+}
+
+#[test]
+fn stdin_path() {
+    assert!(is_stdin(Path::new("-")));
+    assert!(!is_stdin(Path::new("./-")));
+    assert!(!is_stdin(Path::new("input.csv")));
+}
+
+#[tokio::test]
+async fn bom() {
+    let input = "type, client, tx, amount
+deposit, 1, 1, 1.0
+withdrawal, 1, 2, 0.5
+dispute, 1, 1,";
+
+    let expected = read_file(input, DEFAULT_BUFFER_CAPACITY).await.unwrap();
+    assert_eq!(expected.len(), 3);
+
+    // A buffer that is smaller than the mark splits it across reads.
+    for buffer_capacity in [1, 2, DEFAULT_BUFFER_CAPACITY] {
+        let bom_input = format!("\u{feff}{}", input);
+        let transactions = read_file(&bom_input, buffer_capacity).await;
+        assert_eq!(
+            transactions.unwrap(),
+            expected,
+            "Buffer capacity: {}",
+            buffer_capacity
+        );
+    }
+}
+
+#[tokio::test]
+async fn bom_mid_file() {
+    let input = "type, client, tx, amount
+deposit, 1, 1, 1.0
+\u{feff}deposit, 1, 2, 1.0";
+
+    let result = read_file(input, DEFAULT_BUFFER_CAPACITY).await;
+    assert!(matches!(
+        result,
+        Err(ReaderError::Input { source, .. }) if matches!(*source, ReaderError::Csv(_))
+    ));
+}
+
+#[tokio::test]
+async fn multiple_files() {
+    let dir = TempDir::new("reader").unwrap();
+    let first = dir.path().join("first.csv");
+    let second = dir.path().join("second.csv");
+    let third = dir.path().join("third.csv");
+    fs::write(&first, "type, client, tx, amount\ndeposit, 1, 1, 1.0\n").unwrap();
+    fs::write(
+        &second,
+        "\u{feff}client, type, tx, amount\n2, deposit, 2, 2.0\n",
+    )
+    .unwrap();
+    fs::write(&third, "type, client, tx, amount\nwithdrawal, 1, 3, 0.5\n").unwrap();
+
+    let (tx, mut rx) = mpsc::channel(16);
+    let paths = vec![first, second, third];
+    let summary = reader_task(paths, ReaderConfig::default(), tx, None, None)
+        .await
+        .unwrap();
+
+    let mut transactions = Vec::new();
+    while let Some(transaction) = rx.recv().await {
+        transactions.push(transaction);
+    }
+    assert_eq!(
+        transactions,
+        vec![
+            Transaction::deposit(1, 1, 1.0),
+            Transaction::deposit(2, 2, 2.0),
+            Transaction::withdrawal(1, 3, 0.5),
+        ]
+    );
+    assert_eq!(summary.records.deposit, 2);
+    assert_eq!(summary.records.withdrawal, 1);
+}
+
+#[tokio::test]
+async fn multiple_files_error() {
+    let dir = TempDir::new("reader").unwrap();
+    let first = dir.path().join("first.csv");
+    let second = dir.path().join("second.csv");
+    fs::write(&first, "type, client, tx, amount\ndeposit, 1, 1, 1.0\n").unwrap();
+    fs::write(&second, "type, client, tx, amount\ndeposit, 1, 2\n").unwrap();
+
+    let (tx, mut rx) = mpsc::channel(16);
+    let paths = vec![first, second.clone()];
+    let result = reader_task(paths, ReaderConfig::default(), tx, None, None).await;
+
+    assert_eq!(rx.recv().await, Some(Transaction::deposit(1, 1, 1.0)));
+    match result {
+        Err(e @ ReaderError::Input { .. }) => {
+            assert!(e.to_string().contains(second.to_str().unwrap()));
+        }
+        result => panic!("Unexpected result: {:?}", result),
+    }
+}
+
+/// Appends `rows` to the file at `path`.
+fn append(path: &Path, rows: &str) {
+    let mut file = fs::OpenOptions::new().append(true).open(path).unwrap();
+    file.write_all(rows.as_bytes()).unwrap();
+}
+
+/// Receives the next transaction from `rx`, failing if none is received within a few seconds.
+async fn next(rx: &mut mpsc::Receiver<Transaction>) -> Option<Transaction> {
+    timeout(Duration::from_secs(5), rx.recv()).await.unwrap()
+}
+
+#[tokio::test]
+async fn follow() {
+    let dir = TempDir::new("reader").unwrap();
+    let path = dir.path().join("input.csv");
+    fs::write(&path, "type, client, tx, amount\ndeposit, 1, 1, 1.0\n").unwrap();
+
+    let config = ReaderConfig {
+        follow: true,
+        ..Default::default()
+    };
+    let (tx, mut rx) = mpsc::channel(8);
+    let task = tokio::spawn(reader_task(vec![path.clone()], config, tx, None, None));
+
+    assert_eq!(next(&mut rx).await, Some(Transaction::deposit(1, 1, 1.0)));
+
+    append(&path, "deposit, 2, 2, 2.0\nwithdrawal, 1, 3, 0.5\n");
+    assert_eq!(next(&mut rx).await, Some(Transaction::deposit(2, 2, 2.0)));
+    assert_eq!(
+        next(&mut rx).await,
+        Some(Transaction::withdrawal(1, 3, 0.5))
+    );
+
+    // A partially written row is only read once it is terminated.
+    append(&path, "dispute, 1,");
+    assert!(timeout(Duration::from_millis(300), rx.recv())
+        .await
+        .is_err());
+    append(&path, " 1\n");
+    assert_eq!(next(&mut rx).await, Some(Transaction::dispute(1, 1)));
+
+    // Blank lines are skipped, and a quoted field that spans lines is read once it is closed.
+    append(&path, "\n  \ndeposit, 3, 4,\"2.0\n");
+    assert!(timeout(Duration::from_millis(300), rx.recv())
+        .await
+        .is_err());
+    append(&path, "\"\n");
+    assert_eq!(next(&mut rx).await, Some(Transaction::deposit(3, 4, 2.0)));
+
+    append(&path, "#end\ndeposit, 3, 5, 1.0\n");
+    let summary = timeout(Duration::from_secs(5), task)
+        .await
+        .unwrap()
+        .unwrap();
+    let records = TypeCounts {
+        deposit: 3,
+        withdrawal: 1,
+        dispute: 1,
+        ..Default::default()
+    };
+    assert_eq!(
+        summary.unwrap(),
+        ReadSummary {
+            records,
+            ..Default::default()
+        }
+    );
+    assert_eq!(rx.recv().await, None);
+}
+
+/// Reads the transactions in `path`, mapping it in to memory if `mmap` is set.
+#[cfg(feature = "mmap")]
+async fn read_path(path: PathBuf, mmap: bool) -> (Vec<Transaction>, ReadSummary) {
+    let config = ReaderConfig {
+        mmap,
+        ..Default::default()
+    };
+    let (tx, mut rx) = mpsc::channel(DEFAULT_BUFFER_CAPACITY);
+    let collect = async move {
+        let mut transactions = Vec::new();
+        while let Some(transaction) = rx.recv().await {
+            transactions.push(transaction);
+        }
+        transactions
+    };
+
+    let (summary, transactions) =
+        join(reader_task(vec![path], config, tx, None, None), collect).await;
+    (transactions, summary.unwrap())
+}
+
+/// Writes a file of `count` deposits, withdrawals and disputes to `path`.
+#[cfg(feature = "mmap")]
+fn write_feed(path: &Path, count: u32) {
+    let mut file = fs::File::create(path).unwrap();
+    writeln!(file, "\u{feff}type, client, tx, amount").unwrap();
+    for tx in 1..=count {
+        let client = tx % 100;
+        match tx % 3 {
+            0 => writeln!(file, "deposit, {}, {}, {}.1234", client, tx, tx).unwrap(),
+            1 => writeln!(file, "withdrawal, {}, {}, 0.5", client, tx).unwrap(),
+            _ => writeln!(file, "dispute, {}, {},", client, tx - 2).unwrap(),
+        }
+    }
+}
+
+#[cfg(feature = "mmap")]
+#[tokio::test]
+async fn mmap_matches_buffered() {
+    let dir = TempDir::new("reader").unwrap();
+    let path = dir.path().join("input.csv");
+    write_feed(&path, 1000);
+
+    let buffered = read_path(path.clone(), false).await;
+    let mapped = read_path(path, true).await;
+    assert_eq!(mapped.0.len(), 1000);
+    assert_eq!(mapped, buffered);
+}
+
+#[cfg(feature = "mmap")]
+#[tokio::test]
+async fn mmap_empty_file() {
+    let dir = TempDir::new("reader").unwrap();
+    let path = dir.path().join("input.csv");
+    fs::write(&path, "").unwrap();
+
+    let (transactions, summary) = read_path(path, true).await;
+    assert!(transactions.is_empty());
+    assert_eq!(summary, ReadSummary::default());
+}
+
+#[test]
+fn client_metadata_limits() {
+    let dir = TempDir::new("reader").unwrap();
+    let path = dir.path().join("metadata.csv");
+    fs::write(
+        &path,
+        "client, name, region, tier, min_balance, max_held\n\
+         1, Alice, eu-west, gold, 5.0,\n\
+         2, Bob, eu-west, gold, , 100\n",
+    )
+    .unwrap();
+    let limits = read_client_metadata(path.clone())
+        .unwrap()
+        .into_iter()
+        .map(|(client, _, limits)| (client, limits))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        limits,
+        vec![
+            (
+                1,
+                ClientLimits {
+                    min_balance: Some(5.0),
+                    max_held: None
+                }
+            ),
+            (
+                2,
+                ClientLimits {
+                    min_balance: None,
+                    max_held: Some(100.0)
+                }
+            ),
+        ]
+    );
+
+    fs::write(
+        &path,
+        "client, name, region, tier\n1, Alice, eu-west, gold\n",
+    )
+    .unwrap();
+    let (_, _, limits) = read_client_metadata(path.clone()).unwrap().remove(0);
+    assert_eq!(limits, ClientLimits::default());
+
+    fs::write(
+        &path,
+        "client, name, region, tier, min_balance\n1, Alice, eu-west, gold, -1\n",
+    )
+    .unwrap();
+    assert!(matches!(
+        read_client_metadata(path),
+        Err(ReaderError::Parse(e)) if e == CsvParseError::InvalidLimit.to_string()
+    ));
+}
+
+#[test]
+fn line_parser_reuses_reader() {
+    let mut parser = LineParser::new(&ReaderConfig::default());
+    let lines = [
+        (
+            "type, client, tx, amount",
+            vec!["type", "client", "tx", "amount"],
+        ),
+        ("deposit, 1, 1, 1.0", vec!["deposit", "1", "1", "1.0"]),
+        ("\"with, comma\", 2", vec!["with, comma", "2"]),
+        ("dispute, 1, 1", vec!["dispute", "1", "1"]),
+    ];
+    for (line, expected) in lines {
+        let record = parser.parse(line).unwrap();
+        assert_eq!(record.iter().collect::<Vec<_>>(), expected);
+    }
+}
@@ -0,0 +1,259 @@
+#[cfg(test)]
+mod tests;
+
+use crate::client::ClientError;
+use crate::parser::CsvTransaction;
+use crate::transaction::Transaction;
+use csv::{StringRecord, Writer, WriterBuilder};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+const HEADERS: [&str; 7] = [
+    "type",
+    "client",
+    "tx",
+    "amount",
+    TO_HEADER,
+    DISPUTED_AMOUNT_HEADER,
+    "reason",
+];
+/// The header of the optional column that holds the destination client of a transfer.
+const TO_HEADER: &str = "to";
+/// The header of the optional column that holds the disputed part of a partial dispute.
+const DISPUTED_AMOUNT_HEADER: &str = "disputed_amount";
+
+#[derive(Error, Debug)]
+pub enum RejectsError {
+    #[error("An error was produced when writing a rejected transaction: `{0}`")]
+    Csv(csv::Error),
+    #[error("The rejects writer was poisoned")]
+    Poisoned,
+}
+
+impl From<csv::Error> for RejectsError {
+    fn from(e: csv::Error) -> Self {
+        RejectsError::Csv(e)
+    }
+}
+
+/// The reason that a transaction was read but not applied.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectReason {
+    /// The record could not be parsed in to a transaction.
+    Parse,
+    /// The transaction contained a negative amount.
+    NegativeAmount,
+    /// The client has insufficient funds.
+    InsufficientFunds,
+    /// The withdrawal would leave the client's available funds below the minimum balance.
+    BelowMinimumBalance,
+    /// The dispute would take the client's held funds above their limit.
+    HeldLimitExceeded,
+    /// The transaction would take the client's funds outside of the range that can be held.
+    BalanceOverflow,
+    /// The client's account is frozen.
+    Frozen,
+    /// The transaction duplicates one that has already been applied.
+    Duplicate,
+    /// The transaction referenced a transaction that does not exist.
+    NotFound,
+    /// The transaction was not for the client that executed it.
+    MismatchedClient,
+    /// The transaction is not valid for the state of the dispute that it references.
+    InvalidDispute,
+    /// The client has reached its limit of open disputes.
+    TooManyDisputes,
+    /// The dispute referenced a transaction that was executed longer ago than the dispute window.
+    DisputeWindowExpired,
+    /// The dispute would exceed the limit on the total funds under dispute across all clients.
+    SystemDisputeCap,
+    /// The transaction failed a custom validation rule.
+    Validation,
+    /// The transfer was executed against a single client, rather than between its two clients.
+    UncoordinatedTransfer,
+    /// The store failed while executing the transaction.
+    Store,
+}
+
+impl RejectReason {
+    /// Every reason, in the order that they are declared.
+    pub const ALL: [RejectReason; 17] = [
+        RejectReason::Parse,
+        RejectReason::NegativeAmount,
+        RejectReason::InsufficientFunds,
+        RejectReason::BelowMinimumBalance,
+        RejectReason::HeldLimitExceeded,
+        RejectReason::BalanceOverflow,
+        RejectReason::Frozen,
+        RejectReason::Duplicate,
+        RejectReason::NotFound,
+        RejectReason::MismatchedClient,
+        RejectReason::InvalidDispute,
+        RejectReason::TooManyDisputes,
+        RejectReason::DisputeWindowExpired,
+        RejectReason::SystemDisputeCap,
+        RejectReason::Validation,
+        RejectReason::UncoordinatedTransfer,
+        RejectReason::Store,
+    ];
+
+    /// Returns the name of this reason as it appears in a rejects file.
+    pub fn name(&self) -> &str {
+        match self {
+            RejectReason::Parse => "parse",
+            RejectReason::NegativeAmount => "negative_amount",
+            RejectReason::InsufficientFunds => "insufficient_funds",
+            RejectReason::BelowMinimumBalance => "below_minimum_balance",
+            RejectReason::HeldLimitExceeded => "held_limit_exceeded",
+            RejectReason::BalanceOverflow => "balance_overflow",
+            RejectReason::Frozen => "frozen",
+            RejectReason::Duplicate => "duplicate",
+            RejectReason::NotFound => "not_found",
+            RejectReason::MismatchedClient => "mismatched_client",
+            RejectReason::InvalidDispute => "invalid_dispute",
+            RejectReason::TooManyDisputes => "too_many_disputes",
+            RejectReason::DisputeWindowExpired => "dispute_window_expired",
+            RejectReason::SystemDisputeCap => "system_dispute_cap",
+            RejectReason::Validation => "validation",
+            RejectReason::UncoordinatedTransfer => "uncoordinated_transfer",
+            RejectReason::Store => "store",
+        }
+    }
+}
+
+impl From<&ClientError> for RejectReason {
+    fn from(e: &ClientError) -> Self {
+        match e {
+            ClientError::NegativeValue => RejectReason::NegativeAmount,
+            ClientError::InsufficientFunds => RejectReason::InsufficientFunds,
+            ClientError::BelowMinimumBalance => RejectReason::BelowMinimumBalance,
+            ClientError::HeldLimitExceeded => RejectReason::HeldLimitExceeded,
+            ClientError::BalanceOverflow => RejectReason::BalanceOverflow,
+            ClientError::MismatchedClientId | ClientError::ClientMismatchOnDispute => {
+                RejectReason::MismatchedClient
+            }
+            ClientError::AccountFrozen | ClientError::TransactionAfterFreeze => {
+                RejectReason::Frozen
+            }
+            ClientError::TransactionNotFound => RejectReason::NotFound,
+            ClientError::DuplicateTransaction => RejectReason::Duplicate,
+            ClientError::DisputeError(_) => RejectReason::InvalidDispute,
+            ClientError::TooManyDisputes => RejectReason::TooManyDisputes,
+            ClientError::DisputeWindowExpired => RejectReason::DisputeWindowExpired,
+            ClientError::SystemDisputeCapExceeded => RejectReason::SystemDisputeCap,
+            ClientError::ValidationFailed(_) => RejectReason::Validation,
+            ClientError::UncoordinatedTransfer => RejectReason::UncoordinatedTransfer,
+            ClientError::StoreError(_) => RejectReason::Store,
+        }
+    }
+}
+
+/// A row in a rejects file. The transaction's fields are held as they were read so that records
+/// which failed to parse can be reported verbatim.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rejection {
+    #[serde(rename = "type")]
+    pub tx_type: String,
+    pub client: String,
+    pub tx: String,
+    pub amount: String,
+    /// The destination client of a rejected transfer, so that it can be retried. It is empty for
+    /// every other transaction, and defaults to empty for rejects files written without it.
+    #[serde(default)]
+    pub to: String,
+    /// The disputed part of a rejected partial dispute. It is empty for every other transaction,
+    /// and defaults to empty for rejects files written without it.
+    #[serde(default)]
+    pub disputed_amount: String,
+    pub reason: RejectReason,
+}
+
+impl Rejection {
+    /// Constructs a rejection from a raw CSV `record`, read with `headers`, that was rejected for
+    /// `reason`. The optional `to` and `disputed_amount` columns are found by their headers, as
+    /// they may not be present.
+    pub fn from_record(
+        headers: &StringRecord,
+        record: &StringRecord,
+        reason: RejectReason,
+    ) -> Rejection {
+        let field = |i| record.get(i).unwrap_or_default().to_string();
+        let optional = |name| {
+            headers
+                .iter()
+                .position(|header| header == name)
+                .map(field)
+                .unwrap_or_default()
+        };
+
+        Rejection {
+            tx_type: field(0),
+            client: field(1),
+            tx: field(2),
+            amount: field(3),
+            to: optional(TO_HEADER),
+            disputed_amount: optional(DISPUTED_AMOUNT_HEADER),
+            reason,
+        }
+    }
+
+    /// Constructs a rejection from a `transaction` that was rejected for `reason`.
+    pub fn from_transaction(transaction: Transaction, reason: RejectReason) -> Rejection {
+        let CsvTransaction {
+            tx_type,
+            client,
+            tx,
+            amount,
+            to,
+            disputed_amount,
+        } = transaction.into();
+
+        Rejection {
+            tx_type: tx_type.name().to_string(),
+            client: client.to_string(),
+            tx: tx.to_string(),
+            amount: amount.map(|amount| amount.to_string()).unwrap_or_default(),
+            to: to.map(|to| to.to_string()).unwrap_or_default(),
+            disputed_amount: disputed_amount
+                .map(|amount| amount.to_string())
+                .unwrap_or_default(),
+            reason,
+        }
+    }
+}
+
+/// A CSV writer of rejected transactions that may be shared between the reader and IO tasks.
+#[derive(Debug, Clone)]
+pub struct RejectsWriter {
+    writer: Arc<Mutex<Writer<File>>>,
+}
+
+impl RejectsWriter {
+    /// Creates, or truncates, the rejects file at `path` and writes its headers.
+    pub fn create(path: PathBuf) -> Result<RejectsWriter, RejectsError> {
+        let mut writer = WriterBuilder::new().has_headers(false).from_path(path)?;
+        writer.write_record(HEADERS)?;
+
+        Ok(RejectsWriter {
+            writer: Arc::new(Mutex::new(writer)),
+        })
+    }
+
+    /// Writes `rejection` to the rejects file.
+    pub fn write(&self, rejection: Rejection) -> Result<(), RejectsError> {
+        let mut writer = self.writer.lock().map_err(|_| RejectsError::Poisoned)?;
+        writer.serialize(rejection).map_err(Into::into)
+    }
+
+    /// Flushes any buffered rejections to the rejects file.
+    pub fn flush(&self) -> Result<(), RejectsError> {
+        let mut writer = self.writer.lock().map_err(|_| RejectsError::Poisoned)?;
+        writer
+            .flush()
+            .map_err(|e| RejectsError::Csv(csv::Error::from(e)))
+    }
+}
@@ -0,0 +1,127 @@
+use crate::data::mem_store;
+use crate::io::{IoConfig, IoTask};
+use crate::parser::reader::{reader_task, ReaderConfig};
+use crate::parser::rejects::{RejectReason, Rejection, RejectsWriter};
+use futures::future::join;
+use std::fs;
+use tempdir::TempDir;
+use tokio::sync::mpsc;
+
+const CHANNEL_SIZE: usize = 8;
+
+fn rejection(
+    tx_type: &str,
+    client: &str,
+    tx: &str,
+    amount: &str,
+    reason: RejectReason,
+) -> Rejection {
+    Rejection {
+        tx_type: tx_type.to_string(),
+        client: client.to_string(),
+        tx: tx.to_string(),
+        amount: amount.to_string(),
+        to: String::new(),
+        disputed_amount: String::new(),
+        reason,
+    }
+}
+
+#[tokio::test]
+async fn rejects_file() {
+    let dir = TempDir::new("rejects").unwrap();
+    let input = dir.path().join("input.csv");
+    let rejects_path = dir.path().join("rejects.csv");
+    fs::write(
+        &input,
+        "type, client, tx, amount
+deposit, 1, 1, 10.0
+deposit, 1, 2
+withdrawal, 1, 3, 50.0
+dispute, 1, 4,
+dispute, 1, 1,
+dispute, 1, 1,
+chargeback, 1, 1,
+deposit, 1, 5, 1.0
+deposit, 2, 6, 1.0
+deposit, 2, 6, 2.0",
+    )
+    .unwrap();
+
+    let store = mem_store();
+    let rejects = RejectsWriter::create(rejects_path.clone()).unwrap();
+    let (tx, rx) = mpsc::channel(CHANNEL_SIZE);
+
+    let io_task = IoTask::new(
+        rx,
+        store.clone(),
+        IoConfig::default(),
+        Some(rejects.clone()),
+    )
+    .run(CHANNEL_SIZE);
+    let config = ReaderConfig {
+        skip_malformed: true,
+        ..Default::default()
+    };
+    let reader_task = reader_task(vec![input], config, tx, Some(rejects.clone()), None);
+    let (io_result, reader_result) = join(io_task, reader_task).await;
+    assert!(io_result.is_ok());
+    assert!(reader_result.is_ok());
+    rejects.flush().unwrap();
+
+    let rejections = csv::Reader::from_path(rejects_path)
+        .unwrap()
+        .into_deserialize::<Rejection>()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+
+    assert_eq!(
+        rejections,
+        vec![
+            rejection("deposit", "1", "2", "", RejectReason::Parse),
+            rejection(
+                "withdrawal",
+                "1",
+                "3",
+                "50",
+                RejectReason::InsufficientFunds
+            ),
+            rejection("dispute", "1", "4", "", RejectReason::NotFound),
+            rejection("dispute", "1", "1", "", RejectReason::InvalidDispute),
+            rejection("deposit", "1", "5", "1", RejectReason::Frozen),
+            rejection("deposit", "2", "6", "2", RejectReason::Duplicate),
+        ]
+    );
+    assert!(store.get_client_state(2).unwrap().is_some());
+}
+
+#[tokio::test]
+async fn fail_without_rejects() {
+    let dir = TempDir::new("rejects").unwrap();
+    let input = dir.path().join("input.csv");
+    fs::write(&input, "type, client, tx, amount\ndeposit, 1, 1\n").unwrap();
+
+    let (tx, _rx) = mpsc::channel(CHANNEL_SIZE);
+    let result = reader_task(vec![input.clone()], ReaderConfig::default(), tx, None, None).await;
+    assert!(result.is_err());
+
+    // A rejects file does not skip a record that cannot be parsed without `skip_malformed`.
+    let rejects = RejectsWriter::create(dir.path().join("rejects.csv")).unwrap();
+    let (tx, _rx) = mpsc::channel(CHANNEL_SIZE);
+    let result = reader_task(
+        vec![input],
+        ReaderConfig::default(),
+        tx,
+        Some(rejects),
+        None,
+    )
+    .await;
+    assert!(result.is_err());
+}
+
+#[test]
+fn reasons_in_order() {
+    for (index, reason) in RejectReason::ALL.iter().enumerate() {
+        assert_eq!(*reason as usize, index);
+    }
+}
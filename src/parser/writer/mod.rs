@@ -1,43 +1,399 @@
-use crate::client::{deserialize, ClientState, ClientStore, Keyspace};
-use crate::db::{MemStore, Poisoned, StoreError};
-use serde::Serialize;
+#[cfg(test)]
+mod tests;
+
+#[cfg(feature = "parquet")]
+mod parquet_file;
+
+use crate::client::{ClientStore, State};
+use crate::db::{StoreEngine, StoreError};
+use crate::scale::DECIMAL_PLACES;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
 use std::fmt::{Display, Formatter};
+use std::io::{self, Write};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// A UTF-8 byte order mark.
+const BOM: &[u8] = "\u{feff}".as_bytes();
+
+/// The order to write client states in.
+///
+/// States are collected in client order, so every other order requires all of the states to be
+/// buffered and sorted before any are written.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum SortBy {
+    /// Ascending client ID.
+    #[default]
+    Client,
+    /// Descending total funds.
+    Total,
+    /// Descending available funds.
+    Available,
+    /// Descending held funds.
+    Held,
+}
+
+impl FromStr for SortBy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "client" => Ok(SortBy::Client),
+            "total" => Ok(SortBy::Total),
+            "available" => Ok(SortBy::Available),
+            "held" => Ok(SortBy::Held),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The line ending to terminate each written line with.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum LineEnding {
+    /// `\n`.
+    #[default]
+    Lf,
+    /// `\r\n`.
+    Crlf,
+}
+
+impl LineEnding {
+    /// Returns the characters that terminate a line.
+    pub fn as_str(&self) -> &str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+}
+
+impl FromStr for LineEnding {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lf" => Ok(LineEnding::Lf),
+            "crlf" => Ok(LineEnding::Crlf),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Which group of client states to write first when grouping them by whether their account is
+/// frozen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatusGroup {
+    /// Active accounts, followed by frozen accounts.
+    ActiveFirst,
+    /// Frozen accounts, followed by active accounts.
+    FrozenFirst,
+}
+
+impl StatusGroup {
+    /// Returns the position of the group that `state` is written in.
+    fn group(&self, state: &State) -> u8 {
+        match self {
+            StatusGroup::ActiveFirst => u8::from(state.locked),
+            StatusGroup::FrozenFirst => u8::from(!state.locked),
+        }
+    }
+}
+
+impl FromStr for StatusGroup {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "active-first" => Ok(StatusGroup::ActiveFirst),
+            "frozen-first" => Ok(StatusGroup::FrozenFirst),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The format to write client states in.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// Comma-separated rows, following a header row.
+    #[default]
+    Csv,
+    /// A JSON array of client objects, including any metadata, followed by a line ending. The byte
+    /// order mark is ignored.
+    Json,
+    /// A Parquet file with typed columns. The line ending and byte order mark are ignored.
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+impl FromStr for OutputFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            #[cfg(feature = "parquet")]
+            "parquet" => Ok(OutputFormat::Parquet),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Configuration for writing client states.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct WriterConfig {
+    /// The format to write client states in.
+    pub format: OutputFormat,
+    /// The order to write client states in.
+    pub sort_by: SortBy,
+    /// The line ending to terminate each line with.
+    pub line_ending: LineEnding,
+    /// Whether to write a UTF-8 byte order mark before the headers.
+    pub bom: bool,
+    /// Whether to skip any client state record that cannot be deserialized and continue writing,
+    /// rather than failing.
+    pub skip_corrupt: bool,
+    /// Whether to skip any client that has not been loaded to execute transactions through the
+    /// store, such as a client whose state was only seeded.
+    pub skip_untouched: bool,
+    /// If set, then only the first `n` client states in the order specified by `sort_by` are
+    /// written.
+    pub top: Option<usize>,
+    /// If set, then client states are grouped by whether their account is frozen, and each group
+    /// is written in the order specified by `sort_by`.
+    pub group_by_status: Option<StatusGroup>,
+    /// Whether to write a blank line between the groups of CSV rows when grouping by status.
+    pub group_separator: bool,
+}
+
+#[derive(Error, Debug)]
+pub enum WriterError {
+    #[error("An error was produced when collecting client states: `{0}`")]
+    Store(StoreError),
+    #[error("An IO error was produced: `{0}`")]
+    Io(io::Error),
+    #[error("An error was produced when writing JSON: `{0}`")]
+    Json(serde_json::Error),
+    #[cfg(feature = "parquet")]
+    #[error("An error was produced when writing Parquet: `{0}`")]
+    Parquet(parquet::errors::ParquetError),
+}
+
+impl From<StoreError> for WriterError {
+    fn from(e: StoreError) -> Self {
+        WriterError::Store(e)
+    }
+}
+
+impl From<io::Error> for WriterError {
+    fn from(e: io::Error) -> Self {
+        WriterError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for WriterError {
+    fn from(e: serde_json::Error) -> Self {
+        WriterError::Json(e)
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl From<parquet::errors::ParquetError> for WriterError {
+    fn from(e: parquet::errors::ParquetError) -> Self {
+        WriterError::Parquet(e)
+    }
+}
+
+/// A summary of the client states that were written from a store.
+#[derive(Debug, Default, PartialEq)]
+pub struct WriteSummary {
+    /// The keys of any client state records that were skipped because they could not be
+    /// deserialized.
+    pub skipped: Vec<Vec<u8>>,
+}
+
+/// Collects every client state in `store` and writes it to `writer` in the order and format
+/// specified by `config`. If `config` skips corrupt records, then the keys of any that were
+/// skipped are returned in the summary. If `config` skips untouched clients, then only the clients
+/// that executed transactions through `store` are written. If `config` has a top `n`, then the
+/// states are selected as they are scanned, so that no more than `n + 1` are held at once.
+pub fn write_state<D, W>(
+    store: ClientStore<D>,
+    config: WriterConfig,
+    writer: &mut W,
+) -> Result<WriteSummary, WriterError>
+where
+    D: StoreEngine,
+    W: Write + Send,
+{
+    let mut skipped = Vec::new();
+    let mut top = config.top.map(|n| TopStates::new(n, config.sort_by));
+    let mut states = Vec::new();
+    for state in store.client_states(config.skip_corrupt, &mut skipped) {
+        let state = state?;
+        if config.skip_untouched && !store.is_touched(state.id()) {
+            continue;
+        }
+        match &mut top {
+            Some(top) => top.push(State::from(state)),
+            None => states.push(State::from(state)),
+        }
+    }
+    if let Some(top) = top {
+        states = top.into_states();
+    }
+    skipped.sort();
 
-/// Queries `store`'s `Clients` keyspace. Deserializing every client state record and printing it
-/// to the standard output.
-pub fn write_state(store: ClientStore<MemStore>) -> Result<(), StoreError> {
-    let inner = store.inner().delegate();
-    let read_lock = inner
-        .read()
-        .map_err(|_| StoreError::Read(Box::new(Poisoned)))?;
-    let transactions_space = read_lock
-        .get(Keyspace::Clients.name())
-        .ok_or(StoreError::KeyspaceNotFound)?;
+    write_states(states, config, writer)?;
+    Ok(WriteSummary { skipped })
+}
 
-    write_headers();
+/// Writes `states` to `writer` in the order and format specified by `config`.
+pub fn write_states<W>(
+    mut states: Vec<State>,
+    config: WriterConfig,
+    writer: &mut W,
+) -> Result<(), WriterError>
+where
+    W: Write + Send,
+{
+    if let Some(n) = config.top {
+        states = top_states(states, n, config.sort_by);
+    }
+    sort_states(&mut states, config.sort_by);
+    if let Some(group_by) = config.group_by_status {
+        // The sort is stable, so each group keeps the order that was sorted by.
+        states.sort_by_key(|state| group_by.group(state));
+    }
+
+    match config.format {
+        OutputFormat::Csv => {}
+        OutputFormat::Json => {
+            serde_json::to_writer(&mut *writer, &states)?;
+            write!(writer, "{}", config.line_ending.as_str())?;
+            return Ok(());
+        }
+        #[cfg(feature = "parquet")]
+        OutputFormat::Parquet => return Ok(parquet_file::write_states_to(states, writer)?),
+    }
 
-    for value in transactions_space.values() {
-        let state = deserialize::<ClientState>(value.as_ref())?;
-        let state = State::from(state);
-        println!("{}", state);
+    if config.bom {
+        writer.write_all(BOM)?;
+    }
+
+    let line_ending = config.line_ending.as_str();
+    write!(writer, "{}{}", HEADERS, line_ending)?;
+
+    let mut previous = None;
+    for state in states {
+        let group = config
+            .group_by_status
+            .map(|group_by| group_by.group(&state));
+        if config.group_separator && previous.is_some() && group != previous {
+            write!(writer, "{}", line_ending)?;
+        }
+        previous = group;
+        write!(writer, "{}{}", state, line_ending)?;
     }
 
     Ok(())
 }
 
-fn write_headers() {
-    println!("client,\tavailable,\theld,\ttotal,\tlocked");
+/// Sorts `states` by `sort_by`. Ties are broken by ascending client ID.
+fn sort_states(states: &mut [State], sort_by: SortBy) {
+    states.sort_by(|left, right| compare_states(left, right, sort_by));
+}
+
+/// Compares `left` and `right` by the order that they are written in when sorting by `sort_by`.
+fn compare_states(left: &State, right: &State, sort_by: SortBy) -> Ordering {
+    let key = |state: &State| match sort_by {
+        SortBy::Client => 0.0,
+        SortBy::Total => state.total,
+        SortBy::Available => state.available,
+        SortBy::Held => state.held,
+    };
+
+    key(right)
+        .partial_cmp(&key(left))
+        .unwrap_or(Ordering::Equal)
+        .then(left.client.cmp(&right.client))
+}
+
+/// Returns the first `n` of `states` in the order specified by `sort_by`, in no particular order.
+fn top_states<I>(states: I, n: usize, sort_by: SortBy) -> Vec<State>
+where
+    I: IntoIterator<Item = State>,
+{
+    let mut top = TopStates::new(n, sort_by);
+    for state in states {
+        top.push(state);
+    }
+    top.into_states()
+}
+
+/// The first `n` of the states that have been pushed, in the order specified by `sort_by`. The
+/// states are selected using a bounded heap, so no more than `n + 1` are held at once.
+struct TopStates {
+    heap: BinaryHeap<Ranked>,
+    n: usize,
+    sort_by: SortBy,
+}
+
+impl TopStates {
+    fn new(n: usize, sort_by: SortBy) -> Self {
+        TopStates {
+            heap: BinaryHeap::with_capacity(n + 1),
+            n,
+            sort_by,
+        }
+    }
+
+    fn push(&mut self, state: State) {
+        self.heap.push(Ranked {
+            state,
+            sort_by: self.sort_by,
+        });
+        if self.heap.len() > self.n {
+            // The greatest state is the one that would be written last.
+            let _last = self.heap.pop();
+        }
+    }
+
+    /// Returns the selected states, in no particular order.
+    fn into_states(self) -> Vec<State> {
+        self.heap.into_iter().map(|ranked| ranked.state).collect()
+    }
+}
+
+/// A state that is ordered by where it is written when sorting by `sort_by`.
+struct Ranked {
+    state: State,
+    sort_by: SortBy,
 }
 
-#[derive(Serialize, Debug)]
-struct State {
-    client: u16,
-    available: f64,
-    held: f64,
-    total: f64,
-    locked: bool,
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_states(&self.state, &other.state, self.sort_by)
+    }
+}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked {}
+
+const HEADERS: &str = "client,\tavailable,\theld,\ttotal,\tlocked";
+
 impl Display for State {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let State {
@@ -46,29 +402,13 @@ impl Display for State {
             held,
             total,
             locked,
+            ..
         } = self;
 
         write!(
             f,
-            "{}, {:.4}, {:.4}, {:.4}, {}",
-            client, available, held, total, locked
+            "{}, {:.*}, {:.*}, {:.*}, {}",
+            client, DECIMAL_PLACES, available, DECIMAL_PLACES, held, DECIMAL_PLACES, total, locked
         )
     }
 }
-
-impl From<ClientState> for State {
-    fn from(client: ClientState) -> Self {
-        let (id, balance, frozen) = client.split();
-        let available = balance.get_available();
-        let held = balance.get_frozen();
-        let total = balance.get_total();
-
-        State {
-            client: id,
-            available,
-            held,
-            total,
-            locked: frozen,
-        }
-    }
-}
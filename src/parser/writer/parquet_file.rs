@@ -0,0 +1,104 @@
+use crate::client::State;
+use parquet::data_type::{BoolType, DataType, DoubleType, Int32Type};
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::{SerializedFileWriter, SerializedRowGroupWriter};
+use parquet::schema::parser::parse_message_type;
+use std::io::Write;
+use std::sync::Arc;
+
+/// The number of states that are buffered in to each row group before it is written.
+const ROW_GROUP_SIZE: usize = 8192;
+
+const SCHEMA: &str = "
+message state {
+    REQUIRED INT32 client (INTEGER(16, false));
+    REQUIRED DOUBLE available;
+    REQUIRED DOUBLE held;
+    REQUIRED DOUBLE total;
+    REQUIRED BOOLEAN locked;
+}
+";
+
+/// The columns of a single row group.
+#[derive(Default)]
+struct Columns {
+    client: Vec<i32>,
+    available: Vec<f64>,
+    held: Vec<f64>,
+    total: Vec<f64>,
+    locked: Vec<bool>,
+}
+
+impl Columns {
+    fn push(&mut self, state: State) {
+        self.client.push(i32::from(state.client));
+        self.available.push(state.available);
+        self.held.push(state.held);
+        self.total.push(state.total);
+        self.locked.push(state.locked);
+    }
+
+    fn len(&self) -> usize {
+        self.client.len()
+    }
+
+    /// Writes these columns to `writer` as a row group and clears them.
+    fn write_to<W>(&mut self, writer: &mut SerializedFileWriter<W>) -> Result<(), ParquetError>
+    where
+        W: Write + Send,
+    {
+        let mut row_group = writer.next_row_group()?;
+        write_column::<Int32Type, _>(&mut row_group, &self.client)?;
+        write_column::<DoubleType, _>(&mut row_group, &self.available)?;
+        write_column::<DoubleType, _>(&mut row_group, &self.held)?;
+        write_column::<DoubleType, _>(&mut row_group, &self.total)?;
+        write_column::<BoolType, _>(&mut row_group, &self.locked)?;
+        row_group.close()?;
+
+        *self = Columns::default();
+        Ok(())
+    }
+}
+
+/// Writes the next column of `row_group`, which must have the physical type `T`.
+fn write_column<T, W>(
+    row_group: &mut SerializedRowGroupWriter<'_, W>,
+    values: &[T::T],
+) -> Result<(), ParquetError>
+where
+    T: DataType,
+    W: Write + Send,
+{
+    let mut column = row_group
+        .next_column()?
+        .ok_or_else(|| ParquetError::General("Missing a column in the schema".to_string()))?;
+    column.typed::<T>().write_batch(values, None, None)?;
+    column.close()
+}
+
+/// Writes `states` to `writer` as a Parquet file with typed columns. States are written in row
+/// groups of `ROW_GROUP_SIZE`, so only a single row group is held in memory at once.
+pub fn write_states_to<I, W>(states: I, writer: W) -> Result<(), ParquetError>
+where
+    I: IntoIterator<Item = State>,
+    W: Write + Send,
+{
+    let schema = Arc::new(parse_message_type(SCHEMA)?);
+    let properties = Arc::new(WriterProperties::builder().build());
+    let mut writer = SerializedFileWriter::new(writer, schema, properties)?;
+    let mut columns = Columns::default();
+
+    for state in states {
+        columns.push(state);
+        if columns.len() == ROW_GROUP_SIZE {
+            columns.write_to(&mut writer)?;
+        }
+    }
+    if columns.len() > 0 {
+        columns.write_to(&mut writer)?;
+    }
+
+    writer.close()?;
+    Ok(())
+}
@@ -0,0 +1,493 @@
+use crate::client::{
+    ClientError, ClientLimits, ClientMetadata, ClientState, ClientStore, Keyspace, State,
+};
+use crate::data::mem_store;
+#[cfg(feature = "rocks")]
+use crate::db::DiskStore;
+use crate::db::{MemStore, StoreEngine, StoreError};
+use crate::io::{IoConfig, IoTask};
+use crate::parser::reader::read_client_metadata;
+use crate::parser::writer::{
+    sort_states, write_state, write_states, LineEnding, OutputFormat, SortBy, StatusGroup,
+    WriteSummary, WriterConfig, WriterError,
+};
+use crate::scale::DECIMAL_PLACES;
+use crate::transaction::Transaction;
+use std::fs::File;
+use std::io::Write;
+use tempdir::TempDir;
+use tokio::sync::mpsc;
+
+#[test]
+fn metadata_survives_processing() {
+    let dir = TempDir::new("metadata").unwrap();
+    let path = dir.path().join("metadata.csv");
+    let mut file = File::create(&path).unwrap();
+    file.write_all(
+        b"client, name, region, tier, min_balance, max_held\n\
+          1, Alice, eu-west, gold, 5.0,\n",
+    )
+    .unwrap();
+
+    let store = mem_store();
+    for (id, metadata, limits) in read_client_metadata(path).unwrap() {
+        store.put_client_metadata(id, metadata, limits).unwrap();
+    }
+
+    let mut client = store.get_client_state(1).unwrap().unwrap();
+    let result = client.execute_transaction(Transaction::deposit(1, 1, 10.0), &store);
+    assert!(result.is_ok());
+    let result = client.execute_transaction(Transaction::withdrawal(1, 2, 2.5), &store);
+    assert!(result.is_ok());
+    let result = client.execute_transaction(Transaction::withdrawal(1, 3, 2.5001), &store);
+    assert_eq!(result, Err(ClientError::BelowMinimumBalance));
+
+    let state = State::from(store.get_client_state(1).unwrap().unwrap());
+    assert_eq!(state.available, 7.5);
+    assert_eq!(
+        state.metadata,
+        Some(ClientMetadata {
+            name: "Alice".to_string(),
+            region: "eu-west".to_string(),
+            tier: "gold".to_string(),
+        })
+    );
+
+    let json = serde_json::to_value(&state).unwrap();
+    assert_eq!(json["metadata"]["name"], "Alice");
+    assert_eq!(json["metadata"]["region"], "eu-west");
+    assert_eq!(json["metadata"]["tier"], "gold");
+}
+
+#[test]
+fn no_metadata() {
+    let store = mem_store();
+    let mut client = ClientState::new(2);
+    let result = client.execute_transaction(Transaction::deposit(2, 1, 10.0), &store);
+    assert!(result.is_ok());
+
+    let state = State::from(store.get_client_state(2).unwrap().unwrap());
+    let json = serde_json::to_value(&state).unwrap();
+    assert!(json.get("metadata").is_none());
+}
+
+#[test]
+fn display_precision() {
+    let state = State {
+        client: 1,
+        available: 1.123456789,
+        held: 0.5,
+        total: 1.623456789,
+        locked: false,
+        metadata: None,
+    };
+
+    #[cfg(not(feature = "scale-8"))]
+    assert_eq!(state.to_string(), "1, 1.1235, 0.5000, 1.6235, false");
+    #[cfg(feature = "scale-8")]
+    assert_eq!(
+        state.to_string(),
+        "1, 1.12345679, 0.50000000, 1.62345679, false"
+    );
+}
+
+fn state(client: u16, available: f64, held: f64) -> State {
+    State {
+        client,
+        available,
+        held,
+        total: available + held,
+        locked: false,
+        metadata: None,
+    }
+}
+
+fn sorted_clients(sort_by: SortBy) -> Vec<u16> {
+    let mut states = vec![
+        state(1, 10.0, 0.0),
+        state(2, 5.0, 20.0),
+        state(3, 30.0, 5.0),
+        state(4, 25.0, 0.0),
+        state(5, 0.0, 5.0),
+    ];
+    sort_states(&mut states, sort_by);
+    states.iter().map(|state| state.client).collect()
+}
+
+#[test]
+fn sort_by() {
+    assert_eq!(sorted_clients(SortBy::Client), vec![1, 2, 3, 4, 5]);
+    assert_eq!(sorted_clients(SortBy::Total), vec![3, 2, 4, 1, 5]);
+    assert_eq!(sorted_clients(SortBy::Available), vec![3, 4, 1, 2, 5]);
+    assert_eq!(sorted_clients(SortBy::Held), vec![2, 3, 5, 1, 4]);
+}
+
+fn grouped_rows(config: WriterConfig) -> Vec<String> {
+    let frozen = |client, available| State {
+        locked: true,
+        ..state(client, available, 0.0)
+    };
+    let states = vec![
+        frozen(4, 1.0),
+        state(3, 2.0, 0.0),
+        frozen(1, 3.0),
+        state(5, 4.0, 0.0),
+        state(2, 5.0, 0.0),
+    ];
+
+    let mut bytes = Vec::new();
+    write_states(states, config, &mut bytes).unwrap();
+    String::from_utf8(bytes)
+        .unwrap()
+        .lines()
+        .skip(1)
+        .map(|line| line.split(',').next().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn group_by_status() {
+    let config = WriterConfig {
+        group_by_status: Some(StatusGroup::ActiveFirst),
+        ..Default::default()
+    };
+    assert_eq!(grouped_rows(config), vec!["2", "3", "5", "1", "4"]);
+
+    let config = WriterConfig {
+        group_by_status: Some(StatusGroup::FrozenFirst),
+        group_separator: true,
+        ..Default::default()
+    };
+    assert_eq!(grouped_rows(config), vec!["1", "4", "", "2", "3", "5"]);
+
+    let config = WriterConfig {
+        group_by_status: Some(StatusGroup::ActiveFirst),
+        sort_by: SortBy::Available,
+        ..Default::default()
+    };
+    assert_eq!(grouped_rows(config), vec!["2", "5", "3", "1", "4"]);
+
+    let config = WriterConfig {
+        group_separator: true,
+        ..Default::default()
+    };
+    assert_eq!(grouped_rows(config), vec!["1", "2", "3", "4", "5"]);
+}
+
+#[test]
+fn missing_keyspaces() {
+    let store = ClientStore::new(MemStore::default());
+
+    assert_eq!(store.collect_states(), Ok(Vec::new()));
+    let mut bytes = Vec::new();
+    assert!(write_state(store, WriterConfig::default(), &mut bytes).is_ok());
+    assert_eq!(bytes, b"client,\tavailable,\theld,\ttotal,\tlocked\n");
+}
+
+fn written(config: WriterConfig) -> Vec<u8> {
+    let store = mem_store();
+    let mut client = ClientState::new(1);
+    let result = client.execute_transaction(Transaction::deposit(1, 1, 1.5), &store);
+    assert!(result.is_ok());
+
+    let mut bytes = Vec::new();
+    write_state(store, config, &mut bytes).unwrap();
+    bytes
+}
+
+#[test]
+fn line_endings() {
+    #[cfg(not(feature = "scale-8"))]
+    let row = "1, 1.5000, 0.0000, 1.5000, false";
+    #[cfg(feature = "scale-8")]
+    let row = "1, 1.50000000, 0.00000000, 1.50000000, false";
+    let headers = "client,\tavailable,\theld,\ttotal,\tlocked";
+
+    let lf = written(WriterConfig::default());
+    assert_eq!(lf, format!("{}\n{}\n", headers, row).into_bytes());
+
+    let crlf = written(WriterConfig {
+        line_ending: LineEnding::Crlf,
+        ..Default::default()
+    });
+    assert_eq!(crlf, format!("{}\r\n{}\r\n", headers, row).into_bytes());
+}
+
+#[test]
+fn bom() {
+    let bytes = written(WriterConfig {
+        bom: true,
+        ..Default::default()
+    });
+    assert_eq!(&bytes[..3], &[0xEF, 0xBB, 0xBF]);
+    assert!(bytes[3..].starts_with(b"client,"));
+
+    let bytes = written(WriterConfig::default());
+    assert!(bytes.starts_with(b"client,"));
+}
+
+#[test]
+fn json() {
+    let bytes = written(WriterConfig {
+        format: OutputFormat::Json,
+        bom: true,
+        ..Default::default()
+    });
+    assert_eq!(
+        String::from_utf8(bytes).unwrap(),
+        "[{\"client\":1,\"available\":1.5,\"held\":0.0,\"total\":1.5,\"locked\":false}]\n"
+    );
+}
+
+#[tokio::test]
+async fn json_metadata() {
+    let config = WriterConfig {
+        format: OutputFormat::Json,
+        sort_by: SortBy::Total,
+        ..Default::default()
+    };
+    let mut bytes = Vec::new();
+    write_state(seeded_store().await, config, &mut bytes).unwrap();
+
+    let json: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    let states = json.as_array().unwrap();
+    assert_eq!(states.len(), 2);
+    assert_eq!(states[0]["client"], 1);
+    assert_eq!(states[0]["metadata"]["name"], "client-1");
+    assert_eq!(states[1]["client"], 2);
+}
+
+#[test]
+fn skip_corrupt_records() {
+    let store = mem_store();
+    for client_id in 1..=3 {
+        let mut client = ClientState::new(client_id);
+        let result = client.execute_transaction(Transaction::deposit(client_id, 1, 1.5), &store);
+        assert!(result.is_ok());
+    }
+    let key = bincode::serialize(&2u16).unwrap();
+    store
+        .inner()
+        .put(Keyspace::Clients, &key, &[1, 2, 3])
+        .unwrap();
+
+    let mut bytes = Vec::new();
+    let result = write_state(store.clone(), WriterConfig::default(), &mut bytes);
+    assert!(matches!(
+        result,
+        Err(WriterError::Store(StoreError::TruncatedRecord { .. }))
+    ));
+
+    let config = WriterConfig {
+        skip_corrupt: true,
+        ..Default::default()
+    };
+    let mut bytes = Vec::new();
+    let summary = write_state(store.clone(), config, &mut bytes).unwrap();
+    assert_eq!(
+        summary,
+        WriteSummary {
+            skipped: vec![key.clone()]
+        }
+    );
+
+    // The corrupt record is also skipped when the states are selected as they are scanned.
+    let top = WriterConfig {
+        top: Some(1),
+        ..config
+    };
+    let mut top_bytes = Vec::new();
+    let summary = write_state(store, top, &mut top_bytes).unwrap();
+    assert_eq!(summary, WriteSummary { skipped: vec![key] });
+    let row = format!(
+        "1, {:.*}, {:.*}, {:.*}, false",
+        DECIMAL_PLACES, 1.5, DECIMAL_PLACES, 0.0, DECIMAL_PLACES, 1.5
+    );
+    assert_eq!(
+        String::from_utf8(top_bytes).unwrap().lines().nth(1),
+        Some(row.as_str())
+    );
+
+    let output = String::from_utf8(bytes).unwrap();
+    let clients = output
+        .lines()
+        .skip(1)
+        .map(|line| line.split(',').next().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(clients, vec!["1", "3"]);
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn parquet() {
+    use crate::parser::writer::write_states;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::RowAccessor;
+
+    let states = vec![
+        State {
+            client: 2,
+            available: 1.5,
+            held: 0.25,
+            total: 1.75,
+            locked: true,
+            metadata: None,
+        },
+        State {
+            client: u16::MAX,
+            available: -3.0,
+            held: 0.0,
+            total: -3.0,
+            locked: false,
+            metadata: None,
+        },
+    ];
+    let config = WriterConfig {
+        format: OutputFormat::Parquet,
+        ..Default::default()
+    };
+
+    let dir = TempDir::new("parquet").unwrap();
+    let path = dir.path().join("states.parquet");
+    let mut file = File::create(&path).unwrap();
+    write_states(states.clone(), config, &mut file).unwrap();
+
+    let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+    let rows = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| {
+            let row = row.unwrap();
+            State {
+                client: row.get_ushort(0).unwrap(),
+                available: row.get_double(1).unwrap(),
+                held: row.get_double(2).unwrap(),
+                total: row.get_double(3).unwrap(),
+                locked: row.get_bool(4).unwrap(),
+                metadata: None,
+            }
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(rows, states);
+}
+
+/// Attaches metadata to clients 1 and 2, then executes a deposit for client 1 through an IO task.
+async fn seeded_store() -> ClientStore<MemStore> {
+    let store = mem_store();
+    for client_id in 1..=2 {
+        let metadata = ClientMetadata {
+            name: format!("client-{}", client_id),
+            region: "eu-west".to_string(),
+            tier: "gold".to_string(),
+        };
+        store
+            .put_client_metadata(client_id, metadata, ClientLimits::default())
+            .unwrap();
+    }
+
+    let (tx, rx) = mpsc::channel(1);
+    tx.send(Transaction::deposit(1, 1, 10.0)).await.unwrap();
+    drop(tx);
+    let result = IoTask::new(rx, store.clone(), IoConfig::default(), None)
+        .run(1)
+        .await;
+    assert!(result.is_ok());
+    store
+}
+
+fn written_clients<D>(store: ClientStore<D>, config: WriterConfig) -> Vec<String>
+where
+    D: StoreEngine,
+{
+    let mut bytes = Vec::new();
+    write_state(store, config, &mut bytes).unwrap();
+    String::from_utf8(bytes)
+        .unwrap()
+        .lines()
+        .skip(1)
+        .map(|line| line.split(',').next().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn untouched_clients() {
+    let store = seeded_store().await;
+    assert!(store.is_touched(1));
+    assert!(!store.is_touched(2));
+
+    assert_eq!(
+        written_clients(store.clone(), WriterConfig::default()),
+        vec!["1", "2"]
+    );
+
+    let config = WriterConfig {
+        skip_untouched: true,
+        ..Default::default()
+    };
+    assert_eq!(written_clients(store, config), vec!["1"]);
+}
+
+#[test]
+fn top() {
+    let store = mem_store();
+    for client_id in 1..=100u16 {
+        // Totals rise to a peak at client 50 and then fall, so the largest are not contiguous.
+        let amount = f64::from(50 - (i32::from(client_id) - 50).abs());
+        let mut client = ClientState::new(client_id);
+        let result = client.execute_transaction(
+            Transaction::deposit(client_id, u32::from(client_id), amount + 1.0),
+            &store,
+        );
+        assert!(result.is_ok());
+    }
+
+    let config = WriterConfig {
+        sort_by: SortBy::Total,
+        top: Some(3),
+        ..Default::default()
+    };
+    assert_eq!(
+        written_clients(store.clone(), config),
+        vec!["50", "49", "51"]
+    );
+
+    let config = WriterConfig {
+        top: Some(3),
+        ..Default::default()
+    };
+    assert_eq!(written_clients(store, config), vec!["1", "2", "3"]);
+}
+
+#[test]
+fn shuffled_clients() {
+    let store = mem_store();
+    // 37 is coprime with 101, so every client from 1 to 100 is inserted once, out of order.
+    let clients = (1..=100u16).map(|n| n * 37 % 101).collect::<Vec<_>>();
+    for &client_id in &clients {
+        let mut client = ClientState::new(client_id);
+        let deposit = Transaction::deposit(client_id, u32::from(client_id), 1.0);
+        assert!(client.execute_transaction(deposit, &store).is_ok());
+    }
+
+    let expected = (1..=100u16)
+        .map(|client_id| client_id.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(written_clients(store, WriterConfig::default()), expected);
+}
+
+#[cfg(feature = "rocks")]
+#[test]
+fn disk_store() {
+    let dir = TempDir::new("writer_disk_store").unwrap();
+    let store = ClientStore::new(DiskStore::new(dir.path()).unwrap());
+    for client_id in 1..=3u16 {
+        let mut client = ClientState::new(client_id);
+        let deposit = Transaction::deposit(client_id, u32::from(client_id), 1.0);
+        assert!(client.execute_transaction(deposit, &store).is_ok());
+    }
+
+    assert_eq!(
+        written_clients(store, WriterConfig::default()),
+        vec!["1", "2", "3"]
+    );
+}
@@ -0,0 +1,89 @@
+#[cfg(test)]
+mod tests;
+
+// Retrying is only offered against a persistent store.
+#[cfg(any(feature = "rocks", test))]
+mod retry;
+#[cfg(any(feature = "rocks", test))]
+pub use retry::{retry_rejects, RetrySummary};
+// Replaying is only offered from a persistent store.
+#[cfg(any(feature = "rocks", test))]
+mod replay;
+
+use crate::client::{ClientState, Policy};
+use crate::data::mem_store_with_policy;
+use crate::db::StoreError;
+use crate::io::{IoConfig, IoError, IoTask};
+use crate::parser::reader::ReaderError;
+use crate::transaction::Transaction;
+use futures::future::join;
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+const CHANNEL_SIZE: usize = 256;
+
+/// Runs transactions through the engine without reading them from a CSV file, such as to embed the
+/// engine or to test a sequence of transactions. It is also used to replay and retry transactions
+/// against a persistent store.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Processor {
+    /// The policies to execute transactions with.
+    policy: Policy,
+    /// Configuration for the IO task.
+    config: IoConfig,
+}
+
+impl Processor {
+    /// Constructs a new processor that will execute transactions using `policy` in an IO task
+    /// configured by `config`.
+    pub fn new(policy: Policy, config: IoConfig) -> Processor {
+        Processor { policy, config }
+    }
+
+    /// Executes `transactions`, in order, against a new in-memory store. Returning the final
+    /// state of every client, ordered by client ID.
+    pub async fn run_transactions(
+        &self,
+        transactions: Vec<Transaction>,
+    ) -> Result<Vec<ClientState>, IoError> {
+        let store = mem_store_with_policy(self.policy);
+        let (tx, rx) = mpsc::channel(CHANNEL_SIZE);
+
+        let feed = async move {
+            for transaction in transactions {
+                if tx.send(transaction).await.is_err() {
+                    // The IO task has stopped and its error is returned below.
+                    break;
+                }
+            }
+        };
+        let io_task = IoTask::new(rx, store.clone(), self.config, None).run(CHANNEL_SIZE);
+
+        let (result, _) = join(io_task, feed).await;
+        result?;
+
+        Ok(store.collect_client_states()?)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum RetryError {
+    #[error("An error was produced by the reader task: `{0}`")]
+    Reader(ReaderError),
+    #[error("An error was produced by the IO task: `{0}`")]
+    Io(IoError),
+}
+
+#[derive(Error, Debug)]
+pub enum ReplayError {
+    #[error("An error was produced when reading the stored transactions: `{0}`")]
+    Store(StoreError),
+    #[error("An error was produced by the IO task: `{0}`")]
+    Io(IoError),
+}
+
+impl From<StoreError> for ReplayError {
+    fn from(e: StoreError) -> Self {
+        ReplayError::Store(e)
+    }
+}
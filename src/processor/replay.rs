@@ -0,0 +1,182 @@
+use crate::client::{ClientState, ClientStore};
+use crate::data::mem_store_with_policy;
+use crate::db::StoreEngine;
+use crate::io::IoTask;
+use crate::processor::{Processor, ReplayError, CHANNEL_SIZE};
+use crate::transaction::{
+    DisputeStatus, DisputedTransaction, DisputedTransactionKind, Transaction, TransferTransaction,
+    TransferTransactionKind,
+};
+use futures::future::join;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::time::SystemTime;
+use tokio::sync::mpsc;
+
+/// The number of stored transactions that are read from the store at once.
+const REPLAY_BATCH_SIZE: usize = 256;
+
+/// A transition of a stored transaction's dispute status that is waiting to be replayed, once
+/// every transfer that was executed before it has been.
+#[derive(Debug)]
+struct PendingEvent {
+    recorded_at: SystemTime,
+    tx: u32,
+    sequence: u64,
+    transaction: Transaction,
+}
+
+impl PendingEvent {
+    fn key(&self) -> (SystemTime, u32, u64) {
+        (self.recorded_at, self.tx, self.sequence)
+    }
+}
+
+impl PartialEq for PendingEvent {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for PendingEvent {}
+
+impl PartialOrd for PendingEvent {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PendingEvent {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+/// Returns the dispute, resolve or chargeback that moved the dispute status of `transfer` to `to`.
+/// A dispute is of the part of `transfer` that was last disputed, as the dispute log does not
+/// record the amount of each dispute.
+fn event_transaction(transfer: &TransferTransaction, to: DisputeStatus) -> Transaction {
+    let (kind, amount) = match to {
+        DisputeStatus::Disputed => (DisputedTransactionKind::Dispute, transfer.disputed_amount),
+        DisputeStatus::NotDisputed => (DisputedTransactionKind::Resolve, None),
+        DisputeStatus::ChargedBack => (DisputedTransactionKind::Chargeback, None),
+    };
+    Transaction::Disputed(DisputedTransaction {
+        kind,
+        client: transfer.client,
+        tx: transfer.tx,
+        amount,
+    })
+}
+
+/// Returns `transfer` as it was before it was executed, without its dispute status or time.
+fn unexecuted(transfer: &TransferTransaction) -> Transaction {
+    let TransferTransaction {
+        kind,
+        client,
+        tx,
+        amount,
+        ..
+    } = *transfer;
+    match kind {
+        TransferTransactionKind::Deposit => Transaction::deposit(client, tx, amount),
+        TransferTransactionKind::Withdrawal => Transaction::withdrawal(client, tx, amount),
+    }
+}
+
+/// Reads the history of the transactions stored in `source` and sends it over `sender`, one
+/// client at a time. Sending stops early if the receiver is dropped.
+///
+/// A client's deposits and withdrawals are sent in transaction ID order. Each transition in the
+/// dispute log of a transfer is sent as the dispute, resolve or chargeback that made it, before
+/// the first later transfer that was executed after it was recorded. The transfers are read in
+/// batches of `REPLAY_BATCH_SIZE`, so only the stored IDs, a batch and the pending transitions of
+/// the current client are held in memory.
+async fn send_history<D>(
+    source: &ClientStore<D>,
+    sender: mpsc::Sender<Transaction>,
+) -> Result<(), ReplayError>
+where
+    D: StoreEngine,
+{
+    let mut pending = BinaryHeap::new();
+    let mut current = None;
+
+    for ids in source.transaction_ids()?.chunks(REPLAY_BATCH_SIZE) {
+        for (&(client, tx), transaction) in ids.iter().zip(source.get_transactions(ids)?) {
+            if current != Some(client) {
+                current = Some(client);
+                while let Some(Reverse(PendingEvent { transaction, .. })) = pending.pop() {
+                    if sender.send(transaction).await.is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+
+            let transfer = match transaction {
+                Some(Transaction::Transfer(transfer)) => transfer,
+                _ => continue,
+            };
+            while let Some(Reverse(event)) = pending.peek() {
+                if transfer
+                    .executed_at
+                    .is_none_or(|executed_at| event.recorded_at > executed_at)
+                {
+                    break;
+                }
+                if let Some(Reverse(PendingEvent { transaction, .. })) = pending.pop() {
+                    if sender.send(transaction).await.is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+            if sender.send(unexecuted(&transfer)).await.is_err() {
+                return Ok(());
+            }
+            for event in source.get_dispute_log(client, tx)? {
+                pending.push(Reverse(PendingEvent {
+                    recorded_at: event.recorded_at,
+                    tx,
+                    sequence: event.sequence,
+                    transaction: event_transaction(&transfer, event.to),
+                }));
+            }
+        }
+    }
+
+    while let Some(Reverse(PendingEvent { transaction, .. })) = pending.pop() {
+        if sender.send(transaction).await.is_err() {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+impl Processor {
+    /// Replays the history of the transactions stored in `source` against a new in-memory store,
+    /// using this processor's policies. Returning the final state of every client, ordered by
+    /// client ID. `source` is only read, so the counterfactual balances can be compared against
+    /// the stored ones.
+    ///
+    /// Only the deposits and withdrawals that were stored, and the transitions in their dispute
+    /// logs, are replayed. A transaction that was rejected when the store was written is not
+    /// stored, so it is not replayed, even if it would have been applied under these policies.
+    pub async fn replay_store<D>(
+        &self,
+        source: &ClientStore<D>,
+    ) -> Result<Vec<ClientState>, ReplayError>
+    where
+        D: StoreEngine,
+    {
+        let store = mem_store_with_policy(self.policy);
+        let (tx, rx) = mpsc::channel(CHANNEL_SIZE);
+
+        let io_task = IoTask::new(rx, store.clone(), self.config, None).run(CHANNEL_SIZE);
+        let (result, sent) = join(io_task, send_history(source, tx)).await;
+        // If the IO task stopped early, then its error is why the history was not fully sent.
+        result.map_err(ReplayError::Io)?;
+        sent?;
+
+        Ok(store.collect_client_states()?)
+    }
+}
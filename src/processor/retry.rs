@@ -0,0 +1,57 @@
+use crate::client::ClientStore;
+use crate::db::StoreEngine;
+use crate::io::{IoConfig, IoTask, Tally};
+use crate::parser::reader::{reader_task, ReaderConfig};
+use crate::parser::rejects::RejectsWriter;
+use crate::processor::{RetryError, CHANNEL_SIZE};
+use futures::future::try_join;
+use futures::TryFutureExt;
+use std::path::PathBuf;
+use tokio::sync::mpsc;
+
+/// The outcome of retrying the transactions in a rejects file.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct RetrySummary {
+    /// The number of transactions that were applied.
+    pub applied: usize,
+    /// The number of records that were rejected again, including any that still could not be
+    /// parsed.
+    pub rejected: usize,
+}
+
+/// Re-applies the transactions in the rejects file at `path`, which was written by a prior run,
+/// against `store` using an IO task configured by `config`. The reason column of the file is
+/// ignored. Every record that is rejected again is written to `rejects`, if it is provided, so that
+/// it may be retried once more. Without `rejects`, a record that cannot be parsed fails the retry.
+pub async fn retry_rejects<D>(
+    path: PathBuf,
+    store: ClientStore<D>,
+    config: IoConfig,
+    rejects: Option<RejectsWriter>,
+) -> Result<RetrySummary, RetryError>
+where
+    D: StoreEngine + 'static,
+{
+    let (tx, rx) = mpsc::channel(CHANNEL_SIZE);
+    let tally = Tally::default();
+
+    let io_task = IoTask::new(rx, store, config, rejects.clone())
+        .with_tally(tally.clone())
+        .run(CHANNEL_SIZE)
+        .map_err(RetryError::Io);
+    // A record that still cannot be parsed is written to the rejects again, rather than failing.
+    let reader_config = ReaderConfig {
+        skip_malformed: rejects.is_some(),
+        ..Default::default()
+    };
+    let reader_task =
+        reader_task(vec![path], reader_config, tx, rejects, None).map_err(RetryError::Reader);
+
+    let (_, read_summary) = try_join(io_task, reader_task).await?;
+    let outcomes = tally.outcomes();
+
+    Ok(RetrySummary {
+        applied: outcomes.applied.total(),
+        rejected: outcomes.rejected.total() + read_summary.unparsed,
+    })
+}
@@ -0,0 +1,393 @@
+use crate::client::{
+    ClientError, ClientState, ClientStore, Policy, State, WithdrawalDisputePolicy,
+};
+use crate::clock::MockClock;
+use crate::data::{mem_store, mem_store_with_policy};
+use crate::db::MemStore;
+use crate::io::{IoConfig, IoError};
+use crate::parser::rejects::{RejectReason, Rejection, RejectsWriter};
+use crate::processor::{retry_rejects, Processor, RetrySummary};
+use crate::transaction::Transaction;
+use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
+use tempdir::TempDir;
+
+fn balances(states: Vec<ClientState>) -> Vec<(u16, f64, f64, bool)> {
+    states
+        .into_iter()
+        .map(State::from)
+        .map(|state| (state.client, state.available, state.held, state.locked))
+        .collect()
+}
+
+#[tokio::test]
+async fn run_transactions() {
+    let transactions = vec![
+        Transaction::deposit(2, 1, 10.0),
+        Transaction::deposit(1, 2, 20.0),
+        Transaction::deposit(1, 3, 5.0),
+        Transaction::dispute(1, 2),
+        Transaction::dispute(2, 1),
+        Transaction::chargeback(2, 1),
+        // Rejected, as only five of client one's funds remain available.
+        Transaction::withdrawal(1, 4, 10.0),
+    ];
+
+    let states = Processor::default()
+        .run_transactions(transactions)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        balances(states),
+        vec![(1, 5.0, 20.0, false), (2, 0.0, 0.0, true)]
+    );
+}
+
+#[tokio::test]
+async fn run_transactions_fatal() {
+    let policy = Policy {
+        strict_freeze: true,
+        ..Default::default()
+    };
+    let transactions = vec![
+        Transaction::deposit(1, 1, 10.0),
+        Transaction::dispute(1, 1),
+        Transaction::chargeback(1, 1),
+        Transaction::deposit(1, 2, 10.0),
+    ];
+
+    let result = Processor::new(policy, IoConfig::default())
+        .run_transactions(transactions)
+        .await;
+    assert!(matches!(
+        result,
+        Err(IoError::Client(ClientError::TransactionAfterFreeze))
+    ));
+}
+
+#[tokio::test]
+async fn replay_freeze_on_chargeback() {
+    let history = vec![
+        Transaction::deposit(1, 1, 10.0),
+        Transaction::deposit(1, 2, 5.0),
+        Transaction::dispute(1, 1),
+        Transaction::chargeback(1, 1),
+        Transaction::deposit(1, 3, 20.0),
+        Transaction::deposit(2, 4, 5.0),
+    ];
+
+    let frozen = Processor::new(Policy::default(), IoConfig::default())
+        .run_transactions(history.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        balances(frozen),
+        vec![(1, 5.0, 0.0, true), (2, 5.0, 0.0, false)]
+    );
+
+    let policy = Policy {
+        freeze_on_chargeback: false,
+        ..Default::default()
+    };
+    let not_frozen = Processor::new(policy, IoConfig::default())
+        .run_transactions(history)
+        .await
+        .unwrap();
+    assert_eq!(
+        balances(not_frozen),
+        vec![(1, 25.0, 0.0, false), (2, 5.0, 0.0, false)]
+    );
+}
+
+/// Replays a dispute and chargeback of a withdrawal under `withdrawal_dispute`.
+async fn replay_withdrawal_dispute(
+    withdrawal_dispute: WithdrawalDisputePolicy,
+) -> Vec<(u16, f64, f64, bool)> {
+    let policy = Policy {
+        withdrawal_dispute,
+        ..Default::default()
+    };
+    let history = vec![
+        Transaction::deposit(1, 1, 10.0),
+        Transaction::withdrawal(1, 2, 4.0),
+        Transaction::dispute(1, 2),
+        Transaction::chargeback(1, 2),
+    ];
+    let states = Processor::new(policy, IoConfig::default())
+        .run_transactions(history)
+        .await
+        .unwrap();
+    balances(states)
+}
+
+#[tokio::test]
+async fn replay_withdrawal_disputes() {
+    // The dispute and chargeback are rejected, so the withdrawal stands.
+    assert_eq!(
+        replay_withdrawal_dispute(WithdrawalDisputePolicy::Reject).await,
+        vec![(1, 6.0, 0.0, false)]
+    );
+    // Otherwise, the chargeback returns the withdrawn funds to the client.
+    assert_eq!(
+        replay_withdrawal_dispute(WithdrawalDisputePolicy::Hold).await,
+        vec![(1, 10.0, 0.0, true)]
+    );
+    assert_eq!(
+        replay_withdrawal_dispute(WithdrawalDisputePolicy::CreditToAvailable).await,
+        vec![(1, 10.0, 0.0, true)]
+    );
+}
+
+/// Applies `history` to a new memory store that uses `policy`, advancing its clock by a second
+/// after each transaction.
+fn stored_history(policy: Policy, history: Vec<Transaction>) -> ClientStore<MemStore> {
+    let clock = MockClock::new();
+    let mut store = mem_store_with_policy(policy);
+    store.set_clock(Arc::new(clock.clone()));
+    for transaction in history {
+        let _ = store.apply_transaction(transaction);
+        clock.advance(Duration::from_secs(1));
+    }
+    store
+}
+
+#[tokio::test]
+async fn replay_store_freeze_on_chargeback() {
+    let not_frozen = Policy {
+        freeze_on_chargeback: false,
+        ..Default::default()
+    };
+    let source = stored_history(
+        not_frozen,
+        vec![
+            Transaction::deposit(1, 1, 10.0),
+            Transaction::deposit(1, 2, 5.0),
+            Transaction::dispute(1, 1),
+            Transaction::chargeback(1, 1),
+            Transaction::deposit(1, 3, 20.0),
+            Transaction::deposit(2, 4, 5.0),
+        ],
+    );
+
+    let states = Processor::new(Policy::default(), IoConfig::default())
+        .replay_store(&source)
+        .await
+        .unwrap();
+    assert_eq!(
+        balances(states),
+        vec![(1, 5.0, 0.0, true), (2, 5.0, 0.0, false)]
+    );
+
+    let states = Processor::new(not_frozen, IoConfig::default())
+        .replay_store(&source)
+        .await
+        .unwrap();
+    assert_eq!(
+        balances(states),
+        vec![(1, 25.0, 0.0, false), (2, 5.0, 0.0, false)]
+    );
+
+    // The source store is left untouched.
+    assert_eq!(
+        balances(source.collect_client_states().unwrap()),
+        vec![(1, 25.0, 0.0, false), (2, 5.0, 0.0, false)]
+    );
+}
+
+#[tokio::test]
+async fn replay_store_interleaves_disputes() {
+    // The withdrawal is only applied because the dispute of the first deposit was resolved before
+    // it, so the disputes must be replayed between the transfers.
+    let history = vec![
+        Transaction::deposit(1, 1, 10.0),
+        Transaction::partial_dispute(1, 1, 4.0),
+        Transaction::deposit(1, 2, 5.0),
+        Transaction::resolve(1, 1),
+        Transaction::withdrawal(1, 3, 15.0),
+        Transaction::dispute(1, 2),
+    ];
+    let source = stored_history(Policy::default(), history);
+
+    let states = Processor::default().replay_store(&source).await.unwrap();
+    assert_eq!(balances(states), vec![(1, -5.0, 5.0, false)]);
+    assert_eq!(
+        balances(source.collect_client_states().unwrap()),
+        vec![(1, -5.0, 5.0, false)]
+    );
+}
+
+#[tokio::test]
+async fn replay_empty_store() {
+    let states = Processor::default()
+        .replay_store(&mem_store())
+        .await
+        .unwrap();
+    assert!(states.is_empty());
+}
+
+#[tokio::test]
+async fn retry_rejected() {
+    let dir = TempDir::new("retry").unwrap();
+    let input = dir.path().join("input.csv");
+    let rejects_path = dir.path().join("rejects.csv");
+    let retry_path = dir.path().join("retry-rejects.csv");
+    fs::write(
+        &input,
+        "type, client, tx, amount
+deposit, 1, 1, 10.0
+withdrawal, 1, 2, 50.0
+dispute, 1, 3,
+deposit, 1, 4",
+    )
+    .unwrap();
+
+    let store = mem_store();
+    let rejects = RejectsWriter::create(rejects_path.clone()).unwrap();
+    let summary = retry_rejects(
+        input,
+        store.clone(),
+        IoConfig::default(),
+        Some(rejects.clone()),
+    )
+    .await
+    .unwrap();
+    rejects.flush().unwrap();
+    assert_eq!(
+        summary,
+        RetrySummary {
+            applied: 1,
+            rejected: 3
+        }
+    );
+
+    // The withdrawal was rejected for insufficient funds, which a later deposit fixes.
+    store
+        .apply_transaction(Transaction::deposit(1, 5, 100.0))
+        .unwrap();
+
+    let retry = RejectsWriter::create(retry_path.clone()).unwrap();
+    let summary = retry_rejects(
+        rejects_path,
+        store.clone(),
+        IoConfig::default(),
+        Some(retry.clone()),
+    )
+    .await
+    .unwrap();
+    retry.flush().unwrap();
+    assert_eq!(
+        summary,
+        RetrySummary {
+            applied: 1,
+            rejected: 2
+        }
+    );
+
+    let state = State::from(store.get_client_state(1).unwrap().unwrap());
+    assert_eq!(state.available, 60.0);
+
+    let rejections = csv::Reader::from_path(retry_path)
+        .unwrap()
+        .into_deserialize::<Rejection>()
+        .map(|rejection| rejection.unwrap().reason)
+        .collect::<Vec<_>>();
+    // Parse failures are written by the reader, so they may come before those of the IO task.
+    assert_eq!(rejections.len(), 2);
+    assert!(rejections.contains(&RejectReason::NotFound));
+    assert!(rejections.contains(&RejectReason::Parse));
+}
+
+#[tokio::test]
+async fn retry_rejected_transfer() {
+    let dir = TempDir::new("retry").unwrap();
+    let input = dir.path().join("input.csv");
+    let rejects_path = dir.path().join("rejects.csv");
+    fs::write(
+        &input,
+        "type, client, tx, amount, to
+deposit, 1, 1, 10.0,
+transfer, 1, 2, 50.0, 2",
+    )
+    .unwrap();
+
+    let store = mem_store();
+    let rejects = RejectsWriter::create(rejects_path.clone()).unwrap();
+    retry_rejects(
+        input,
+        store.clone(),
+        IoConfig::default(),
+        Some(rejects.clone()),
+    )
+    .await
+    .unwrap();
+    rejects.flush().unwrap();
+    assert_eq!(store.get_client_state(2), Ok(None));
+
+    store
+        .apply_transaction(Transaction::deposit(1, 3, 100.0))
+        .unwrap();
+
+    // The destination is read back from the rejects file, so the transfer can be retried.
+    let summary = retry_rejects(rejects_path, store.clone(), IoConfig::default(), None)
+        .await
+        .unwrap();
+    assert_eq!(
+        summary,
+        RetrySummary {
+            applied: 1,
+            rejected: 0
+        }
+    );
+
+    let source = State::from(store.get_client_state(1).unwrap().unwrap());
+    assert_eq!(source.available, 60.0);
+    let destination = State::from(store.get_client_state(2).unwrap().unwrap());
+    assert_eq!(destination.available, 50.0);
+}
+
+#[tokio::test]
+async fn retry_rejected_partial_dispute() {
+    let dir = TempDir::new("retry").unwrap();
+    let input = dir.path().join("input.csv");
+    let rejects_path = dir.path().join("rejects.csv");
+    fs::write(
+        &input,
+        "type, client, tx, amount, disputed_amount
+dispute, 1, 1, , 4.0",
+    )
+    .unwrap();
+
+    let store = mem_store();
+    let rejects = RejectsWriter::create(rejects_path.clone()).unwrap();
+    retry_rejects(
+        input,
+        store.clone(),
+        IoConfig::default(),
+        Some(rejects.clone()),
+    )
+    .await
+    .unwrap();
+    rejects.flush().unwrap();
+
+    // The dispute was rejected because the deposit that it references had not been applied.
+    store
+        .apply_transaction(Transaction::deposit(1, 1, 10.0))
+        .unwrap();
+
+    let summary = retry_rejects(rejects_path, store.clone(), IoConfig::default(), None)
+        .await
+        .unwrap();
+    assert_eq!(
+        summary,
+        RetrySummary {
+            applied: 1,
+            rejected: 0
+        }
+    );
+
+    // Only the disputed part of the deposit is held.
+    let state = State::from(store.get_client_state(1).unwrap().unwrap());
+    assert_eq!((state.available, state.held), (6.0, 4.0));
+}
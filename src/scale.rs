@@ -0,0 +1,11 @@
+//! The number of decimal places that amounts are represented to. This is selected at compile time
+//! using either the `scale-4` (default) or the `scale-8` feature. If both are enabled, then
+//! `scale-8` takes precedence.
+
+/// The number of decimal places that amounts are represented to.
+#[cfg(not(feature = "scale-8"))]
+pub const DECIMAL_PLACES: usize = 4;
+
+/// The number of decimal places that amounts are represented to.
+#[cfg(feature = "scale-8")]
+pub const DECIMAL_PLACES: usize = 8;
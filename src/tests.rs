@@ -0,0 +1,242 @@
+use crate::cli::{ArgsError, RunArgs};
+use crate::client::{State, ValueCodec};
+use crate::io::{Outcomes, Tally};
+use crate::meta::{MetaError, RunMeta};
+use crate::parser::reader::ReaderError;
+use crate::parser::rejects::RejectReason;
+use crate::transaction::TypeCounts;
+use crate::{
+    execute, process_csv, reader_task, run, run_with_store, ClientStore, IoConfig, IoTask,
+    MemStore, ReaderConfig, TaskError,
+};
+use futures::future::join;
+use std::fs;
+use std::iter;
+use std::path::Path;
+use std::time::Duration;
+use tempdir::TempDir;
+use tokio::sync::mpsc;
+
+fn run_args(path: &Path) -> RunArgs {
+    RunArgs::parse(path.to_string_lossy().to_string(), iter::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn unreadable_input() {
+    let dir = TempDir::new("main").unwrap();
+    let path = dir.path().join("input.csv");
+    fs::write(
+        &path,
+        b"type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,\xff,2,1.0\n",
+    )
+    .unwrap();
+
+    match run(run_args(&path)).await {
+        Err(TaskError::Reader(ReaderError::Input { path: failed, .. })) => {
+            assert_eq!(failed, path)
+        }
+        r => panic!("Expected a reader error, found: `{:?}`", r),
+    }
+}
+
+#[tokio::test]
+async fn missing_input() {
+    let dir = TempDir::new("main").unwrap();
+    let path = dir.path().join("missing.csv");
+
+    // The input files are hashed for the run metadata before they are read.
+    let result = run(run_args(&path)).await;
+    assert!(matches!(result, Err(TaskError::Meta(MetaError::Io(_)))));
+}
+
+#[tokio::test]
+async fn stray_command_arguments() {
+    let dir = TempDir::new("main").unwrap();
+    let input = dir.path().join("input.csv");
+    fs::write(&input, "type,client,tx,amount\ndeposit,1,1,1.0\n").unwrap();
+    let input = input.to_string_lossy().to_string();
+
+    for command in ["lint", "meta", "disputed"] {
+        let args = [command, input.as_str(), "extra"];
+        let result = execute(args.iter().map(|arg| arg.to_string())).await;
+        assert!(
+            matches!(
+                result,
+                Err(TaskError::Args(ArgsError::UnknownArgument(ref arg))) if arg == "extra"
+            ),
+            "{}: {:?}",
+            command,
+            result
+        );
+    }
+
+    let output = dir.path().join("output.csv").to_string_lossy().to_string();
+    let args = ["normalize", input.as_str(), output.as_str(), "extra"];
+    let result = execute(args.iter().map(|arg| arg.to_string())).await;
+    assert!(matches!(
+        result,
+        Err(TaskError::Args(ArgsError::UnknownArgument(ref arg))) if arg == "extra"
+    ));
+}
+
+#[tokio::test]
+async fn mismatched_value_codec() {
+    let dir = TempDir::new("main").unwrap();
+    let path = dir.path().join("input.csv");
+    fs::write(&path, "type,client,tx,amount\ndeposit,1,1,1.0\n").unwrap();
+
+    // The store was written by an earlier run with another codec.
+    let mut meta = RunMeta::new(&run_args(&path)).unwrap();
+    meta.value_codec = ValueCodec::Compact;
+    let store = ClientStore::new(MemStore::default());
+    store.put_run_meta(&meta).unwrap();
+
+    let result = run_with_store(run_args(&path), store.clone()).await;
+    assert!(matches!(
+        result,
+        Err(TaskError::CodecMismatch {
+            stored: ValueCodec::Compact,
+            requested: ValueCodec::Plain,
+        })
+    ));
+    assert_eq!(store.collect_client_states().unwrap(), vec![]);
+}
+
+#[tokio::test]
+async fn process_csv_input() {
+    let dir = TempDir::new("main").unwrap();
+    let path = dir.path().join("input.csv");
+    fs::write(
+        &path,
+        "type, client, tx, amount
+deposit, 1, 1, 10.0
+deposit, 2, 2, 5.0
+withdrawal, 1, 3, 4.0
+dispute, 2, 2,
+",
+    )
+    .unwrap();
+
+    let store = process_csv(&path, ClientStore::new(MemStore::default()))
+        .await
+        .unwrap();
+    let states = store
+        .collect_states()
+        .unwrap()
+        .into_iter()
+        .map(
+            |State {
+                 client,
+                 available,
+                 held,
+                 ..
+             }| (client, available, held),
+        )
+        .collect::<Vec<_>>();
+    assert_eq!(states, vec![(1, 6.0, 0.0), (2, 0.0, 5.0)]);
+}
+
+#[tokio::test]
+async fn metrics() {
+    let dir = TempDir::new("main").unwrap();
+    let path = dir.path().join("input.csv");
+    fs::write(
+        &path,
+        "type, client, tx, amount
+deposit, 1, 1, 10.0
+deposit, 2, 2, 5.0
+withdrawal, 1, 3, 20.0
+dispute, 1, 1,
+resolve, 1, 1,
+dispute, 2, 2,
+chargeback, 2, 2,
+deposit, 2, 4, 1.0
+dispute, 1, 99,
+deposit, 1, 1, 3.0
+",
+    )
+    .unwrap();
+
+    let tally = Tally::default();
+    let (tx, rx) = mpsc::channel(16);
+    let store = ClientStore::new(MemStore::default());
+    let io_task = IoTask::new(rx, store, IoConfig::default(), None)
+        .with_tally(tally.clone())
+        .run(16);
+    let reader_task = reader_task(vec![path], ReaderConfig::default(), tx, None, None);
+    let (io_result, read_result) = join(io_task, reader_task).await;
+    assert!(io_result.is_ok());
+    assert!(read_result.is_ok());
+
+    let metrics = tally.metrics(Duration::from_secs(2));
+    assert_eq!(
+        metrics.outcomes,
+        Outcomes {
+            applied: TypeCounts {
+                deposit: 2,
+                dispute: 2,
+                resolve: 1,
+                chargeback: 1,
+                ..Default::default()
+            },
+            rejected: TypeCounts {
+                deposit: 2,
+                withdrawal: 1,
+                dispute: 1,
+                ..Default::default()
+            },
+        }
+    );
+    assert_eq!(
+        metrics.rejections,
+        vec![
+            (RejectReason::InsufficientFunds, 1),
+            (RejectReason::Frozen, 1),
+            (RejectReason::Duplicate, 1),
+            (RejectReason::NotFound, 1),
+        ]
+    );
+    assert_eq!(metrics.executed(), 10);
+    assert_eq!(metrics.throughput(), 5.0);
+    assert_eq!(
+        metrics.to_string(),
+        "Executed 10 transactions in 2s (5 per second)
+Applied 6: 2 deposits, 0 withdrawals, 0 transfers
+Disputes: 2 opened, 1 resolved, 1 charged back
+Rejected 4: 1 insufficient_funds, 1 frozen, 1 duplicate, 1 not_found"
+    );
+}
+
+#[cfg(all(feature = "sqlite", not(feature = "rocks")))]
+#[tokio::test]
+async fn sqlite_store_dir() {
+    let dir = TempDir::new("main").unwrap();
+    let store_path = dir.path().join("store.db");
+    let output = dir.path().join("output.csv");
+    for (tx, name) in [(1, "first.csv"), (2, "second.csv")] {
+        let input = dir.path().join(name);
+        fs::write(
+            &input,
+            format!("type,client,tx,amount\ndeposit,1,{},1.5\n", tx),
+        )
+        .unwrap();
+        let args = RunArgs::parse(
+            input.to_string_lossy().to_string(),
+            vec![
+                "--store-dir".to_string(),
+                store_path.to_string_lossy().to_string(),
+                "--output".to_string(),
+                output.to_string_lossy().to_string(),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+        run(args).await.unwrap();
+    }
+
+    // The second run continued from the state that the first persisted.
+    let store = ClientStore::new(crate::SqliteStore::new(&store_path).unwrap());
+    let states = store.collect_client_states().unwrap();
+    assert_eq!(states.len(), 1);
+    assert_eq!(State::from(states[0].clone()).total, 3.0);
+}
@@ -1,12 +1,15 @@
 use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
 
-/// An abstraction over transfer and disputed transactions.
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+/// An abstraction over transfer, disputed and internal transactions.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum Transaction {
     /// The transaction is either a `deposit` or a `withdrawal`.
     Transfer(TransferTransaction),
     /// The transaction is either a `dispute`, `resolve` or `chargeback`.
     Disputed(DisputedTransaction),
+    /// The transaction is a `transfer` of funds from one client to another.
+    Internal(InternalTransfer),
 }
 
 impl From<TransferTransaction> for Transaction {
@@ -21,12 +24,20 @@ impl From<DisputedTransaction> for Transaction {
     }
 }
 
+impl From<InternalTransfer> for Transaction {
+    fn from(tx: InternalTransfer) -> Self {
+        Transaction::Internal(tx)
+    }
+}
+
 impl Transaction {
-    /// Returns the client ID associated with this transaction.
+    /// Returns the client ID associated with this transaction. This is the source client of an
+    /// internal transfer.
     pub fn client_id(&self) -> u16 {
         match self {
             Transaction::Transfer(tx) => tx.client,
             Transaction::Disputed(tx) => tx.client,
+            Transaction::Internal(tx) => tx.client,
         }
     }
 
@@ -35,6 +46,16 @@ impl Transaction {
         match self {
             Transaction::Transfer(tx) => tx.tx,
             Transaction::Disputed(tx) => tx.tx,
+            Transaction::Internal(tx) => tx.tx,
+        }
+    }
+
+    /// Returns the type of this transaction.
+    pub fn tx_type(&self) -> TransactionType {
+        match self {
+            Transaction::Transfer(tx) => tx.kind.into(),
+            Transaction::Disputed(tx) => tx.kind.into(),
+            Transaction::Internal(_) => TransactionType::Transfer,
         }
     }
 
@@ -46,6 +67,8 @@ impl Transaction {
             tx,
             amount,
             disputed: DisputeStatus::NotDisputed,
+            executed_at: None,
+            disputed_amount: None,
         })
     }
 
@@ -57,6 +80,8 @@ impl Transaction {
             tx,
             amount,
             disputed: DisputeStatus::NotDisputed,
+            executed_at: None,
+            disputed_amount: None,
         })
     }
 
@@ -66,6 +91,17 @@ impl Transaction {
             kind: DisputedTransactionKind::Dispute,
             client,
             tx,
+            amount: None,
+        })
+    }
+
+    /// Creates a new dispute transaction of only `amount` of the referenced transaction.
+    pub fn partial_dispute(client: u16, tx: u32, amount: f64) -> Transaction {
+        Transaction::Disputed(DisputedTransaction {
+            kind: DisputedTransactionKind::Dispute,
+            client,
+            tx,
+            amount: Some(amount),
         })
     }
 
@@ -75,6 +111,7 @@ impl Transaction {
             kind: DisputedTransactionKind::Resolve,
             client,
             tx,
+            amount: None,
         })
     }
 
@@ -84,12 +121,23 @@ impl Transaction {
             kind: DisputedTransactionKind::Chargeback,
             client,
             tx,
+            amount: None,
+        })
+    }
+
+    /// Creates a new transfer of `amount` from `client` to the client `to`.
+    pub fn transfer(client: u16, to: u16, tx: u32, amount: f64) -> Transaction {
+        Transaction::Internal(InternalTransfer {
+            client,
+            to,
+            tx,
+            amount,
         })
     }
 }
 
 /// A transfer transaction model.
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub struct TransferTransaction {
     /// The type of transfer transaction.
     #[serde(rename = "type")]
@@ -102,38 +150,41 @@ pub struct TransferTransaction {
     pub amount: f64,
     /// Whether this transaction is marked as disputed.
     pub disputed: DisputeStatus,
+    /// When this transaction was executed, which is set when it is first stored. A transaction
+    /// that was stored before executions were timed has no time.
+    pub executed_at: Option<SystemTime>,
+    /// The part of this transaction's amount that is under dispute, if only part of it was
+    /// disputed. A transaction that was disputed in full has no disputed amount.
+    pub disputed_amount: Option<f64>,
 }
 
 /// A flag associated with a transaction's dispute status.
-#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, PartialEq, Copy, Clone, Serialize, Deserialize)]
 pub enum DisputeStatus {
     /// The transaction is not and has never been disputed.
+    #[default]
     NotDisputed,
     /// The transaction is currently under dispute.
     Disputed,
-    /// The transaction was previously disputed but it has been resolved.
-    Resolved,
+    /// The transaction was disputed and then charged back. This is terminal, so the transaction
+    /// can no longer be disputed, resolved or charged back.
+    ChargedBack,
 }
 
-impl Default for DisputeStatus {
-    fn default() -> Self {
-        DisputeStatus::NotDisputed
+impl TransferTransaction {
+    /// Returns the amount of this transaction that is under dispute, which is its whole amount
+    /// unless only part of it was disputed.
+    pub fn amount_under_dispute(&self) -> f64 {
+        self.disputed_amount.unwrap_or(self.amount)
     }
-}
 
-impl TransferTransaction {
     #[cfg(test)]
     pub fn is_disputed(&self) -> bool {
         matches!(self.disputed, DisputeStatus::Disputed)
     }
-
-    #[cfg(test)]
-    pub fn is_resolved(&self) -> bool {
-        matches!(self.disputed, DisputeStatus::Resolved)
-    }
 }
 
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum TransferTransactionKind {
     Deposit,
@@ -141,7 +192,7 @@ pub enum TransferTransactionKind {
 }
 
 /// A disputed transaction model.
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub struct DisputedTransaction {
     /// The type of disputed transaction.
     #[serde(rename = "type")]
@@ -150,9 +201,12 @@ pub struct DisputedTransaction {
     pub client: u16,
     /// A unique transaction number.
     pub tx: u32,
+    /// The part of the referenced transaction's amount to dispute. This is only provided to a
+    /// dispute, and the whole amount is disputed if it is not provided.
+    pub amount: Option<f64>,
 }
 
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum DisputedTransactionKind {
     Dispute,
@@ -160,7 +214,32 @@ pub enum DisputedTransactionKind {
     Chargeback,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// A transfer of funds between two clients. It is executed as a withdrawal from the source client
+/// and a deposit to the destination client, which are each stored with the transfer's ID.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct InternalTransfer {
+    /// The ID of the client that funds are withdrawn from.
+    pub client: u16,
+    /// The ID of the client that funds are deposited to.
+    pub to: u16,
+    /// A unique transaction number.
+    pub tx: u32,
+    /// The value of the transfer.
+    pub amount: f64,
+}
+
+impl InternalTransfer {
+    /// Returns the withdrawal from the source client and the deposit to the destination client
+    /// that this transfer is executed as.
+    pub fn legs(&self) -> (Transaction, Transaction) {
+        (
+            Transaction::withdrawal(self.client, self.tx, self.amount),
+            Transaction::deposit(self.to, self.tx, self.amount),
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum TransactionType {
     Deposit,
@@ -168,6 +247,21 @@ pub enum TransactionType {
     Dispute,
     Resolve,
     Chargeback,
+    Transfer,
+}
+
+impl TransactionType {
+    /// Returns the name of this transaction type as it appears in a CSV file.
+    pub fn name(&self) -> &str {
+        match self {
+            TransactionType::Deposit => "deposit",
+            TransactionType::Withdrawal => "withdrawal",
+            TransactionType::Dispute => "dispute",
+            TransactionType::Resolve => "resolve",
+            TransactionType::Chargeback => "chargeback",
+            TransactionType::Transfer => "transfer",
+        }
+    }
 }
 
 impl From<TransferTransactionKind> for TransactionType {
@@ -188,3 +282,50 @@ impl From<DisputedTransactionKind> for TransactionType {
         }
     }
 }
+
+/// A count of transactions by their type.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TypeCounts {
+    pub deposit: usize,
+    pub withdrawal: usize,
+    pub dispute: usize,
+    pub resolve: usize,
+    pub chargeback: usize,
+    #[serde(default)]
+    pub transfer: usize,
+}
+
+impl TypeCounts {
+    /// Counts a transaction of type `tx_type`.
+    pub fn add(&mut self, tx_type: TransactionType) {
+        let count = match tx_type {
+            TransactionType::Deposit => &mut self.deposit,
+            TransactionType::Withdrawal => &mut self.withdrawal,
+            TransactionType::Dispute => &mut self.dispute,
+            TransactionType::Resolve => &mut self.resolve,
+            TransactionType::Chargeback => &mut self.chargeback,
+            TransactionType::Transfer => &mut self.transfer,
+        };
+        *count += 1;
+    }
+
+    /// Adds the counts in `other` to these counts.
+    pub fn merge(&mut self, other: TypeCounts) {
+        self.deposit += other.deposit;
+        self.withdrawal += other.withdrawal;
+        self.dispute += other.dispute;
+        self.resolve += other.resolve;
+        self.chargeback += other.chargeback;
+        self.transfer += other.transfer;
+    }
+
+    /// Returns the number of transactions of every type.
+    pub fn total(&self) -> usize {
+        self.deposit
+            + self.withdrawal
+            + self.dispute
+            + self.resolve
+            + self.chargeback
+            + self.transfer
+    }
+}
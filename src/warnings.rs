@@ -0,0 +1,57 @@
+//! Structured warnings that are raised during a run. Each warning is also logged, but collecting
+//! them lets automation consume them as data rather than parsing log lines.
+
+use serde::Serialize;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// A warning that was raised during a run, along with the context that it was raised in.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Warning {
+    /// An amount had more decimal places than amounts are output to. The client and transaction
+    /// are only known if the record could be parsed.
+    PrecisionLoss {
+        line: Option<u64>,
+        client: Option<u16>,
+        tx: Option<u32>,
+        amount: String,
+    },
+    /// A record could not be parsed and was skipped.
+    SkippedRecord { line: Option<u64>, error: String },
+    /// A deferred dispute, resolve or chargeback referenced a transaction that was never
+    /// received.
+    OrphanDispute { client: u16, tx: u32 },
+    /// A withdrawal was equal to a deposit that the client made recently.
+    DrainPattern {
+        client: u16,
+        deposit: u32,
+        withdrawal: u32,
+    },
+    /// A client's persisted state could not be deserialized, so it was started with a new state.
+    CorruptState { client: u16 },
+}
+
+/// A collection of the warnings that were raised during a run, in the order that they were
+/// raised. Clones share the same collection, so that it may be read once the run has completed.
+#[derive(Debug, Default, Clone)]
+pub struct WarningCollector {
+    warnings: Arc<Mutex<Vec<Warning>>>,
+}
+
+impl WarningCollector {
+    /// Records that `warning` was raised.
+    pub fn record(&self, warning: Warning) {
+        self.warnings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(warning);
+    }
+
+    /// Returns every warning that has been recorded so far.
+    pub fn warnings(&self) -> Vec<Warning> {
+        self.warnings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}